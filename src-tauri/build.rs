@@ -23,10 +23,16 @@ fn main() {
                 "database",
                 tauri_build::InlinedPlugin::new().commands(&[
                     "import_tracks_to_library",
+                    "cancel_library_import",
+                    "get_scan_failures",
                     "get_all_tracks",
+                    "query_tracks",
                     "remove_tracks",
                     "get_tracks",
                     "update_track",
+                    "record_track_play",
+                    "set_track_rating",
+                    "get_tracks_stats",
                     "get_all_playlists",
                     "get_playlist",
                     "create_playlist",
@@ -41,6 +47,10 @@ fn main() {
                 "default-view",
                 tauri_build::InlinedPlugin::new().commands(&["set"]),
             )
+            .plugin(
+                "watcher",
+                tauri_build::InlinedPlugin::new().commands(&["refresh"]),
+            )
             .plugin(
                 "sleepblocker",
                 tauri_build::InlinedPlugin::new().commands(&["enable", "disable"]),
@@ -63,6 +73,10 @@ fn main() {
                     "dropbox_complete_auth",
                     "dropbox_is_authorized",
                     "dropbox_unauthorize",
+                    // Cloud accounts
+                    "get_cloud_accounts",
+                    "refresh_cloud_account",
+                    "get_cloud_storage_usage",
                     // Generic cloud operations
                     "cloud_list_files",
                     "cloud_list_root_files",
@@ -73,26 +87,55 @@ fn main() {
                     // Cloud folder database operations
                     "get_cloud_music_folders",
                     "get_cloud_music_folders_by_provider",
-                    "get_cloud_folder_by_local_path",
+                    "get_cloud_music_folders_by_local_path",
+                    "set_cloud_folder_sync_mode",
                     "save_cloud_folder",
                     "update_cloud_folder",
                     "delete_cloud_folder",
+                    "relocate_cloud_folder",
+                    "verify_cloud_folder",
+                    "list_cloud_track_revisions",
+                    "restore_cloud_track_revision",
+                    // Device registry
+                    "get_current_device",
+                    "set_device_name",
+                    "publish_device_manifest",
+                    "get_cloud_devices",
+                    "get_adoptable_cloud_folders",
+                    "adopt_cloud_folder",
+                    // Encryption
+                    "get_cloud_folder_encryption_status",
+                    "enable_cloud_folder_encryption",
                     // Cloud track operations
                     "cleanup_missing_local_tracks",
                     "scan_cloud_music_folder",
                     "pull_cloud_metadata",
                     "push_cloud_metadata",
+                    "sync_cloud_playlists",
+                    "sync_cloud_track_stats",
                     "get_cloud_folder_sync_details",
+                    "compute_sync_plan",
+                    "apply_sync_plan",
+                    "resolve_sync_conflict",
+                    "get_sync_history",
+                    "purge_sync_history",
                     "get_queue_items",
                     "get_queue_stats",
                     "set_sync_paused",
+                    "get_sync_paused",
                     "retry_failed_items",
+                    "cancel_queue_items",
+                    "cancel_folder_queue",
+                    "set_queue_items_priority",
+                    "purge_queue",
                     "add_to_upload_queue",
                     "add_to_download_queue",
                     "get_track_sync_status",
                     "cloud:allow-get-track-sync-status",
                     // Unified track commands
                     "get_unified_tracks",
+                    "query_unified_tracks",
+                    "search_tracks",
                     "get_unified_tracks_by_ids",
                     "get_unified_tracks_by_folder",
                     "get_unified_tracks_by_provider",
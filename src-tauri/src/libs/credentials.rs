@@ -0,0 +1,209 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use log::{info, warn};
+
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::plugins::config::get_storage_dir;
+
+const KEY_FILE: &str = "credentials.key";
+const KEY_CONTEXT: &str = "syncudio credential store v1";
+const SECRET_EXTENSION: &str = "enc";
+const NONCE_SIZE: usize = 24;
+
+/**
+ * Encrypted storage for the secrets of providers and services (eg. OAuth
+ * tokens, session keys).
+ *
+ * Secrets are encrypted with XChaCha20-Poly1305, with a key derived from a
+ * random key file. Files are only readable by the current user.
+ *
+ * Previous versions also derived the key from the hostname, so renaming the
+ * machine made every secret unreadable. Such secrets are still read with the
+ * hostname key and encrypted again without it. Secrets that cannot be
+ * decrypted at all fail with a credentials error, and the provider asks the
+ * user to sign in again.
+ */
+pub struct CredentialStore;
+
+impl CredentialStore {
+    fn get_dir() -> PathBuf {
+        get_storage_dir().join("credentials")
+    }
+
+    fn get_secret_path(name: &str) -> PathBuf {
+        Self::get_dir().join(format!("{}.{}", name, SECRET_EXTENSION))
+    }
+
+    /// Read the key file, generating it on first use only: an unreadable key
+    /// file must not be replaced, it would lose every secret
+    fn get_key_file() -> AnyResult<Vec<u8>> {
+        let path = get_storage_dir().join(KEY_FILE);
+
+        match fs::read(&path) {
+            Ok(key_file) => Ok(key_file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut key_file = vec![0u8; 32];
+                OsRng.fill_bytes(&mut key_file);
+                write_private(&path, &key_file)?;
+                info!("Generated credential store key");
+                Ok(key_file)
+            }
+            Err(e) => Err(SyncudioError::Credentials(format!("Could not read the credential store key: {}", e))),
+        }
+    }
+
+    fn cipher() -> AnyResult<XChaCha20Poly1305> {
+        let key = blake3::derive_key(KEY_CONTEXT, &Self::get_key_file()?);
+        Ok(XChaCha20Poly1305::new(&key.into()))
+    }
+
+    /// Cipher of the previous versions, whose key also depended on the hostname
+    fn legacy_cipher() -> AnyResult<XChaCha20Poly1305> {
+        let mut material = Self::get_key_file()?;
+        material.extend_from_slice(tauri_plugin_os::hostname().as_bytes());
+        let key = blake3::derive_key(KEY_CONTEXT, &material);
+        Ok(XChaCha20Poly1305::new(&key.into()))
+    }
+
+    fn decrypt(cipher: &XChaCha20Poly1305, name: &str, nonce: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .ok()
+    }
+
+    /// Get a secret, None if it was never stored
+    pub fn get(name: &str) -> AnyResult<Option<String>> {
+        let Ok(content) = fs::read(Self::get_secret_path(name)) else {
+            return Ok(None);
+        };
+        if content.len() < NONCE_SIZE {
+            return Err(SyncudioError::Credentials(format!("Invalid credentials file: {}", name)));
+        }
+
+        let (nonce, ciphertext) = content.split_at(NONCE_SIZE);
+        if let Some(plaintext) = Self::decrypt(&Self::cipher()?, name, nonce, ciphertext) {
+            let secret = String::from_utf8(plaintext).map_err(|e| SyncudioError::Credentials(e.to_string()))?;
+            return Ok(Some(secret));
+        }
+
+        let Some(plaintext) = Self::decrypt(&Self::legacy_cipher()?, name, nonce, ciphertext) else {
+            return Err(SyncudioError::Credentials(format!(
+                "Could not decrypt credentials: {}, please sign in again",
+                name
+            )));
+        };
+
+        let secret = String::from_utf8(plaintext).map_err(|e| SyncudioError::Credentials(e.to_string()))?;
+        Self::set(name, &secret)?;
+        info!("Encrypted credentials again without the hostname: {}", name);
+        Ok(Some(secret))
+    }
+
+    pub fn set(name: &str, secret: &str) -> AnyResult<()> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = Self::cipher()?
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: secret.as_bytes(),
+                    aad: name.as_bytes(),
+                },
+            )
+            .map_err(|_| SyncudioError::Credentials(format!("Could not encrypt credentials: {}", name)))?;
+
+        let dir = Self::get_dir();
+        fs::create_dir_all(&dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        }
+
+        let mut content = nonce.to_vec();
+        content.extend_from_slice(&ciphertext);
+        write_private(&Self::get_secret_path(name), &content)
+    }
+
+    pub fn delete(name: &str) -> AnyResult<()> {
+        match fs::remove_file(Self::get_secret_path(name)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the names of the stored secrets starting with a prefix
+    pub fn list(prefix: &str) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(Self::get_dir()) else {
+            return Vec::new();
+        };
+
+        entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == SECRET_EXTENSION))
+            .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string))
+            .filter(|name| name.starts_with(prefix))
+            .collect()
+    }
+
+    /**
+     * Move a secret stored in plaintext to the store. The plaintext file is
+     * removed once the secret is encrypted, a secret already in the store is
+     * never overwritten.
+     */
+    pub fn migrate_plaintext_file(name: &str, path: &Path) -> AnyResult<()> {
+        let Ok(secret) = fs::read_to_string(path) else {
+            return Ok(());
+        };
+
+        if Self::get(name)?.is_none() {
+            Self::set(name, secret.trim())?;
+            info!("Migrated plaintext credentials to the credential store: {}", name);
+        }
+
+        if let Err(e) = fs::remove_file(path) {
+            warn!("Could not remove plaintext credentials {:?}: {}", path, e);
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a file only the current user can read
+fn write_private(path: &Path, content: &[u8]) -> AnyResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(content)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    #[cfg(not(unix))]
+    fs::write(path, content)?;
+
+    Ok(())
+}
@@ -1,6 +1,12 @@
+use std::path::Path;
+
 use ormlite::sqlite::SqliteConnection;
 
 use crate::libs::error::AnyResult;
+use crate::plugins::cloud::link_maps_to_local_tracks;
+
+use super::migrations::{get_schema_version, migrate, DERIVED_TABLES_VERSION, MIGRATIONS, TRACK_IDENTITY_VERSION};
+use super::schema::create_derived_tables;
 
 /// Core database struct that holds the SQLite connection
 pub struct DB {
@@ -8,55 +14,20 @@ pub struct DB {
 }
 
 impl DB {
-    /// Create tables within a SQLite connection
-    pub async fn create_tables(&mut self) -> AnyResult<()> {
-        // TODO: move that to SQL files, or derive that from the struct itself, probably need to create a PR for ormlite-cli
-        ormlite::query(
-            "CREATE TABLE IF NOT EXISTS tracks (
-                id TEXT PRIMARY KEY NOT NULL,
-                path TEXT NOT NULL UNIQUE, -- Path as a string and unique
-                title TEXT NOT NULL,
-                album TEXT NOT NULL,
-                artists JSON NOT NULL, -- Array of strings
-                composers JSON NOT NULL, -- Array of strings
-                album_artists JSON NOT NULL, -- Array of strings
-                genres JSON NOT NULL, -- Array of strings
-                track_no INTEGER,
-                track_of INTEGER,
-                disk_no INTEGER,
-                disk_of INTEGER,
-                date TEXT,
-                year INTEGER,
-                duration INTEGER NOT NULL,
-                bitrate INTEGER,
-                sampling_rate INTEGER,
-                channels INTEGER,
-                encoder TEXT,
-                size INTEGER NOT NULL
-            );",
-        )
-        .execute(&mut self.connection)
-        .await?;
-
-        // Index for the path column in Track
-        ormlite::query("CREATE INDEX IF NOT EXISTS index_track_path ON tracks (path);")
-            .execute(&mut self.connection)
-            .await?;
-
-        ormlite::query(
-            "CREATE TABLE IF NOT EXISTS playlists (
-                id TEXT PRIMARY KEY NOT NULL,
-                name TEXT NOT NULL,
-                tracks JSON NOT NULL DEFAULT '[]', -- Array of track IDs
-                import_path TEXT UNIQUE -- Path of the playlist file, unique if it exists
-            );",
-        )
-        .execute(&mut self.connection)
-        .await?;
-
-        // Create cloud-related tables
-        crate::plugins::cloud::create_tables(&mut self.connection).await?;
+    /// Create or migrate the tables within a SQLite connection. The database
+    /// file, if any, is backed up next to itself before being migrated.
+    pub async fn create_tables(&mut self, database_path: Option<&Path>) -> AnyResult<()> {
+        let version = get_schema_version(&mut self.connection).await?;
+        migrate(&mut self.connection, MIGRATIONS, database_path).await?;
+
+        // Views and triggers of the derived tables, filled once they are migrated
+        create_derived_tables(&mut self.connection, version < DERIVED_TABLES_VERSION).await?;
+
+        // Paths only have keys once the derived tables are created
+        if version < TRACK_IDENTITY_VERSION {
+            link_maps_to_local_tracks(&mut self.connection).await?;
+        }
 
         Ok(())
     }
-} 
\ No newline at end of file
+}
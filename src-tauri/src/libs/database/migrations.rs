@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+use log::info;
+use ormlite::sqlite::SqliteConnection;
+
+use crate::libs::error::{AnyResult, SyncudioError};
+
+use super::schema::upgrade_legacy_tables;
+
+/**
+ * Versioned schema migrations. The version of a database is its
+ * `PRAGMA user_version`, the version of the last migration applied to it.
+ * Migrations are applied in order, each in its own transaction, so a failed
+ * migration leaves the database at the previous version.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// Migrations of the database, append new ones and never change applied ones
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "track_mtime",
+        sql: include_str!("migrations/0002_track_mtime.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "track_identity",
+        sql: include_str!("migrations/0003_track_identity.sql"),
+    },
+];
+
+/// Version of the migration creating the derived tables, their rows are built
+/// when a database is migrated from before it
+pub const DERIVED_TABLES_VERSION: u32 = 1;
+
+/// Version of the migration adding the local tracks of the maps, maps are
+/// linked with their tracks when a database is migrated from before it
+pub const TRACK_IDENTITY_VERSION: u32 = 3;
+
+pub async fn get_schema_version(connection: &mut SqliteConnection) -> AnyResult<u32> {
+    let (version,): (i64,) = ormlite::query_as("PRAGMA user_version")
+        .fetch_one(&mut *connection)
+        .await?;
+    Ok(version as u32)
+}
+
+/// Path of the copy of a database taken before migrating it from a version
+pub fn get_backup_path(database_path: &Path, version: u32) -> PathBuf {
+    let file_name = database_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "database".to_string());
+    database_path.with_file_name(format!("{}.v{}.bak", file_name, version))
+}
+
+/// Copy a database to a file. VACUUM INTO gives a consistent copy, with the
+/// changes still in the WAL file, unlike copying the file itself.
+async fn backup_database(connection: &mut SqliteConnection, backup_path: &Path) -> AnyResult<()> {
+    if backup_path.exists() {
+        std::fs::remove_file(backup_path)?;
+    }
+
+    ormlite::query("VACUUM INTO ?")
+        .bind(backup_path.to_string_lossy().to_string())
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+async fn apply_migration(connection: &mut SqliteConnection, migration: &Migration) -> AnyResult<()> {
+    ormlite::query("BEGIN").execute(&mut *connection).await?;
+
+    let result = async {
+        ormlite::query(migration.sql).execute(&mut *connection).await?;
+        // Pragmas cannot be bound
+        ormlite::query(&format!("PRAGMA user_version = {}", migration.version))
+            .execute(&mut *connection)
+            .await?;
+        ormlite::query("COMMIT").execute(&mut *connection).await?;
+        Ok::<_, SyncudioError>(())
+    }
+    .await;
+
+    if let Err(err) = result {
+        ormlite::query("ROLLBACK").execute(&mut *connection).await.ok();
+        return Err(SyncudioError::Migration(format!(
+            "{} ({}): {}",
+            migration.version, migration.name, err
+        )));
+    }
+
+    Ok(())
+}
+
+/// Bring a database to the last version of the migrations. Databases with
+/// tables are first copied next to `database_path`, when given. Returns the
+/// number of migrations applied.
+pub async fn migrate(
+    connection: &mut SqliteConnection,
+    migrations: &[Migration],
+    database_path: Option<&Path>,
+) -> AnyResult<usize> {
+    let version = get_schema_version(connection).await?;
+    let latest = migrations.last().map(|m| m.version).unwrap_or(0);
+
+    if version > latest {
+        return Err(SyncudioError::Migration(format!(
+            "the database is at version {}, newer than this version of the app ({})",
+            version, latest
+        )));
+    }
+
+    let pending: Vec<&Migration> = migrations.iter().filter(|m| m.version > version).collect();
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let (tables,): (i64,) = ormlite::query_as("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'")
+        .fetch_one(&mut *connection)
+        .await?;
+
+    if let Some(database_path) = database_path.filter(|_| tables > 0) {
+        let backup_path = get_backup_path(database_path, version);
+        info!("Backing up database to {:?} before migrating", backup_path);
+        backup_database(connection, &backup_path).await?;
+    }
+
+    // Tables created before versioned migrations may be older than the initial migration
+    if version == 0 && tables > 0 {
+        upgrade_legacy_tables(connection).await?;
+    }
+
+    for migration in &pending {
+        info!("Migrating database to version {} ({})", migration.version, migration.name);
+        apply_migration(connection, migration).await?;
+    }
+
+    Ok(pending.len())
+}
@@ -0,0 +1,236 @@
+-- Schema of the databases created before versioned migrations. Tables may
+-- already exist in those databases, so everything is created if missing.
+
+-- Library
+CREATE TABLE IF NOT EXISTS tracks (
+    id TEXT PRIMARY KEY NOT NULL,
+    path TEXT NOT NULL UNIQUE, -- Path as a string and unique
+    title TEXT NOT NULL,
+    album TEXT NOT NULL,
+    artists JSON NOT NULL, -- Array of strings
+    composers JSON NOT NULL, -- Array of strings
+    album_artists JSON NOT NULL, -- Array of strings
+    genres JSON NOT NULL, -- Array of strings
+    track_no INTEGER,
+    track_of INTEGER,
+    disk_no INTEGER,
+    disk_of INTEGER,
+    date TEXT,
+    year INTEGER,
+    duration INTEGER NOT NULL,
+    bitrate INTEGER,
+    sampling_rate INTEGER,
+    channels INTEGER,
+    encoder TEXT,
+    size INTEGER NOT NULL,
+    path_key TEXT NOT NULL DEFAULT '' -- Key of the path, see local_path_key
+);
+CREATE INDEX IF NOT EXISTS index_track_path ON tracks (path);
+CREATE INDEX IF NOT EXISTS idx_tracks_path_key ON tracks(path_key);
+
+-- Sorts of the library queries, on the same expressions as their ORDER BY
+CREATE INDEX IF NOT EXISTS index_track_artist ON tracks (json_extract(artists, '$[0]') COLLATE NOCASE, album COLLATE NOCASE, disk_no, track_no);
+CREATE INDEX IF NOT EXISTS index_track_album ON tracks (album COLLATE NOCASE, json_extract(artists, '$[0]') COLLATE NOCASE, disk_no, track_no);
+CREATE INDEX IF NOT EXISTS index_track_title ON tracks (title COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS index_track_genre ON tracks (json_extract(genres, '$[0]') COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS index_track_duration ON tracks (duration);
+CREATE INDEX IF NOT EXISTS index_track_year ON tracks (year);
+
+CREATE TABLE IF NOT EXISTS playlists (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    tracks JSON NOT NULL DEFAULT '[]', -- Array of track IDs
+    import_path TEXT UNIQUE -- Path of the playlist file, unique if it exists
+);
+
+CREATE TABLE IF NOT EXISTS track_stats (
+    track_id TEXT PRIMARY KEY NOT NULL,
+    play_count INTEGER NOT NULL DEFAULT 0, -- Plays on this device only
+    last_played DATETIME,
+    rating INTEGER,
+    rating_updated_at DATETIME
+);
+
+-- Cloud folder mappings, a local folder can be synced with several cloud folders
+CREATE TABLE IF NOT EXISTS cloud_music_folders (
+    id TEXT PRIMARY KEY NOT NULL,
+    provider_type TEXT NOT NULL,
+    cloud_folder_id TEXT NOT NULL,
+    cloud_folder_path TEXT NOT NULL,
+    local_folder_path TEXT NOT NULL,
+    account_id TEXT,
+    sync_mode TEXT NOT NULL DEFAULT 'two_way', -- A CloudSyncMode
+    local_path_key TEXT NOT NULL DEFAULT '', -- Key of local_folder_path, see local_path_key
+    UNIQUE (local_folder_path, provider_type, cloud_folder_id)
+);
+
+CREATE TABLE IF NOT EXISTS cloud_accounts (
+    id TEXT PRIMARY KEY NOT NULL,
+    provider_type TEXT NOT NULL,
+    provider_account_id TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    quota_used INTEGER,
+    quota_allocated INTEGER,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    UNIQUE (provider_type, provider_account_id)
+);
+
+CREATE TABLE IF NOT EXISTS cloud_tracks (
+    id TEXT PRIMARY KEY NOT NULL,
+    file_name TEXT NOT NULL,
+    updated_at DATETIME NOT NULL,
+    size INTEGER NOT NULL,
+    tags JSON -- JSON object of CloudTrackTag
+);
+
+-- Maps cloud tracks to their locations
+CREATE TABLE IF NOT EXISTS cloud_maps (
+    id TEXT PRIMARY KEY NOT NULL,
+    cloud_track_id TEXT NOT NULL,
+    cloud_music_folder_id TEXT NOT NULL,
+    relative_path TEXT NOT NULL,
+    cloud_file_id TEXT UNIQUE, -- Location-specific
+    path_key TEXT NOT NULL DEFAULT '', -- Key of relative_path, see RelativePath::key
+    FOREIGN KEY (cloud_track_id) REFERENCES cloud_tracks(id),
+    FOREIGN KEY (cloud_music_folder_id) REFERENCES cloud_music_folders(id)
+);
+CREATE INDEX IF NOT EXISTS idx_cloud_maps_cloud_track_id ON cloud_maps(cloud_track_id);
+CREATE INDEX IF NOT EXISTS idx_cloud_maps_cloud_music_folder_id ON cloud_maps(cloud_music_folder_id);
+CREATE INDEX IF NOT EXISTS idx_cloud_maps_folder_track ON cloud_maps(cloud_music_folder_id, cloud_track_id);
+CREATE INDEX IF NOT EXISTS idx_cloud_maps_folder_path_key ON cloud_maps(cloud_music_folder_id, path_key);
+
+-- Sync queues, with a single active operation per map and direction
+CREATE TABLE IF NOT EXISTS download_queue (
+    id TEXT PRIMARY KEY NOT NULL,
+    cloud_map_id TEXT NOT NULL,
+    provider_type TEXT NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL,
+    error_message TEXT,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    revision_id TEXT -- Download a previous revision instead of the current file
+);
+CREATE INDEX IF NOT EXISTS idx_download_queue_cloud_map_id ON download_queue(cloud_map_id);
+CREATE INDEX IF NOT EXISTS idx_download_queue_status ON download_queue(status);
+CREATE INDEX IF NOT EXISTS idx_download_queue_map_status ON download_queue(cloud_map_id, status);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_download_queue_active_ops ON download_queue(cloud_map_id)
+    WHERE status IN ('pending', 'in_progress');
+CREATE INDEX IF NOT EXISTS idx_download_queue_status_priority ON download_queue(status, priority DESC, created_at);
+
+CREATE TABLE IF NOT EXISTS upload_queue (
+    id TEXT PRIMARY KEY NOT NULL,
+    cloud_map_id TEXT NOT NULL,
+    provider_type TEXT NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL,
+    error_message TEXT,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS idx_upload_queue_cloud_map_id ON upload_queue(cloud_map_id);
+CREATE INDEX IF NOT EXISTS idx_upload_queue_status ON upload_queue(status);
+CREATE INDEX IF NOT EXISTS idx_upload_queue_map_status ON upload_queue(cloud_map_id, status);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_upload_queue_active_ops ON upload_queue(cloud_map_id)
+    WHERE status IN ('pending', 'in_progress');
+CREATE INDEX IF NOT EXISTS idx_upload_queue_status_priority ON upload_queue(status, priority DESC, created_at);
+
+-- Pause state of each queue direction
+CREATE TABLE IF NOT EXISTS sync_queue_state (
+    operation TEXT PRIMARY KEY NOT NULL,
+    paused BOOLEAN NOT NULL DEFAULT 0,
+    updated_at DATETIME NOT NULL
+);
+
+-- Playlists shared between devices
+CREATE TABLE IF NOT EXISTS cloud_playlists (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    tracks JSON NOT NULL DEFAULT '[]', -- Array of CloudPlaylistTrackRef
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    local_playlist_id TEXT UNIQUE, -- Linked local playlist
+    synced_tracks JSON NOT NULL DEFAULT '[]', -- Tracks at last sync (merge base)
+    synced_name TEXT NOT NULL,
+    deleted BOOLEAN NOT NULL DEFAULT 0
+);
+
+-- Play counts and ratings shared between devices
+CREATE TABLE IF NOT EXISTS cloud_track_stats (
+    cloud_track_id TEXT PRIMARY KEY NOT NULL,
+    cloud_file_id TEXT,
+    play_counts JSON NOT NULL DEFAULT '{}', -- Object of device id -> plays
+    last_played JSON NOT NULL DEFAULT '{}', -- Object of device id -> date
+    rating INTEGER,
+    rating_updated_at DATETIME
+);
+CREATE INDEX IF NOT EXISTS idx_cloud_track_stats_cloud_file_id ON cloud_track_stats(cloud_file_id);
+
+-- Folders encrypted on the client side
+CREATE TABLE IF NOT EXISTS cloud_folder_encryption (
+    cloud_music_folder_id TEXT PRIMARY KEY NOT NULL,
+    salt TEXT NOT NULL,
+    key_check TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (cloud_music_folder_id) REFERENCES cloud_music_folders(id)
+);
+
+-- Plaintext hashes of encrypted tracks, never uploaded
+CREATE TABLE IF NOT EXISTS cloud_file_hashes (
+    cloud_map_id TEXT PRIMARY KEY NOT NULL,
+    plaintext_hash TEXT NOT NULL,
+    updated_at DATETIME NOT NULL,
+    FOREIGN KEY (cloud_map_id) REFERENCES cloud_maps(id)
+);
+
+-- Audit log of sync operations
+CREATE TABLE IF NOT EXISTS sync_history (
+    id TEXT PRIMARY KEY NOT NULL,
+    timestamp DATETIME NOT NULL,
+    operation TEXT NOT NULL,
+    status TEXT NOT NULL,
+    provider_type TEXT NOT NULL,
+    cloud_music_folder_id TEXT,
+    cloud_track_id TEXT,
+    relative_path TEXT,
+    bytes INTEGER NOT NULL DEFAULT 0,
+    duration_ms INTEGER NOT NULL DEFAULT 0,
+    error_message TEXT,
+    details TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_sync_history_timestamp ON sync_history(timestamp);
+CREATE INDEX IF NOT EXISTS idx_sync_history_cloud_track_id ON sync_history(cloud_track_id);
+CREATE INDEX IF NOT EXISTS idx_sync_history_folder ON sync_history(cloud_music_folder_id);
+
+-- Tables derived from the tracks, maps and folders, kept up to date by the
+-- triggers created on startup (see create_derived_tables). Databases from
+-- before versioned migrations may already have them, their rows are built
+-- again from scratch once migrated.
+
+-- Matches of the unified tracks, see unified_tracks.rs
+CREATE TABLE IF NOT EXISTS unified_track_links (
+    local_track_id TEXT, -- None for cloud-only tracks
+    cloud_music_folder_id TEXT NOT NULL,
+    cloud_map_id TEXT, -- None for local tracks that are not mapped
+    cloud_track_id TEXT -- Copied from the map, to look tracks up by any ID
+);
+CREATE INDEX IF NOT EXISTS idx_unified_track_links_local_track_id ON unified_track_links(local_track_id);
+CREATE INDEX IF NOT EXISTS idx_unified_track_links_folder ON unified_track_links(cloud_music_folder_id);
+CREATE INDEX IF NOT EXISTS idx_unified_track_links_cloud_map_id ON unified_track_links(cloud_map_id);
+CREATE INDEX IF NOT EXISTS idx_unified_track_links_cloud_track_id ON unified_track_links(cloud_track_id);
+
+-- Full-text index of the local and cloud tracks, see search.rs
+CREATE TABLE IF NOT EXISTS library_search_rows (
+    id INTEGER PRIMARY KEY, -- Row ID in library_search
+    local_track_id TEXT UNIQUE,
+    cloud_track_id TEXT UNIQUE
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS library_search USING fts5(
+    title, album, artists, album_artists, composers, genres,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
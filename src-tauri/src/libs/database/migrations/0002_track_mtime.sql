@@ -0,0 +1,3 @@
+-- Modification time of the track files, to rescan only the files changed since
+-- they were read. Tracks read before have none, and are read again once.
+ALTER TABLE tracks ADD COLUMN mtime INTEGER;
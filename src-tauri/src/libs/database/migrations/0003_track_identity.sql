@@ -0,0 +1,12 @@
+-- Track IDs used to be derived from their path. Existing IDs are kept as they
+-- are, they now stay the same when files move, so the playlists and stats
+-- referencing them are still valid.
+
+-- Hash of the audio content, to find the track of a moved file
+ALTER TABLE tracks ADD COLUMN fingerprint TEXT;
+CREATE INDEX IF NOT EXISTS index_track_fingerprint ON tracks (fingerprint);
+
+-- Local track of a map, kept when the local file moves. Maps are linked with
+-- their tracks on startup, once their paths have keys, see link_maps_to_local_tracks
+ALTER TABLE cloud_maps ADD COLUMN local_track_id TEXT;
+CREATE INDEX IF NOT EXISTS idx_cloud_maps_local_track_id ON cloud_maps(local_track_id);
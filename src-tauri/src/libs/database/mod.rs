@@ -1,5 +1,8 @@
 pub(crate) mod core;
+pub(crate) mod migrations;
+pub(crate) mod schema;
 pub(crate) mod track;
 pub(crate) mod playlist;
+pub(crate) mod track_stats;
 
 pub use core::*; 
\ No newline at end of file
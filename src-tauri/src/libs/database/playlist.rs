@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 use ormlite::model::ModelBuilder;
 use ormlite::Model;
@@ -104,4 +105,70 @@ impl DB {
 
         Ok(())
     }
-} 
\ No newline at end of file
+
+    /// Give the playlists imported from a relocated folder their new import
+    /// path. Their tracks keep their IDs. Returns the number of updated
+    /// playlists.
+    pub async fn relocate_playlists(&mut self, from: &Path, to: &Path) -> AnyResult<usize> {
+        let playlists = Playlist::select().fetch_all(&mut self.connection).await?;
+        let mut updated = 0;
+
+        for mut playlist in playlists {
+            let import_path = playlist
+                .import_path
+                .as_ref()
+                .and_then(|path| Path::new(path).strip_prefix(from).ok())
+                .map(|relative_path| to.join(relative_path).to_string_lossy().to_string());
+            let Some(import_path) = import_path else {
+                continue;
+            };
+
+            playlist.import_path = Some(import_path);
+            playlist.update_all_fields(&mut self.connection).await?;
+            updated += 1;
+        }
+
+        Ok(updated)
+    }
+
+    /// Remove tracks from the playlists they are in. Returns the number of
+    /// updated playlists.
+    pub async fn remove_tracks_from_playlists(&mut self, track_ids: &[String]) -> AnyResult<usize> {
+        let removed: HashSet<&String> = track_ids.iter().collect();
+        let playlists = Playlist::select().fetch_all(&mut self.connection).await?;
+        let mut updated = 0;
+
+        for mut playlist in playlists {
+            if !playlist.tracks.iter().any(|id| removed.contains(id)) {
+                continue;
+            }
+
+            playlist.tracks.retain(|id| !removed.contains(id));
+            playlist.update_all_fields(&mut self.connection).await?;
+            updated += 1;
+        }
+
+        Ok(updated)
+    }
+
+    /// Replace a track by another one in the playlists it is in. Returns the
+    /// number of updated playlists.
+    pub async fn replace_track_in_playlists(&mut self, track_id: &str, new_track_id: &str) -> AnyResult<usize> {
+        let playlists = Playlist::select().fetch_all(&mut self.connection).await?;
+        let mut updated = 0;
+
+        for mut playlist in playlists {
+            if !playlist.tracks.iter().any(|id| id == track_id) {
+                continue;
+            }
+
+            for id in playlist.tracks.iter_mut().filter(|id| *id == track_id) {
+                *id = new_track_id.to_string();
+            }
+            playlist.update_all_fields(&mut self.connection).await?;
+            updated += 1;
+        }
+
+        Ok(updated)
+    }
+}
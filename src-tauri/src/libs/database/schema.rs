@@ -0,0 +1,134 @@
+use ormlite::sqlite::SqliteConnection;
+
+use crate::libs::error::AnyResult;
+use crate::plugins::cloud::{create_library_search, create_unified_tracks};
+
+/// Add a column to an existing table of a database from before versioned
+/// migrations
+pub async fn add_column_if_missing(
+    connection: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> AnyResult<()> {
+    let columns: Vec<(String,)> = ormlite::query_as(&format!("SELECT name FROM pragma_table_info('{}')", table))
+        .fetch_all(&mut *connection)
+        .await?;
+
+    if !columns.iter().any(|(name,)| name == column) {
+        ormlite::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(&mut *connection)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Columns of the cloud folders table in the initial migration, a local folder
+/// can be synced with several cloud folders
+const CLOUD_MUSIC_FOLDERS_COLUMNS: &str = "
+    id TEXT PRIMARY KEY NOT NULL,
+    provider_type TEXT NOT NULL,
+    cloud_folder_id TEXT NOT NULL,
+    cloud_folder_path TEXT NOT NULL,
+    local_folder_path TEXT NOT NULL,
+    account_id TEXT,
+    sync_mode TEXT NOT NULL DEFAULT 'two_way', -- A CloudSyncMode
+    local_path_key TEXT NOT NULL DEFAULT '', -- Key of local_folder_path, see local_path_key
+    UNIQUE (local_folder_path, provider_type, cloud_folder_id)";
+
+/**
+ * Local folders used to be unique, which only allowed a single cloud folder
+ * per local folder. SQLite cannot drop a constraint, so the table is rebuilt.
+ */
+async fn allow_multiple_cloud_folders_per_local_folder(connection: &mut SqliteConnection) -> AnyResult<()> {
+    let table: Option<(String,)> =
+        ormlite::query_as("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'cloud_music_folders'")
+            .fetch_optional(&mut *connection)
+            .await?;
+    if !table.is_some_and(|(sql,)| sql.contains("local_folder_path TEXT NOT NULL UNIQUE")) {
+        return Ok(());
+    }
+
+    // Foreign keys cannot be toggled in a transaction, and would block the drop
+    let (foreign_keys,): (bool,) = ormlite::query_as("PRAGMA foreign_keys").fetch_one(&mut *connection).await?;
+    ormlite::query("PRAGMA foreign_keys = OFF").execute(&mut *connection).await?;
+
+    // The view depending on the table is created again afterwards
+    let rebuild = format!(
+        "BEGIN;
+         DROP VIEW IF EXISTS unified_tracks;
+         CREATE TABLE cloud_music_folders_new ({});
+         INSERT INTO cloud_music_folders_new
+            (id, provider_type, cloud_folder_id, cloud_folder_path, local_folder_path, account_id, sync_mode)
+            SELECT id, provider_type, cloud_folder_id, cloud_folder_path, local_folder_path, account_id, sync_mode
+            FROM cloud_music_folders;
+         DROP TABLE cloud_music_folders;
+         ALTER TABLE cloud_music_folders_new RENAME TO cloud_music_folders;
+         COMMIT;",
+        CLOUD_MUSIC_FOLDERS_COLUMNS
+    );
+    let result = ormlite::query(&rebuild).execute(&mut *connection).await;
+    if result.is_err() {
+        ormlite::query("ROLLBACK").execute(&mut *connection).await.ok();
+    }
+
+    if foreign_keys {
+        ormlite::query("PRAGMA foreign_keys = ON").execute(&mut *connection).await?;
+    }
+
+    result?;
+    Ok(())
+}
+
+/// Whether a table exists, tables of databases from before versioned
+/// migrations may be missing
+async fn table_exists(connection: &mut SqliteConnection, table: &str) -> AnyResult<bool> {
+    let table: Option<(String,)> = ormlite::query_as("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .bind(table)
+        .fetch_optional(&mut *connection)
+        .await?;
+    Ok(table.is_some())
+}
+
+/**
+ * Databases created before versioned migrations have the tables of the
+ * version that created them, which `CREATE TABLE IF NOT EXISTS` never updated.
+ * Bring those tables to the initial migration before it runs.
+ */
+pub async fn upgrade_legacy_tables(connection: &mut SqliteConnection) -> AnyResult<()> {
+    // Folders created before multiple accounts and cloud folders per local folder were supported
+    if table_exists(connection, "cloud_music_folders").await? {
+        add_column_if_missing(connection, "cloud_music_folders", "account_id", "TEXT").await?;
+        add_column_if_missing(connection, "cloud_music_folders", "sync_mode", "TEXT NOT NULL DEFAULT 'two_way'").await?;
+        allow_multiple_cloud_folders_per_local_folder(connection).await?;
+    }
+
+    if table_exists(connection, "download_queue").await? {
+        add_column_if_missing(connection, "download_queue", "revision_id", "TEXT").await?;
+    }
+
+    // Rows from before path keys existed get their keys on startup, see fill_path_keys
+    for (table, column) in [
+        ("tracks", "path_key"),
+        ("cloud_maps", "path_key"),
+        ("cloud_music_folders", "local_path_key"),
+    ] {
+        if table_exists(connection, table).await? {
+            add_column_if_missing(connection, table, column, "TEXT NOT NULL DEFAULT ''").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Create the triggers and views of the derived tables, which the migrations
+/// create. They are always created again to pick up changes. The rows are
+/// built from the other tables when `rebuild` is set, eg. once migrated.
+pub async fn create_derived_tables(connection: &mut SqliteConnection, rebuild: bool) -> AnyResult<()> {
+    // Unified tracks and library search, maintained by triggers
+    create_unified_tracks(connection, rebuild).await?;
+    create_library_search(connection, rebuild).await?;
+
+    Ok(())
+}
@@ -1,9 +1,12 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use ormlite::Model;
 
 use crate::libs::error::AnyResult;
+use crate::libs::relative_path::local_path_key;
 use crate::libs::track::Track;
+use crate::libs::track_query::{QueryValue, TrackPage, TrackQuery, TrackQueryTarget};
 use crate::libs::utils::TimeLogger;
 
 use super::core::DB;
@@ -43,8 +46,96 @@ impl DB {
         Ok(tracks)
     }
 
+    /// Get the tracks at the given paths, in the same order. Paths without a
+    /// track are skipped.
+    pub async fn get_tracks_by_paths(&mut self, paths: &[PathBuf]) -> AnyResult<Vec<Track>> {
+        let placeholders = paths.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+        let where_statement = format!("path IN ({})", placeholders);
+
+        let mut query_builder = Track::select().dangerous_where(&where_statement);
+
+        for path in paths {
+            query_builder = query_builder.bind(path.to_string_lossy().to_string());
+        }
+
+        let tracks_by_path: HashMap<String, Track> = query_builder
+            .fetch_all(&mut self.connection)
+            .await?
+            .into_iter()
+            .map(|track| (track.path.clone(), track))
+            .collect();
+
+        let tracks = paths
+            .iter()
+            .filter_map(|path| tracks_by_path.get(path.to_string_lossy().as_ref()).cloned())
+            .collect();
+
+        Ok(tracks)
+    }
+
+    /// Find the track of a file that was moved or renamed: a track with the
+    /// same audio content, whose file is gone.
+    pub async fn find_moved_track(&mut self, track: &Track) -> AnyResult<Option<Track>> {
+        let Some(fingerprint) = &track.fingerprint else {
+            return Ok(None);
+        };
+
+        let candidates = Track::select()
+            .where_("fingerprint = ? AND path != ?")
+            .bind(fingerprint)
+            .bind(&track.path)
+            .fetch_all(&mut self.connection)
+            .await?;
+
+        Ok(candidates
+            .into_iter()
+            .find(|candidate| !Path::new(&candidate.path).exists()))
+    }
+
+    /// Get a page of tracks, filtered and sorted
+    pub async fn query_tracks(&mut self, query: &TrackQuery) -> AnyResult<TrackPage<Track>> {
+        let total = if query.with_total {
+            let (where_sql, values) = query.where_sql(TrackQueryTarget::Tracks);
+            let count_sql = format!("SELECT COUNT(*) FROM tracks t {}", where_sql);
+            let mut count_query = ormlite::query_as::<_, (i64,)>(&count_sql);
+            for value in &values {
+                count_query = match value {
+                    QueryValue::Text(text) => count_query.bind(text),
+                    QueryValue::Integer(integer) => count_query.bind(integer),
+                };
+            }
+            let (total,) = count_query.fetch_one(&mut self.connection).await?;
+            Some(total as u32)
+        } else {
+            None
+        };
+
+        // One more row than the limit tells whether there is a next page
+        let (where_sql, values) = query.page_where_sql(TrackQueryTarget::Tracks)?;
+        let select_sql = format!(
+            "SELECT t.* FROM tracks t {} {} LIMIT ?",
+            where_sql,
+            query.order_sql(TrackQueryTarget::Tracks)
+        );
+        let mut select_query = Track::query(&select_sql);
+        for value in &values {
+            select_query = match value {
+                QueryValue::Text(text) => select_query.bind(text),
+                QueryValue::Integer(integer) => select_query.bind(integer),
+            };
+        }
+        let items = select_query
+            .bind(query.get_limit() + 1)
+            .fetch_all(&mut self.connection)
+            .await?;
+
+        Ok(TrackPage::new(items, query, TrackQueryTarget::Tracks, total))
+    }
+
     /// Update a track in the database
-    pub async fn update_track(&mut self, track: Track) -> AnyResult<Track> {
+    pub async fn update_track(&mut self, mut track: Track) -> AnyResult<Track> {
+        // Tracks from the front-end have no path key
+        track.path_key = local_path_key(&track.path);
         let updated_track = track.update_all_fields(&mut self.connection).await?;
         Ok(updated_track)
     }
@@ -61,16 +152,148 @@ impl DB {
         Ok(())
     }
 
+    /// Insert a track read from its file, or update the track it already is:
+    /// the one at the same path, or the one of a moved file. The track takes
+    /// the ID of the updated track. Returns true if the track was inserted.
+    pub async fn upsert_track(&mut self, track: &mut Track) -> AnyResult<bool> {
+        track.path_key = local_path_key(&track.path);
+        let existing = Track::select()
+            .where_bind("path = ?", &track.path)
+            .fetch_optional(&mut self.connection)
+            .await?;
+
+        let existing = match existing {
+            Some(existing) => Some(existing),
+            None => self.find_moved_track(track).await?,
+        };
+
+        match existing {
+            Some(existing) => {
+                track.id = existing.id;
+                track.clone().update_all_fields(&mut self.connection).await?;
+                Ok(false)
+            }
+            None => {
+                track.clone().insert(&mut self.connection).await?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Get the tracks at the given paths, or under them for folders
+    pub async fn get_tracks_by_path(&mut self, paths: &[PathBuf]) -> AnyResult<Vec<Track>> {
+        let mut tracks: Vec<Track> = Vec::new();
+
+        for path in paths {
+            // Keys ignore case, the paths themselves tell which tracks are really under the path
+            let key = local_path_key(&path.to_string_lossy());
+            let candidates = Track::select()
+                .where_("path_key = ? OR (path_key > ? AND path_key < ?)")
+                .bind(&key)
+                .bind(format!("{}/", key))
+                .bind(format!("{}0", key))
+                .fetch_all(&mut self.connection)
+                .await?;
+
+            for track in candidates {
+                // A removed folder and the files it had may both be given
+                if Path::new(&track.path).starts_with(path) && !tracks.iter().any(|t| t.id == track.id) {
+                    tracks.push(track);
+                }
+            }
+        }
+
+        Ok(tracks)
+    }
+
     /// Insert a new track in the DB, will fail in case there is a duplicate unique
     /// key (like track.path)
     ///
     /// Doc: https://github.com/khonsulabs/bonsaidb/blob/main/examples/basic-local/examples/basic-local-multidb.rs
     pub async fn insert_tracks(&mut self, tracks: Vec<Track>) -> AnyResult<()> {
         // Weirdly, this is fast enough with SQLite, no need to create transactions
-        for track in tracks {
+        for mut track in tracks {
+            track.path_key = local_path_key(&track.path);
             track.insert(&mut self.connection).await?;
         }
 
         Ok(())
     }
-} 
\ No newline at end of file
+
+    /// Move the tracks under a folder to another folder. They keep their IDs,
+    /// so their stats and playlists are not affected. Returns the number of
+    /// moved tracks.
+    pub async fn relocate_tracks(&mut self, from: &Path, to: &Path) -> AnyResult<usize> {
+        let key = local_path_key(&from.to_string_lossy());
+        let tracks = Track::select()
+            .where_("path_key > ? AND path_key < ?")
+            .bind(format!("{}/", key))
+            .bind(format!("{}0", key))
+            .fetch_all(&mut self.connection)
+            .await?;
+
+        let mut relocated = 0;
+
+        for track in tracks {
+            let Ok(relative_path) = Path::new(&track.path).strip_prefix(from) else {
+                continue;
+            };
+            let path = to.join(relative_path).to_string_lossy().to_string();
+
+            // The new location may already have been imported, eg. by the watcher
+            let duplicate = Track::select()
+                .where_("path = ? AND id != ?")
+                .bind(&path)
+                .bind(&track.id)
+                .fetch_optional(&mut self.connection)
+                .await?;
+            if let Some(duplicate) = duplicate {
+                self.merge_duplicate_track(&duplicate.id, &track.id).await?;
+            }
+
+            ormlite::query("UPDATE tracks SET path = ?, path_key = ? WHERE id = ?")
+                .bind(&path)
+                .bind(local_path_key(&path))
+                .bind(&track.id)
+                .execute(&mut self.connection)
+                .await?;
+
+            relocated += 1;
+        }
+
+        Ok(relocated)
+    }
+
+    /// Merge a track into another one for the same file, then delete it: its
+    /// playlists, stats and cloud mappings go to the other track
+    async fn merge_duplicate_track(&mut self, duplicate_id: &str, track_id: &str) -> AnyResult<()> {
+        self.replace_track_in_playlists(duplicate_id, track_id).await?;
+
+        ormlite::query(
+            "INSERT INTO track_stats (track_id, play_count, last_played, rating, rating_updated_at)
+            SELECT ?, play_count, last_played, rating, rating_updated_at FROM track_stats WHERE track_id = ?
+            ON CONFLICT(track_id) DO UPDATE SET
+                play_count = play_count + excluded.play_count,
+                last_played = MAX(COALESCE(last_played, excluded.last_played), COALESCE(excluded.last_played, last_played)),
+                rating = CASE WHEN excluded.rating_updated_at > COALESCE(rating_updated_at, '')
+                    THEN excluded.rating ELSE rating END,
+                rating_updated_at = MAX(COALESCE(rating_updated_at, excluded.rating_updated_at), COALESCE(excluded.rating_updated_at, rating_updated_at));",
+        )
+        .bind(track_id)
+        .bind(duplicate_id)
+        .execute(&mut self.connection)
+        .await?;
+
+        ormlite::query("UPDATE cloud_maps SET local_track_id = ? WHERE local_track_id = ?")
+            .bind(track_id)
+            .bind(duplicate_id)
+            .execute(&mut self.connection)
+            .await?;
+
+        for query in ["DELETE FROM track_stats WHERE track_id = ?", "DELETE FROM tracks WHERE id = ?"] {
+            ormlite::query(query).bind(duplicate_id).execute(&mut self.connection).await?;
+        }
+
+        Ok(())
+    }
+}
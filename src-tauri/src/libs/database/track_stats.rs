@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use ormlite::{FromRow, Model};
+
+use crate::libs::error::AnyResult;
+use crate::libs::track_stats::{TrackStats, TrackStatsDTO};
+use crate::plugins::cloud::CloudTrackStats;
+
+use super::core::DB;
+
+/// Local stats of a track, joined with the cloud track it is mapped to
+#[derive(Debug, FromRow)]
+struct TrackStatsRow {
+    track_id: String,
+    play_count: Option<u32>,
+    last_played: Option<DateTime<Utc>>,
+    rating: Option<u32>,
+    rating_updated_at: Option<DateTime<Utc>>,
+    cloud_track_id: Option<String>,
+}
+
+impl DB {
+    /// Get the stats recorded on this device for all tracks
+    pub async fn get_all_track_stats(&mut self) -> AnyResult<Vec<TrackStats>> {
+        let stats = TrackStats::select().fetch_all(&mut self.connection).await?;
+        Ok(stats)
+    }
+
+    /// Record a play of a track on this device
+    pub async fn record_track_play(&mut self, track_id: &str, played_at: DateTime<Utc>) -> AnyResult<()> {
+        ormlite::query(
+            "INSERT INTO track_stats (track_id, play_count, last_played) VALUES (?, 1, ?)
+            ON CONFLICT(track_id) DO UPDATE SET
+                play_count = play_count + 1,
+                last_played = excluded.last_played;",
+        )
+        .bind(track_id)
+        .bind(played_at)
+        .execute(&mut self.connection)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Set the rating of a track, None to remove it
+    pub async fn set_track_rating(
+        &mut self,
+        track_id: &str,
+        rating: Option<u32>,
+        updated_at: DateTime<Utc>,
+    ) -> AnyResult<()> {
+        ormlite::query(
+            "INSERT INTO track_stats (track_id, play_count, rating, rating_updated_at) VALUES (?, 0, ?, ?)
+            ON CONFLICT(track_id) DO UPDATE SET
+                rating = excluded.rating,
+                rating_updated_at = excluded.rating_updated_at;",
+        )
+        .bind(track_id)
+        .bind(rating)
+        .bind(updated_at)
+        .execute(&mut self.connection)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the stats of tracks given a set of IDs, including the plays and
+    /// ratings synced from other devices
+    pub async fn get_tracks_stats(
+        &mut self,
+        track_ids: &[String],
+        device_id: &str,
+    ) -> AnyResult<Vec<TrackStatsDTO>> {
+        let placeholders = track_ids.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+        let query = format!(
+            r#"
+            SELECT
+                t.id as track_id,
+                ts.play_count,
+                ts.last_played,
+                ts.rating,
+                ts.rating_updated_at,
+                l.cloud_track_id
+            FROM tracks t
+            LEFT JOIN track_stats ts ON ts.track_id = t.id
+            LEFT JOIN unified_track_links l ON l.local_track_id = t.id AND l.cloud_track_id IS NOT NULL
+            WHERE t.id IN ({})
+            "#,
+            placeholders
+        );
+
+        let mut query_builder = ormlite::query_as(&query);
+        for id in track_ids {
+            query_builder = query_builder.bind(id);
+        }
+        let rows: Vec<TrackStatsRow> = query_builder.fetch_all(&mut self.connection).await?;
+
+        let cloud_stats: HashMap<String, CloudTrackStats> = CloudTrackStats::select()
+            .fetch_all(&mut self.connection)
+            .await?
+            .into_iter()
+            .map(|s| (s.cloud_track_id.clone(), s))
+            .collect();
+
+        let mut stats: HashMap<String, TrackStatsDTO> = HashMap::new();
+
+        for row in rows {
+            let shared = row.cloud_track_id.as_ref().and_then(|id| cloud_stats.get(id));
+
+            // A track may be listed in several folders, only count it once
+            if stats.get(&row.track_id).is_some() && shared.is_none() {
+                continue;
+            }
+
+            let mut rating = row.rating;
+            let mut play_count = row.play_count.unwrap_or(0);
+            let mut last_played = row.last_played;
+
+            if let Some(shared) = shared {
+                play_count += shared.play_count_excluding(device_id);
+                last_played = last_played.max(shared.last_played_excluding(device_id));
+                if shared.rating_updated_at > row.rating_updated_at {
+                    rating = shared.rating;
+                }
+            }
+
+            stats.insert(
+                row.track_id.clone(),
+                TrackStatsDTO {
+                    track_id: row.track_id,
+                    play_count,
+                    last_played,
+                    rating,
+                },
+            );
+        }
+
+        // Keep the order of the requested IDs
+        Ok(track_ids.iter().filter_map(|id| stats.remove(id)).collect())
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::libs::error::AnyResult;
+use crate::plugins::config::get_storage_dir;
+
+fn get_device_id_path() -> PathBuf {
+    get_storage_dir().join("device_id")
+}
+
+/**
+ * Get the unique ID of this install, generating it on first use.
+ *
+ * It is used to attribute synced data (eg. play counts) to the device that
+ * produced it.
+ */
+pub fn get_device_id() -> AnyResult<String> {
+    let path = get_device_id_path();
+
+    if let Ok(device_id) = fs::read_to_string(&path) {
+        let device_id = device_id.trim();
+        if !device_id.is_empty() {
+            return Ok(device_id.to_string());
+        }
+    }
+
+    let device_id = Uuid::new_v4().to_string();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, &device_id)?;
+    info!("Generated device ID: {}", device_id);
+
+    Ok(device_id)
+}
+
+/// Settings of this install, name and last sync are shared with other devices
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceSettings {
+    pub name: Option<String>,                  // Custom name, defaults to the hostname
+    pub last_sync_at: Option<DateTime<Utc>>,  // Last successful metadata sync
+    pub default_cloud_account: Option<String>, // Account used when none is given
+}
+
+fn get_device_settings_path() -> PathBuf {
+    get_storage_dir().join("device.json")
+}
+
+pub fn get_device_settings() -> AnyResult<DeviceSettings> {
+    match fs::read_to_string(get_device_settings_path()) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(_) => Ok(DeviceSettings::default()),
+    }
+}
+
+pub fn save_device_settings(settings: &DeviceSettings) -> AnyResult<()> {
+    let path = get_device_settings_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(settings)?)?;
+    Ok(())
+}
+
+/// Get the display name of this device
+pub fn get_device_name() -> AnyResult<String> {
+    let name = get_device_settings()?
+        .name
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(tauri_plugin_os::hostname);
+    Ok(name)
+}
@@ -28,6 +28,9 @@ pub enum SyncudioError {
     #[error(transparent)]
     NoSleep(#[from] nosleep::Error),
 
+    #[error(transparent)]
+    Notify(#[from] notify::Error),
+
     #[error("An error occurred while manipulating the config: {0}")]
     Config(String),
 
@@ -76,8 +79,53 @@ pub enum SyncudioError {
     #[error("Invalid track metadata: {0}")]
     InvalidTrackMetadata(String),
 
+    #[error("Invalid rating: {0}")]
+    InvalidRating(u32),
+
     #[error("Unsupported provider: {0}")]
     UnsupportedProvider(String),
+
+    #[error("Cloud file not found: {0}")]
+    CloudFileNotFound(String),
+
+    #[error("Cloud folder not found: {0}")]
+    CloudFolderNotFound(String),
+
+    #[error("Cloud account not found: {0}")]
+    CloudAccountNotFound(String),
+
+    #[error("Folder is already synced: {0}")]
+    FolderAlreadySynced(String),
+
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+
+    #[error("Credentials error: {0}")]
+    Credentials(String),
+
+    #[error("Storage quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Folder relocation failed: {0}")]
+    FolderRelocation(String),
+
+    #[error("Invalid sync mode: {0}")]
+    InvalidSyncMode(String),
+
+    #[error("File revisions are not supported by {0}")]
+    RevisionsNotSupported(String),
+
+    #[error("Revision restore failed: {0}")]
+    RevisionRestore(String),
+
+    #[error("Database migration failed: {0}")]
+    Migration(String),
+
+    #[error("Invalid sync plan: {0}")]
+    InvalidSyncPlan(String),
+
+    #[error("Invalid query cursor: {0}")]
+    InvalidQueryCursor(String),
 }
 
 /**
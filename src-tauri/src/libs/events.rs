@@ -15,6 +15,8 @@ pub enum IPCEvent<'a> {
     PlaybackStart,
     // Scan-related events
     LibraryScanProgress,
+    LibraryScanComplete,
+    LibraryChanged,
     // Menu-related events
     GoToLibrary,
     GoToPlaylists,
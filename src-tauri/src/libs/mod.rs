@@ -1,8 +1,16 @@
 pub mod constants;
+pub mod credentials;
 pub mod database;
+pub mod device;
 pub mod error;
 pub mod events;
 pub mod file_associations;
 pub mod playlist;
+pub mod relative_path;
 pub mod track;
+pub mod track_query;
+pub mod track_stats;
 pub mod utils;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/**
+ * A path relative to a synced folder, written the same way on every platform:
+ * forward slashes, no leading, trailing or repeated slashes, no "." segments,
+ * and Unicode NFC (macOS writes file names in NFD). Backslashes are separators,
+ * like on Windows, cloud providers do not allow them in names anyway.
+ *
+ * Providers like Dropbox ignore case, so paths are compared with their key.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct RelativePath(String);
+
+impl RelativePath {
+    pub fn new(path: &str) -> Self {
+        let path: String = path.nfc().collect();
+        let segments: Vec<&str> = path
+            .split(['/', '\\'])
+            .filter(|segment| !segment.is_empty() && *segment != ".")
+            .collect();
+        Self(segments.join("/"))
+    }
+
+    /// Get the path of a local file relative to a folder, if the file lives in it
+    pub fn from_local(root: &Path, path: &Path) -> Option<Self> {
+        let relative = path.strip_prefix(root).ok()?;
+        let segments = relative
+            .components()
+            .map(|component| match component {
+                Component::Normal(segment) => Some(segment.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let relative_path = Self::new(&segments.join("/"));
+        (!relative_path.is_empty()).then_some(relative_path)
+    }
+
+    /// Get the path of a cloud file relative to a cloud folder, ignoring case
+    /// like Dropbox does
+    pub fn from_cloud(folder_path: &str, path: &str) -> Option<Self> {
+        let folder = Self::new(folder_path);
+        let path = Self::new(path);
+
+        let folder_segments: Vec<&str> = folder.segments().collect();
+        let segments: Vec<&str> = path.segments().collect();
+        if segments.len() <= folder_segments.len() {
+            return None;
+        }
+
+        let in_folder = folder_segments
+            .iter()
+            .zip(&segments)
+            .all(|(a, b)| Self::new(a).key() == Self::new(b).key());
+
+        in_folder.then(|| Self(segments[folder_segments.len()..].join("/")))
+    }
+
+    /// Case-folded form of the path, equal for paths a provider considers the same
+    pub fn key(&self) -> String {
+        self.0.to_lowercase().nfc().collect()
+    }
+
+    /// Get the local path of the file, in a local folder
+    pub fn to_local_path(&self, root: &Path) -> PathBuf {
+        let mut path = root.to_path_buf();
+        path.extend(self.segments());
+        path
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/').filter(|segment| !segment.is_empty())
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.0.rsplit('/').next().unwrap_or_default()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Key of a local path, written like the key of a relative path: the key of a
+/// file in a folder is the key of the folder, a slash and the key of its
+/// relative path
+pub fn local_path_key(path: &str) -> String {
+    RelativePath::new(path).key()
+}
+
+impl fmt::Display for RelativePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for RelativePath {
+    fn from(path: &str) -> Self {
+        Self::new(path)
+    }
+}
+
+impl From<RelativePath> for String {
+    fn from(path: RelativePath) -> Self {
+        path.0
+    }
+}
+
+impl AsRef<str> for RelativePath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Paths coming from the front-end are normalized too
+impl<'de> Deserialize<'de> for RelativePath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::new(&String::deserialize(deserializer)?))
+    }
+}
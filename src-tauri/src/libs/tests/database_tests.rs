@@ -3,8 +3,9 @@ use std::path::PathBuf;
 use ormlite::sqlite::{SqliteConnectOptions, SqliteConnection};
 use ormlite::Connection;
 
-use super::database::DB;
-use super::track::Track;
+use crate::libs::database::DB;
+use crate::libs::relative_path::local_path_key;
+use crate::libs::track::Track;
 
 /** ----------------------------------------------------------------------------
  * Test data
@@ -31,6 +32,10 @@ fn sample_track_1() -> Track {
         sampling_rate: Some(44100),
         channels: Some(2),
         encoder: Some("LAME".to_string()),
+        size: 8_400_000,
+        mtime: None,
+        fingerprint: None,
+        path_key: local_path_key("/music/artist1/album1/track1.mp3"),
     }
 }
 
@@ -55,6 +60,10 @@ fn sample_track_2() -> Track {
         sampling_rate: Some(48000),
         channels: Some(2),
         encoder: None,
+        size: 5_760_000,
+        mtime: None,
+        fingerprint: None,
+        path_key: local_path_key("/music/artist2/album2/track2.mp3"),
     }
 }
 
@@ -79,6 +88,10 @@ fn sample_track_3() -> Track {
         sampling_rate: Some(44100),
         channels: Some(2),
         encoder: Some("FLAC".to_string()),
+        size: 28_000_000,
+        mtime: None,
+        fingerprint: None,
+        path_key: local_path_key("/music/artist3/album3/track3.mp3"),
     }
 }
 
@@ -90,7 +103,7 @@ async fn get_test_db() -> DB {
     let connection = SqliteConnection::connect_with(&options).await.unwrap();
 
     let mut db = DB { connection };
-    db.create_tables().await.unwrap();
+    db.create_tables(None).await.unwrap();
 
     db
 }
@@ -132,21 +145,23 @@ async fn test_tracks_db() {
     assert_eq!(
         tracks,
         vec![Track {
-            id: "2".to_string(),
-            path: "/music/artist2/album2/track2.mp3".to_string(),
             title: "Song Two Point Five".to_string(),
-            album: "Album Two".to_string(),
-            artists: vec!["Artist Two".to_string()],
-            genres: vec!["Jazz".to_string()],
-            year: None,
-            duration: 180,
-            track_no: None,
-            track_of: None,
-            disk_no: None,
-            disk_of: None,
+            ..sample_track_2()
         }]
     );
 
+    // Tracks are found by their path or a folder they are in, folders only match whole names
+    tracks = db
+        .get_tracks_by_path(&[
+            PathBuf::from("/music/artist1"),
+            PathBuf::from("/music/artist1/album1/track1.mp3"),
+            PathBuf::from("/music/artist"),
+            PathBuf::from("/music/ARTIST3"),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(tracks, vec![sample_track_1()]);
+
     // Test deletion
     db.remove_tracks(&vec!["2".to_string()]).await.unwrap();
     all_tracks = db.get_all_tracks().await.unwrap();
@@ -0,0 +1,89 @@
+-- A database from before versioned migrations, when a local folder could only
+-- be synced with a single cloud folder and queues could not download revisions
+
+CREATE TABLE tracks (
+    id TEXT PRIMARY KEY NOT NULL,
+    path TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    album TEXT NOT NULL,
+    artists JSON NOT NULL,
+    composers JSON NOT NULL,
+    album_artists JSON NOT NULL,
+    genres JSON NOT NULL,
+    track_no INTEGER,
+    track_of INTEGER,
+    disk_no INTEGER,
+    disk_of INTEGER,
+    date TEXT,
+    year INTEGER,
+    duration INTEGER NOT NULL,
+    bitrate INTEGER,
+    sampling_rate INTEGER,
+    channels INTEGER,
+    encoder TEXT,
+    size INTEGER NOT NULL
+);
+CREATE INDEX index_track_path ON tracks (path);
+
+CREATE TABLE playlists (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    tracks JSON NOT NULL DEFAULT '[]',
+    import_path TEXT UNIQUE
+);
+
+CREATE TABLE cloud_music_folders (
+    id TEXT PRIMARY KEY NOT NULL,
+    provider_type TEXT NOT NULL,
+    cloud_folder_id TEXT NOT NULL,
+    cloud_folder_path TEXT NOT NULL,
+    local_folder_path TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE cloud_tracks (
+    id TEXT PRIMARY KEY NOT NULL,
+    file_name TEXT NOT NULL,
+    updated_at DATETIME NOT NULL,
+    size INTEGER NOT NULL,
+    tags JSON
+);
+
+CREATE TABLE cloud_maps (
+    id TEXT PRIMARY KEY NOT NULL,
+    cloud_track_id TEXT NOT NULL,
+    cloud_music_folder_id TEXT NOT NULL,
+    relative_path TEXT NOT NULL,
+    cloud_file_id TEXT UNIQUE,
+    FOREIGN KEY (cloud_track_id) REFERENCES cloud_tracks(id),
+    FOREIGN KEY (cloud_music_folder_id) REFERENCES cloud_music_folders(id)
+);
+
+CREATE TABLE download_queue (
+    id TEXT PRIMARY KEY NOT NULL,
+    cloud_map_id TEXT NOT NULL,
+    provider_type TEXT NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL,
+    error_message TEXT,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO tracks VALUES (
+    'track-1', '/music/Album/Song.mp3', 'Song', 'Album', '["Artist"]', '[]', '[]', '["Rock"]',
+    1, 10, 1, 1, '2001', 2001, 180, 320, 44100, 2, 'LAME', 4000000
+);
+INSERT INTO tracks VALUES (
+    'track-2', '/music/Album/Other.mp3', 'Other', 'Album', '["Artist"]', '[]', '[]', '["Rock"]',
+    2, 10, 1, 1, '2001', 2001, 200, 320, 44100, 2, 'LAME', 4500000
+);
+INSERT INTO playlists (id, name, tracks) VALUES ('playlist-1', 'Favorites', '["track-2","track-1"]');
+
+INSERT INTO cloud_music_folders VALUES ('folder-1', 'dropbox', 'cloud-folder-1', '/Music', '/music');
+INSERT INTO cloud_tracks VALUES ('cloud-track-1', 'Song.mp3', '2024-01-01T00:00:00Z', 4000000, NULL);
+INSERT INTO cloud_tracks VALUES ('cloud-track-2', 'Remote.mp3', '2024-01-01T00:00:00Z', 3000000, NULL);
+INSERT INTO cloud_maps VALUES ('map-1', 'cloud-track-1', 'folder-1', 'Album/Song.mp3', 'file-1');
+INSERT INTO cloud_maps VALUES ('map-2', 'cloud-track-2', 'folder-1', 'Album/Remote.mp3', 'file-2');
+INSERT INTO download_queue (id, cloud_map_id, provider_type, status, created_at, updated_at)
+    VALUES ('download-1', 'map-2', 'dropbox', 'pending', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z');
@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use ormlite::Model;
+
+use crate::libs::track::{read_track_from_file, ScanFailureReason};
+use crate::plugins::cloud::tests::{
+    find_track, get_folder_tracks, put_cloud_track, tag_track_file, write_track_file, write_untagged_track_file,
+    TestDevice,
+};
+use crate::plugins::cloud::{compute_folder_sync_plan, scan_folder, CloudMusicFolder, CloudTrackMap};
+use crate::plugins::db::{import_tracks, ImportJob, ScanMode, ScanResult};
+
+/// A device with a cloud folder, whose local folder is the library
+struct TestLibrary {
+    device: TestDevice,
+    folder: CloudMusicFolder,
+    root: PathBuf,
+}
+
+impl TestLibrary {
+    async fn new() -> Self {
+        let device = TestDevice::new().await;
+        let folder = device.add_folder("Music").await;
+        let root = PathBuf::from(&folder.local_folder_path);
+        Self { device, folder, root }
+    }
+
+    /// Import the library folder, like the library view does
+    async fn import(&self, mode: ScanMode, import_untagged: bool) -> ScanResult {
+        self.import_with_job(mode, import_untagged, &ImportJob::new()).await
+    }
+
+    async fn import_with_job(&self, mode: ScanMode, import_untagged: bool, job: &ImportJob) -> ScanResult {
+        import_tracks(&self.device.db_state, &[self.root.clone()], mode, import_untagged, job, |_| {})
+            .await
+            .unwrap()
+    }
+}
+
+/** ----------------------------------------------------------------------------
+ * Library rescan
+ * -------------------------------------------------------------------------- */
+
+/// Tag a file again with another title
+fn retag_track_file(path: &PathBuf, title: &str) {
+    tag_track_file(path, title);
+    let modified = std::time::SystemTime::now() + Duration::from_secs(10);
+    std::fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+}
+
+#[tokio::test]
+async fn test_rescan_updates_changed_and_removes_vanished_tracks() {
+    let library = TestLibrary::new().await;
+    let TestLibrary { device, folder, root } = &library;
+
+    let changed = device.add_local_track(&folder, "changed.wav", "Changed").await;
+    let vanished = device.add_local_track(&folder, "vanished.wav", "Vanished").await;
+    let playlist = {
+        let mut db = device.db_state.get_lock().await;
+        db.create_playlist("Playlist".to_string(), vec![changed.id.clone(), vanished.id.clone()], None)
+            .await
+            .unwrap()
+    };
+    put_cloud_track(&device, "/Music/vanished.wav", "Vanished");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    // Nothing changed since the tracks were read
+    let result = library.import(ScanMode::Rescan, false).await;
+    assert_eq!((result.tracks_added, result.tracks_updated, result.tracks_removed), (0, 0, 0));
+
+    retag_track_file(&PathBuf::from(&changed.path), "Changed again");
+    std::fs::remove_file(&vanished.path).unwrap();
+    write_track_file(&root.join("added.wav"), "Added");
+
+    // Imports only add the new files
+    let result = library.import(ScanMode::Import, false).await;
+    assert_eq!((result.tracks_added, result.tracks_updated, result.tracks_removed), (1, 0, 0));
+
+    let result = library.import(ScanMode::Rescan, false).await;
+    assert_eq!((result.tracks_added, result.tracks_updated, result.tracks_removed), (0, 1, 1));
+
+    let mut db = device.db_state.get_lock().await;
+    let tracks = db.get_tracks(&vec![changed.id.clone(), vanished.id.clone()]).await.unwrap();
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].title, "Changed again");
+
+    // Removed tracks leave their playlists and cloud maps
+    let playlist = db.get_playlist(&playlist.id).await.unwrap().unwrap();
+    assert_eq!(playlist.tracks, vec![changed.id.clone()]);
+    let maps = CloudTrackMap::select()
+        .where_bind("relative_path = ?", "vanished.wav")
+        .fetch_all(&mut db.connection)
+        .await
+        .unwrap();
+    assert!(maps.is_empty());
+}
+
+#[tokio::test]
+async fn test_rescan_relinks_moved_tracks() {
+    let library = TestLibrary::new().await;
+    let TestLibrary { device, folder, root } = &library;
+
+    let track = device.add_local_track(&folder, "album/track.wav", "Track").await;
+    let playlist = {
+        let mut db = device.db_state.get_lock().await;
+        db.create_playlist("Playlist".to_string(), vec![track.id.clone()], None).await.unwrap()
+    };
+    put_cloud_track(&device, "/Music/album/track.wav", "Track");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    // Moved and tagged again, the audio is the same
+    let moved_path = root.join("other album/renamed.wav");
+    std::fs::create_dir_all(moved_path.parent().unwrap()).unwrap();
+    std::fs::rename(&track.path, &moved_path).unwrap();
+    retag_track_file(&moved_path, "Renamed");
+
+    let result = library.import(ScanMode::Rescan, false).await;
+    assert_eq!((result.tracks_added, result.tracks_moved, result.tracks_removed), (0, 1, 0));
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let plan = compute_folder_sync_plan(&device.db_state, &*device.provider, &folder.id, 1).await.unwrap();
+    let mut db = device.db_state.get_lock().await;
+    let moved = db.get_tracks(&vec![track.id.clone()]).await.unwrap();
+    assert_eq!(moved.len(), 1);
+    assert_eq!(moved[0].path, moved_path.to_string_lossy());
+    assert_eq!(moved[0].title, "Renamed");
+
+    let playlist = db.get_playlist(&playlist.id).await.unwrap().unwrap();
+    assert_eq!(playlist.tracks, vec![track.id.clone()]);
+
+    // The map stays with the track, and the move is reported instead of a new upload
+    assert!(find_track(&tracks, "album/track.wav").local_track_id.is_none());
+    let (local_track_id,): (Option<String>,) =
+        ormlite::query_as("SELECT local_track_id FROM cloud_maps WHERE relative_path = 'album/track.wav'")
+            .fetch_one(&mut db.connection)
+            .await
+            .unwrap();
+    assert_eq!(local_track_id, Some(track.id.clone()));
+    assert_eq!((plan.upload_count, plan.download_count, plan.conflict_count), (0, 0, 1));
+    assert_eq!(plan.items[0].relative_path, "other album/renamed.wav");
+    assert_eq!(plan.items[0].previous_relative_path.as_deref(), Some("album/track.wav"));
+}
+
+#[tokio::test]
+async fn test_cancelled_import_saves_nothing_more() {
+    let library = TestLibrary::new().await;
+    let TestLibrary { root, .. } = &library;
+    write_track_file(&root.join("track.wav"), "Track");
+
+    let job = ImportJob::new();
+    job.cancel();
+    let result = library.import_with_job(ScanMode::Import, false, &job).await;
+    assert!(result.cancelled);
+    assert_eq!((result.track_count, result.tracks_added), (0, 0));
+
+    // The next import picks the file up
+    let result = library.import(ScanMode::Import, false).await;
+    assert!(!result.cancelled);
+    assert_eq!((result.track_count, result.tracks_added), (1, 1));
+}
+
+#[tokio::test]
+async fn test_import_adds_playlists_once() {
+    let library = TestLibrary::new().await;
+    let TestLibrary { device, root, .. } = &library;
+    write_track_file(&root.join("album/first.wav"), "First");
+    write_track_file(&root.join("album/second.wav"), "Second");
+    std::fs::write(root.join("album/Favorites.m3u"), "second.wav\n../missing.wav\nfirst.wav\n").unwrap();
+
+    let result = library.import(ScanMode::Import, false).await;
+    assert_eq!((result.playlist_count, result.playlist_failures), (1, 0));
+
+    // Tracks keep the order of the playlist, missing ones are left out
+    {
+        let mut db = device.db_state.get_lock().await;
+        let playlists = db.get_all_playlists().await.unwrap();
+        assert_eq!(playlists.len(), 1);
+        assert_eq!(playlists[0].name, "Favorites");
+
+        let tracks = db.get_tracks(&playlists[0].tracks).await.unwrap();
+        let titles: Vec<_> = tracks.iter().map(|track| track.title.as_str()).collect();
+        assert_eq!(titles, vec!["Second", "First"]);
+    }
+
+    // Playlists already imported are skipped
+    let result = library.import(ScanMode::Import, false).await;
+    assert_eq!((result.playlist_count, result.playlist_failures), (0, 0));
+}
+
+#[tokio::test]
+async fn test_import_reports_failures() {
+    let library = TestLibrary::new().await;
+    let TestLibrary { device, root, .. } = &library;
+    write_track_file(&root.join("tagged.wav"), "Tagged");
+    write_untagged_track_file(&root.join("Album/03 - Some Artist - Some Title.wav"), "Untagged");
+    std::fs::write(root.join("corrupt.wav"), b"RIFF\0\0\0\0WAVEjunk").unwrap();
+    std::fs::write(root.join("unknown.weba"), b"not audio at all").unwrap();
+
+    let result = library.import(ScanMode::Import, false).await;
+    assert_eq!((result.track_count, result.track_failures), (1, 3));
+
+    let mut failures = result
+        .failures
+        .iter()
+        .map(|failure| (PathBuf::from(&failure.path).strip_prefix(&root).unwrap().to_path_buf(), failure.reason))
+        .collect::<Vec<_>>();
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        failures,
+        vec![
+            (PathBuf::from("Album/03 - Some Artist - Some Title.wav"), ScanFailureReason::NoTag),
+            (PathBuf::from("corrupt.wav"), ScanFailureReason::CorruptHeader),
+            (PathBuf::from("unknown.weba"), ScanFailureReason::UnsupportedCodec),
+        ]
+    );
+
+    // Untagged files can be imported with metadata from their name
+    let result = library.import(ScanMode::Import, true).await;
+    assert_eq!((result.tracks_added, result.track_failures), (1, 2));
+
+    let mut db = device.db_state.get_lock().await;
+    let tracks = db.get_tracks_by_paths(&[root.join("Album/03 - Some Artist - Some Title.wav")]).await.unwrap();
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].title, "Some Title");
+    assert_eq!(tracks[0].artists, vec!["Some Artist".to_string()]);
+    assert_eq!(tracks[0].album, "Album");
+    assert_eq!(tracks[0].track_no, Some(3));
+
+    // Files that cannot be read are not reported as damaged
+    let failure = read_track_from_file(&root.join("gone.wav"), false).unwrap_err();
+    assert_eq!(failure.reason, ScanFailureReason::Io);
+}
@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::Path;
+
+use ormlite::sqlite::{SqliteConnectOptions, SqliteConnection};
+use ormlite::Connection;
+use uuid::Uuid;
+
+use crate::libs::database::migrations::{get_backup_path, get_schema_version, migrate, Migration, MIGRATIONS};
+use crate::libs::database::DB;
+
+const LEGACY_SCHEMA: &str = include_str!("fixtures/legacy_schema.sql");
+
+async fn open_database(options: SqliteConnectOptions) -> DB {
+    let connection = SqliteConnection::connect_with(&options).await.unwrap();
+    DB { connection }
+}
+
+/// A database with the tables and rows of an old schema
+async fn open_fixture(schema: &str, path: Option<&Path>) -> DB {
+    let options = match path {
+        Some(path) => SqliteConnectOptions::new().filename(path).create_if_missing(true),
+        None => SqliteConnectOptions::new().in_memory(true),
+    };
+    let mut db = open_database(options).await;
+    ormlite::query(schema).execute(&mut db.connection).await.unwrap();
+    db
+}
+
+async fn count(db: &mut DB, query: &str) -> i64 {
+    let (count,): (i64,) = ormlite::query_as(query).fetch_one(&mut db.connection).await.unwrap();
+    count
+}
+
+fn latest_version() -> u32 {
+    MIGRATIONS.last().unwrap().version
+}
+
+#[test]
+fn test_migrations_are_ordered() {
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+        assert_eq!(migration.version, index as u32 + 1, "{}", migration.name);
+    }
+}
+
+#[tokio::test]
+async fn test_migrate_new_database() {
+    let mut db = open_database(SqliteConnectOptions::new().in_memory(true)).await;
+
+    db.create_tables(None).await.unwrap();
+    assert_eq!(get_schema_version(&mut db.connection).await.unwrap(), latest_version());
+
+    // Nothing is left to apply the next time
+    assert_eq!(migrate(&mut db.connection, MIGRATIONS, None).await.unwrap(), 0);
+    db.create_tables(None).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_database() {
+    let mut db = open_fixture(LEGACY_SCHEMA, None).await;
+    assert_eq!(get_schema_version(&mut db.connection).await.unwrap(), 0);
+
+    db.create_tables(None).await.unwrap();
+    assert_eq!(get_schema_version(&mut db.connection).await.unwrap(), latest_version());
+
+    // Rows are kept, with the defaults of the new columns
+    assert_eq!(count(&mut db, "SELECT COUNT(*) FROM tracks").await, 2);
+    assert_eq!(count(&mut db, "SELECT COUNT(*) FROM playlists WHERE tracks = '[\"track-2\",\"track-1\"]'").await, 1);
+    assert_eq!(count(&mut db, "SELECT COUNT(*) FROM cloud_music_folders WHERE sync_mode = 'two_way'").await, 1);
+    assert_eq!(count(&mut db, "SELECT COUNT(*) FROM download_queue WHERE revision_id IS NULL").await, 1);
+
+    // Track IDs are kept, and maps reference the track of their path
+    assert_eq!(count(&mut db, "SELECT COUNT(*) FROM cloud_maps WHERE id = 'map-1' AND local_track_id = 'track-1'").await, 1);
+    assert_eq!(count(&mut db, "SELECT COUNT(*) FROM cloud_maps WHERE id = 'map-2' AND local_track_id IS NULL").await, 1);
+
+    // A local folder can now be synced with another cloud folder
+    ormlite::query(
+        "INSERT INTO cloud_music_folders (id, provider_type, cloud_folder_id, cloud_folder_path, local_folder_path)
+         VALUES ('folder-2', 'gdrive', 'cloud-folder-2', '/Backup', '/music')",
+    )
+    .execute(&mut db.connection)
+    .await
+    .unwrap();
+
+    // Derived tables are built from the migrated rows
+    let tracks = db.get_unified_tracks().await.unwrap();
+    assert_eq!(tracks.iter().filter(|t| t.cloud_folder_id.as_deref() == Some("folder-1")).count(), 3);
+    assert_eq!(tracks.iter().filter(|t| t.cloud_folder_id.as_deref() == Some("folder-2")).count(), 2);
+    assert_eq!(db.search_tracks("song", 10).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_failed_migration_is_rolled_back_after_a_backup() {
+    let dir = std::env::temp_dir().join(format!("syncudio-test-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let database_path = dir.join("syncudio.db");
+
+    let mut db = open_fixture(LEGACY_SCHEMA, Some(&database_path)).await;
+    let migrations = [
+        MIGRATIONS[0],
+        Migration {
+            version: 2,
+            name: "broken",
+            sql: "CREATE TABLE partial (id TEXT); INSERT INTO missing_table VALUES (1);",
+        },
+    ];
+
+    assert!(migrate(&mut db.connection, &migrations, Some(&database_path)).await.is_err());
+    assert_eq!(get_schema_version(&mut db.connection).await.unwrap(), 1);
+    assert_eq!(count(&mut db, "SELECT COUNT(*) FROM sqlite_master WHERE name = 'partial'").await, 0);
+
+    // The backup is the database as it was before migrating
+    let backup_path = get_backup_path(&database_path, 0);
+    let mut backup = open_database(SqliteConnectOptions::new().filename(&backup_path)).await;
+    assert_eq!(get_schema_version(&mut backup.connection).await.unwrap(), 0);
+    assert_eq!(count(&mut backup, "SELECT COUNT(*) FROM tracks").await, 2);
+    assert_eq!(count(&mut backup, "SELECT COUNT(*) FROM pragma_table_info('download_queue') WHERE name = 'revision_id'").await, 0);
+
+    // Migrating again starts from the last applied migration
+    assert_eq!(migrate(&mut db.connection, MIGRATIONS, Some(&database_path)).await.unwrap(), latest_version() as usize - 1);
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn test_newer_database_is_refused() {
+    let mut db = open_database(SqliteConnectOptions::new().in_memory(true)).await;
+    ormlite::query(&format!("PRAGMA user_version = {}", latest_version() + 1))
+        .execute(&mut db.connection)
+        .await
+        .unwrap();
+
+    assert!(db.create_tables(None).await.is_err());
+}
@@ -0,0 +1,6 @@
+mod database_tests;
+mod library_tests;
+mod migration_tests;
+mod search_tests;
+mod track_query_tests;
+mod track_tests;
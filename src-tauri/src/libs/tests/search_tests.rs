@@ -0,0 +1,83 @@
+use ormlite::Model;
+
+use crate::libs::track::get_track_from_file;
+use crate::plugins::cloud::tests::{put_cloud_track, write_track_file, TestDevice};
+use crate::plugins::cloud::{rebuild_library_search, scan_folder, CloudTrack};
+
+/** ----------------------------------------------------------------------------
+ * Search
+ * -------------------------------------------------------------------------- */
+
+async fn search_titles(device: &TestDevice, text: &str) -> Vec<String> {
+    let mut db = device.db_state.get_lock().await;
+    db.search_tracks(text, 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|result| match (result.track, result.cloud_track) {
+            (Some(track), _) => track.title,
+            (None, Some(cloud_track)) => format!("cloud:{}", cloud_track.tags.unwrap().title),
+            (None, None) => unreachable!(),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_search_tracks() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+
+    device.add_local_track(&folder, "cafe.wav", "Café Olé").await;
+    let pattern = device.add_local_track(&folder, "pattern.wav", "Test Pattern").await;
+
+    // A cloud track without local file
+    let path = device.dir.join("remote").join("society.wav");
+    write_track_file(&path, "Cafe Society");
+    {
+        let cloud_track = CloudTrack::from_track(get_track_from_file(&path).unwrap()).unwrap();
+        let mut db = device.db_state.get_lock().await;
+        cloud_track.insert(&mut db.connection).await.unwrap();
+    }
+
+    // Prefixes match, diacritics are ignored both ways
+    assert_eq!(search_titles(&device, "caf").await.len(), 2);
+    assert_eq!(search_titles(&device, "ole").await, vec!["Café Olé"]);
+    assert_eq!(search_titles(&device, "CAFÉ soc").await, vec!["cloud:Cafe Society"]);
+
+    // Titles rank above the album and artists every track has
+    let titles = search_titles(&device, "test").await;
+    assert_eq!(titles.len(), 3);
+    assert_eq!(titles[0], "Test Pattern");
+
+    // Operators and quotes are searched as text
+    assert!(search_titles(&device, "\"caf OR -").await.len() <= 2);
+    assert!(search_titles(&device, "- *").await.is_empty());
+
+    // The index follows the tracks
+    {
+        let mut db = device.db_state.get_lock().await;
+        let mut track = pattern.clone();
+        track.title = "Renamed".to_string();
+        db.update_track(track).await.unwrap();
+    }
+    assert_eq!(search_titles(&device, "renamed").await, vec!["Renamed"]);
+    assert!(search_titles(&device, "pattern").await.is_empty());
+
+    {
+        let mut db = device.db_state.get_lock().await;
+        db.remove_tracks(&vec![pattern.id.clone()]).await.unwrap();
+    }
+    assert!(search_titles(&device, "renamed").await.is_empty());
+
+    // Cloud tracks are found through their local track once downloaded
+    put_cloud_track(&device, "/Music/cafe.wav", "Café Olé");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+    assert_eq!(search_titles(&device, "ole").await, vec!["Café Olé"]);
+
+    // A rebuild finds the same tracks
+    {
+        let mut db = device.db_state.get_lock().await;
+        rebuild_library_search(&mut db.connection).await.unwrap();
+    }
+    assert_eq!(search_titles(&device, "caf").await.len(), 2);
+}
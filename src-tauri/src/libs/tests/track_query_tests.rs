@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+
+use crate::libs::error::SyncudioError;
+use crate::libs::track_query::{TrackFilter, TrackQuery, TrackSort};
+use crate::plugins::cloud::tests::{put_cloud_track, TestDevice};
+use crate::plugins::cloud::{scan_folder, TrackLocationState};
+use crate::plugins::config::{SortBy, SortOrder};
+
+/** ----------------------------------------------------------------------------
+ * Library queries
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_query_tracks_pages_filters_and_sorts() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+
+    for (index, title) in ["delta", "Alpha", "charlie", "Bravo", "echo"].iter().enumerate() {
+        let track = device.add_local_track(&folder, &format!("{}.wav", title), title).await;
+        let mut db = device.db_state.get_lock().await;
+        ormlite::query("UPDATE tracks SET year = ? WHERE id = ?")
+            .bind(2000 + index as i64)
+            .bind(&track.id)
+            .execute(&mut db.connection)
+            .await
+            .unwrap();
+    }
+    put_cloud_track(&device, "/Music/cloud.wav", "Cloud");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let mut db = device.db_state.get_lock().await;
+    let mut query = TrackQuery {
+        sort: TrackSort {
+            sort_by: SortBy::Title,
+            sort_order: SortOrder::Asc,
+            then_by: vec![],
+        },
+        limit: Some(2),
+        with_total: true,
+        ..Default::default()
+    };
+
+    // Pages follow each other until the last one, titles ignore case
+    let mut titles = Vec::new();
+    loop {
+        let page = db.query_tracks(&query).await.unwrap();
+        assert_eq!(page.total, Some(5));
+        titles.extend(page.items.into_iter().map(|t| t.title));
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(titles, vec!["Alpha", "Bravo", "charlie", "delta", "echo"]);
+
+    // Tracks of equal keys are not skipped nor repeated from one page to another
+    for sort_order in [SortOrder::Asc, SortOrder::Dsc] {
+        query.cursor = None;
+        query.with_total = false;
+        query.sort.sort_by = SortBy::Album;
+        query.sort.sort_order = sort_order;
+
+        let mut track_ids = HashSet::new();
+        let mut unified_ids = HashSet::new();
+        loop {
+            let page = db.query_tracks(&query).await.unwrap();
+            assert_eq!(page.total, None);
+            track_ids.extend(page.items.into_iter().map(|t| t.id));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        query.cursor = None;
+        loop {
+            let page = db.query_unified_tracks(&query).await.unwrap();
+            unified_ids.extend(page.items.into_iter().map(|t| (t.local_track_id, t.cloud_map_id)));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(track_ids.len(), 5);
+        assert_eq!(unified_ids.len(), 6);
+    }
+
+    query.cursor = Some("not a cursor".to_string());
+    assert!(matches!(
+        db.query_tracks(&query).await,
+        Err(SyncudioError::InvalidQueryCursor(_))
+    ));
+    query.with_total = true;
+    query.sort.sort_by = SortBy::Title;
+
+    query.cursor = None;
+    query.limit = None;
+    query.sort.sort_order = SortOrder::Dsc;
+    query.filter = TrackFilter {
+        text: Some("a".to_string()),
+        year_from: Some(2001),
+        ..Default::default()
+    };
+    let page = db.query_tracks(&query).await.unwrap();
+    let titles: Vec<_> = page.items.iter().map(|t| t.title.as_str()).collect();
+    // Every track has "Test Album", the year filter leaves delta out
+    assert_eq!(titles, vec!["echo", "charlie", "Bravo", "Alpha"]);
+    assert_eq!(page.next_cursor, None);
+
+    // Wildcards are matched as text
+    query.filter = TrackFilter {
+        text: Some("%".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(db.query_tracks(&query).await.unwrap().total, Some(0));
+
+    // Cloud-only tracks are only in the unified tracks
+    query.filter = TrackFilter {
+        location_state: Some(TrackLocationState::CloudOnly),
+        folder_id: Some(folder.id.clone()),
+        ..Default::default()
+    };
+    assert_eq!(db.query_tracks(&query).await.unwrap().total, Some(0));
+    let page = db.query_unified_tracks(&query).await.unwrap();
+    assert_eq!(page.total, Some(1));
+    assert_eq!(page.items[0].title, "Cloud");
+
+    query.filter.location_state = Some(TrackLocationState::LocalOnly);
+    assert_eq!(db.query_tracks(&query).await.unwrap().total, Some(5));
+    assert_eq!(db.query_unified_tracks(&query).await.unwrap().total, Some(5));
+}
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+use uuid::Uuid;
+
+use crate::libs::track::get_audio_fingerprint;
+use crate::plugins::cloud::tests::{tag_track_file, write_track_file, write_untagged_track_file};
+
+/** ----------------------------------------------------------------------------
+ * Audio fingerprints
+ * -------------------------------------------------------------------------- */
+
+fn fingerprint(path: &PathBuf) -> Option<String> {
+    let tagged_file = lofty::read_from_path(path).unwrap();
+    get_audio_fingerprint(path, &tagged_file)
+}
+
+#[test]
+fn test_audio_fingerprint_ignores_tags() {
+    let dir = std::env::temp_dir().join(format!("syncudio-test-{}", Uuid::new_v4()));
+    let path = dir.join("track.wav");
+
+    write_untagged_track_file(&path, "Track");
+    let untagged = fingerprint(&path);
+    assert!(untagged.is_some());
+
+    // Tags of any size leave the audio as it is
+    tag_track_file(&path, "Track");
+    assert_eq!(fingerprint(&path), untagged);
+    tag_track_file(&path, &"Much longer title ".repeat(100));
+    assert_eq!(fingerprint(&path), untagged);
+
+    // Other audio is another track
+    let other_path = dir.join("other.wav");
+    write_track_file(&other_path, "Other");
+    assert_ne!(fingerprint(&other_path), untagged);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -1,17 +1,24 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use lofty::file::{AudioFile, TaggedFileExt};
-use lofty::tag::{Accessor, ItemKey};
+use lofty::error::{ErrorKind, LoftyError};
+use lofty::file::{AudioFile, FileType, TaggedFile, TaggedFileExt};
+use lofty::tag::{Accessor, ItemKey, Tag, TagType};
 use log::warn;
 use ormlite::model::Model;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 use uuid::Uuid;
 
+use crate::libs::relative_path::local_path_key;
+
 /**
  * Track
- * represent a single track, id and path should be unique
+ * represent a single track, id and path should be unique. The id is generated
+ * once and follows the track when its file is moved or renamed.
  */
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
 #[ormlite(table = "tracks")]
@@ -42,6 +49,48 @@ pub struct Track {
     pub channels: Option<u32>,
     pub encoder: Option<String>,
     pub size: u32,
+    #[ts(type = "number | null")]
+    pub mtime: Option<i64>, // Modification time of the file, in milliseconds since epoch
+    pub fingerprint: Option<String>, // Hash of the audio content, see get_audio_fingerprint
+    #[serde(skip)]
+    pub path_key: String, // Key of the path, see local_path_key
+}
+
+/// Why a file could not be imported as a track
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum ScanFailureReason {
+    /// The format of the file is not one we can read
+    UnsupportedCodec,
+    /// The file could not be decoded, it is likely truncated or damaged
+    CorruptHeader,
+    /// The file has no tags, and untagged files are not imported
+    NoTag,
+    PermissionDenied,
+    /// The file could not be read, eg. it was removed while being scanned
+    Io,
+    /// Another track of the library already has this path
+    DuplicatePath,
+}
+
+/// A file that could not be imported as a track
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct ScanFailure {
+    pub path: String,
+    pub reason: ScanFailureReason,
+    pub message: String, // Underlying error, for the logs and the curious
+}
+
+impl ScanFailure {
+    pub fn new(path: &Path, reason: ScanFailureReason, message: impl ToString) -> Self {
+        ScanFailure {
+            path: path.to_string_lossy().into_owned(),
+            reason,
+            message: message.to_string(),
+        }
+    }
 }
 
 /**
@@ -49,97 +98,371 @@ pub struct Track {
  * file
  */
 pub fn get_track_from_file(path: &PathBuf) -> Option<Track> {
-    match lofty::read_from_path(path) {
-        Ok(tagged_file) => {
-            let tag = tagged_file.primary_tag()?;
-            let properties = tagged_file.properties();
-            let metadata = std::fs::metadata(path).ok()?;
-
-            let mut artists: Vec<String> = tag
-                .get_strings(&ItemKey::TrackArtist)
-                .map(ToString::to_string)
-                .collect();
-
-            if artists.is_empty() {
-                artists = tag
-                    .get_strings(&ItemKey::AlbumArtist)
-                    .map(ToString::to_string)
-                    .collect();
-            }
+    read_track_from_file(path, false).ok()
+}
+
+/**
+ * Generate a Track struct from a Path, or tell why it is not a valid audio
+ * file. Files without tags are read too with import_untagged, their metadata
+ * being guessed from their name, see get_tag_from_filename.
+ */
+pub fn read_track_from_file(path: &Path, import_untagged: bool) -> Result<Track, ScanFailure> {
+    let tagged_file = lofty::read_from_path(path).map_err(|err| {
+        warn!("Failed to get ID3 tags: \"{}\". File {:?}", err, path);
+        ScanFailure::new(path, get_lofty_failure_reason(&err), err)
+    })?;
+
+    let metadata = std::fs::metadata(path).map_err(|err| {
+        warn!("Failed to get file metadata: \"{}\". File {:?}", err, path);
+        ScanFailure::new(path, get_io_failure_reason(&err), err)
+    })?;
+
+    let filename_tag;
+    let tag = match tagged_file.primary_tag() {
+        Some(tag) => tag,
+        None if import_untagged => {
+            filename_tag = get_tag_from_filename(path, tagged_file.primary_tag_type());
+            &filename_tag
+        }
+        None => {
+            warn!("No tags found. File {:?}", path);
+            return Err(ScanFailure::new(
+                path,
+                ScanFailureReason::NoTag,
+                "The file has no tags",
+            ));
+        }
+    };
+
+    let properties = tagged_file.properties();
+
+    let mut artists: Vec<String> = tag
+        .get_strings(&ItemKey::TrackArtist)
+        .map(ToString::to_string)
+        .collect();
+
+    if artists.is_empty() {
+        artists = tag
+            .get_strings(&ItemKey::AlbumArtist)
+            .map(ToString::to_string)
+            .collect();
+    }
+
+    if artists.is_empty() {
+        artists = vec!["Unknown Artist".into()];
+    }
+
+    let composers: Vec<String> = tag
+        .get_strings(&ItemKey::Composer)
+        .map(ToString::to_string)
+        .collect();
 
-            if artists.is_empty() {
-                artists = vec!["Unknown Artist".into()];
+    let album_artists: Vec<String> = tag
+        .get_strings(&ItemKey::AlbumArtist)
+        .map(ToString::to_string)
+        .collect();
+
+    Ok(Track {
+        id: Uuid::new_v4().to_string(),
+        path: path.to_string_lossy().into_owned(),
+        title: tag
+            .get_string(&ItemKey::TrackTitle)
+            .unwrap_or("Unknown")
+            .to_string(),
+        album: tag
+            .get_string(&ItemKey::AlbumTitle)
+            .unwrap_or("Unknown")
+            .to_string(),
+        artists,
+        composers,
+        album_artists,
+        genres: tag
+            .get_strings(&ItemKey::Genre)
+            .map(ToString::to_string)
+            .collect(),
+        track_no: tag.track(),
+        track_of: tag.track_total(),
+        disk_no: tag.disk(),
+        disk_of: tag.disk_total(),
+        date: tag.get_string(&ItemKey::ReleaseDate).map(String::from),
+        year: tag.year(),
+        duration: u32::try_from(properties.duration().as_secs()).unwrap_or(0),
+        bitrate: properties.audio_bitrate(),
+        sampling_rate: properties.sample_rate(),
+        channels: properties.channels().map(|c| c as u32),
+        encoder: tag.get_string(&ItemKey::EncodedBy).map(String::from),
+        size: metadata.size() as u32,
+        mtime: get_file_mtime(&metadata),
+        fingerprint: get_audio_fingerprint(path, &tagged_file),
+        path_key: local_path_key(&path.to_string_lossy()),
+    })
+}
+
+fn get_lofty_failure_reason(err: &LoftyError) -> ScanFailureReason {
+    match err.kind() {
+        ErrorKind::UnknownFormat => ScanFailureReason::UnsupportedCodec,
+        ErrorKind::Io(err) => get_io_failure_reason(err),
+        _ => ScanFailureReason::CorruptHeader,
+    }
+}
+
+fn get_io_failure_reason(err: &std::io::Error) -> ScanFailureReason {
+    match err.kind() {
+        std::io::ErrorKind::PermissionDenied => ScanFailureReason::PermissionDenied,
+        // The file ended too early, or its content made no sense
+        std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::InvalidData => ScanFailureReason::CorruptHeader,
+        _ => ScanFailureReason::Io,
+    }
+}
+
+/**
+ * Guess the tag of an untagged file from its name, like "01 - Artist - Title"
+ * or "01. Title". Its album is the name of its folder.
+ */
+fn get_tag_from_filename(path: &Path, tag_type: TagType) -> Tag {
+    let mut tag = Tag::new(tag_type);
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut parts: Vec<&str> = stem
+        .split(" - ")
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    // A leading track number, either a part of its own or a prefix of the first one
+    if let Some(first) = parts.first().copied() {
+        let rest = first.trim_start_matches(|c: char| c.is_ascii_digit());
+        let digits = &first[..first.len() - rest.len()];
+
+        if (1..=3).contains(&digits.len())
+            && (rest.is_empty() || rest.starts_with(['.', '-', '_', ' ']))
+        {
+            let rest = rest.trim_start_matches(['.', '-', '_', ' ']);
+
+            if rest.is_empty() && parts.len() > 1 {
+                parts.remove(0);
+                tag.set_track(digits.parse().unwrap_or_default());
+            } else if !rest.is_empty() {
+                parts[0] = rest;
+                tag.set_track(digits.parse().unwrap_or_default());
             }
+        }
+    }
+
+    match parts.as_slice() {
+        [] => {}
+        [title] => tag.set_title(title.to_string()),
+        [artist, title @ ..] => {
+            tag.set_artist(artist.to_string());
+            tag.set_title(title.join(" - "));
+        }
+    }
+
+    if let Some(folder) = path.parent().and_then(Path::file_name) {
+        tag.set_album(folder.to_string_lossy().into_owned());
+    }
+
+    tag
+}
+
+/// Modification time of a file in milliseconds since epoch, if the platform has it
+pub fn get_file_mtime(metadata: &std::fs::Metadata) -> Option<i64> {
+    let modified = metadata.modified().ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    i64::try_from(since_epoch.as_millis()).ok()
+}
+
+/// Whether the file of a track changed since the track was read from it.
+/// Tracks read before modification times were stored are always changed.
+pub fn is_track_file_changed(track: &Track, metadata: &std::fs::Metadata) -> bool {
+    track.mtime.is_none() || track.mtime != get_file_mtime(metadata) || track.size != metadata.size() as u32
+}
 
-            let composers: Vec<String> = tag
-                .get_strings(&ItemKey::Composer)
-                .map(ToString::to_string)
-                .collect();
-
-            let album_artists: Vec<String> = tag
-                .get_strings(&ItemKey::AlbumArtist)
-                .map(ToString::to_string)
-                .collect();
-
-            let id = get_track_id_for_path(path)?;
-
-            Some(Track {
-                id,
-                path: path.to_string_lossy().into_owned(),
-                title: tag
-                    .get_string(&ItemKey::TrackTitle)
-                    .unwrap_or("Unknown")
-                    .to_string(),
-                album: tag
-                    .get_string(&ItemKey::AlbumTitle)
-                    .unwrap_or("Unknown")
-                    .to_string(),
-                artists,
-                composers,
-                album_artists,
-                genres: tag
-                    .get_strings(&ItemKey::Genre)
-                    .map(ToString::to_string)
-                    .collect(),
-                track_no: tag.track(),
-                track_of: tag.track_total(),
-                disk_no: tag.disk(),
-                disk_of: tag.disk_total(),
-                date: tag.get_string(&ItemKey::ReleaseDate).map(String::from),
-                year: tag.year(),
-                duration: u32::try_from(properties.duration().as_secs()).unwrap_or(0),
-                bitrate: properties.audio_bitrate(),
-                sampling_rate: properties.sample_rate(),
-                channels: properties.channels().map(|c| c as u32),
-                encoder: tag.get_string(&ItemKey::EncodedBy).map(String::from),
-                size: metadata.size() as u32,
-            })
+/**
+ * Hash the audio content of a file, ie. the file without its tags. It stays
+ * the same when the file is moved, renamed or tagged again, so it tells which
+ * track a file that appeared somewhere else in the library is. The audio is
+ * streamed from the file, which can be large.
+ */
+pub fn get_audio_fingerprint(path: &Path, tagged_file: &TaggedFile) -> Option<String> {
+    let result = File::open(path).and_then(|mut file| {
+        let ranges = get_audio_ranges(&mut file, tagged_file.file_type())?;
+
+        let mut hasher = blake3::Hasher::new();
+        for range in ranges {
+            file.seek(SeekFrom::Start(range.start))?;
+            hasher.update_reader((&mut file).take(range.end - range.start))?;
         }
+        Ok(hasher.finalize())
+    });
+
+    match result {
+        Ok(hash) => Some(hash.to_hex().to_string()),
         Err(err) => {
-            warn!("Failed to get ID3 tags: \"{}\". File {:?}", err, path);
+            warn!("Failed to fingerprint audio: \"{}\". File {:?}", err, path);
             None
         }
     }
 }
 
+/// Byte ranges of the audio content of a file, leaving out its tags and the
+/// headers whose sizes change with them
+fn get_audio_ranges(file: &mut File, file_type: FileType) -> io::Result<Vec<Range<u64>>> {
+    let len = file.metadata()?.len();
+
+    let ranges = match file_type {
+        FileType::Wav => find_chunk(file, 12..len, b"data", false)?.into_iter().collect(),
+        FileType::Aiff => find_chunk(file, 12..len, b"SSND", true)?.into_iter().collect(),
+        FileType::Mp4 => find_atom(file, 0..len, b"mdat")?.into_iter().collect(),
+        FileType::Flac => {
+            let start = skip_flac_metadata(file, skip_id3v2(file, 0)?)?;
+            vec![start..skip_trailing_tags(file, start, len)?]
+        }
+        FileType::Vorbis | FileType::Opus | FileType::Speex => get_ogg_audio_ranges(file, len)?,
+        // Raw streams, tagged at their start or end
+        _ => {
+            let start = skip_id3v2(file, 0)?;
+            vec![start..skip_trailing_tags(file, start, len)?]
+        }
+    };
+
+    if ranges.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no audio content found"));
+    }
+
+    Ok(ranges)
+}
+
+fn read_at<const N: usize>(file: &mut File, position: u64) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    file.seek(SeekFrom::Start(position))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Find the content of a chunk of a RIFF (WAV) or IFF (AIFF) file
+fn find_chunk(file: &mut File, range: Range<u64>, id: &[u8; 4], big_endian: bool) -> io::Result<Option<Range<u64>>> {
+    let mut position = range.start;
+    while position + 8 <= range.end {
+        let header: [u8; 8] = read_at(file, position)?;
+        let size_bytes = [header[4], header[5], header[6], header[7]];
+        let size = match big_endian {
+            true => u32::from_be_bytes(size_bytes),
+            false => u32::from_le_bytes(size_bytes),
+        } as u64;
+
+        let start = position + 8;
+        if &header[..4] == id {
+            return Ok(Some(start..(start + size).min(range.end)));
+        }
+        // Chunks are padded to an even size
+        position = start + size + size % 2;
+    }
+
+    Ok(None)
+}
+
+/// Find the content of a top-level atom of an MP4 file
+fn find_atom(file: &mut File, range: Range<u64>, id: &[u8; 4]) -> io::Result<Option<Range<u64>>> {
+    let mut position = range.start;
+    while position + 8 <= range.end {
+        let header: [u8; 8] = read_at(file, position)?;
+        let (start, size) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64 {
+            0 => (position + 8, range.end - position - 8), // Up to the end of the file
+            1 => (position + 16, u64::from_be_bytes(read_at(file, position + 8)?).saturating_sub(16)),
+            size => (position + 8, size.saturating_sub(8)),
+        };
+
+        if &header[4..] == id {
+            return Ok(Some(start..start.saturating_add(size).min(range.end)));
+        }
+        position = start.saturating_add(size);
+    }
+
+    Ok(None)
+}
+
+/// Position after the ID3v2 tags at a position, if any
+fn skip_id3v2(file: &mut File, mut position: u64) -> io::Result<u64> {
+    loop {
+        let header: [u8; 10] = match read_at(file, position) {
+            Ok(header) => header,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(position),
+            Err(err) => return Err(err),
+        };
+        if &header[..3] != b"ID3" {
+            return Ok(position);
+        }
+
+        // Sizes are "synchsafe", with 7 bits per byte
+        let size = header[6..].iter().fold(0u64, |size, byte| (size << 7) | (byte & 0x7f) as u64);
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        position += 10 + size + footer;
+    }
+}
+
+/// End of the content of a file before its ID3v1 and APEv2 tags, if any
+fn skip_trailing_tags(file: &mut File, start: u64, mut end: u64) -> io::Result<u64> {
+    if end >= start + 128 && &read_at::<3>(file, end - 128)? == b"TAG" {
+        end -= 128;
+    }
+
+    if end >= start + 32 {
+        let footer: [u8; 32] = read_at(file, end - 32)?;
+        if &footer[..8] == b"APETAGEX" {
+            // The size includes the footer, but not the header
+            let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as u64;
+            let header = if footer[23] & 0x80 != 0 { 32 } else { 0 };
+            end = end.saturating_sub(size + header).max(start);
+        }
+    }
+
+    Ok(end)
+}
+
+/// Position of the first audio frame of a FLAC stream, after its metadata blocks
+fn skip_flac_metadata(file: &mut File, start: u64) -> io::Result<u64> {
+    if &read_at::<4>(file, start)? != b"fLaC" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a FLAC stream"));
+    }
+
+    let mut position = start + 4;
+    loop {
+        let header: [u8; 4] = read_at(file, position)?;
+        position += 4 + u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        if header[0] & 0x80 != 0 {
+            return Ok(position);
+        }
+    }
+}
+
 /**
- * Generate an ID for a track based on its location.
- *
- * We leverage UUID v3 on tracks paths to easily retrieve tracks by path.
- * This is not great and ideally we should use a DB view instead. One day.
+ * Payloads of the audio pages of an Ogg stream. Audio starts on a new page,
+ * after the header pages with the comments, whose granule position is 0, or
+ * -1 for the pages of a header that goes on in the next page.
+ * Page headers are left out, as their sequence numbers change with the
+ * number of header pages.
  */
-pub fn get_track_id_for_path(path: &PathBuf) -> Option<String> {
-    match std::fs::canonicalize(path) {
-        Ok(canonicalized_path) => Some(
-            Uuid::new_v3(
-                &Uuid::NAMESPACE_OID,
-                canonicalized_path.to_string_lossy().as_bytes(),
-            )
-            .to_string(),
-        ),
-        Err(err) => {
-            warn!(r#"ID could not be generated for path {:?}: {}"#, path, err);
-            None
+fn get_ogg_audio_ranges(file: &mut File, len: u64) -> io::Result<Vec<Range<u64>>> {
+    let mut ranges = Vec::new();
+    let mut position = 0;
+    while position + 27 <= len {
+        let header: [u8; 27] = read_at(file, position)?;
+        if &header[..4] != b"OggS" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid Ogg page"));
+        }
+
+        let mut segments = vec![0; header[26] as usize];
+        file.read_exact(&mut segments)?;
+        let start = position + 27 + segments.len() as u64;
+        let end = start + segments.iter().map(|size| *size as u64).sum::<u64>();
+
+        let granule_position = u64::from_le_bytes(header[6..14].try_into().unwrap());
+        if !ranges.is_empty() || (granule_position != 0 && granule_position != u64::MAX) {
+            ranges.push(start..end.min(len));
         }
+        position = end;
     }
+
+    Ok(ranges)
 }
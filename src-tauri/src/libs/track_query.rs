@@ -0,0 +1,387 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::libs::track::Track;
+use crate::plugins::cloud::{TrackLocationState, UnifiedTrack};
+use crate::plugins::config::{SortBy, SortOrder};
+
+/// Rows of a page when no limit is given
+pub const DEFAULT_QUERY_LIMIT: u32 = 200;
+/// Most rows a page can have
+pub const MAX_QUERY_LIMIT: u32 = 5000;
+
+/** ----------------------------------------------------------------------------
+ * TrackQuery
+ * a page of the library, filtered and sorted by the database instead of the UI
+ * -------------------------------------------------------------------------- */
+
+/// Conditions tracks must all meet, text conditions ignore ASCII case
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(default)]
+pub struct TrackFilter {
+    pub text: Option<String>, // Part of the title, album, artists or genres
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub year_from: Option<u32>,
+    pub year_to: Option<u32>,
+    pub location_state: Option<TrackLocationState>,
+    pub folder_id: Option<String>, // Cloud folder the track is synced with
+}
+
+/// Sort of a query, the first key follows the order, the next ones are always
+/// ascending like in the library view
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct TrackSort {
+    pub sort_by: SortBy,
+    pub sort_order: SortOrder,
+    #[serde(default)]
+    pub then_by: Vec<SortBy>, // Secondary keys, the ones of the library view when empty
+}
+
+impl TrackSort {
+    /// Secondary keys of the library view for a sort
+    fn default_then_by(sort_by: &SortBy) -> Vec<SortBy> {
+        match sort_by {
+            SortBy::Artist => vec![SortBy::Album],
+            SortBy::Album => vec![SortBy::Artist],
+            SortBy::Title | SortBy::Duration | SortBy::Genre => vec![SortBy::Artist, SortBy::Album],
+        }
+    }
+}
+
+impl Default for TrackSort {
+    fn default() -> Self {
+        TrackSort {
+            sort_by: SortBy::Artist,
+            sort_order: SortOrder::Asc,
+            then_by: vec![],
+        }
+    }
+}
+
+/// A query for a page of tracks. The cursor is the position of the last track
+/// of the previous page, as returned with it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(default)]
+pub struct TrackQuery {
+    pub filter: TrackFilter,
+    pub sort: TrackSort,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    pub with_total: bool, // Counting the tracks matching the filter reads all of them
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct TrackPage<T> {
+    pub items: Vec<T>,
+    pub total: Option<u32>, // Tracks matching the filter, across pages, if requested
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+impl<T: TrackQueryRow> TrackPage<T> {
+    /// Build a page from the rows fetched after the cursor, one more than the
+    /// limit tells whether there is a next page
+    pub fn new(mut items: Vec<T>, query: &TrackQuery, target: TrackQueryTarget, total: Option<u32>) -> Self {
+        let limit = query.get_limit() as usize;
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|item| query.cursor_of(item, target))
+        } else {
+            None
+        };
+
+        TrackPage {
+            items,
+            total,
+            next_cursor,
+        }
+    }
+}
+
+/// The rows a query runs on, tracks are aliased as `t`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackQueryTarget {
+    Tracks,
+    UnifiedTracks,
+}
+
+/// A value bound to a query placeholder, or of a sort key in a cursor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum QueryValue {
+    Text(String),
+    Integer(i64),
+}
+
+/// A key rows are sorted on, the last ones tell apart rows of equal keys
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortKey {
+    Sort(SortBy),
+    DiskNo,
+    TrackNo,
+    TrackId,
+    LocalTrackId,
+    CloudMapId,
+    CloudFolderId,
+}
+
+impl SortKey {
+    /// Expression the key sorts on, matching the expressions of the tracks indexes
+    fn sql(&self) -> &'static str {
+        match self {
+            SortKey::Sort(SortBy::Artist) => "json_extract(artists, '$[0]') COLLATE NOCASE",
+            SortKey::Sort(SortBy::Album) => "album COLLATE NOCASE",
+            SortKey::Sort(SortBy::Title) => "title COLLATE NOCASE",
+            SortKey::Sort(SortBy::Duration) => "duration",
+            SortKey::Sort(SortBy::Genre) => "json_extract(genres, '$[0]') COLLATE NOCASE",
+            SortKey::DiskNo => "disk_no",
+            SortKey::TrackNo => "track_no",
+            SortKey::TrackId => "t.id",
+            SortKey::LocalTrackId => "local_track_id",
+            SortKey::CloudMapId => "cloud_map_id",
+            SortKey::CloudFolderId => "cloud_folder_id",
+        }
+    }
+}
+
+/// Rows of a query, their cursor is the value of their sort keys
+pub trait TrackQueryRow {
+    /// Value of a sort key, like the database sorts on it. None for NULL.
+    fn sort_value(&self, key: &SortKey) -> Option<QueryValue>;
+}
+
+impl TrackQueryRow for Track {
+    fn sort_value(&self, key: &SortKey) -> Option<QueryValue> {
+        match key {
+            SortKey::Sort(SortBy::Artist) => self.artists.first().cloned().map(QueryValue::Text),
+            SortKey::Sort(SortBy::Album) => Some(QueryValue::Text(self.album.clone())),
+            SortKey::Sort(SortBy::Title) => Some(QueryValue::Text(self.title.clone())),
+            SortKey::Sort(SortBy::Duration) => Some(QueryValue::Integer(self.duration as i64)),
+            SortKey::Sort(SortBy::Genre) => self.genres.first().cloned().map(QueryValue::Text),
+            SortKey::DiskNo => self.disk_no.map(|disk_no| QueryValue::Integer(disk_no as i64)),
+            SortKey::TrackNo => self.track_no.map(|track_no| QueryValue::Integer(track_no as i64)),
+            SortKey::TrackId => Some(QueryValue::Text(self.id.clone())),
+            SortKey::LocalTrackId | SortKey::CloudMapId | SortKey::CloudFolderId => None,
+        }
+    }
+}
+
+impl TrackQueryRow for UnifiedTrack {
+    fn sort_value(&self, key: &SortKey) -> Option<QueryValue> {
+        let first = |values: &Option<Vec<String>>| values.as_ref().and_then(|values| values.first()).cloned();
+
+        match key {
+            SortKey::Sort(SortBy::Artist) => first(&self.artists).map(QueryValue::Text),
+            SortKey::Sort(SortBy::Album) => Some(QueryValue::Text(self.album.clone())),
+            SortKey::Sort(SortBy::Title) => Some(QueryValue::Text(self.title.clone())),
+            SortKey::Sort(SortBy::Duration) => Some(QueryValue::Integer(self.duration as i64)),
+            SortKey::Sort(SortBy::Genre) => first(&self.genres).map(QueryValue::Text),
+            SortKey::DiskNo => self.disk_no.map(|disk_no| QueryValue::Integer(disk_no as i64)),
+            SortKey::TrackNo => self.track_no.map(|track_no| QueryValue::Integer(track_no as i64)),
+            SortKey::TrackId => None,
+            SortKey::LocalTrackId => self.local_track_id.clone().map(QueryValue::Text),
+            SortKey::CloudMapId => self.cloud_map_id.clone().map(QueryValue::Text),
+            SortKey::CloudFolderId => self.cloud_folder_id.clone().map(QueryValue::Text),
+        }
+    }
+}
+
+/// Escape the wildcards of a LIKE pattern, the query uses '\' as escape character
+fn like_pattern(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+impl TrackQuery {
+    pub fn get_limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT)
+    }
+
+    /// Build the WHERE clause of the filter, with the values of its placeholders
+    pub fn where_sql(&self, target: TrackQueryTarget) -> (String, Vec<QueryValue>) {
+        let (conditions, values) = self.filter_conditions(target);
+        (Self::join_conditions(&conditions), values)
+    }
+
+    /// Build the WHERE clause of the page: the rows of the filter after the cursor
+    pub fn page_where_sql(&self, target: TrackQueryTarget) -> AnyResult<(String, Vec<QueryValue>)> {
+        let (mut conditions, mut values) = self.filter_conditions(target);
+        if let Some((condition, cursor_values)) = self.cursor_condition(target)? {
+            conditions.push(condition);
+            values.extend(cursor_values);
+        }
+        Ok((Self::join_conditions(&conditions), values))
+    }
+
+    fn join_conditions(conditions: &[String]) -> String {
+        if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        }
+    }
+
+    fn filter_conditions(&self, target: TrackQueryTarget) -> (Vec<String>, Vec<QueryValue>) {
+        let filter = &self.filter;
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values = Vec::new();
+
+        if let Some(text) = filter.text.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            conditions.push(
+                "(title LIKE ? ESCAPE '\\' OR album LIKE ? ESCAPE '\\'
+                  OR artists LIKE ? ESCAPE '\\' OR genres LIKE ? ESCAPE '\\')"
+                    .to_string(),
+            );
+            values.extend(std::iter::repeat(QueryValue::Text(like_pattern(text))).take(4));
+        }
+
+        if let Some(artist) = &filter.artist {
+            conditions.push("EXISTS (SELECT 1 FROM json_each(artists) WHERE value = ? COLLATE NOCASE)".to_string());
+            values.push(QueryValue::Text(artist.clone()));
+        }
+
+        if let Some(album) = &filter.album {
+            conditions.push("album = ? COLLATE NOCASE".to_string());
+            values.push(QueryValue::Text(album.clone()));
+        }
+
+        if let Some(genre) = &filter.genre {
+            conditions.push("EXISTS (SELECT 1 FROM json_each(genres) WHERE value = ? COLLATE NOCASE)".to_string());
+            values.push(QueryValue::Text(genre.clone()));
+        }
+
+        if let Some(year_from) = filter.year_from {
+            conditions.push("year >= ?".to_string());
+            values.push(QueryValue::Integer(year_from as i64));
+        }
+
+        if let Some(year_to) = filter.year_to {
+            conditions.push("year <= ?".to_string());
+            values.push(QueryValue::Integer(year_to as i64));
+        }
+
+        let mut sync_conditions = Vec::new();
+        if let Some(state) = &filter.location_state {
+            sync_conditions.push("location_state = ?");
+            values.push(QueryValue::Text(state.as_str().to_string()));
+        }
+        if let Some(folder_id) = &filter.folder_id {
+            sync_conditions.push("cloud_folder_id = ?");
+            values.push(QueryValue::Text(folder_id.clone()));
+        }
+
+        // Tracks are synced with any number of cloud folders, unified tracks have a row for each
+        if !sync_conditions.is_empty() {
+            conditions.push(match target {
+                TrackQueryTarget::Tracks => format!(
+                    "EXISTS (SELECT 1 FROM unified_tracks u WHERE u.local_track_id = t.id AND {})",
+                    sync_conditions.join(" AND ")
+                ),
+                TrackQueryTarget::UnifiedTracks => sync_conditions.join(" AND "),
+            });
+        }
+
+        (conditions, values)
+    }
+
+    /// Keys the rows are sorted on, with whether they are descending
+    fn sort_keys(&self, target: TrackQueryTarget) -> Vec<(SortKey, bool)> {
+        let sort = &self.sort;
+        let then_by = if sort.then_by.is_empty() {
+            TrackSort::default_then_by(&sort.sort_by)
+        } else {
+            sort.then_by.clone()
+        };
+
+        let mut keys = vec![(SortKey::Sort(sort.sort_by.clone()), sort.sort_order == SortOrder::Dsc)];
+        keys.extend(then_by.into_iter().map(|key| (SortKey::Sort(key), false)));
+        keys.push((SortKey::DiskNo, false));
+        keys.push((SortKey::TrackNo, false));
+        match target {
+            TrackQueryTarget::Tracks => keys.push((SortKey::TrackId, false)),
+            TrackQueryTarget::UnifiedTracks => keys.extend([
+                (SortKey::LocalTrackId, false),
+                (SortKey::CloudMapId, false),
+                (SortKey::CloudFolderId, false),
+            ]),
+        }
+        keys
+    }
+
+    /// Cursor of the rows after a row
+    pub fn cursor_of<T: TrackQueryRow>(&self, row: &T, target: TrackQueryTarget) -> String {
+        let values: Vec<Option<QueryValue>> =
+            self.sort_keys(target).iter().map(|(key, _)| row.sort_value(key)).collect();
+        serde_json::to_string(&values).unwrap_or_default()
+    }
+
+    /// Condition of the rows after the cursor: a key is after the one of the
+    /// cursor, and the keys before it are equal. NULLs come first in ascending
+    /// order, and last in descending order.
+    fn cursor_condition(&self, target: TrackQueryTarget) -> AnyResult<Option<(String, Vec<QueryValue>)>> {
+        let Some(cursor) = &self.cursor else {
+            return Ok(None);
+        };
+
+        let keys = self.sort_keys(target);
+        let cursor_values: Vec<Option<QueryValue>> = serde_json::from_str(cursor)
+            .ok()
+            .filter(|values: &Vec<_>| values.len() == keys.len())
+            .ok_or_else(|| SyncudioError::InvalidQueryCursor(cursor.clone()))?;
+
+        let mut alternatives = Vec::new();
+        let mut values = Vec::new();
+        let mut equal_conditions: Vec<String> = Vec::new();
+        let mut equal_values = Vec::new();
+
+        for ((key, descending), value) in keys.iter().zip(cursor_values) {
+            let sql = key.sql();
+            let after = match (descending, &value) {
+                (false, None) => Some(format!("{} IS NOT NULL", sql)),
+                (false, Some(_)) => Some(format!("{} > ?", sql)),
+                (true, None) => None,
+                (true, Some(_)) => Some(format!("({0} < ? OR {0} IS NULL)", sql)),
+            };
+
+            if let Some(after) = after {
+                let conditions: Vec<String> = equal_conditions.iter().cloned().chain([after]).collect();
+                alternatives.push(format!("({})", conditions.join(" AND ")));
+                values.extend(equal_values.iter().cloned());
+                values.extend(value.clone());
+            }
+
+            match value {
+                Some(value) => {
+                    equal_conditions.push(format!("{} = ?", sql));
+                    equal_values.push(value);
+                }
+                None => equal_conditions.push(format!("{} IS NULL", sql)),
+            }
+        }
+
+        // The cursor is the last row of all
+        if alternatives.is_empty() {
+            return Ok(Some(("0".to_string(), values)));
+        }
+
+        Ok(Some((format!("({})", alternatives.join(" OR ")), values)))
+    }
+
+    /// Build the ORDER BY clause of the query, rows of equal keys keep the
+    /// same order from one page to another
+    pub fn order_sql(&self, target: TrackQueryTarget) -> String {
+        let keys: Vec<String> = self
+            .sort_keys(target)
+            .iter()
+            .map(|(key, descending)| format!("{} {}", key.sql(), if *descending { "DESC" } else { "ASC" }))
+            .collect();
+
+        format!("ORDER BY {}", keys.join(", "))
+    }
+}
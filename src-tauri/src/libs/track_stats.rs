@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use ormlite::model::Model;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+pub const MAX_RATING: u32 = 5;
+
+/** ----------------------------------------------------------------------------
+ * TrackStats
+ * listening statistics of a track, as recorded on this device
+ * -------------------------------------------------------------------------- */
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
+#[ormlite(table = "track_stats")]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct TrackStats {
+    #[ormlite(primary_key)]
+    pub track_id: String,
+    pub play_count: u32, // plays on this device only
+    pub last_played: Option<DateTime<Utc>>,
+    pub rating: Option<u32>, // from 1 to MAX_RATING, None if unrated
+    pub rating_updated_at: Option<DateTime<Utc>>,
+}
+
+/**
+ * Statistics of a track as displayed in the UI, combining the plays of every
+ * device sharing the same cloud track
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct TrackStatsDTO {
+    pub track_id: String,
+    pub play_count: u32,
+    pub last_played: Option<DateTime<Utc>>,
+    pub rating: Option<u32>,
+}
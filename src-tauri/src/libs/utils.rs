@@ -1,10 +1,13 @@
 /**
  * Small utility to display time metrics with a log message
  */
-use log::info;
-use std::path::{Path, PathBuf};
+use log::{info, warn};
+use std::env::temp_dir;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
 use std::{ffi::OsStr, time::Instant};
 use tauri::Theme;
+use uuid::Uuid;
 use walkdir::WalkDir;
 
 use crate::plugins::config::SYSTEM_THEME;
@@ -33,6 +36,37 @@ impl TimeLogger {
     }
 }
 
+/**
+ * A file in the temporary folder, with a name unique to each instance so that
+ * concurrent operations do not share it. It is removed once dropped, whether
+ * the operation using it succeeded or not.
+ */
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    pub fn new(file_name: &str) -> Self {
+        TempFile {
+            path: temp_dir().join(format!("syncudio_{}_{}.tmp", Uuid::new_v4(), file_name)),
+        }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            if err.kind() != ErrorKind::NotFound {
+                warn!("Could not remove temporary file {:?}: {}", self.path, err);
+            }
+        }
+    }
+}
+
 /**
  * Check if a directory or a file is visible or not, by checking if it start
  * with a dot
@@ -77,6 +111,24 @@ pub fn scan_dir(path: &PathBuf, allowed_extensions: &[&str]) -> Vec<PathBuf> {
         .collect()
 }
 
+/**
+ * Resolve the "." and ".." of a path without looking at the filesystem, eg.
+ * for the relative paths of playlist entries
+ */
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
 /**
  * Give an arbitrary string (usually the theme value from the config), returns
  * a Tauri theme
@@ -89,8 +141,3 @@ pub fn get_theme_from_name(theme_name: &str) -> Option<Theme> {
         _ => None, // ? :]
     }
 }
-
-/// Normalize a relative path by removing leading slashes
-pub fn normalize_relative_path(path: &str) -> String {
-    path.trim_start_matches('/').to_string()
-}
@@ -47,6 +47,7 @@ fn main() {
         .plugin(plugins::sleepblocker::init())
         .plugin(plugins::cloud::init())
         .plugin(plugins::lastfm::init())
+        .plugin(plugins::watcher::init())
         // Tauri integrations with the Operating System
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
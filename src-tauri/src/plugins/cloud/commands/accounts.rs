@@ -0,0 +1,200 @@
+use log::{info, warn};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tauri::State;
+
+use crate::libs::device::{get_device_settings, save_device_settings, DeviceSettings};
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::providers::{CloudProviderType, Dropbox};
+use crate::plugins::cloud::{CloudProvider, CloudState};
+use crate::plugins::db::DBState;
+
+/// Create or update the record of the account a client is authorized for
+pub(crate) async fn register_account(db_state: &DBState, provider: &Dropbox) -> AnyResult<CloudAccount> {
+    let info = provider.get_account_info().await?;
+    let provider_type = CloudProviderType::Dropbox.as_str();
+
+    let mut db = db_state.get_lock().await;
+    match db
+        .get_cloud_account_by_provider_id(provider_type, &info.provider_account_id)
+        .await?
+    {
+        Some(mut account) => {
+            account.update_info(info);
+            db.update_cloud_account(account).await
+        }
+        None => {
+            let account = CloudAccount::new(provider_type.to_string(), info);
+            info!("Registering cloud account {} ({})", account.display_name, account.email);
+            db.save_cloud_account(account).await
+        }
+    }
+}
+
+/**
+ * Register the account of the default client if it has none yet, ie. it was
+ * authorized before multiple accounts were supported. Its credentials move to
+ * the account and existing folders are attached to it.
+ */
+pub(crate) async fn migrate_legacy_account(db_state: &DBState, cloud_state: &CloudState) -> AnyResult<()> {
+    let mut settings = get_device_settings()?;
+    if settings.default_cloud_account.is_some() || !cloud_state.dropbox.is_authorized().await {
+        return Ok(());
+    }
+
+    let account = register_account(db_state, &cloud_state.dropbox).await?;
+    cloud_state.dropbox.save_credentials(&account.id).await?;
+    set_default_account(db_state, &mut settings, &account).await?;
+    Dropbox::delete_legacy_credentials()?;
+
+    info!("Migrated legacy Dropbox credentials to account {}", account.id);
+    Ok(())
+}
+
+async fn set_default_account(
+    db_state: &DBState,
+    settings: &mut DeviceSettings,
+    account: &CloudAccount,
+) -> AnyResult<()> {
+    settings.default_cloud_account = Some(account.id.clone());
+    save_device_settings(settings)?;
+
+    // Folders without an account belong to the default one
+    let mut db = db_state.get_lock().await;
+    db.assign_cloud_folders_to_account(&account.provider_type, &account.id).await?;
+    Ok(())
+}
+
+/**
+ * Store the account a pending authorization was completed for, and make its
+ * client available. The first account becomes the default one.
+ */
+pub(crate) async fn complete_account_authorization(
+    db_state: &DBState,
+    cloud_state: &CloudState,
+) -> AnyResult<CloudAccount> {
+    migrate_legacy_account(db_state, cloud_state).await?;
+
+    let pending = &cloud_state.pending_dropbox;
+    let account = register_account(db_state, pending).await?;
+    pending.save_credentials(&account.id).await?;
+
+    let mut settings = get_device_settings()?;
+    match settings.default_cloud_account.as_deref() {
+        None => {
+            cloud_state.dropbox.authorize_with(pending).await;
+            set_default_account(db_state, &mut settings, &account).await?;
+        }
+        Some(default_account) if default_account == account.id => {
+            cloud_state.dropbox.authorize_with(pending).await;
+        }
+        Some(_) => {
+            let client = Dropbox::empty();
+            client.authorize_with(pending).await;
+            cloud_state
+                .accounts
+                .write()
+                .await
+                .insert(account.id.clone(), Arc::new(client));
+        }
+    }
+
+    pending.unauthorize().await;
+    Ok(account)
+}
+
+/// Forget the credentials of an account, None is the default account
+pub(crate) async fn unauthorize_account(cloud_state: &CloudState, account_id: Option<&str>) -> AnyResult<()> {
+    if cloud_state.is_default_account(account_id)? {
+        cloud_state.dropbox.unauthorize().await;
+        Dropbox::delete_legacy_credentials()?;
+
+        let mut settings = get_device_settings()?;
+        if let Some(default_account) = settings.default_cloud_account.take() {
+            Dropbox::delete_credentials(&default_account)?;
+            save_device_settings(&settings)?;
+        }
+        return Ok(());
+    }
+
+    let account_id = account_id.unwrap_or_default();
+    if let Some(client) = cloud_state.accounts.write().await.remove(account_id) {
+        client.unauthorize().await;
+    }
+    Dropbox::delete_credentials(account_id)?;
+
+    Ok(())
+}
+
+/// Get the IDs of the folders of an account, None is the default account
+pub(crate) fn get_account_folder_ids(
+    cloud_state: &CloudState,
+    folders: &[CloudMusicFolder],
+    account_id: Option<&str>,
+) -> AnyResult<HashSet<String>> {
+    let is_default = cloud_state.is_default_account(account_id)?;
+
+    let mut folder_ids = HashSet::new();
+    for folder in folders {
+        let belongs = match folder.account_id.as_deref() {
+            Some(folder_account) if account_id == Some(folder_account) => true,
+            folder_account => is_default && cloud_state.is_default_account(folder_account)?,
+        };
+        if belongs {
+            folder_ids.insert(folder.id.clone());
+        }
+    }
+
+    Ok(folder_ids)
+}
+
+#[tauri::command]
+pub async fn get_cloud_accounts(
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<Vec<CloudAccountStatus>> {
+    if let Err(err) = migrate_legacy_account(&db_state, &cloud_state).await {
+        warn!("Could not migrate legacy cloud account: {}", err);
+    }
+
+    let accounts = {
+        let mut db = db_state.get_lock().await;
+        db.get_cloud_accounts().await?
+    };
+
+    let mut statuses = Vec::new();
+    for account in accounts {
+        let authorized = match cloud_state.get_provider(Some(&account.id)).await {
+            Ok(provider) => provider.is_authorized().await,
+            Err(_) => false,
+        };
+        let is_default = cloud_state.is_default_account(Some(&account.id))?;
+        statuses.push(CloudAccountStatus {
+            account,
+            authorized,
+            is_default,
+        });
+    }
+
+    Ok(statuses)
+}
+
+/// Update the name, email and quota of an account from the provider
+#[tauri::command]
+pub async fn refresh_cloud_account(
+    account_id: String,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<CloudAccount> {
+    let exists = {
+        let mut db = db_state.get_lock().await;
+        db.get_cloud_account(&account_id).await?.is_some()
+    };
+    if !exists {
+        return Err(SyncudioError::CloudAccountNotFound(account_id));
+    }
+
+    let provider = cloud_state.get_provider(Some(&account_id)).await?;
+    register_account(&db_state, &provider).await
+}
@@ -6,7 +6,9 @@ use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
 use crate::{
+    libs::database::DB,
     libs::error::AnyResult,
+    libs::relative_path::local_path_key,
     plugins::db::DBState,
     plugins::cloud::models::*,
     libs::track::Track,
@@ -19,6 +21,7 @@ pub struct CleanupResult {
     pub removed_tracks: usize,
     pub removed_cloud_mappings: usize,
     pub removed_cloud_tracks: usize,
+    pub updated_playlists: usize,
 }
 
 /// Clean up tracks whose local files no longer exist, including their cloud mappings
@@ -26,72 +29,78 @@ pub struct CleanupResult {
 pub async fn cleanup_missing_local_tracks(
     db_state: State<'_, DBState>,
 ) -> AnyResult<CleanupResult> {
+    cleanup_missing_tracks(&db_state).await
+}
+
+pub(crate) async fn cleanup_missing_tracks(db_state: &DBState) -> AnyResult<CleanupResult> {
     info!("Starting cleanup of tracks with missing local files");
     let mut db = db_state.get_lock().await;
-    let mut result = CleanupResult {
-        removed_tracks: 0,
-        removed_cloud_mappings: 0,
-        removed_cloud_tracks: 0,
-    };
 
     // Get all local tracks
     let tracks = Track::select()
         .fetch_all(&mut db.connection)
         .await?;
 
+    // If local file doesn't exist, clean up the track and its related data
+    let missing_tracks: Vec<Track> = tracks
+        .into_iter()
+        .filter(|track| !PathBuf::from(&track.path).exists())
+        .collect();
+
+    let result = remove_local_tracks(&mut db, missing_tracks).await?;
+
+    info!("Cleanup complete. Removed {} tracks, {} cloud mappings, {} orphaned cloud tracks", 
+          result.removed_tracks, result.removed_cloud_mappings, result.removed_cloud_tracks);
+    Ok(result)
+}
+
+/// Remove tracks from the library, with their cloud mappings, and from the
+/// playlists they are in
+pub(crate) async fn remove_local_tracks(db: &mut DB, tracks: Vec<Track>) -> AnyResult<CleanupResult> {
+    let mut result = CleanupResult {
+        removed_tracks: 0,
+        removed_cloud_mappings: 0,
+        removed_cloud_tracks: 0,
+        updated_playlists: 0,
+    };
+    let track_ids: Vec<String> = tracks.iter().map(|track| track.id.clone()).collect();
+
     for track in tracks {
-        let path = PathBuf::from(&track.path);
-
-        // If local file doesn't exist, clean up the track and its related data
-        if !path.exists() {
-            info!("Local file missing, cleaning up track: {:?}", path);
-
-            // First remove any cloud mappings for this track by matching the path
-            let maps = CloudTrackMap::query(r#"
-                SELECT ctm.* 
-                FROM cloud_maps ctm
-                INNER JOIN cloud_music_folders cmf ON ctm.cloud_music_folder_id = cmf.id
-                WHERE cmf.local_folder_path || '/' || ctm.relative_path = ?"#)
-                .bind(&track.path)
-                .fetch_all(&mut db.connection)
-                .await?;
-
-            for map in maps {
-                let cloud_track_id = map.cloud_track_id.clone();
-                map.delete(&mut db.connection).await?;
-                result.removed_cloud_mappings += 1;
-
-                // Check if cloud track has any remaining mappings
-                let remaining_maps = CloudTrackMap::select()
-                    .where_("cloud_track_id = ?")
-                    .bind(&cloud_track_id)
-                    .fetch_optional(&mut db.connection)
-                    .await?;
-
-                // If no remaining mappings, remove the cloud track
-                // if remaining_maps.is_none() {
-                //     if let Ok(cloud_track) = CloudTrack::select()
-                //         .where_("id = ?")
-                //         .bind(&cloud_track_id)
-                //         .fetch_optional(&mut db.connection)
-                //         .await
-                //     {
-                //         if let Some(cloud_track) = cloud_track {
-                //             cloud_track.delete(&mut db.connection).await?;
-                //             result.removed_cloud_tracks += 1;
-                //             info!("Removed orphaned cloud track: {}", cloud_track_id);
-                //         }
-                //     }
-                // }
+        info!("Local file missing, cleaning up track: {:?}", track.path);
+
+        // First remove any cloud mappings for this track, by matching the path or
+        // the track the map was linked with before the file moved
+        let maps = CloudTrackMap::query(r#"
+            SELECT ctm.* 
+            FROM cloud_maps ctm
+            INNER JOIN cloud_music_folders cmf ON ctm.cloud_music_folder_id = cmf.id
+            WHERE cmf.local_path_key || '/' || ctm.path_key = ?
+            OR ctm.local_track_id = ?"#)
+            .bind(local_path_key(&track.path))
+            .bind(&track.id)
+            .fetch_all(&mut db.connection)
+            .await?;
+
+        for map in maps {
+            // Hashes reference the map, and pending transfers of the map cannot run anymore
+            for query in [
+                "DELETE FROM cloud_file_hashes WHERE cloud_map_id = ?",
+                "DELETE FROM upload_queue WHERE cloud_map_id = ? AND status = 'pending'",
+                "DELETE FROM download_queue WHERE cloud_map_id = ? AND status = 'pending'",
+            ] {
+                ormlite::query(query).bind(&map.id).execute(&mut db.connection).await?;
             }
 
-            // Finally remove the local track
-            track.delete(&mut db.connection).await?;
-            result.removed_tracks += 1;
+            map.delete(&mut db.connection).await?;
+            result.removed_cloud_mappings += 1;
         }
+
+        // Finally remove the local track
+        track.delete(&mut db.connection).await?;
+        result.removed_tracks += 1;
     }
 
-    info!("Cleanup complete. Removed {} tracks, {} cloud mappings, {} orphaned cloud tracks", 
-          result.removed_tracks, result.removed_cloud_mappings, result.removed_cloud_tracks);
+    result.updated_playlists = db.remove_tracks_from_playlists(&track_ids).await?;
+
     Ok(result)
 }
@@ -1,9 +1,20 @@
-use log::info;
-use tauri::State;
+use log::{info, warn};
+use tauri::{AppHandle, Runtime, State};
 
-use crate::libs::error::AnyResult;
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::libs::track_query::{TrackPage, TrackQuery};
 use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::DEFAULT_SEARCH_LIMIT;
 use crate::plugins::db::DBState;
+use crate::plugins::watcher;
+
+/// Watch the local folders of the cloud folders again, after they changed.
+/// The folder change itself succeeded, so failures are only logged.
+pub(crate) async fn refresh_watcher<R: Runtime>(app_handle: AppHandle<R>) {
+    if let Err(err) = watcher::refresh(app_handle).await {
+        warn!("Could not refresh the watched folders: {}", err);
+    }
+}
 
 // Cloud Folder Operations
 #[tauri::command]
@@ -21,31 +32,74 @@ pub async fn get_cloud_music_folders_by_provider(provider_type: String, db_state
 }
 
 #[tauri::command]
-pub async fn get_cloud_folder_by_local_path(local_path: String, db_state: State<'_, DBState>) -> AnyResult<Option<CloudMusicFolder>> {
-    info!("Getting cloud folder for local path: {}", local_path);
+pub async fn get_cloud_music_folders_by_local_path(local_path: String, db_state: State<'_, DBState>) -> AnyResult<Vec<CloudMusicFolder>> {
+    info!("Getting cloud folders for local path: {}", local_path);
     let mut db = db_state.get_lock().await;
-    db.get_cloud_folder_by_local_path(&local_path).await
+    db.get_cloud_folders_by_local_path(&local_path).await
 }
 
 #[tauri::command]
-pub async fn save_cloud_folder(folder: CloudMusicFolder, db_state: State<'_, DBState>) -> AnyResult<CloudMusicFolder> {
-    info!("Saving cloud folder: {:?}", folder);
+pub async fn set_cloud_folder_sync_mode(
+    folder_id: String,
+    sync_mode: CloudSyncMode,
+    db_state: State<'_, DBState>,
+) -> AnyResult<CloudMusicFolder> {
+    info!("Setting sync mode of cloud folder {} to {}", folder_id, sync_mode.as_str());
     let mut db = db_state.get_lock().await;
-    db.save_cloud_folder(folder).await
+    let mut folder = db
+        .get_cloud_folder(&folder_id)
+        .await?
+        .ok_or(SyncudioError::CloudFolderNotFound(folder_id))?;
+    folder.sync_mode = sync_mode.as_str().to_string();
+    db.update_cloud_folder(folder).await
 }
 
 #[tauri::command]
-pub async fn update_cloud_folder(folder: CloudMusicFolder, db_state: State<'_, DBState>) -> AnyResult<CloudMusicFolder> {
+pub async fn save_cloud_folder<R: Runtime>(
+    app_handle: AppHandle<R>,
+    folder: CloudMusicFolder,
+    db_state: State<'_, DBState>,
+) -> AnyResult<CloudMusicFolder> {
+    info!("Saving cloud folder: {:?}", folder);
+    let folder = {
+        let mut db = db_state.get_lock().await;
+        db.save_cloud_folder(folder).await?
+    };
+
+    refresh_watcher(app_handle).await;
+    Ok(folder)
+}
+
+#[tauri::command]
+pub async fn update_cloud_folder<R: Runtime>(
+    app_handle: AppHandle<R>,
+    folder: CloudMusicFolder,
+    db_state: State<'_, DBState>,
+) -> AnyResult<CloudMusicFolder> {
     info!("Updating cloud folder: {:?}", folder);
-    let mut db = db_state.get_lock().await;
-    db.update_cloud_folder(folder).await
+    let folder = {
+        let mut db = db_state.get_lock().await;
+        db.update_cloud_folder(folder).await?
+    };
+
+    refresh_watcher(app_handle).await;
+    Ok(folder)
 }
 
 #[tauri::command]
-pub async fn delete_cloud_folder(id: String, db_state: State<'_, DBState>) -> AnyResult<()> {
+pub async fn delete_cloud_folder<R: Runtime>(
+    app_handle: AppHandle<R>,
+    id: String,
+    db_state: State<'_, DBState>,
+) -> AnyResult<()> {
     info!("Deleting cloud folder with id: {}", id);
-    let mut db = db_state.get_lock().await;
-    db.delete_cloud_folder(&id).await
+    {
+        let mut db = db_state.get_lock().await;
+        db.delete_cloud_folder(&id).await?;
+    }
+
+    refresh_watcher(app_handle).await;
+    Ok(())
 }
 
 #[tauri::command]
@@ -55,6 +109,27 @@ pub async fn get_unified_tracks(db_state: State<'_, DBState>) -> AnyResult<Vec<U
     db.get_unified_tracks().await
 }
 
+/// Get a page of unified tracks, filtered and sorted by the database
+#[tauri::command]
+pub async fn query_unified_tracks(
+    query: TrackQuery,
+    db_state: State<'_, DBState>
+) -> AnyResult<TrackPage<UnifiedTrack>> {
+    let mut db = db_state.get_lock().await;
+    db.query_unified_tracks(&query).await
+}
+
+/// Search the library and the cloud-only tracks, best matches first
+#[tauri::command]
+pub async fn search_tracks(
+    query: String,
+    limit: Option<u32>,
+    db_state: State<'_, DBState>
+) -> AnyResult<Vec<TrackSearchResultDTO>> {
+    let mut db = db_state.get_lock().await;
+    db.search_tracks(&query, limit.unwrap_or(DEFAULT_SEARCH_LIMIT)).await
+}
+
 #[tauri::command]
 pub async fn get_unified_tracks_by_ids(
     ids: Vec<String>,
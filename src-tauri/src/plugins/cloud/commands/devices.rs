@@ -0,0 +1,246 @@
+use chrono::Utc;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::path::Path;
+use tauri::{AppHandle, Runtime, State};
+
+use crate::libs::device::{get_device_id, get_device_name, get_device_settings, save_device_settings};
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::{CloudProvider, CloudState};
+use crate::plugins::db::DBState;
+
+use super::database::refresh_watcher;
+use super::metadata::{download_cloud_json, upload_cloud_json};
+
+pub(crate) const DEVICES_FOLDER_NAME: &str = "devices";
+pub(crate) const DEVICES_FOLDER_PATH: &str = "/Syncudio/devices";
+
+/// Build the manifest describing this device
+async fn build_device_manifest(db_state: &DBState) -> AnyResult<DeviceManifest> {
+    let folders = {
+        let mut db = db_state.get_lock().await;
+        db.get_cloud_music_folders().await?
+    };
+    let settings = get_device_settings()?;
+
+    Ok(DeviceManifest {
+        id: get_device_id()?,
+        name: get_device_name()?,
+        os: format!("{} {}", tauri_plugin_os::type_(), tauri_plugin_os::version()),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        folders: folders.iter().map(DeviceFolder::from).collect(),
+        last_sync_at: settings.last_sync_at,
+        updated_at: Utc::now(),
+    })
+}
+
+/**
+ * Publish the manifest of this device to the cloud. When `synced` is set, the
+ * last sync time is updated first.
+ */
+pub(crate) async fn publish_manifest<P>(db_state: &DBState, provider: &P, synced: bool) -> AnyResult<DeviceManifest>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    if synced {
+        let mut settings = get_device_settings()?;
+        settings.last_sync_at = Some(Utc::now());
+        save_device_settings(&settings)?;
+    }
+
+    let manifest = build_device_manifest(db_state).await?;
+
+    provider.ensure_syncudio_folder(DEVICES_FOLDER_NAME).await?;
+    upload_cloud_json(provider, DEVICES_FOLDER_PATH, &format!("{}.json", manifest.id), &manifest).await?;
+    info!("Published manifest of device {} ({})", manifest.name, manifest.id);
+
+    Ok(manifest)
+}
+
+/// Download the manifests of every device sharing this cloud storage
+pub(crate) async fn fetch_device_manifests<P>(provider: &P) -> AnyResult<Vec<DeviceManifest>>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let files = match provider.list_files(DEVICES_FOLDER_PATH, DEVICES_FOLDER_PATH, false).await {
+        Ok(files) => files,
+        Err(e) => {
+            info!("No devices folder found: {}", e);
+            return Ok(Vec::new());
+        }
+    };
+
+    let mut manifests = Vec::new();
+    for file in files.iter().filter(|f| !f.is_folder && f.name.ends_with(".json")) {
+        match download_cloud_json::<DeviceManifest, _>(provider, &file.id, &file.name).await {
+            Ok(Some(manifest)) => manifests.push(manifest),
+            Ok(None) => {}
+            Err(e) => warn!("Ignoring invalid device manifest {}: {}", file.name, e),
+        }
+    }
+
+    Ok(manifests)
+}
+
+/// Get the manifest of this device, as it would be published
+#[tauri::command]
+pub async fn get_current_device(db_state: State<'_, DBState>) -> AnyResult<DeviceManifest> {
+    info!("Getting current device");
+    build_device_manifest(&db_state).await
+}
+
+/// Rename this device, the new name is published on the next sync
+#[tauri::command]
+pub async fn set_device_name(name: String, db_state: State<'_, DBState>) -> AnyResult<DeviceManifest> {
+    info!("Renaming device to: {}", name);
+    let mut settings = get_device_settings()?;
+    settings.name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+    save_device_settings(&settings)?;
+
+    build_device_manifest(&db_state).await
+}
+
+#[tauri::command]
+pub async fn publish_device_manifest(
+    account_id: Option<String>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<DeviceManifest> {
+    info!("Publishing device manifest");
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+    publish_manifest(&db_state, &*provider, false).await
+}
+
+/// Get the manifests of every device, including this one
+#[tauri::command]
+pub async fn get_cloud_devices(
+    account_id: Option<String>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<Vec<DeviceManifest>> {
+    info!("Getting cloud devices");
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+    fetch_device_manifests(&*provider).await
+}
+
+/// Get the cloud folders synced by other devices but not by this one
+pub(crate) async fn list_adoptable_folders<P>(db_state: &DBState, provider: &P) -> AnyResult<Vec<AdoptableCloudFolder>>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let device_id = get_device_id()?;
+    let manifests = fetch_device_manifests(provider).await?;
+
+    let local_folders = {
+        let mut db = db_state.get_lock().await;
+        db.get_cloud_music_folders_by_provider(provider.provider_type().as_str()).await?
+    };
+
+    let mut adoptable: HashMap<String, AdoptableCloudFolder> = HashMap::new();
+
+    for manifest in manifests.iter().filter(|m| m.id != device_id) {
+        for folder in &manifest.folders {
+            if folder.provider_type != provider.provider_type().as_str()
+                || local_folders.iter().any(|f| f.cloud_folder_id == folder.cloud_folder_id)
+            {
+                continue;
+            }
+
+            adoptable
+                .entry(folder.cloud_folder_id.clone())
+                .or_insert_with(|| AdoptableCloudFolder {
+                    provider_type: folder.provider_type.clone(),
+                    cloud_folder_id: folder.cloud_folder_id.clone(),
+                    cloud_folder_path: folder.cloud_folder_path.clone(),
+                    device_names: Vec::new(),
+                })
+                .device_names
+                .push(manifest.name.clone());
+        }
+    }
+
+    let mut adoptable: Vec<AdoptableCloudFolder> = adoptable.into_values().collect();
+    adoptable.sort_by(|a, b| a.cloud_folder_path.cmp(&b.cloud_folder_path));
+
+    Ok(adoptable)
+}
+
+/**
+ * Map a cloud folder synced by another device to a local folder of this
+ * device. The folder still needs to be scanned to list its tracks.
+ */
+pub(crate) async fn adopt_folder<P>(
+    db_state: &DBState,
+    provider: &P,
+    cloud_folder_id: &str,
+    local_folder_path: &str,
+    account_id: Option<String>,
+) -> AnyResult<CloudMusicFolder>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let device_id = get_device_id()?;
+
+    let folder = fetch_device_manifests(provider)
+        .await?
+        .into_iter()
+        .filter(|m| m.id != device_id)
+        .flat_map(|m| m.folders)
+        .find(|f| f.cloud_folder_id == cloud_folder_id && f.provider_type == provider.provider_type().as_str())
+        .ok_or(SyncudioError::CloudFolderNotFound(cloud_folder_id.to_string()))?;
+
+    std::fs::create_dir_all(Path::new(local_folder_path))?;
+
+    let mut db = db_state.get_lock().await;
+
+    if db
+        .get_cloud_music_folders_by_provider(&folder.provider_type)
+        .await?
+        .iter()
+        .any(|f| f.cloud_folder_id == cloud_folder_id)
+    {
+        return Err(SyncudioError::FolderAlreadySynced(folder.cloud_folder_path));
+    }
+
+    let cloud_folder = CloudMusicFolder {
+        account_id,
+        ..CloudMusicFolder::new(
+            folder.provider_type,
+            folder.cloud_folder_id,
+            folder.cloud_folder_path,
+            local_folder_path.to_string(),
+        )
+    };
+
+    db.save_cloud_folder(cloud_folder).await
+}
+
+/// Get the cloud folders synced by other devices but not by this one
+#[tauri::command]
+pub async fn get_adoptable_cloud_folders(
+    account_id: Option<String>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<Vec<AdoptableCloudFolder>> {
+    info!("Getting adoptable cloud folders");
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+    list_adoptable_folders(&db_state, &*provider).await
+}
+
+/// Adopt a cloud folder synced by another device
+#[tauri::command]
+pub async fn adopt_cloud_folder<R: Runtime>(
+    app_handle: AppHandle<R>,
+    cloud_folder_id: String,
+    local_folder_path: String,
+    account_id: Option<String>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<CloudMusicFolder> {
+    info!("Adopting cloud folder {} at {}", cloud_folder_id, local_folder_path);
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+    let folder = adopt_folder(&db_state, &*provider, &cloud_folder_id, &local_folder_path, account_id).await?;
+
+    refresh_watcher(app_handle).await;
+    Ok(folder)
+}
@@ -0,0 +1,256 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use log::info;
+use ormlite::Model;
+use std::path::Path;
+use tauri::State;
+
+use crate::libs::constants::SUPPORTED_TRACKS_EXTENSIONS;
+use crate::libs::database::core::DB;
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::libs::utils::TempFile;
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::{
+    decrypted_size, CloudFile, CloudProvider, CloudState, EncryptionHeader, FolderKey, ENCRYPTION_HEADER_FILE,
+};
+use crate::plugins::db::DBState;
+
+use super::metadata::{download_cloud_json, upload_cloud_json, METADATA_FOLDER_PATH};
+
+const ENCRYPTION_HEADER_VERSION: u32 = 1;
+
+/**
+ * Get the key of a cloud folder. Returns None if the folder is not encrypted,
+ * and fails if it is but this device was never unlocked.
+ */
+pub(crate) async fn get_folder_key(db: &mut DB, folder: &CloudMusicFolder) -> AnyResult<Option<FolderKey>> {
+    if db.get_cloud_folder_encryption(&folder.id).await?.is_none() {
+        return Ok(None);
+    }
+
+    match FolderKey::load(&folder.cloud_folder_id)? {
+        Some(key) => Ok(Some(key)),
+        None => Err(SyncudioError::Encryption(format!(
+            "Missing key for encrypted folder {}",
+            folder.cloud_folder_path
+        ))),
+    }
+}
+
+/// Get the encrypted folders of a provider, with their key if this device has it
+pub(crate) async fn get_encrypted_folders(
+    db: &mut DB,
+    provider_type: &str,
+) -> AnyResult<Vec<(CloudMusicFolder, Option<FolderKey>)>> {
+    let mut encrypted_folders = Vec::new();
+
+    for folder in db.get_cloud_music_folders_by_provider(provider_type).await? {
+        if db.get_cloud_folder_encryption(&folder.id).await?.is_some() {
+            let key = FolderKey::load(&folder.cloud_folder_id)?;
+            encrypted_folders.push((folder, key));
+        }
+    }
+
+    Ok(encrypted_folders)
+}
+
+/// Decrypt the names of the files listed in an encrypted folder. Files that
+/// cannot be decrypted (eg. the encryption header) are ignored.
+pub(crate) fn decrypt_cloud_files(files: Vec<CloudFile>, key: Option<&FolderKey>) -> Vec<CloudFile> {
+    let Some(key) = key else {
+        return files;
+    };
+
+    files
+        .into_iter()
+        .filter(|f| !f.is_folder)
+        .filter_map(|mut f| {
+            let relative_path = key.decrypt_path(&f.relative_path).ok()?;
+            f.name = relative_path.rsplit('/').next().unwrap_or_default().to_string();
+            f.relative_path = relative_path;
+            f.size = decrypted_size(f.size as u64) as u32;
+            Some(f)
+        })
+        .collect()
+}
+
+/// Name of the encrypted track metadata of a folder. It is derived from the
+/// folder ID so it does not reveal the folder name.
+fn encrypted_metadata_file_name(cloud_folder_id: &str) -> String {
+    let hash = blake3::hash(cloud_folder_id.as_bytes()).to_hex();
+    format!("tracks-{}.enc", &hash[..16])
+}
+
+/// Download and decrypt the track metadata of an encrypted folder
+pub(crate) async fn download_encrypted_metadata<P>(
+    provider: &P,
+    cloud_folder_id: &str,
+    key: &FolderKey,
+) -> AnyResult<Option<CloudMetadataCollection>>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let file_name = encrypted_metadata_file_name(cloud_folder_id);
+    let remote_path = format!("{}/{}", METADATA_FOLDER_PATH, file_name);
+    let encrypted_file = TempFile::new(&file_name);
+    let decrypted_file = TempFile::new(&format!("{}.json", file_name));
+
+    match provider.download_file(&remote_path, encrypted_file.path()).await {
+        Ok(_) => {}
+        Err(SyncudioError::CloudFileNotFound(_)) => {
+            info!("No existing {} found", file_name);
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    }
+
+    key.decrypt_file(encrypted_file.path(), decrypted_file.path())?;
+    let content = std::fs::read_to_string(decrypted_file.path())?;
+
+    Ok(Some(serde_json::from_str(&content)?))
+}
+
+/// Encrypt and upload the track metadata of an encrypted folder
+pub(crate) async fn upload_encrypted_metadata<P>(
+    provider: &P,
+    cloud_folder_id: &str,
+    key: &FolderKey,
+    metadata: &CloudMetadataCollection,
+) -> AnyResult<()>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    provider.ensure_metadata_folder().await?;
+
+    let file_name = encrypted_metadata_file_name(cloud_folder_id);
+    let plaintext_file = TempFile::new(&format!("{}.json", file_name));
+    let encrypted_file = TempFile::new(&file_name);
+
+    std::fs::write(plaintext_file.path(), serde_json::to_string(metadata)?)?;
+    key.encrypt_file(plaintext_file.path(), encrypted_file.path())?;
+    drop(plaintext_file); // The plaintext does not wait for the upload
+
+    provider
+        .upload_file(encrypted_file.path(), &file_name, Some(METADATA_FOLDER_PATH))
+        .await?;
+
+    Ok(())
+}
+
+fn verify_passphrase(passphrase: &str, salt: &str, key_check: &str) -> AnyResult<FolderKey> {
+    let salt = URL_SAFE_NO_PAD
+        .decode(salt)
+        .map_err(|e| SyncudioError::Encryption(e.to_string()))?;
+    let key = FolderKey::derive(passphrase, &salt)?;
+
+    if key.key_check() != key_check {
+        return Err(SyncudioError::Encryption("Invalid passphrase".to_string()));
+    }
+
+    Ok(key)
+}
+
+#[tauri::command]
+pub async fn get_cloud_folder_encryption_status(
+    folder_id: String,
+    db_state: State<'_, DBState>,
+) -> AnyResult<CloudFolderEncryptionStatus> {
+    info!("Getting encryption status of cloud folder {}", folder_id);
+    let mut db = db_state.get_lock().await;
+
+    let folder = CloudMusicFolder::select()
+        .where_("id = ?")
+        .bind(&folder_id)
+        .fetch_one(&mut db.connection)
+        .await?;
+    let encrypted = db.get_cloud_folder_encryption(&folder.id).await?.is_some();
+
+    Ok(CloudFolderEncryptionStatus {
+        encrypted,
+        unlocked: encrypted && FolderKey::load(&folder.cloud_folder_id)?.is_some(),
+    })
+}
+
+/**
+ * Enable encryption for a cloud folder, or unlock it on this device if it was
+ * encrypted by another one. Encryption can only be enabled on folders that do
+ * not contain plaintext tracks yet.
+ */
+#[tauri::command]
+pub async fn enable_cloud_folder_encryption(
+    folder_id: String,
+    passphrase: String,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<CloudFolderEncryptionStatus> {
+    info!("Enabling encryption of cloud folder {}", folder_id);
+
+    let (folder, encryption) = {
+        let mut db = db_state.get_lock().await;
+        let folder = CloudMusicFolder::select()
+            .where_("id = ?")
+            .bind(&folder_id)
+            .fetch_one(&mut db.connection)
+            .await?;
+        let encryption = db.get_cloud_folder_encryption(&folder.id).await?;
+        (folder, encryption)
+    };
+    let provider = cloud_state.get_provider(folder.account_id.as_deref()).await?;
+
+    // Already encrypted on this device, only the key may be missing
+    if let Some(encryption) = encryption {
+        let key = verify_passphrase(&passphrase, &encryption.salt, &encryption.key_check)?;
+        key.save(&folder.cloud_folder_id)?;
+        return Ok(CloudFolderEncryptionStatus { encrypted: true, unlocked: true });
+    }
+
+    let header_path = format!("{}/{}", folder.cloud_folder_path, ENCRYPTION_HEADER_FILE);
+    let header = match download_cloud_json::<EncryptionHeader, _>(&*provider, &header_path, ENCRYPTION_HEADER_FILE).await? {
+        // Encrypted by another device
+        Some(header) => {
+            let key = verify_passphrase(&passphrase, &header.salt, &header.key_check)?;
+            key.save(&folder.cloud_folder_id)?;
+            header
+        }
+        None => {
+            let has_plaintext_tracks = provider
+                .list_files(&folder.cloud_folder_id, &folder.cloud_folder_path, true)
+                .await?
+                .iter()
+                .filter(|f| !f.is_folder)
+                .filter_map(|f| Path::new(&f.name).extension().and_then(|ext| ext.to_str()))
+                .any(|ext| SUPPORTED_TRACKS_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+
+            if has_plaintext_tracks {
+                return Err(SyncudioError::Encryption(format!(
+                    "{} already contains unencrypted tracks",
+                    folder.cloud_folder_path
+                )));
+            }
+
+            let salt = FolderKey::generate_salt();
+            let key = FolderKey::derive(&passphrase, &salt)?;
+            let header = EncryptionHeader {
+                version: ENCRYPTION_HEADER_VERSION,
+                salt: URL_SAFE_NO_PAD.encode(&salt),
+                key_check: key.key_check(),
+            };
+
+            upload_cloud_json(&*provider, &folder.cloud_folder_path, ENCRYPTION_HEADER_FILE, &header).await?;
+            key.save(&folder.cloud_folder_id)?;
+            header
+        }
+    };
+
+    let mut db = db_state.get_lock().await;
+    db.save_cloud_folder_encryption(CloudFolderEncryption {
+        cloud_music_folder_id: folder.id,
+        salt: header.salt,
+        key_check: header.key_check,
+        created_at: Utc::now(),
+    })
+    .await?;
+
+    Ok(CloudFolderEncryptionStatus { encrypted: true, unlocked: true })
+}
@@ -0,0 +1,107 @@
+use chrono::{Duration, Utc};
+use log::info;
+use ormlite::Model;
+use tauri::State;
+
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::plugins::cloud::models::*;
+use crate::plugins::db::DBState;
+
+/// Get the sync history, most recent first
+#[tauri::command]
+pub async fn get_sync_history(
+    filter: Option<SyncHistoryFilter>,
+    db_state: State<'_, DBState>,
+) -> AnyResult<Vec<SyncHistoryEntry>> {
+    let mut db = db_state.get_lock().await;
+    db.get_sync_history(filter.unwrap_or_default()).await
+}
+
+/**
+ * Delete sync history entries older than a number of days, defaults to the
+ * retention period. Returns the number of deleted entries.
+ */
+#[tauri::command]
+pub async fn purge_sync_history(
+    older_than_days: Option<u32>,
+    db_state: State<'_, DBState>,
+) -> AnyResult<u64> {
+    let days = older_than_days
+        .map(i64::from)
+        .unwrap_or(SYNC_HISTORY_RETENTION_DAYS);
+    let mut db = db_state.get_lock().await;
+
+    let purged = db.purge_sync_history(Utc::now() - Duration::days(days)).await?;
+    info!("Purged {} sync history entries older than {} days", purged, days);
+
+    Ok(purged)
+}
+
+/// Resolve a sync conflict by queueing the transfer of the kept side
+#[tauri::command]
+pub async fn resolve_sync_conflict(
+    cloud_map_id: String,
+    resolution: SyncConflictResolution,
+    db_state: State<'_, DBState>,
+) -> AnyResult<()> {
+    info!("Resolving sync conflict of {} with {:?}", cloud_map_id, resolution);
+    let mut db = db_state.get_lock().await;
+
+    let track_map = CloudTrackMap::select()
+        .where_("id = ?")
+        .bind(&cloud_map_id)
+        .fetch_one(&mut db.connection)
+        .await?;
+
+    let folder = CloudMusicFolder::select()
+        .where_("id = ?")
+        .bind(&track_map.cloud_music_folder_id)
+        .fetch_one(&mut db.connection)
+        .await?;
+
+    // Drop pending transfers, the resolution replaces them
+    ormlite::query("DELETE FROM upload_queue WHERE cloud_map_id = ? AND status = 'pending'")
+        .bind(&track_map.id)
+        .execute(&mut db.connection)
+        .await?;
+    ormlite::query("DELETE FROM download_queue WHERE cloud_map_id = ? AND status = 'pending'")
+        .bind(&track_map.id)
+        .execute(&mut db.connection)
+        .await?;
+
+    let details = match resolution {
+        SyncConflictResolution::KeepLocal => {
+            let local_path = folder.local_path_of(&track_map.relative_path);
+            if !local_path.exists() {
+                return Err(SyncudioError::FileNotFound(local_path.to_string_lossy().to_string()));
+            }
+
+            UploadQueueItem::new(track_map.id.clone(), folder.provider_type.clone(), 0)
+                .insert(&mut db.connection)
+                .await?;
+            "kept local"
+        }
+        SyncConflictResolution::KeepCloud => {
+            if track_map.cloud_file_id.is_none() {
+                return Err(SyncudioError::FileNotFound(format!(
+                    "{}/{}",
+                    folder.cloud_folder_path, track_map.relative_path
+                )));
+            }
+
+            DownloadQueueItem::new(track_map.id.clone(), folder.provider_type.clone(), 0)
+                .insert(&mut db.connection)
+                .await?;
+            "kept cloud"
+        }
+    };
+
+    db.record_sync_history(SyncHistoryEntry {
+        details: Some(details.to_string()),
+        ..SyncHistoryEntry::new(SyncHistoryOperation::ConflictResolution, folder.provider_type.clone())
+            .with_track(&track_map)
+    })
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,265 @@
+use chrono::Utc;
+use log::info;
+use ormlite::Model;
+use std::collections::HashMap;
+use std::path::Path;
+use tauri::State;
+
+use crate::libs::database::core::DB;
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::{hash_file, CloudFile, CloudProvider, CloudState};
+use crate::plugins::db::DBState;
+
+use super::encryption::{decrypt_cloud_files, get_folder_key};
+use super::sync_plan::get_modified_at;
+
+type Issue = (IntegrityIssueKind, IntegrityIssueLocation, String);
+
+/**
+ * Find what is wrong with the copies of a mapped track, if anything. A copy
+ * smaller than the other one is reported as truncated, other differences as
+ * mismatched, blaming the oldest copy. Files of encrypted folders are compared
+ * with the plaintext hash recorded at their last transfer.
+ */
+fn check_track<P>(
+    provider: &P,
+    map: &CloudTrackMap,
+    local_path: &Path,
+    cloud_file: Option<&CloudFile>,
+    recorded_hash: Option<&str>,
+    encrypted: bool,
+) -> AnyResult<Option<Issue>>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    use IntegrityIssueKind::*;
+    use IntegrityIssueLocation::*;
+
+    if !local_path.is_file() {
+        return Ok(Some((Missing, Local, "Local file not found".to_string())));
+    }
+
+    if let Err(err) = lofty::read_from_path(local_path) {
+        return Ok(Some((Corrupted, Local, format!("Local file cannot be decoded: {}", err))));
+    }
+
+    let cloud_file = match (&map.cloud_file_id, cloud_file) {
+        (Some(_), Some(cloud_file)) => cloud_file,
+        (Some(cloud_file_id), None) => {
+            return Ok(Some((Missing, Cloud, format!("Cloud file {} no longer resolves", cloud_file_id))));
+        }
+        (None, _) => return Ok(None), // Not uploaded yet
+    };
+
+    let local_size = std::fs::metadata(local_path)?.len();
+    let cloud_size = cloud_file.size as u64;
+    if local_size != cloud_size {
+        let location = if cloud_size < local_size { Cloud } else { Local };
+        return Ok(Some((
+            Truncated,
+            location,
+            format!("Local file has {} bytes, cloud file has {}", local_size, cloud_size),
+        )));
+    }
+
+    let same_content = if encrypted {
+        match recorded_hash {
+            Some(hash) => Some(hash_file(local_path)? == hash),
+            None => None,
+        }
+    } else {
+        match &cloud_file.hash {
+            Some(hash) => Some(provider.hash_local_file(local_path)?.value() == hash.value()),
+            None => None,
+        }
+    };
+
+    if same_content == Some(false) {
+        let local_is_newer = get_modified_at(local_path).is_some_and(|m| m > cloud_file.modified_at);
+        let location = if local_is_newer { Cloud } else { Local };
+        return Ok(Some((Mismatched, location, "Local and cloud content differ".to_string())));
+    }
+
+    Ok(None)
+}
+
+/// Queue a repair of a track, unless it is already being transferred
+async fn queue_repair(db: &mut DB, folder: &CloudMusicFolder, map_id: &str, repair: &SyncOperationType) -> AnyResult<bool> {
+    let active: Option<(String,)> = ormlite::query_as(
+        "SELECT id FROM upload_queue WHERE cloud_map_id = ? AND status IN ('pending', 'in_progress')
+         UNION
+         SELECT id FROM download_queue WHERE cloud_map_id = ? AND status IN ('pending', 'in_progress')",
+    )
+    .bind(map_id)
+    .bind(map_id)
+    .fetch_optional(&mut db.connection)
+    .await?;
+
+    if active.is_some() {
+        return Ok(false);
+    }
+
+    match repair {
+        SyncOperationType::Upload => {
+            UploadQueueItem::new(map_id.to_string(), folder.provider_type.clone(), 0)
+                .insert(&mut db.connection)
+                .await?;
+        }
+        SyncOperationType::Download => {
+            DownloadQueueItem::new(map_id.to_string(), folder.provider_type.clone(), 0)
+                .insert(&mut db.connection)
+                .await?;
+        }
+    }
+
+    Ok(true)
+}
+
+/**
+ * Check every mapped track of a cloud folder: the local file exists and
+ * decodes, the cloud file still resolves, with the expected size and hash.
+ * Repairs copy the healthy copy over the broken one, within what the sync
+ * mode of the folder allows, and are only queued when asked.
+ */
+pub(crate) async fn verify_folder<P>(
+    db_state: &DBState,
+    provider: &P,
+    folder_id: &str,
+    enqueue_repairs: bool,
+) -> AnyResult<FolderIntegrityReportDTO>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let (folder, maps, key, recorded_hashes) = {
+        let mut db = db_state.get_lock().await;
+        let folder = db
+            .get_cloud_folder(folder_id)
+            .await?
+            .ok_or(SyncudioError::CloudFolderNotFound(folder_id.to_string()))?;
+        let maps = CloudTrackMap::select()
+            .where_bind("cloud_music_folder_id = ?", folder_id)
+            .fetch_all(&mut db.connection)
+            .await?;
+        let key = get_folder_key(&mut db, &folder).await?;
+
+        let mut recorded_hashes = HashMap::new();
+        if key.is_some() {
+            for map in &maps {
+                if let Some(hash) = db.get_cloud_file_hash(&map.id).await? {
+                    recorded_hashes.insert(map.id.clone(), hash);
+                }
+            }
+        }
+
+        (folder, maps, key, recorded_hashes)
+    };
+
+    info!("Verifying {} tracks of cloud folder {}", maps.len(), folder.id);
+
+    // List cloud files - No DB lock needed
+    let cloud_files = provider
+        .list_files(&folder.cloud_folder_id, &folder.cloud_folder_path, true)
+        .await?;
+    let cloud_files: HashMap<String, CloudFile> = decrypt_cloud_files(cloud_files, key.as_ref())
+        .into_iter()
+        .filter(|f| !f.is_folder)
+        .map(|f| (f.id.clone(), f))
+        .collect();
+
+    let sync_mode = folder.get_sync_mode();
+    let mut issues = Vec::new();
+
+    for map in &maps {
+        let local_path = folder.local_path_of(&map.relative_path);
+        let cloud_file = map.cloud_file_id.as_ref().and_then(|id| cloud_files.get(id));
+
+        let Some((kind, location, detail)) = check_track(
+            provider,
+            map,
+            &local_path,
+            cloud_file,
+            recorded_hashes.get(&map.id).map(String::as_str),
+            key.is_some(),
+        )?
+        else {
+            continue;
+        };
+
+        // Cloud issues are only reported for healthy local files
+        let repair = match location {
+            IntegrityIssueLocation::Local if cloud_file.is_some() && sync_mode.can_download() => {
+                Some(SyncOperationType::Download)
+            }
+            IntegrityIssueLocation::Cloud if sync_mode.can_upload() => Some(SyncOperationType::Upload),
+            _ => None,
+        };
+
+        issues.push(IntegrityIssueDTO {
+            cloud_track_id: map.cloud_track_id.clone(),
+            cloud_map_id: map.id.clone(),
+            relative_path: map.relative_path.clone(),
+            kind,
+            location,
+            detail,
+            repair,
+            repair_queued: false,
+        });
+    }
+
+    if enqueue_repairs {
+        let mut db = db_state.get_lock().await;
+        for issue in issues.iter_mut() {
+            if let Some(repair) = &issue.repair {
+                issue.repair_queued = queue_repair(&mut db, &folder, &issue.cloud_map_id, repair).await?;
+            }
+        }
+    }
+
+    let count = |kind: IntegrityIssueKind| issues.iter().filter(|i| i.kind == kind).count();
+
+    let report = FolderIntegrityReportDTO {
+        folder_id: folder.id.clone(),
+        tracks_checked: maps.len(),
+        tracks_verified: maps.len() - issues.len(),
+        corrupted_count: count(IntegrityIssueKind::Corrupted),
+        truncated_count: count(IntegrityIssueKind::Truncated),
+        missing_count: count(IntegrityIssueKind::Missing),
+        mismatched_count: count(IntegrityIssueKind::Mismatched),
+        repairs_queued: issues.iter().filter(|i| i.repair_queued).count(),
+        issues,
+        checked_at: Utc::now(),
+    };
+
+    info!(
+        "Verified cloud folder {}: {} issues, {} repairs queued",
+        folder.id,
+        report.issues.len(),
+        report.repairs_queued
+    );
+
+    Ok(report)
+}
+
+/// Verify the integrity of the tracks of a cloud folder, optionally queueing repairs
+#[tauri::command]
+pub async fn verify_cloud_folder(
+    folder_id: String,
+    enqueue_repairs: Option<bool>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<FolderIntegrityReportDTO> {
+    let folder = {
+        let mut db = db_state.get_lock().await;
+        db.get_cloud_folder(&folder_id)
+            .await?
+            .ok_or(SyncudioError::CloudFolderNotFound(folder_id.clone()))?
+    };
+
+    let provider = match folder.provider_type.as_str() {
+        "dropbox" => cloud_state.get_provider(folder.account_id.as_deref()).await?,
+        _ => return Err(SyncudioError::UnsupportedProvider(folder.provider_type)),
+    };
+
+    verify_folder(&db_state, &*provider, &folder_id, enqueue_repairs.unwrap_or(false)).await
+}
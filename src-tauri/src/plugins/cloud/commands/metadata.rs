@@ -1,74 +1,234 @@
-use crate::libs::error::AnyResult;
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::libs::relative_path::RelativePath;
+use crate::libs::utils::TempFile;
 use crate::plugins::cloud::models::{
-    CloudMetadataCollection, CloudTrack, CloudTrackFullDTO, CloudTrackMap, CloudTrackMetadata,
+    CloudMetadataCollection, CloudMusicFolder, CloudTrack, CloudTrackFullDTO, CloudTrackMap, CloudTrackMetadata,
+    SyncHistoryEntry, SyncHistoryOperation,
 };
 use crate::plugins::cloud::{CloudMetadataSyncResult, CloudMetadataUpdateResult, CloudProvider, CloudState};
 use crate::plugins::db::DBState;
 use chrono::Utc;
-use log::info;
+use log::{info, warn};
 use ormlite::Model;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::path::PathBuf;
 use tauri::State;
 use uuid::Uuid;
-use std::env::temp_dir;
+use std::time::Instant;
 use chrono::DateTime;
 
+use super::accounts::get_account_folder_ids;
+use super::devices::publish_manifest;
+use super::encryption::{download_encrypted_metadata, get_encrypted_folders, upload_encrypted_metadata};
+use super::playlist_sync::sync_playlists;
+use super::stats_sync::sync_track_stats;
+
+pub(crate) const METADATA_FOLDER_PATH: &str = "/Syncudio/metadata";
+pub(crate) const TRACKS_METADATA_FILE: &str = "tracks.json";
+pub(crate) const PLAYLISTS_METADATA_FILE: &str = "playlists.json";
+pub(crate) const STATS_METADATA_FILE: &str = "stats.json";
+
+/// Download and parse a JSON file from the cloud, returns None if it does not
+/// exist yet
+pub(crate) async fn download_cloud_json<T, P>(provider: &P, file_ref: &str, file_name: &str) -> AnyResult<Option<T>>
+where
+    T: DeserializeOwned,
+    P: CloudProvider + Sync + ?Sized,
+{
+    let temp_file = TempFile::new(file_name);
+
+    match provider.download_file(file_ref, temp_file.path()).await {
+        Ok(_) => {
+            let content = std::fs::read_to_string(temp_file.path())?;
+            info!("Read {} content: {} bytes", file_name, content.len());
+            Ok(Some(serde_json::from_str(&content)?))
+        }
+        Err(SyncudioError::CloudFileNotFound(_)) => {
+            info!("No existing {} found", file_name);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Serialize a value and upload it to a cloud folder, the folder must exist
+pub(crate) async fn upload_cloud_json<T, P>(provider: &P, folder_path: &str, file_name: &str, value: &T) -> AnyResult<()>
+where
+    T: Serialize,
+    P: CloudProvider + Sync + ?Sized,
+{
+    let temp_file = TempFile::new(file_name);
+    std::fs::write(temp_file.path(), serde_json::to_string_pretty(value)?)?;
+    provider
+        .upload_file(temp_file.path(), file_name, Some(folder_path))
+        .await?;
+
+    Ok(())
+}
+
+/// Download and parse a JSON file from the metadata folder, returns None if it
+/// does not exist yet
+pub(crate) async fn download_metadata_json<T, P>(provider: &P, file_name: &str) -> AnyResult<Option<T>>
+where
+    T: DeserializeOwned,
+    P: CloudProvider + Sync + ?Sized,
+{
+    let remote_path = format!("{}/{}", METADATA_FOLDER_PATH, file_name);
+    download_cloud_json(provider, &remote_path, file_name).await
+}
+
+/// Serialize a value and upload it to the metadata folder
+pub(crate) async fn upload_metadata_json<T, P>(provider: &P, file_name: &str, value: &T) -> AnyResult<()>
+where
+    T: Serialize,
+    P: CloudProvider + Sync + ?Sized,
+{
+    provider.ensure_metadata_folder().await?;
+    upload_cloud_json(provider, METADATA_FOLDER_PATH, file_name, value).await
+}
+
+/// Record a metadata push or pull in the sync history
+async fn record_metadata_sync<T>(
+    db_state: &DBState,
+    operation: SyncHistoryOperation,
+    provider_type: &str,
+    started_at: Instant,
+    result: &AnyResult<T>,
+    details: impl FnOnce(&T) -> String,
+) -> AnyResult<()> {
+    let entry = SyncHistoryEntry {
+        duration_ms: started_at.elapsed().as_millis() as i64,
+        ..SyncHistoryEntry::new(operation, provider_type.to_string())
+    };
+    let entry = match result {
+        Ok(value) => SyncHistoryEntry { details: Some(details(value)), ..entry },
+        Err(e) => entry.fail(e.to_string()),
+    };
+
+    let mut db = db_state.get_lock().await;
+    db.record_sync_history(entry).await?;
+    Ok(())
+}
+
+/// Get the IDs of the folders whose metadata is stored in an account
+pub(crate) async fn get_metadata_folder_ids(
+    db_state: &DBState,
+    cloud_state: &CloudState,
+    account_id: Option<&str>,
+) -> AnyResult<HashSet<String>> {
+    let folders = {
+        let mut db = db_state.get_lock().await;
+        db.get_cloud_music_folders().await?
+    };
+    get_account_folder_ids(cloud_state, &folders, account_id)
+}
+
+/// Map the cloud folders synced by this device to its own folder IDs, which
+/// differ between devices: pulled tracks are matched by cloud folder. Several
+/// local folders may sync the same cloud folder.
+pub(crate) fn get_local_folder_ids(
+    folders: Vec<CloudMusicFolder>,
+    folder_ids: &HashSet<String>,
+) -> HashMap<String, Vec<String>> {
+    let mut local_folder_ids: HashMap<String, Vec<String>> = HashMap::new();
+    for folder in folders.into_iter().filter(|f| folder_ids.contains(&f.id)) {
+        local_folder_ids.entry(folder.cloud_folder_id).or_default().push(folder.id);
+    }
+    local_folder_ids
+}
+
 #[tauri::command]
 pub async fn pull_cloud_metadata(
+    account_id: Option<String>,
     db_state: State<'_, DBState>,
     cloud_state: State<'_, CloudState>,
 ) -> AnyResult<CloudMetadataSyncResult> {
     info!("Starting cloud metadata sync");
-    let provider = &cloud_state.dropbox;
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+    let folder_ids = get_metadata_folder_ids(&db_state, &cloud_state, account_id.as_deref()).await?;
+    let started_at = Instant::now();
+
+    let result = pull_metadata(&db_state, &*provider, &folder_ids).await;
+    record_metadata_sync(
+        &db_state,
+        SyncHistoryOperation::MetadataPull,
+        provider.provider_type().as_str(),
+        started_at,
+        &result,
+        |r| format!("{} tracks updated, {} tracks created", r.tracks_updated, r.tracks_created),
+    )
+    .await?;
+
+    result
+}
+
+pub(crate) async fn pull_metadata<P>(
+    db_state: &DBState,
+    provider: &P,
+    folder_ids: &HashSet<String>,
+) -> AnyResult<CloudMetadataSyncResult>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
 
     // 1. Download metadata from cloud - No DB lock needed
-    let metadata_folder = provider.ensure_metadata_folder().await?;
-    info!("Ensured metadata folder exists");
-    
-    let temp_path = temp_dir().join("syncudio_tracks.json.tmp");
-    info!("Using temp file: {}", temp_path.display());
-    
-    let (cloud_metadata, is_fresh_start) = match provider.download_file("/Syncudio/metadata/tracks.json", &temp_path).await {
-        Ok(_) => {
-            info!("Successfully downloaded metadata file");
-            let content = std::fs::read_to_string(&temp_path)?;
-            info!("Read metadata file content: {} bytes", content.len());
-            
-            let metadata: CloudMetadataCollection = serde_json::from_str(&content)?;
-            info!("Parsed metadata with {} tracks", metadata.tracks.len());
-            
-            std::fs::remove_file(&temp_path)?;
-            info!("Cleaned up temp file");
-            
-            (metadata, false)
-        }
-        Err(e) => {
-            info!("No existing metadata found, starting fresh: {}", e);
-            (CloudMetadataCollection::new(), true)
-        }
-    };
+    let (mut cloud_metadata, is_fresh_start) =
+        match download_metadata_json::<CloudMetadataCollection, _>(provider, TRACKS_METADATA_FILE).await? {
+            Some(metadata) => {
+                info!("Parsed metadata with {} tracks", metadata.tracks.len());
+                (metadata, false)
+            }
+            None => {
+                info!("No existing metadata found, starting fresh");
+                (CloudMetadataCollection::new(), true)
+            }
+        };
 
     let mut result = CloudMetadataSyncResult::new(is_fresh_start);
 
+    // Tracks of encrypted folders are stored in their own encrypted metadata
+    let encrypted_folders = {
+        let mut db = db_state.get_lock().await;
+        get_encrypted_folders(&mut db, provider.provider_type().as_str())
+            .await?
+            .into_iter()
+            .filter(|(folder, _)| folder_ids.contains(&folder.id))
+            .collect::<Vec<_>>()
+    };
+    for (folder, key) in &encrypted_folders {
+        let Some(key) = key else {
+            warn!("Skipping metadata of locked folder {}", folder.cloud_folder_path);
+            continue;
+        };
+        if let Some(metadata) = download_encrypted_metadata(provider, &folder.cloud_folder_id, key).await? {
+            info!("Parsed encrypted metadata with {} tracks", metadata.tracks.len());
+            cloud_metadata.tracks.extend(metadata.tracks);
+        }
+    }
+
     // 2. Load database state with minimal lock time
     info!("Loading database state");
-    let db_tracks = {
+    let (db_tracks, folders_by_cloud_id) = {
         let mut db = db_state.get_lock().await;
-        let tracks = db.get_cloud_tracks_full_by_provider(provider.provider_type().as_str()).await?;
+        let mut tracks = db.get_cloud_tracks_full_by_provider(provider.provider_type().as_str()).await?;
+        tracks.retain(|t| folder_ids.contains(&t.folder_id));
         info!("Loaded {} tracks from database", tracks.len());
-        tracks
+
+        let folders_by_cloud_id = get_local_folder_ids(db.get_cloud_music_folders().await?, folder_ids);
+        (tracks, folders_by_cloud_id)
     };
 
     // Create lookup maps - No DB lock needed
-    let mut db_tracks_by_path: HashMap<String, CloudTrackFullDTO> = HashMap::new();
-    let mut db_tracks_by_cloud_id: HashMap<String, CloudTrackFullDTO> = HashMap::new();
+    let mut db_tracks_by_path: HashMap<(String, String), CloudTrackFullDTO> = HashMap::new();
+    let mut db_tracks_by_cloud_id: HashMap<(String, String), CloudTrackFullDTO> = HashMap::new();
 
     for track in &db_tracks {
-        db_tracks_by_path.insert(track.relative_path.clone(), track.clone());
+        db_tracks_by_path.insert((track.folder_id.clone(), track.relative_path.clone()), track.clone());
         if let Some(cloud_id) = &track.cloud_file_id {
-            db_tracks_by_cloud_id.insert(cloud_id.clone(), track.clone());
+            db_tracks_by_cloud_id.insert((track.folder_id.clone(), cloud_id.clone()), track.clone());
         }
     }
     info!("Created lookup maps: {} by path, {} by cloud ID", 
@@ -77,94 +237,102 @@ pub async fn pull_cloud_metadata(
 
     // Process cloud metadata tracks in batches to minimize lock time
     for cloud_track in &cloud_metadata.tracks {
-        let db_track = db_tracks_by_path
-            .get(&cloud_track.relative_path)
-            .or_else(|| db_tracks_by_cloud_id.get(&cloud_track.cloud_file_id));
-
-        match db_track {
-            Some(track) => {
-                // Convert last_modified from string timestamp to DateTime
-                let cloud_modified = cloud_track.last_modified.parse::<i64>()
-                    .map(|ts| DateTime::from_timestamp(ts / 1000, 0).unwrap_or_default())
-                    .unwrap_or_default();
-
-                if cloud_modified > track.track_updated_at {
-                    // Update with minimal lock time
-                    let mut db = db_state.get_lock().await;
+        let Some(local_folder_ids) = folders_by_cloud_id.get(&cloud_track.cloud_folder_id) else {
+            info!("Skipping track of a folder not synced by this device (path: {})",
+                  cloud_track.relative_path);
+            continue;
+        };
+
+        // Several local folders may sync the same cloud folder, each has its own track
+        for folder_id in local_folder_ids {
+            let db_track = db_tracks_by_path
+                .get(&(folder_id.clone(), cloud_track.relative_path.clone()))
+                .or_else(|| db_tracks_by_cloud_id.get(&(folder_id.clone(), cloud_track.cloud_file_id.clone())));
+
+            match db_track {
+                Some(track) => {
+                    // Convert last_modified from string timestamp to DateTime
+                    let cloud_modified = cloud_track.last_modified.parse::<i64>()
+                        .map(|ts| DateTime::from_timestamp(ts / 1000, 0).unwrap_or_default())
+                        .unwrap_or_default();
+
+                    if cloud_modified > track.track_updated_at {
+                        // Update with minimal lock time
+                        let mut db = db_state.get_lock().await;
                     
-                    info!("Updating track {} from cloud metadata (path: {})", 
-                          track.track_id, 
-                          cloud_track.relative_path);
+                        info!("Updating track {} from cloud metadata (path: {})", 
+                              track.track_id, 
+                              cloud_track.relative_path);
                     
-                    let mut updated_track = CloudTrack::select()
-                        .where_("id = ?")
-                        .bind(&track.track_id)
-                        .fetch_one(&mut db.connection)
-                        .await?;
-
-                    updated_track.tags = cloud_track.tags.clone();
-                    updated_track.updated_at = cloud_modified;
-                    updated_track.update_all_fields(&mut db.connection).await?;
-
-                    // Update map if cloud_file_id changed
-                    if let Some(map) = CloudTrackMap::select()
-                        .where_("cloud_track_id = ?")
-                        .bind(&track.track_id)
-                        .fetch_optional(&mut db.connection)
-                        .await?
-                    {
-                        if map.cloud_file_id.as_ref() != Some(&cloud_track.cloud_file_id) {
-                            info!("Updating cloud file ID for track {}: {} -> {}", 
-                                  track.track_id,
-                                  map.cloud_file_id.as_deref().unwrap_or("none"),
-                                  cloud_track.cloud_file_id);
+                        let mut updated_track = CloudTrack::select()
+                            .where_("id = ?")
+                            .bind(&track.track_id)
+                            .fetch_one(&mut db.connection)
+                            .await?;
+
+                        updated_track.tags = cloud_track.tags.clone();
+                        updated_track.updated_at = cloud_modified;
+                        updated_track.update_all_fields(&mut db.connection).await?;
+
+                        // Update map if cloud_file_id changed
+                        if let Some(map) = CloudTrackMap::select()
+                            .where_("cloud_track_id = ?")
+                            .bind(&track.track_id)
+                            .fetch_optional(&mut db.connection)
+                            .await?
+                        {
+                            if map.cloud_file_id.as_ref() != Some(&cloud_track.cloud_file_id) {
+                                info!("Updating cloud file ID for track {}: {} -> {}", 
+                                      track.track_id,
+                                      map.cloud_file_id.as_deref().unwrap_or("none"),
+                                      cloud_track.cloud_file_id);
                                   
-                            let mut updated_map = map;
-                            updated_map.cloud_file_id = Some(cloud_track.cloud_file_id.clone());
-                            updated_map.update_all_fields(&mut db.connection).await?;
+                                let mut updated_map = map;
+                                updated_map.cloud_file_id = Some(cloud_track.cloud_file_id.clone());
+                                updated_map.update_all_fields(&mut db.connection).await?;
+                            }
                         }
+                        result.tracks_updated += 1;
+                    } else {
+                        info!("Skipping track {} (not modified)", track.track_id);
                     }
-                    result.tracks_updated += 1;
-                } else {
-                    info!("Skipping track {} (not modified)", track.track_id);
                 }
-            }
-            None => {
-                // Create new entry with minimal lock time
-                let mut db = db_state.get_lock().await;
+                None => {
+                    // Create new entry with minimal lock time
+                    let mut db = db_state.get_lock().await;
                 
-                info!("Creating new track from cloud metadata (path: {})", 
-                      cloud_track.relative_path);
+                    info!("Creating new track from cloud metadata (path: {})", 
+                          cloud_track.relative_path);
                 
-                // Convert last_modified from string timestamp to DateTime
-                let cloud_modified = cloud_track.last_modified.parse::<i64>()
-                    .map(|ts| DateTime::from_timestamp(ts / 1000, 0).unwrap_or_default())
-                    .unwrap_or_default();
-
-                let track = CloudTrack {
-                    id: Uuid::new_v4().to_string(),
-                    file_name: Path::new(&cloud_track.cloud_path)
-                        .file_name()
-                        .unwrap_or_default()
-                        .to_string_lossy()
-                        .to_string(),
-                    size: cloud_track.size,
-                    updated_at: cloud_modified,
-                    tags: cloud_track.tags.clone(),
-                };
-                let track_id = track.id.clone();
-                track.insert(&mut db.connection).await?;
-
-                // Create map
-                let map = CloudTrackMap {
-                    id: Uuid::new_v4().to_string(),
-                    cloud_track_id: track_id.clone(),
-                    cloud_music_folder_id: cloud_track.cloud_folder_id.clone(),
-                    relative_path: cloud_track.relative_path.clone(),
-                    cloud_file_id: Some(cloud_track.cloud_file_id.clone()),
-                };
-                map.insert(&mut db.connection).await?;
-                result.tracks_created += 1;
+                    // Convert last_modified from string timestamp to DateTime
+                    let cloud_modified = cloud_track.last_modified.parse::<i64>()
+                        .map(|ts| DateTime::from_timestamp(ts / 1000, 0).unwrap_or_default())
+                        .unwrap_or_default();
+
+                    let track = CloudTrack {
+                        id: Uuid::new_v4().to_string(),
+                        file_name: Path::new(&cloud_track.cloud_path)
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string(),
+                        size: cloud_track.size,
+                        updated_at: cloud_modified,
+                        tags: cloud_track.tags.clone(),
+                    };
+                    let track_id = track.id.clone();
+                    track.insert(&mut db.connection).await?;
+
+                    // Create map
+                    let map = CloudTrackMap::new(
+                        track_id.clone(),
+                        folder_id.clone(),
+                        RelativePath::new(&cloud_track.relative_path),
+                        Some(cloud_track.cloud_file_id.clone()),
+                    );
+                    map.insert(&mut db.connection).await?;
+                    result.tracks_created += 1;
+                }
             }
         }
     }
@@ -173,26 +341,63 @@ pub async fn pull_cloud_metadata(
           result.tracks_updated, 
           result.tracks_created);
 
+    // Playlists reference cloud tracks, so they are merged once tracks are up to date
+    result.playlists = sync_playlists(db_state, provider, folder_ids, false).await?;
+    result.stats = sync_track_stats(db_state, provider, folder_ids, false).await?;
+
+    // Let other devices know this one is up to date
+    publish_manifest(db_state, provider, true).await?;
+
     Ok(result)
 }
 
 #[tauri::command]
 pub async fn push_cloud_metadata(
+    account_id: Option<String>,
     db_state: State<'_, DBState>,
     cloud_state: State<'_, CloudState>,
 ) -> AnyResult<CloudMetadataUpdateResult> {
     info!("Updating cloud metadata");
-    let provider = &cloud_state.dropbox;
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+    let folder_ids = get_metadata_folder_ids(&db_state, &cloud_state, account_id.as_deref()).await?;
+    let started_at = Instant::now();
+
+    let result = push_metadata(&db_state, &*provider, &folder_ids).await;
+    record_metadata_sync(
+        &db_state,
+        SyncHistoryOperation::MetadataPush,
+        provider.provider_type().as_str(),
+        started_at,
+        &result,
+        |r| format!("{} tracks included, {} tracks skipped", r.tracks_included, r.tracks_skipped),
+    )
+    .await?;
+
+    result
+}
+
+pub(crate) async fn push_metadata<P>(
+    db_state: &DBState,
+    provider: &P,
+    folder_ids: &HashSet<String>,
+) -> AnyResult<CloudMetadataUpdateResult>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
     let mut result = CloudMetadataUpdateResult::new();
 
     // 1. Get current database state with minimal lock time
-    let tracks = {
+    let (tracks, encrypted_folders) = {
         let mut db = db_state.get_lock().await;
-        db.get_cloud_tracks_full_by_provider(provider.provider_type().as_str()).await?
+        let mut tracks = db.get_cloud_tracks_full_by_provider(provider.provider_type().as_str()).await?;
+        tracks.retain(|t| folder_ids.contains(&t.folder_id));
+        let mut encrypted_folders = get_encrypted_folders(&mut db, provider.provider_type().as_str()).await?;
+        encrypted_folders.retain(|(folder, _)| folder_ids.contains(&folder.id));
+        (tracks, encrypted_folders)
     };
 
     // 2. Convert to cloud metadata format - No DB lock needed
-    let metadata = CloudMetadataCollection {
+    let mut metadata = CloudMetadataCollection {
         tracks: tracks
             .into_iter()
             .filter_map(|t| {
@@ -222,13 +427,38 @@ pub async fn push_cloud_metadata(
             .collect(),
     };
 
+    // Tracks of encrypted folders never go to the plaintext metadata
+    let (encrypted_tracks, tracks): (Vec<_>, Vec<_>) = metadata.tracks.into_iter().partition(|t| {
+        encrypted_folders
+            .iter()
+            .any(|(folder, _)| folder.cloud_folder_id == t.cloud_folder_id)
+    });
+    metadata.tracks = tracks;
+
     // 3. Upload to cloud - No DB lock needed
-    let temp_path = temp_dir().join("syncudio_tracks.json.tmp");
-    std::fs::write(&temp_path, serde_json::to_string_pretty(&metadata)?)?;
-    provider
-        .upload_file(&temp_path, "tracks.json", Some("/Syncudio/metadata"))
-        .await?;
-    std::fs::remove_file(temp_path)?;
+    upload_metadata_json(provider, TRACKS_METADATA_FILE, &metadata).await?;
+
+    for (folder, key) in &encrypted_folders {
+        let Some(key) = key else {
+            warn!("Skipping metadata of locked folder {}", folder.cloud_folder_path);
+            continue;
+        };
+        let folder_metadata = CloudMetadataCollection {
+            tracks: encrypted_tracks
+                .iter()
+                .filter(|t| t.cloud_folder_id == folder.cloud_folder_id)
+                .cloned()
+                .collect(),
+        };
+        upload_encrypted_metadata(provider, &folder.cloud_folder_id, key, &folder_metadata).await?;
+    }
+
+    // 4. Push playlists alongside track metadata
+    result.playlists = sync_playlists(db_state, provider, folder_ids, true).await?;
+    result.stats = sync_track_stats(db_state, provider, folder_ids, true).await?;
+
+    // 5. Let other devices know this one is up to date
+    publish_manifest(db_state, provider, true).await?;
 
     Ok(result)
 }
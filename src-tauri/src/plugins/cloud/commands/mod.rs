@@ -1,41 +1,64 @@
+mod accounts;
 mod database;
+mod devices;
+mod encryption;
+mod history;
+mod integrity;
 mod provider;
+mod quota;
+mod relocate;
+mod revisions;
 mod sync;
+mod sync_plan;
 mod sync_queue;
 mod cleanup;
 mod metadata;
+mod playlist_sync;
+mod stats_sync;
 mod fs;
 
 use chrono::{DateTime, Utc};
 use ormlite::Model;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fs::File;
 use std::time::UNIX_EPOCH;
 use tauri::State;
 use uuid::Uuid;
 
+pub use accounts::*;
 pub use database::*;
+pub use devices::*;
+pub use encryption::*;
+pub use history::*;
+pub use integrity::*;
 pub use fs::*;
 pub use metadata::*;
+pub use playlist_sync::*;
+pub use stats_sync::*;
 pub use provider::*;
+pub use quota::*;
+pub use relocate::*;
+pub use revisions::*;
 pub use sync::*;
+pub use sync_plan::*;
 pub use sync_queue::*;
 pub use cleanup::*;
 
 
 use crate::libs::constants::SUPPORTED_TRACKS_EXTENSIONS;
 use crate::libs::error::SyncudioError;
-use crate::libs::utils::normalize_relative_path;
-use crate::plugins::cloud::CloudFile;
+use crate::libs::relative_path::RelativePath;
+use crate::plugins::cloud::{hash_file, CloudFile};
 use crate::{libs::error::AnyResult, plugins::db::DBState};
 
 use super::models::*;
 use super::{CloudProvider, CloudProviderType, CloudState};
 
 use crate::libs::track::Track;
-use log::info;
+use log::warn;
 use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
@@ -51,6 +74,9 @@ pub struct CloudFolderScanResult {
     pub tracks_updated: usize,
     /// Number of track mappings that were cleared (cloud_file_id set to None)
     pub mappings_cleared: usize,
+    /// Number of local tracks ignored, as their path only differs by case or
+    /// Unicode form from another track, which the cloud cannot tell apart
+    pub path_collisions: usize,
 }
 
 #[tauri::command]
@@ -59,28 +85,48 @@ pub async fn scan_cloud_music_folder(
     db_state: State<'_, DBState>,
     cloud_state: State<'_, CloudState>,
 ) -> AnyResult<CloudFolderScanResult> {
+    let account_id = {
+        let mut db = db_state.get_lock().await;
+        db.get_cloud_folder(&folder_id)
+            .await?
+            .ok_or(SyncudioError::CloudFolderNotFound(folder_id.clone()))?
+            .account_id
+    };
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+
+    scan_folder(&db_state, &*provider, &folder_id).await
+}
+
+/// Match the local tracks and cloud files of a folder, and record them as cloud tracks
+pub(crate) async fn scan_folder<P>(db_state: &DBState, provider: &P, folder_id: &str) -> AnyResult<CloudFolderScanResult>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
     let mut db = db_state.get_lock().await;
     let folder = CloudMusicFolder::select()
         .where_("id = ?")
-        .bind(&folder_id)
+        .bind(folder_id)
         .fetch_one(&mut db.connection)
         .await?;
 
     // Get cloud files for this folder
-    let provider = &cloud_state.dropbox;
     let cloud_files = provider
         .list_files(&folder.cloud_folder_id, &folder.cloud_folder_path, true)
         .await?;
 
+    let key = get_folder_key(&mut db, &folder).await?;
+    let cloud_files = decrypt_cloud_files(cloud_files, key.as_ref());
+
     let mut result = CloudFolderScanResult {
         cloud_tracks_found: 0,
         local_tracks_found: 0,
         tracks_created: 0,
         tracks_updated: 0,
         mappings_cleared: 0,
+        path_collisions: 0,
     };
 
-    // Create maps for efficient lookups
+    // Create maps for efficient lookups, by path key as providers like Dropbox ignore case
     let cloud_files_map: HashMap<String, CloudFile> = cloud_files
         .into_iter()
         .filter(|f| {
@@ -96,7 +142,7 @@ pub async fn scan_cloud_music_folder(
             }
             false
         })
-        .map(|f| (f.relative_path.clone(), f))
+        .map(|f| (RelativePath::new(&f.relative_path).key(), f))
         .collect();
 
     // Get local tracks
@@ -108,18 +154,22 @@ pub async fn scan_cloud_music_folder(
 
     result.local_tracks_found = local_tracks.len();
 
-    let local_tracks_map: HashMap<String, Track> = local_tracks
-        .into_iter()
-        .map(|t| {
-            let rel_path = t
-                .path
-                .strip_prefix(&folder.local_folder_path)
-                .unwrap_or(&t.path)
-                .trim_start_matches('/')
-                .to_string();
-            (rel_path, t)
-        })
-        .collect();
+    let mut local_tracks_map: HashMap<String, (RelativePath, Track)> = HashMap::new();
+    for track in local_tracks {
+        let Some(rel_path) = folder.relative_path_of(&track.path) else {
+            continue;
+        };
+
+        match local_tracks_map.entry(rel_path.key()) {
+            Entry::Occupied(entry) => {
+                warn!("Ignoring {}, its path collides with {}", track.path, entry.get().1.path);
+                result.path_collisions += 1;
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((rel_path, track));
+            }
+        }
+    }
 
     // Get existing cloud tracks and maps for this folder
     let existing_tracks: Vec<(String, String, Option<String>)> = ormlite::query_as(
@@ -128,7 +178,7 @@ pub async fn scan_cloud_music_folder(
          INNER JOIN cloud_maps ctm ON ct.id = ctm.cloud_track_id
          WHERE ctm.cloud_music_folder_id = ?"
     )
-    .bind(&folder_id)
+    .bind(folder_id)
     .fetch_all(&mut db.connection)
     .await?;
 
@@ -136,7 +186,7 @@ pub async fn scan_cloud_music_folder(
     let mut existing_by_path: HashMap<String, String> = HashMap::new();
     let mut existing_by_cloud_id: HashMap<String, String> = HashMap::new();
     for (id, path, cloud_id) in existing_tracks {
-        existing_by_path.insert(path, id.clone());
+        existing_by_path.insert(RelativePath::new(&path).key(), id.clone());
         if let Some(cloud_id) = cloud_id {
             existing_by_cloud_id.insert(cloud_id, id);
         }
@@ -145,12 +195,12 @@ pub async fn scan_cloud_music_folder(
     let mut processed_track_ids = Vec::new();
 
     // Process local tracks first
-    for (rel_path, local_track) in local_tracks_map.iter() {
-        let cloud_file = cloud_files_map.get(rel_path);
+    for (path_key, (rel_path, local_track)) in local_tracks_map.iter() {
+        let cloud_file = cloud_files_map.get(path_key);
 
         // Try to find existing track ID by path or cloud_file_id
         let existing_id = existing_by_path
-            .get(rel_path)
+            .get(path_key)
             .cloned()
             .or_else(|| cloud_file.and_then(|f| existing_by_cloud_id.get(&f.id).cloned()));
 
@@ -171,7 +221,17 @@ pub async fn scan_cloud_music_folder(
                     DateTime::from_timestamp(local_mtime.as_secs() as i64, 0)
                         .unwrap_or_default();
 
-                if local_updated_at > track.updated_at {
+                // Encrypted tracks are compared by content, as their mtime
+                // changes whenever they are decrypted
+                let content_unchanged = match &key {
+                    Some(_) => db
+                        .get_cloud_file_hash_by_track(&id, folder_id)
+                        .await?
+                        .is_some_and(|hash| hash_file(Path::new(&local_track.path)).ok() == Some(hash)),
+                    None => false,
+                };
+
+                if local_updated_at > track.updated_at && !content_unchanged {
                     track.tags = Some(CloudTrackTag::from_track(local_track.clone()));
                     track.updated_at = local_updated_at;
                     track.update_all_fields(&mut db.connection).await?;
@@ -182,7 +242,7 @@ pub async fn scan_cloud_music_folder(
                 let track_map = CloudTrackMap::select()
                     .where_("cloud_track_id = ? AND cloud_music_folder_id = ?")
                     .bind(&id)
-                    .bind(&folder_id)
+                    .bind(folder_id)
                     .fetch_optional(&mut db.connection)
                     .await?;
 
@@ -200,13 +260,12 @@ pub async fn scan_cloud_music_folder(
                     }
                     None => {
                         // Create new map
-                        let map = CloudTrackMap {
-                            id: Uuid::new_v4().to_string(),
-                            cloud_track_id: id.clone(),
-                            cloud_music_folder_id: folder_id.clone(),
-                            relative_path: rel_path.clone(),
-                            cloud_file_id: cloud_file.map(|f| f.id.clone()),
-                        };
+                        let map = CloudTrackMap::new(
+                            id.clone(),
+                            folder_id.to_string(),
+                            rel_path.clone(),
+                            cloud_file.map(|f| f.id.clone()),
+                        );
                         map.insert(&mut db.connection).await?;
                     }
                 }
@@ -217,7 +276,7 @@ pub async fn scan_cloud_music_folder(
                 // Create new track
                 let track = CloudTrack {
                     id: Uuid::new_v4().to_string(),
-                    file_name: local_track.path.split('/').last().unwrap_or("").to_string(),
+                    file_name: rel_path.file_name().to_string(),
                     size: local_track.size,
                     updated_at: Utc::now(),
                     tags: Some(CloudTrackTag::from_track(local_track.clone())),
@@ -226,13 +285,12 @@ pub async fn scan_cloud_music_folder(
                 track.insert(&mut db.connection).await?;
 
                 // Create map
-                let map = CloudTrackMap {
-                    id: Uuid::new_v4().to_string(),
-                    cloud_track_id: track_id.clone(),
-                    cloud_music_folder_id: folder_id.clone(),
-                    relative_path: rel_path.clone(),
-                    cloud_file_id: cloud_file.map(|f| f.id.clone()),
-                };
+                let map = CloudTrackMap::new(
+                    track_id.clone(),
+                    folder_id.to_string(),
+                    rel_path.clone(),
+                    cloud_file.map(|f| f.id.clone()),
+                );
                 map.insert(&mut db.connection).await?;
 
                 processed_track_ids.push(track_id);
@@ -242,8 +300,8 @@ pub async fn scan_cloud_music_folder(
     }
 
     // Process remaining cloud files
-    for (rel_path, cloud_file) in cloud_files_map.iter() {
-        if local_tracks_map.contains_key(rel_path) {
+    for (path_key, cloud_file) in cloud_files_map.iter() {
+        if local_tracks_map.contains_key(path_key) {
             continue; // Already processed with local track
         }
 
@@ -268,13 +326,12 @@ pub async fn scan_cloud_music_folder(
                 track.insert(&mut db.connection).await?;
 
                 // Create map
-                let map = CloudTrackMap {
-                    id: Uuid::new_v4().to_string(),
-                    cloud_track_id: track_id.clone(),
-                    cloud_music_folder_id: folder_id.clone(),
-                    relative_path: rel_path.clone(),
-                    cloud_file_id: Some(cloud_file.id.clone()),
-                };
+                let map = CloudTrackMap::new(
+                    track_id.clone(),
+                    folder_id.to_string(),
+                    RelativePath::new(&cloud_file.relative_path),
+                    Some(cloud_file.id.clone()),
+                );
                 map.insert(&mut db.connection).await?;
 
                 processed_track_ids.push(track_id);
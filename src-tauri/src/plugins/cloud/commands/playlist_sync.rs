@@ -0,0 +1,338 @@
+use chrono::Utc;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use tauri::State;
+
+use crate::libs::error::AnyResult;
+use crate::libs::playlist::Playlist;
+use crate::libs::relative_path::RelativePath;
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::{CloudProvider, CloudState};
+use crate::plugins::db::DBState;
+
+use super::metadata::{download_metadata_json, get_metadata_folder_ids, upload_metadata_json, PLAYLISTS_METADATA_FILE};
+
+/// Translates local track IDs to cloud playlist references, and back
+struct PlaylistTrackResolver {
+    track_refs: HashMap<String, CloudPlaylistTrackRef>, // local track id -> reference
+    track_ids: HashMap<(String, String), String>,       // (cloud folder id, relative path key) -> local track id
+}
+
+impl PlaylistTrackResolver {
+    /// Build the references of the local tracks of some folders from their
+    /// unified tracks, where tracks and maps are already matched by path key.
+    /// A track living in several folders is referenced in the first one.
+    fn new(folders: &[CloudMusicFolder], unified_tracks: &[UnifiedTrack]) -> Self {
+        let mut track_refs = HashMap::new();
+        let mut track_ids = HashMap::new();
+
+        for folder in folders {
+            let folder_tracks = unified_tracks
+                .iter()
+                .filter(|t| t.cloud_folder_id.as_deref() == Some(folder.id.as_str()));
+
+            for unified_track in folder_tracks {
+                let Some(local_track_id) = &unified_track.local_track_id else {
+                    continue;
+                };
+
+                // Local tracks not mapped yet are referenced by their path in the folder
+                let relative_path = match (&unified_track.cloud_relative_path, &unified_track.local_path) {
+                    (Some(relative_path), _) => RelativePath::new(relative_path),
+                    (None, Some(local_path)) => match folder.relative_path_of(local_path) {
+                        Some(relative_path) => relative_path,
+                        None => continue,
+                    },
+                    (None, None) => continue,
+                };
+
+                track_ids
+                    .entry((folder.cloud_folder_id.clone(), relative_path.key()))
+                    .or_insert_with(|| local_track_id.clone());
+                track_refs
+                    .entry(local_track_id.clone())
+                    .or_insert_with(|| CloudPlaylistTrackRef {
+                        cloud_track_id: unified_track.cloud_track_id.clone(),
+                        cloud_folder_id: folder.cloud_folder_id.clone(),
+                        relative_path: relative_path.into(),
+                    });
+            }
+        }
+
+        PlaylistTrackResolver { track_refs, track_ids }
+    }
+
+    fn to_ref(&self, local_track_id: &str) -> Option<CloudPlaylistTrackRef> {
+        self.track_refs.get(local_track_id).cloned()
+    }
+
+    fn to_local(&self, track_ref: &CloudPlaylistTrackRef) -> Option<String> {
+        let key = RelativePath::new(&track_ref.relative_path).key();
+        self.track_ids.get(&(track_ref.cloud_folder_id.clone(), key)).cloned()
+    }
+
+    /// Build the local side of the merge. References that cannot be resolved on
+    /// this device (eg. cloud-only tracks) are kept where they were at the last
+    /// sync, otherwise they would look like local deletions.
+    fn local_refs(&self, playlist: &Playlist, base: &[CloudPlaylistTrackRef]) -> Vec<CloudPlaylistTrackRef> {
+        let mut refs: Vec<CloudPlaylistTrackRef> =
+            playlist.tracks.iter().filter_map(|id| self.to_ref(id)).collect();
+
+        let mut previous: Option<&CloudPlaylistTrackRef> = None;
+        for track_ref in base {
+            if self.to_local(track_ref).is_none() {
+                let position = previous
+                    .and_then(|prev| refs.iter().position(|r| r.key() == prev.key()))
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+                refs.insert(position, track_ref.clone());
+            }
+            previous = Some(track_ref);
+        }
+
+        refs
+    }
+
+    /// Convert merged references to local track IDs. Local tracks that are not
+    /// part of any cloud folder cannot be synced, they are kept at the end.
+    fn local_tracks(&self, refs: &[CloudPlaylistTrackRef], current: &[String]) -> Vec<String> {
+        let mut tracks: Vec<String> = refs.iter().filter_map(|r| self.to_local(r)).collect();
+        tracks.extend(current.iter().filter(|id| self.to_ref(id).is_none()).cloned());
+        tracks
+    }
+}
+
+/**
+ * Merge the playlists stored in the cloud with the local ones, resolving their
+ * tracks in the given folders only. When `push` is set, the merged result is
+ * uploaded back to the cloud.
+ */
+pub(crate) async fn sync_playlists<P>(
+    db_state: &DBState,
+    provider: &P,
+    folder_ids: &HashSet<String>,
+    push: bool,
+) -> AnyResult<CloudPlaylistSyncResult>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let mut result = CloudPlaylistSyncResult::default();
+
+    // 1. Download remote playlists - No DB lock needed
+    let remote = download_metadata_json::<CloudPlaylistCollection, _>(provider, PLAYLISTS_METADATA_FILE)
+        .await?
+        .unwrap_or_default();
+    info!("Found {} playlists in cloud metadata", remote.playlists.len());
+
+    // 2. Merge with the local state
+    let cloud_playlists = {
+        let mut db = db_state.get_lock().await;
+
+        let mut folders = db
+            .get_cloud_music_folders_by_provider(provider.provider_type().as_str())
+            .await?;
+        folders.retain(|f| folder_ids.contains(&f.id));
+
+        let mut unified_tracks = Vec::new();
+        for folder in &folders {
+            unified_tracks.extend(db.get_unified_tracks_by_folder(&folder.id).await?);
+        }
+        let resolver = PlaylistTrackResolver::new(&folders, &unified_tracks);
+
+        let mut local_playlists: HashMap<String, Playlist> = db
+            .get_all_playlists()
+            .await?
+            .into_iter()
+            .map(|p| (p.id.clone(), p))
+            .collect();
+
+        let mut known: HashMap<String, CloudPlaylist> = db
+            .get_cloud_playlists()
+            .await?
+            .into_iter()
+            .map(|p| (p.id.clone(), p))
+            .collect();
+
+        let mut merged_playlists: Vec<CloudPlaylist> = Vec::new();
+
+        for remote_playlist in remote.playlists {
+            let Some(mut cloud_playlist) = known.remove(&remote_playlist.id) else {
+                // Playlist created on another device
+                let mut cloud_playlist = CloudPlaylist {
+                    id: remote_playlist.id.clone(),
+                    name: remote_playlist.name.clone(),
+                    tracks: remote_playlist.tracks.clone(),
+                    created_at: remote_playlist.created_at,
+                    updated_at: remote_playlist.updated_at,
+                    local_playlist_id: None,
+                    synced_tracks: remote_playlist.tracks.clone(),
+                    synced_name: remote_playlist.name.clone(),
+                    deleted: remote_playlist.deleted,
+                };
+
+                if !remote_playlist.deleted {
+                    let track_ids = resolver.local_tracks(&remote_playlist.tracks, &[]);
+                    info!(r#"Creating playlist "{}" from cloud"#, remote_playlist.name);
+                    let playlist = db
+                        .create_playlist(remote_playlist.name.clone(), track_ids, None)
+                        .await?;
+                    cloud_playlist.local_playlist_id = Some(playlist.id);
+                    result.playlists_created += 1;
+                }
+
+                db.save_cloud_playlist(cloud_playlist.clone()).await?;
+                merged_playlists.push(cloud_playlist);
+                continue;
+            };
+
+            let local_playlist = cloud_playlist
+                .local_playlist_id
+                .as_ref()
+                .and_then(|id| local_playlists.remove(id));
+
+            match (local_playlist, remote_playlist.deleted) {
+                (_, true) if cloud_playlist.deleted => {}
+                (Some(local_playlist), true) => {
+                    info!(r#"Deleting playlist "{}", it was deleted on another device"#, local_playlist.name);
+                    db.delete_playlist(&local_playlist.id).await?;
+                    cloud_playlist.deleted = true;
+                    cloud_playlist.tracks = Vec::new();
+                    cloud_playlist.local_playlist_id = None;
+                    cloud_playlist.updated_at = remote_playlist.updated_at;
+                    result.playlists_deleted += 1;
+                }
+                (None, _) => {
+                    // Deleted locally since the last sync, propagate the tombstone
+                    if !cloud_playlist.deleted {
+                        cloud_playlist.deleted = true;
+                        cloud_playlist.tracks = Vec::new();
+                        cloud_playlist.local_playlist_id = None;
+                        cloud_playlist.updated_at = Utc::now();
+                    }
+                }
+                (Some(local_playlist), false) => {
+                    let local_refs = resolver.local_refs(&local_playlist, &cloud_playlist.synced_tracks);
+                    let tracks = merge_playlist_tracks(
+                        &cloud_playlist.synced_tracks,
+                        &local_refs,
+                        &remote_playlist.tracks,
+                    );
+                    let name = merge_playlist_name(
+                        &cloud_playlist.synced_name,
+                        &local_playlist.name,
+                        &remote_playlist.name,
+                    );
+
+                    let track_ids = resolver.local_tracks(&tracks, &local_playlist.tracks);
+                    let mut updated = false;
+
+                    if track_ids != local_playlist.tracks {
+                        db.set_playlist_tracks(&local_playlist.id, track_ids).await?;
+                        updated = true;
+                    }
+
+                    if name != local_playlist.name {
+                        db.rename_playlist(&local_playlist.id, name.clone()).await?;
+                        updated = true;
+                    }
+
+                    if updated {
+                        result.playlists_updated += 1;
+                    }
+
+                    cloud_playlist.updated_at = if tracks != remote_playlist.tracks || name != remote_playlist.name {
+                        Utc::now()
+                    } else {
+                        remote_playlist.updated_at
+                    };
+                    cloud_playlist.tracks = tracks;
+                    cloud_playlist.name = name;
+                }
+            }
+
+            // Until pushed, the remote version is the common ancestor
+            cloud_playlist.synced_tracks = remote_playlist.tracks;
+            cloud_playlist.synced_name = remote_playlist.name;
+
+            merged_playlists.push(cloud_playlist);
+        }
+
+        // Playlists known locally but missing from the cloud metadata (never pushed)
+        for (_, mut cloud_playlist) in known {
+            match cloud_playlist
+                .local_playlist_id
+                .as_ref()
+                .and_then(|id| local_playlists.remove(id))
+            {
+                Some(local_playlist) => {
+                    cloud_playlist.tracks =
+                        resolver.local_refs(&local_playlist, &cloud_playlist.synced_tracks);
+                    cloud_playlist.name = local_playlist.name;
+                }
+                None if !cloud_playlist.deleted => {
+                    cloud_playlist.deleted = true;
+                    cloud_playlist.tracks = Vec::new();
+                    cloud_playlist.local_playlist_id = None;
+                    cloud_playlist.updated_at = Utc::now();
+                }
+                None => {}
+            }
+            merged_playlists.push(cloud_playlist);
+        }
+
+        // Local playlists that were never synced
+        for (_, local_playlist) in local_playlists {
+            let mut cloud_playlist =
+                CloudPlaylist::new(local_playlist.name.clone(), Some(local_playlist.id.clone()));
+            cloud_playlist.tracks = resolver.local_refs(&local_playlist, &[]);
+
+            db.save_cloud_playlist(cloud_playlist.clone()).await?;
+            merged_playlists.push(cloud_playlist);
+        }
+
+        if push {
+            for playlist in merged_playlists.iter_mut() {
+                playlist.synced_tracks = playlist.tracks.clone();
+                playlist.synced_name = playlist.name.clone();
+            }
+        }
+
+        for playlist in &merged_playlists {
+            db.update_cloud_playlist(playlist.clone()).await?;
+        }
+
+        merged_playlists
+    };
+
+    // 3. Upload merged playlists - No DB lock needed
+    if push {
+        let collection = CloudPlaylistCollection {
+            playlists: cloud_playlists.iter().map(CloudPlaylistMetadata::from).collect(),
+        };
+        upload_metadata_json(provider, PLAYLISTS_METADATA_FILE, &collection).await?;
+        result.playlists_pushed = collection.playlists.len() as u32;
+    }
+
+    info!(
+        "Playlist sync completed: {} created, {} updated, {} deleted, {} pushed",
+        result.playlists_created,
+        result.playlists_updated,
+        result.playlists_deleted,
+        result.playlists_pushed
+    );
+
+    Ok(result)
+}
+
+/// Merge local and cloud playlists, and push the result to the cloud
+#[tauri::command]
+pub async fn sync_cloud_playlists(
+    account_id: Option<String>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<CloudPlaylistSyncResult> {
+    info!("Syncing playlists with cloud");
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+    let folder_ids = get_metadata_folder_ids(&db_state, &cloud_state, account_id.as_deref()).await?;
+    sync_playlists(&db_state, &*provider, &folder_ids, true).await
+}
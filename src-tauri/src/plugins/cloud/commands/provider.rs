@@ -1,4 +1,4 @@
-use log::{info, error};
+use log::info;
 use std::path::PathBuf;
 use tauri::State;
 
@@ -7,44 +7,50 @@ use crate::libs::error::SyncudioError;
 use crate::plugins::cloud::providers::CloudProviderType;
 use crate::plugins::cloud::CloudState;
 use crate::plugins::cloud::{CloudFile, CloudProvider};
+use crate::plugins::cloud::{complete_account_authorization, unauthorize_account, CloudAccount};
+use crate::plugins::db::DBState;
 
 // Dropbox-specific auth commands
 #[tauri::command]
 pub async fn dropbox_start_auth(cloud_state: State<'_, CloudState>) -> AnyResult<String> {
     info!("Starting Dropbox authorization");
-    cloud_state.dropbox.start_authorization().await
+    cloud_state.pending_dropbox.start_authorization().await
 }
 
+/// Complete the authorization of an account, the first one becomes the default account
 #[tauri::command]
 pub async fn dropbox_complete_auth(
     auth_code: String,
+    db_state: State<'_, DBState>,
     cloud_state: State<'_, CloudState>,
-) -> AnyResult<()> {
-    info!(
-        "Completing Dropbox authorization with auth code: {}",
-        auth_code
-    );
-    let auth_data = cloud_state.dropbox.complete_authorization(&auth_code).await?;
-    if let Some(auth_data) = auth_data {
-        info!(
-            "Dropbox authorization completed successfully: {:?}",
-            auth_data
-        );
-    } else {
-        error!("Dropbox authorization failed");
-    }
-    Ok(())
+) -> AnyResult<CloudAccount> {
+    info!("Completing Dropbox authorization");
+    cloud_state.pending_dropbox.complete_authorization(&auth_code).await?;
+
+    let account = complete_account_authorization(&db_state, &cloud_state).await?;
+    info!("Dropbox authorization completed for account {}", account.id);
+
+    Ok(account)
 }
 
 #[tauri::command]
-pub async fn dropbox_is_authorized(cloud_state: State<'_, CloudState>) -> AnyResult<bool> {
-    Ok(cloud_state.dropbox.is_authorized().await)
+pub async fn dropbox_is_authorized(
+    account_id: Option<String>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<bool> {
+    match cloud_state.get_provider(account_id.as_deref()).await {
+        Ok(provider) => Ok(provider.is_authorized().await),
+        Err(SyncudioError::CloudAccountNotFound(_)) => Ok(false),
+        Err(err) => Err(err),
+    }
 }
 
 #[tauri::command]
-pub async fn dropbox_unauthorize(cloud_state: State<'_, CloudState>) -> AnyResult<()> {
-    cloud_state.dropbox.unauthorize().await;
-    Ok(())
+pub async fn dropbox_unauthorize(
+    account_id: Option<String>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<()> {
+    unauthorize_account(&cloud_state, account_id.as_deref()).await
 }
 
 // Generic cloud file operation commands
@@ -54,13 +60,17 @@ pub async fn cloud_list_files(
     folder_id: String,
     folder_path: String,
     recursive: bool,
+    account_id: Option<String>,
     cloud_state: State<'_, CloudState>,
     // Add other providers here when implemented
 ) -> AnyResult<Vec<CloudFile>> {
     let provider = CloudProviderType::from_str(&provider_type)?;
 
     match provider {
-        CloudProviderType::Dropbox => cloud_state.dropbox.list_files(&folder_id, &folder_path, recursive).await,
+        CloudProviderType::Dropbox => {
+            let dropbox = cloud_state.get_provider(account_id.as_deref()).await?;
+            dropbox.list_files(&folder_id, &folder_path, recursive).await
+        }
         CloudProviderType::GoogleDrive => Err(SyncudioError::GoogleDrive("Google Drive not implemented yet".to_string())),
     }
 }
@@ -69,13 +79,17 @@ pub async fn cloud_list_files(
 pub async fn cloud_list_root_files(
     provider_type: String,
     recursive: bool,
+    account_id: Option<String>,
     cloud_state: State<'_, CloudState>,
     // Add other providers here when implemented
 ) -> AnyResult<Vec<CloudFile>> {
     let provider = CloudProviderType::from_str(&provider_type)?;
 
     match provider {
-        CloudProviderType::Dropbox => cloud_state.dropbox.list_root_files(recursive).await,
+        CloudProviderType::Dropbox => {
+            let dropbox = cloud_state.get_provider(account_id.as_deref()).await?;
+            dropbox.list_root_files(recursive).await
+        }
         CloudProviderType::GoogleDrive => Err(SyncudioError::GoogleDrive("Google Drive not implemented yet".to_string())),
     }
 }
@@ -85,6 +99,7 @@ pub async fn cloud_create_folder(
     provider_type: String,
     name: String,
     parent_id: Option<String>,
+    account_id: Option<String>,
     cloud_state: State<'_, CloudState>,
     // Add other providers here when implemented
 ) -> AnyResult<CloudFile> {
@@ -92,7 +107,8 @@ pub async fn cloud_create_folder(
 
     match provider {
         CloudProviderType::Dropbox => {
-            Ok(cloud_state.dropbox.create_folder(&name, parent_id.as_deref()).await?)
+            let dropbox = cloud_state.get_provider(account_id.as_deref()).await?;
+            Ok(dropbox.create_folder(&name, parent_id.as_deref()).await?)
         }
         CloudProviderType::GoogleDrive => Err(SyncudioError::GoogleDrive("Google Drive not implemented yet".to_string())),
     }
@@ -104,6 +120,7 @@ pub async fn cloud_upload_file(
     abs_local_path: String,
     name: String,
     parent_id: Option<String>,
+    account_id: Option<String>,
     cloud_state: State<'_, CloudState>,
     // Add other providers here when implemented
 ) -> AnyResult<CloudFile> {
@@ -111,7 +128,8 @@ pub async fn cloud_upload_file(
 
     match provider {
         CloudProviderType::Dropbox => {
-            cloud_state.dropbox
+            let dropbox = cloud_state.get_provider(account_id.as_deref()).await?;
+            dropbox
                 .upload_file(&PathBuf::from(abs_local_path), &name, parent_id.as_deref())
                 .await
         }
@@ -124,6 +142,7 @@ pub async fn cloud_download_file(
     provider_type: String,
     file_id: String,
     abs_local_path: String,
+    account_id: Option<String>,
     cloud_state: State<'_, CloudState>,
     // Add other providers here when implemented
 ) -> AnyResult<()> {
@@ -131,7 +150,8 @@ pub async fn cloud_download_file(
 
     match provider {
         CloudProviderType::Dropbox => {
-            cloud_state.dropbox
+            let dropbox = cloud_state.get_provider(account_id.as_deref()).await?;
+            dropbox
                 .download_file(&file_id, &PathBuf::from(abs_local_path))
                 .await
         }
@@ -143,13 +163,17 @@ pub async fn cloud_download_file(
 pub async fn cloud_delete_file(
     provider_type: String,
     file_id: String,
+    account_id: Option<String>,
     cloud_state: State<'_, CloudState>,
     // Add other providers here when implemented
 ) -> AnyResult<()> {
     let provider = CloudProviderType::from_str(&provider_type)?;
 
     match provider {
-        CloudProviderType::Dropbox => cloud_state.dropbox.delete_file(&file_id).await,
+        CloudProviderType::Dropbox => {
+            let dropbox = cloud_state.get_provider(account_id.as_deref()).await?;
+            dropbox.delete_file(&file_id).await
+        }
         CloudProviderType::GoogleDrive => Err(SyncudioError::GoogleDrive("Google Drive not implemented yet".to_string())),
     }
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+use log::warn;
+use std::collections::HashMap;
+use tauri::State;
+
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::{CloudProvider, CloudState};
+use crate::plugins::db::DBState;
+
+use super::get_account_folder_ids;
+
+/// Get the space used in an account and the size of the uploads queued for its folders
+pub(crate) async fn get_storage_usage<P>(
+    db_state: &DBState,
+    provider: &P,
+    account_id: Option<String>,
+    folder_ids: &[String],
+) -> AnyResult<CloudStorageUsageDTO>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let space_usage = provider.get_space_usage().await?;
+    let pending_upload_bytes = {
+        let mut db = db_state.get_lock().await;
+        db.get_pending_upload_bytes(folder_ids).await?
+    };
+
+    let needed = space_usage.used + pending_upload_bytes;
+    Ok(CloudStorageUsageDTO {
+        account_id,
+        used: space_usage.used,
+        allocated: space_usage.allocated,
+        pending_upload_bytes,
+        available_after_uploads: space_usage.allocated.map(|allocated| allocated.saturating_sub(needed)),
+        exceeds_quota: space_usage.allocated.is_some_and(|allocated| needed > allocated),
+    })
+}
+
+/// Refuse uploads that do not fit in the quota, forced uploads are only logged
+pub(crate) fn check_quota(usage: &CloudStorageUsageDTO, upload_bytes: u64, force: bool) -> AnyResult<()> {
+    if usage.fits(upload_bytes) {
+        return Ok(());
+    }
+
+    let message = format!(
+        "{} bytes to upload, {} bytes available after the queued uploads",
+        upload_bytes,
+        usage.available_after_uploads.unwrap_or_default()
+    );
+    if force {
+        warn!("Queueing uploads over the storage quota: {}", message);
+        return Ok(());
+    }
+
+    Err(SyncudioError::QuotaExceeded(message))
+}
+
+async fn get_account_usage(
+    db_state: &DBState,
+    cloud_state: &CloudState,
+    folders: &[CloudMusicFolder],
+    account_id: Option<String>,
+) -> AnyResult<CloudStorageUsageDTO> {
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+    let folder_ids: Vec<String> = get_account_folder_ids(cloud_state, folders, account_id.as_deref())?
+        .into_iter()
+        .collect();
+
+    get_storage_usage(db_state, &*provider, account_id, &folder_ids).await
+}
+
+/**
+ * Check that uploads to cloud folders, in bytes by folder ID, fit in the quota
+ * of their accounts. Accounts that cannot be reached are not checked, their
+ * uploads will fail in the queue instead.
+ */
+pub(crate) async fn check_upload_quota(
+    db_state: &DBState,
+    cloud_state: &CloudState,
+    upload_bytes: HashMap<String, u64>,
+    force: bool,
+) -> AnyResult<()> {
+    let folders = {
+        let mut db = db_state.get_lock().await;
+        db.get_cloud_music_folders().await?
+    };
+
+    let mut bytes_by_account: HashMap<Option<String>, u64> = HashMap::new();
+    for (folder_id, bytes) in upload_bytes {
+        let folder = folders
+            .iter()
+            .find(|f| f.id == folder_id)
+            .ok_or(SyncudioError::CloudFolderNotFound(folder_id.clone()))?;
+        let account_id = match cloud_state.is_default_account(folder.account_id.as_deref())? {
+            true => None,
+            false => folder.account_id.clone(),
+        };
+        *bytes_by_account.entry(account_id).or_default() += bytes;
+    }
+
+    for (account_id, bytes) in bytes_by_account {
+        match get_account_usage(db_state, cloud_state, &folders, account_id.clone()).await {
+            Ok(usage) => check_quota(&usage, bytes, force)?,
+            Err(err) => warn!("Could not check the storage quota of account {:?}: {}", account_id, err),
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the space used in an account, None is the default account
+#[tauri::command]
+pub async fn get_cloud_storage_usage(
+    account_id: Option<String>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<CloudStorageUsageDTO> {
+    let folders = {
+        let mut db = db_state.get_lock().await;
+        db.get_cloud_music_folders().await?
+    };
+
+    get_account_usage(&db_state, &cloud_state, &folders, account_id).await
+}
@@ -0,0 +1,237 @@
+use log::info;
+use ormlite::Model;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::libs::database::core::DB;
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::{hash_file, CloudProvider, CloudState};
+use crate::plugins::config::ConfigManager;
+use crate::plugins::db::DBState;
+
+use super::database::refresh_watcher;
+use super::encryption::get_folder_key;
+
+/**
+ * Check that a sample of the files of a cloud folder are at a new location,
+ * with the content they have in the cloud. Files of encrypted folders are
+ * compared with the plaintext hashes recorded when they were transferred.
+ * Returns the number of verified files.
+ */
+pub(crate) async fn verify_relocation<P>(
+    db_state: &DBState,
+    provider: &P,
+    folder: &CloudMusicFolder,
+    local_folder_path: &Path,
+    sample_size: usize,
+) -> AnyResult<usize>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let (maps, key) = {
+        let mut db = db_state.get_lock().await;
+        let maps = CloudTrackMap::select()
+            .where_("cloud_music_folder_id = ? AND cloud_file_id IS NOT NULL")
+            .bind(&folder.id)
+            .fetch_all(&mut db.connection)
+            .await?;
+        (maps, get_folder_key(&mut db, folder).await?)
+    };
+
+    if maps.is_empty() || sample_size == 0 {
+        return Ok(0);
+    }
+
+    // Cloud hashes of encrypted files describe the ciphertext, they are useless here
+    let cloud_hashes: HashMap<String, String> = match key {
+        Some(_) => HashMap::new(),
+        None => provider
+            .list_files(&folder.cloud_folder_id, &folder.cloud_folder_path, true)
+            .await?
+            .into_iter()
+            .filter_map(|file| file.hash.map(|hash| (file.id, hash.value().to_string())))
+            .collect(),
+    };
+
+    let step = (maps.len() / sample_size).max(1);
+    let mut verified = 0;
+    let mut mismatches = Vec::new();
+
+    for map in maps.iter().step_by(step).take(sample_size) {
+        let path = local_folder_path.join(&map.relative_path);
+        if !path.exists() {
+            mismatches.push(map.relative_path.clone());
+            continue;
+        }
+
+        let matches = match key {
+            Some(_) => {
+                let mut db = db_state.get_lock().await;
+                match db.get_cloud_file_hash(&map.id).await? {
+                    Some(hash) => Some(hash_file(&path)? == hash),
+                    None => None,
+                }
+            }
+            None => match map.cloud_file_id.as_ref().and_then(|id| cloud_hashes.get(id)) {
+                Some(hash) => Some(provider.hash_local_file(&path)?.value() == hash),
+                None => None,
+            },
+        };
+
+        match matches {
+            Some(true) => verified += 1,
+            Some(false) => mismatches.push(map.relative_path.clone()),
+            None => {} // Nothing to compare with
+        }
+    }
+
+    if !mismatches.is_empty() {
+        return Err(SyncudioError::FolderRelocation(format!(
+            "{} sampled files are missing or differ in {}: {}",
+            mismatches.len(),
+            local_folder_path.display(),
+            mismatches.join(", ")
+        )));
+    }
+
+    Ok(verified)
+}
+
+async fn apply_relocation(
+    db: &mut DB,
+    folder: CloudMusicFolder,
+    targets: Vec<CloudMusicFolder>,
+    to: &Path,
+) -> AnyResult<FolderRelocationResultDTO> {
+    let previous_local_folder_path = folder.local_folder_path.clone();
+    let from = PathBuf::from(&previous_local_folder_path);
+
+    let tracks_relocated = db.relocate_tracks(&from, to).await?;
+    let playlists_updated = db.relocate_playlists(&from, to).await?;
+
+    // Every cloud folder synced with the local folder follows it
+    let local_folder_path = to.to_string_lossy().to_string();
+    for mut target in targets {
+        target.local_folder_path = local_folder_path.clone();
+        db.update_cloud_folder(target).await?;
+    }
+
+    Ok(FolderRelocationResultDTO {
+        folder_id: folder.id,
+        previous_local_folder_path,
+        local_folder_path,
+        tracks_relocated,
+        playlists_updated,
+        files_verified: 0,
+    })
+}
+
+/**
+ * Point a cloud folder to a new local folder and move its library tracks
+ * there, in a single transaction. Tracks keep their IDs, so play stats and
+ * playlists follow them. Other cloud folders synced with the same local folder
+ * are moved too.
+ */
+pub(crate) async fn relocate_folder(
+    db_state: &DBState,
+    folder_id: &str,
+    local_folder_path: &Path,
+) -> AnyResult<FolderRelocationResultDTO> {
+    if !local_folder_path.is_dir() {
+        return Err(SyncudioError::FolderRelocation(format!(
+            "Not a folder: {}",
+            local_folder_path.display()
+        )));
+    }
+
+    let mut db = db_state.get_lock().await;
+    let folder = db
+        .get_cloud_folder(folder_id)
+        .await?
+        .ok_or(SyncudioError::CloudFolderNotFound(folder_id.to_string()))?;
+    let targets = db.get_cloud_folders_by_local_path(&folder.local_folder_path).await?;
+
+    let local_folder_path_str = local_folder_path.to_string_lossy().to_string();
+    let already_synced = db.get_cloud_folders_by_local_path(&local_folder_path_str).await?;
+    if already_synced.iter().any(|f| !targets.iter().any(|t| t.id == f.id)) {
+        return Err(SyncudioError::FolderAlreadySynced(local_folder_path_str));
+    }
+
+    info!(
+        "Relocating cloud folder {} from {} to {}",
+        folder.id, folder.local_folder_path, local_folder_path_str
+    );
+
+    ormlite::query("BEGIN").execute(&mut db.connection).await?;
+
+    match apply_relocation(&mut db, folder, targets, local_folder_path).await {
+        Ok(result) => {
+            ormlite::query("COMMIT").execute(&mut db.connection).await?;
+            info!(
+                "Relocated {} tracks and {} playlists",
+                result.tracks_relocated, result.playlists_updated
+            );
+            Ok(result)
+        }
+        Err(e) => {
+            ormlite::query("ROLLBACK").execute(&mut db.connection).await?;
+            Err(e)
+        }
+    }
+}
+
+/**
+ * Point a cloud folder to a new local path, eg. after the music was moved to
+ * another disk, without syncing it again. When a sample size is given, that
+ * many files are first compared with the cloud, and nothing changes if one of
+ * them differs.
+ */
+#[tauri::command]
+pub async fn relocate_cloud_folder<R: Runtime>(
+    app_handle: AppHandle<R>,
+    folder_id: String,
+    local_folder_path: String,
+    verify_sample: Option<usize>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<FolderRelocationResultDTO> {
+    // Dropping the trailing separator keeps paths consistent with the tracks ones
+    let local_folder_path: PathBuf = Path::new(&local_folder_path).components().collect();
+
+    let files_verified = match verify_sample {
+        Some(sample_size) if sample_size > 0 => {
+            let folder = {
+                let mut db = db_state.get_lock().await;
+                db.get_cloud_folder(&folder_id)
+                    .await?
+                    .ok_or(SyncudioError::CloudFolderNotFound(folder_id.clone()))?
+            };
+
+            let provider = match folder.provider_type.as_str() {
+                "dropbox" => cloud_state.get_provider(folder.account_id.as_deref()).await?,
+                _ => return Err(SyncudioError::UnsupportedProvider(folder.provider_type)),
+            };
+
+            verify_relocation(&db_state, &*provider, &folder, &local_folder_path, sample_size).await?
+        }
+        _ => 0,
+    };
+
+    let mut result = relocate_folder(&db_state, &folder_id, &local_folder_path).await?;
+    result.files_verified = files_verified;
+
+    // A library folder at the previous location moved too
+    let config_manager = app_handle.state::<ConfigManager>();
+    let mut config = config_manager.get()?;
+    let previous_path = PathBuf::from(&result.previous_local_folder_path);
+    if let Some(library_folder) = config.library_folders.iter_mut().find(|f| **f == previous_path) {
+        *library_folder = local_folder_path;
+        config_manager.update(config)?;
+    }
+
+    refresh_watcher(app_handle).await;
+
+    Ok(result)
+}
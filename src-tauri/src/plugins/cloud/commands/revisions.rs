@@ -0,0 +1,168 @@
+use log::info;
+use ormlite::Model;
+use tauri::State;
+
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::{AccountProvider, CloudFileRevision, CloudProvider, CloudState};
+use crate::plugins::db::DBState;
+
+/// Revisions listed when no limit is given
+const DEFAULT_REVISIONS_LIMIT: usize = 20;
+
+/// Get a track map and its folder, a cloud track has a map in each folder syncing it
+async fn get_track_target(db_state: &DBState, cloud_map_id: &str) -> AnyResult<(CloudTrackMap, CloudMusicFolder)> {
+    let mut db = db_state.get_lock().await;
+    let track_map = CloudTrackMap::select()
+        .where_("id = ?")
+        .bind(cloud_map_id)
+        .fetch_one(&mut db.connection)
+        .await?;
+    let folder = db
+        .get_cloud_folder(&track_map.cloud_music_folder_id)
+        .await?
+        .ok_or(SyncudioError::CloudFolderNotFound(track_map.cloud_music_folder_id.clone()))?;
+    Ok((track_map, folder))
+}
+
+fn get_cloud_file_id(track_map: &CloudTrackMap) -> AnyResult<&str> {
+    track_map
+        .cloud_file_id
+        .as_deref()
+        .ok_or(SyncudioError::RevisionRestore(format!("{} is not in the cloud", track_map.relative_path)))
+}
+
+async fn get_folder_provider<'a>(cloud_state: &'a CloudState, folder: &CloudMusicFolder) -> AnyResult<AccountProvider<'a>> {
+    match folder.provider_type.as_str() {
+        "dropbox" => cloud_state.get_provider(folder.account_id.as_deref()).await,
+        _ => Err(SyncudioError::UnsupportedProvider(folder.provider_type.clone())),
+    }
+}
+
+/// List the revisions of the cloud file of a track, newest first
+pub(crate) async fn list_revisions<P>(
+    db_state: &DBState,
+    provider: &P,
+    cloud_map_id: &str,
+    limit: usize,
+) -> AnyResult<Vec<CloudFileRevision>>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let (track_map, _) = get_track_target(db_state, cloud_map_id).await?;
+    provider.list_revisions(get_cloud_file_id(&track_map)?, limit).await
+}
+
+/**
+ * Roll the cloud and/or local copy of a track back to a previous revision.
+ * The cloud file is restored right away, local copies are downloaded by the
+ * download queue. Rollbacks follow the sync mode of the folder, like syncs.
+ */
+pub(crate) async fn restore_revision<P>(
+    db_state: &DBState,
+    provider: &P,
+    cloud_map_id: &str,
+    revision_id: &str,
+    target: RevisionRestoreTarget,
+) -> AnyResult<RevisionRestoreResultDTO>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let (track_map, folder) = get_track_target(db_state, cloud_map_id).await?;
+    let file_id = get_cloud_file_id(&track_map)?;
+
+    let sync_mode = folder.get_sync_mode();
+    if (target.restores_cloud() && !sync_mode.can_upload()) || (target.restores_local() && !sync_mode.can_download()) {
+        return Err(SyncudioError::RevisionRestore(format!(
+            "The {} sync mode of folder {} does not allow it",
+            sync_mode.as_str(),
+            folder.cloud_folder_path
+        )));
+    }
+
+    {
+        let mut db = db_state.get_lock().await;
+        let active: Option<(String,)> = ormlite::query_as(
+            "SELECT id FROM upload_queue WHERE cloud_map_id = ? AND status IN ('pending', 'in_progress')
+             UNION
+             SELECT id FROM download_queue WHERE cloud_map_id = ? AND status IN ('pending', 'in_progress')",
+        )
+        .bind(&track_map.id)
+        .bind(&track_map.id)
+        .fetch_optional(&mut db.connection)
+        .await?;
+
+        if active.is_some() {
+            return Err(SyncudioError::RevisionRestore(format!(
+                "{} is being transferred",
+                track_map.relative_path
+            )));
+        }
+    }
+
+    // Provider calls - No DB lock needed
+    let revisions = provider.list_revisions(file_id, 100).await?;
+    if !revisions.iter().any(|r| r.id == revision_id) {
+        return Err(SyncudioError::RevisionRestore(format!("Unknown revision: {}", revision_id)));
+    }
+
+    info!(
+        "Restoring revision {} of {} ({:?})",
+        revision_id, track_map.relative_path, target
+    );
+
+    let cloud_revision_id = if target.restores_cloud() {
+        Some(provider.restore_revision(file_id, revision_id).await?.id)
+    } else {
+        None
+    };
+
+    // Once the cloud is restored, the local copy is its current version
+    let download_item_id = if target.restores_local() {
+        let item = DownloadQueueItem {
+            revision_id: cloud_revision_id.is_none().then(|| revision_id.to_string()),
+            ..DownloadQueueItem::new(track_map.id.clone(), folder.provider_type.clone(), 1)
+        };
+
+        let mut db = db_state.get_lock().await;
+        Some(item.insert(&mut db.connection).await?.id)
+    } else {
+        None
+    };
+
+    Ok(RevisionRestoreResultDTO {
+        cloud_map_id: cloud_map_id.to_string(),
+        revision_id: revision_id.to_string(),
+        cloud_revision_id,
+        download_item_id,
+    })
+}
+
+/// List the revisions of a track map, for providers that keep them
+#[tauri::command]
+pub async fn list_cloud_track_revisions(
+    cloud_map_id: String,
+    limit: Option<usize>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<Vec<CloudFileRevision>> {
+    let (_, folder) = get_track_target(&db_state, &cloud_map_id).await?;
+    let provider = get_folder_provider(&cloud_state, &folder).await?;
+
+    list_revisions(&db_state, &*provider, &cloud_map_id, limit.unwrap_or(DEFAULT_REVISIONS_LIMIT)).await
+}
+
+/// Roll a track map back to one of its revisions
+#[tauri::command]
+pub async fn restore_cloud_track_revision(
+    cloud_map_id: String,
+    revision_id: String,
+    target: RevisionRestoreTarget,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<RevisionRestoreResultDTO> {
+    let (_, folder) = get_track_target(&db_state, &cloud_map_id).await?;
+    let provider = get_folder_provider(&cloud_state, &folder).await?;
+
+    restore_revision(&db_state, &*provider, &cloud_map_id, &revision_id, target).await
+}
@@ -0,0 +1,195 @@
+use log::info;
+use ormlite::Model;
+use std::collections::{HashMap, HashSet};
+use tauri::State;
+
+use crate::libs::device::get_device_id;
+use crate::libs::error::AnyResult;
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::{CloudProvider, CloudState};
+use crate::plugins::db::DBState;
+
+use super::metadata::{download_metadata_json, get_metadata_folder_ids, upload_metadata_json, STATS_METADATA_FILE};
+
+/**
+ * Merge the play counts and ratings stored in the cloud with the ones recorded
+ * on this device, for the tracks of the given folders. When `push` is set, the
+ * merged stats are uploaded back.
+ */
+pub(crate) async fn sync_track_stats<P>(
+    db_state: &DBState,
+    provider: &P,
+    folder_ids: &HashSet<String>,
+    push: bool,
+) -> AnyResult<CloudTrackStatsSyncResult>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let mut result = CloudTrackStatsSyncResult::default();
+    let device_id = get_device_id()?;
+
+    // 1. Download remote stats - No DB lock needed
+    let remote = download_metadata_json::<CloudTrackStatsCollection, _>(provider, STATS_METADATA_FILE)
+        .await?
+        .unwrap_or_default();
+    info!("Found stats for {} tracks in cloud metadata", remote.tracks.len());
+
+    // 2. Merge with the local state
+    let merged_stats = {
+        let mut db = db_state.get_lock().await;
+
+        // Local tracks are matched with their maps through the unified tracks
+        let mut cloud_tracks = Vec::new();
+        let mut track_ids: HashMap<String, String> = HashMap::new(); // map id -> local track id
+        for folder_id in folder_ids {
+            cloud_tracks.extend(db.get_cloud_tracks_full_by_folder(folder_id).await?);
+            track_ids.extend(
+                db.get_unified_tracks_by_folder(folder_id)
+                    .await?
+                    .into_iter()
+                    .filter_map(|t| Some((t.cloud_map_id?, t.local_track_id?))),
+            );
+        }
+        let local_stats: HashMap<String, _> = db
+            .get_all_track_stats()
+            .await?
+            .into_iter()
+            .map(|s| (s.track_id.clone(), s))
+            .collect();
+
+        // Cloud track IDs are generated per device, fall back to the file ID
+        let by_file_id: HashMap<&String, &String> = cloud_tracks
+            .iter()
+            .filter_map(|t| t.cloud_file_id.as_ref().map(|file_id| (file_id, &t.track_id)))
+            .collect();
+        let known_ids: HashSet<&String> = cloud_tracks.iter().map(|t| &t.track_id).collect();
+
+        // Stats of the tracks of other accounts stay out of this one
+        let mut stats: HashMap<String, CloudTrackStats> = CloudTrackStats::select()
+            .fetch_all(&mut db.connection)
+            .await?
+            .into_iter()
+            .filter(|s| known_ids.contains(&s.cloud_track_id))
+            .map(|s| (s.cloud_track_id.clone(), s))
+            .collect();
+        let existing: HashSet<String> = stats.keys().cloned().collect();
+        let mut changed: HashSet<String> = HashSet::new();
+
+        let mut unresolved: Vec<CloudTrackStats> = Vec::new();
+
+        for remote_stats in remote.tracks {
+            let cloud_track_id = if known_ids.contains(&&remote_stats.cloud_track_id) {
+                Some(remote_stats.cloud_track_id.clone())
+            } else {
+                remote_stats
+                    .cloud_file_id
+                    .as_ref()
+                    .and_then(|file_id| by_file_id.get(file_id))
+                    .map(|id| (*id).clone())
+            };
+
+            // Stats of tracks unknown to this device are kept untouched
+            let Some(cloud_track_id) = cloud_track_id else {
+                unresolved.push(remote_stats);
+                continue;
+            };
+
+            let entry = stats
+                .entry(cloud_track_id.clone())
+                .or_insert_with(|| CloudTrackStats::new(cloud_track_id.clone(), None));
+
+            if entry.merge(&remote_stats) {
+                changed.insert(cloud_track_id);
+                result.tracks_updated += 1;
+            }
+        }
+
+        // Apply the contribution of this device
+        for cloud_track in &cloud_tracks {
+            let Some(local) = track_ids.get(&cloud_track.map_id).and_then(|id| local_stats.get(id)) else {
+                continue;
+            };
+
+            let entry = stats
+                .entry(cloud_track.track_id.clone())
+                .or_insert_with(|| {
+                    CloudTrackStats::new(cloud_track.track_id.clone(), cloud_track.cloud_file_id.clone())
+                });
+
+            let mut entry_changed = false;
+
+            if entry.play_counts.get(&device_id) != Some(&local.play_count) {
+                entry.play_counts.insert(device_id.clone(), local.play_count);
+                entry_changed = true;
+            }
+
+            if let Some(last_played) = local.last_played {
+                if entry.last_played.get(&device_id) != Some(&last_played) {
+                    entry.last_played.insert(device_id.clone(), last_played);
+                    entry_changed = true;
+                }
+            }
+
+            if entry.cloud_file_id != cloud_track.cloud_file_id && cloud_track.cloud_file_id.is_some() {
+                entry.cloud_file_id = cloud_track.cloud_file_id.clone();
+                entry_changed = true;
+            }
+
+            // Ratings are last-writer-wins
+            if entry.merge_rating(local.rating, local.rating_updated_at) {
+                entry_changed = true;
+            } else if entry.rating_updated_at > local.rating_updated_at {
+                if let Some(updated_at) = entry.rating_updated_at {
+                    db.set_track_rating(&local.track_id, entry.rating, updated_at).await?;
+                }
+            }
+
+            if entry_changed {
+                changed.insert(cloud_track.track_id.clone());
+            }
+        }
+
+        for (cloud_track_id, entry) in stats.iter() {
+            if !changed.contains(cloud_track_id) {
+                continue;
+            }
+
+            if existing.contains(cloud_track_id) {
+                entry.clone().update_all_fields(&mut db.connection).await?;
+            } else {
+                entry.clone().insert(&mut db.connection).await?;
+            }
+        }
+
+        let mut merged: Vec<CloudTrackStats> = stats.into_values().collect();
+        merged.extend(unresolved);
+        merged
+    };
+
+    // 3. Upload merged stats - No DB lock needed
+    if push {
+        let collection = CloudTrackStatsCollection { tracks: merged_stats };
+        upload_metadata_json(provider, STATS_METADATA_FILE, &collection).await?;
+        result.tracks_pushed = collection.tracks.len() as u32;
+    }
+
+    info!(
+        "Stats sync completed: {} tracks updated, {} tracks pushed",
+        result.tracks_updated, result.tracks_pushed
+    );
+
+    Ok(result)
+}
+
+/// Merge local and cloud play counts and ratings, and push the result to the cloud
+#[tauri::command]
+pub async fn sync_cloud_track_stats(
+    account_id: Option<String>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<CloudTrackStatsSyncResult> {
+    info!("Syncing track stats with cloud");
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+    let folder_ids = get_metadata_folder_ids(&db_state, &cloud_state, account_id.as_deref()).await?;
+    sync_track_stats(&db_state, &*provider, &folder_ids, true).await
+}
@@ -23,6 +23,7 @@ impl SyncStatus {
             "pending" => Ok(SyncStatus::Pending),
             "in_progress" => Ok(SyncStatus::InProgress),
             "completed" => Ok(SyncStatus::Completed),
+            "cancelled" => Ok(SyncStatus::Cancelled),
             _ => Ok(SyncStatus::Failed {
                 error: "Unknown status".to_string(),
                 attempts: 0,
@@ -100,10 +101,7 @@ pub async fn get_cloud_folder_sync_details(
 
     for track in tracks_with_maps {
         // Check file existence in both locations
-        let local_path = Path::new(&folder.local_folder_path)
-            .join(&track.relative_path)
-            .to_string_lossy()
-            .to_string();
+        let local_path = folder.local_path_of(&track.relative_path).to_string_lossy().to_string();
         let local_exists = Path::new(&local_path).exists();
 
         // Calculate location state
@@ -204,10 +202,7 @@ pub async fn get_track_sync_status(
         .await?;
 
     // Check file existence
-    let local_path = Path::new(&folder.local_folder_path)
-        .join(&track_map.relative_path)
-        .to_string_lossy()
-        .to_string();
+    let local_path = folder.local_path_of(&track_map.relative_path).to_string_lossy().to_string();
     let local_exists = Path::new(&local_path).exists();
 
     // Get active operations
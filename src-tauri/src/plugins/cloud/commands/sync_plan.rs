@@ -0,0 +1,721 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use ormlite::Model;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tauri::State;
+use uuid::Uuid;
+
+use crate::libs::constants::SUPPORTED_TRACKS_EXTENSIONS;
+use crate::libs::database::core::DB;
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::libs::relative_path::{local_path_key, RelativePath};
+use crate::libs::track::Track;
+use crate::plugins::cloud::models::*;
+use crate::plugins::cloud::{hash_file, CloudFile, CloudProvider, CloudState};
+use crate::plugins::db::DBState;
+
+use super::encryption::{decrypt_cloud_files, get_folder_key};
+use super::quota::check_upload_quota;
+
+/// Transfer rate used for estimates when none is given, in bytes per second
+const DEFAULT_TRANSFER_RATE: u64 = 2 * 1024 * 1024;
+
+fn is_supported_track(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SUPPORTED_TRACKS_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+pub(crate) fn get_modified_at(path: &Path) -> Option<DateTime<Utc>> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    let secs = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    DateTime::from_timestamp(secs as i64, 0)
+}
+
+/// Whether a sync mode lets an action happen, conflicts are always reported
+fn is_allowed(sync_mode: CloudSyncMode, action: &SyncPlanAction) -> bool {
+    match action {
+        SyncPlanAction::Upload | SyncPlanAction::MetadataUpdate => sync_mode.can_upload(),
+        SyncPlanAction::Download => sync_mode.can_download(),
+        _ => true,
+    }
+}
+
+fn plan_item(
+    action: SyncPlanAction,
+    relative_path: &str,
+    size: u64,
+    reason: &str,
+) -> SyncPlanItemDTO {
+    SyncPlanItemDTO {
+        action,
+        cloud_track_id: None,
+        cloud_map_id: None,
+        cloud_file_id: None,
+        relative_path: relative_path.to_string(),
+        previous_relative_path: None,
+        size,
+        reason: reason.to_string(),
+    }
+}
+
+/**
+ * Compare a cloud folder with its local counterpart and list what a sync would
+ * do. Nothing is modified.
+ */
+pub(crate) async fn compute_folder_sync_plan<P>(
+    db_state: &DBState,
+    provider: &P,
+    folder_id: &str,
+    transfer_rate: u64,
+) -> AnyResult<SyncPlanDTO>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let (folder, key) = {
+        let mut db = db_state.get_lock().await;
+        let folder = CloudMusicFolder::select()
+            .where_("id = ?")
+            .bind(folder_id)
+            .fetch_one(&mut db.connection)
+            .await?;
+        let key = get_folder_key(&mut db, &folder).await?;
+        (folder, key)
+    };
+
+    // 1. List cloud files - No DB lock needed
+    let cloud_files = provider
+        .list_files(&folder.cloud_folder_id, &folder.cloud_folder_path, true)
+        .await?;
+    let cloud_files: Vec<CloudFile> = decrypt_cloud_files(cloud_files, key.as_ref())
+        .into_iter()
+        .filter(|f| !f.is_folder && is_supported_track(&f.name))
+        .collect();
+
+    let cloud_by_id: HashMap<&str, &CloudFile> = cloud_files.iter().map(|f| (f.id.as_str(), f)).collect();
+    let cloud_by_path: HashMap<String, &CloudFile> = cloud_files
+        .iter()
+        .map(|f| (RelativePath::new(&f.relative_path).key(), f))
+        .collect();
+
+    // 2. Load the database state
+    let mut db = db_state.get_lock().await;
+
+    let maps = CloudTrackMap::select()
+        .where_("cloud_music_folder_id = ?")
+        .bind(&folder.id)
+        .fetch_all(&mut db.connection)
+        .await?;
+    let tracks: HashMap<String, CloudTrack> = CloudTrack::select()
+        .where_("id IN (SELECT cloud_track_id FROM cloud_maps WHERE cloud_music_folder_id = ?)")
+        .bind(&folder.id)
+        .fetch_all(&mut db.connection)
+        .await?
+        .into_iter()
+        .map(|t| (t.id.clone(), t))
+        .collect();
+    let local_tracks: HashMap<String, (RelativePath, Track)> = Track::select()
+        .where_("path LIKE ?")
+        .bind(format!("{}/%", folder.local_folder_path))
+        .fetch_all(&mut db.connection)
+        .await?
+        .into_iter()
+        .filter_map(|t| folder.relative_path_of(&t.path).map(|rel| (rel.key(), (rel, t))))
+        .collect();
+    let local_paths_by_id: HashMap<&str, &RelativePath> = local_tracks
+        .values()
+        .map(|(rel, t)| (t.id.as_str(), rel))
+        .collect();
+
+    // Local tracks of the maps, which they keep when the local file moves
+    let map_local_tracks: HashMap<String, String> = ormlite::query_as::<_, (String, String)>(
+        "SELECT id, local_track_id FROM cloud_maps WHERE cloud_music_folder_id = ? AND local_track_id IS NOT NULL",
+    )
+    .bind(&folder.id)
+    .fetch_all(&mut db.connection)
+    .await?
+    .into_iter()
+    .collect();
+
+    // Tracks with a pending transfer are already taken care of
+    let active: Vec<(String,)> = ormlite::query_as(
+        "SELECT cloud_map_id FROM upload_queue WHERE status IN ('pending', 'in_progress')
+         UNION
+         SELECT cloud_map_id FROM download_queue WHERE status IN ('pending', 'in_progress')",
+    )
+    .fetch_all(&mut db.connection)
+    .await?;
+    let active: HashSet<String> = active.into_iter().map(|(id,)| id).collect();
+
+    // Encrypted tracks are compared by content, with their hash at the last transfer
+    let file_hashes: HashMap<String, String> = match key {
+        Some(_) => ormlite::query_as::<_, (String, String)>(
+            "SELECT h.cloud_map_id, h.plaintext_hash
+             FROM cloud_file_hashes h
+             INNER JOIN cloud_maps m ON m.id = h.cloud_map_id
+             WHERE m.cloud_music_folder_id = ?",
+        )
+        .bind(&folder.id)
+        .fetch_all(&mut db.connection)
+        .await?
+        .into_iter()
+        .collect(),
+        None => HashMap::new(),
+    };
+
+    drop(db);
+
+    // 3. Compare with the local files - No DB lock needed, hashing can be slow
+    let mut items: Vec<SyncPlanItemDTO> = Vec::new();
+    let mut seen_cloud_files: HashSet<&str> = HashSet::new();
+    // Paths are compared with their keys, providers like Dropbox ignore case
+    let mut seen_paths: HashSet<String> = HashSet::new();
+
+    for map in &maps {
+        let map_path = map.get_relative_path();
+        seen_paths.insert(map_path.key());
+
+        // Local file moved elsewhere in the folder, its new path is not a new track
+        let moved_to = map_local_tracks
+            .get(&map.id)
+            .and_then(|id| local_paths_by_id.get(id.as_str()).copied())
+            .filter(|rel| rel.key() != map_path.key() && !folder.local_path_of(&map.relative_path).exists());
+        if let Some(moved_to) = moved_to {
+            seen_paths.insert(moved_to.key());
+        }
+
+        let cloud_file = map
+            .cloud_file_id
+            .as_deref()
+            .and_then(|id| cloud_by_id.get(id))
+            .or_else(|| cloud_by_path.get(&map_path.key()))
+            .copied();
+        if let Some(cloud_file) = cloud_file {
+            seen_cloud_files.insert(cloud_file.id.as_str());
+            seen_paths.insert(RelativePath::new(&cloud_file.relative_path).key());
+        }
+
+        if active.contains(&map.id) {
+            continue;
+        }
+
+        let Some(track) = tracks.get(&map.cloud_track_id) else {
+            continue;
+        };
+
+        let local_path = folder.local_path_of(&map.relative_path);
+        let local_track = local_tracks.get(&map_path.key()).map(|(_, t)| t);
+        let local_exists = local_path.exists();
+
+        let mut item = match (local_exists, cloud_file) {
+            (_, Some(cloud_file)) if RelativePath::new(&cloud_file.relative_path) != map_path => {
+                let mut item = plan_item(
+                    SyncPlanAction::Move,
+                    &cloud_file.relative_path,
+                    0,
+                    "File was moved in the cloud",
+                );
+                item.previous_relative_path = Some(map.relative_path.clone());
+                item
+            }
+            (false, _) if moved_to.is_some() => {
+                let mut item = plan_item(
+                    SyncPlanAction::Conflict,
+                    moved_to.map(RelativePath::as_str).unwrap_or_default(),
+                    0,
+                    "File was moved locally, but not in the cloud",
+                );
+                item.previous_relative_path = Some(map.relative_path.clone());
+                item
+            }
+            (true, Some(cloud_file)) => {
+                let mut local_changed = get_modified_at(&local_path).is_some_and(|m| m > track.updated_at);
+                let cloud_changed = cloud_file.modified_at > track.updated_at;
+
+                // Encrypted tracks are compared by content
+                if local_changed {
+                    if let Some(hash) = file_hashes.get(&map.id) {
+                        local_changed = hash_file(&local_path).ok().as_ref() != Some(hash);
+                    }
+                }
+
+                match (local_changed, cloud_changed) {
+                    (true, true) => plan_item(
+                        SyncPlanAction::Conflict,
+                        &map.relative_path,
+                        0,
+                        "Changed both locally and in the cloud",
+                    ),
+                    (true, false) => plan_item(
+                        SyncPlanAction::Upload,
+                        &map.relative_path,
+                        std::fs::metadata(&local_path)?.len(),
+                        "Changed locally",
+                    ),
+                    (false, true) => plan_item(
+                        SyncPlanAction::Download,
+                        &map.relative_path,
+                        cloud_file.size as u64,
+                        "Changed in the cloud",
+                    ),
+                    (false, false) => {
+                        let local_tags = local_track.map(|t| CloudTrackTag::from_track(t.clone()));
+                        if local_tags.is_some() && local_tags != track.tags {
+                            plan_item(
+                                SyncPlanAction::MetadataUpdate,
+                                &map.relative_path,
+                                0,
+                                "Local tags differ from the cloud ones",
+                            )
+                        } else {
+                            continue;
+                        }
+                    }
+                }
+            }
+            (true, None) if map.cloud_file_id.is_some() => plan_item(
+                SyncPlanAction::Conflict,
+                &map.relative_path,
+                0,
+                "Deleted from the cloud, but still present locally",
+            ),
+            (true, None) => plan_item(
+                SyncPlanAction::Upload,
+                &map.relative_path,
+                std::fs::metadata(&local_path)?.len(),
+                "Missing from the cloud",
+            ),
+            (false, Some(cloud_file)) => plan_item(
+                SyncPlanAction::Download,
+                &cloud_file.relative_path,
+                cloud_file.size as u64,
+                "Missing locally",
+            ),
+            (false, None) => plan_item(
+                SyncPlanAction::Delete,
+                &map.relative_path,
+                0,
+                "Missing from both locations",
+            ),
+        };
+
+        item.cloud_track_id = Some(map.cloud_track_id.clone());
+        item.cloud_map_id = Some(map.id.clone());
+        item.cloud_file_id = cloud_file.map(|f| f.id.clone());
+        items.push(item);
+    }
+
+    // Local tracks that are not tracked yet
+    for (path_key, (relative_path, local_track)) in &local_tracks {
+        if !seen_paths.contains(path_key) && !cloud_by_path.contains_key(path_key) {
+            items.push(plan_item(
+                SyncPlanAction::Upload,
+                relative_path.as_str(),
+                local_track.size as u64,
+                "New local track",
+            ));
+        }
+    }
+
+    // Cloud files that are not tracked yet
+    for cloud_file in &cloud_files {
+        let path_key = RelativePath::new(&cloud_file.relative_path).key();
+        if seen_cloud_files.contains(cloud_file.id.as_str()) || seen_paths.contains(&path_key) {
+            continue;
+        }
+
+        let mut item = if local_tracks.contains_key(&path_key) {
+            plan_item(
+                SyncPlanAction::Conflict,
+                &cloud_file.relative_path,
+                0,
+                "Exists in both locations but was never synced, scan the folder first",
+            )
+        } else {
+            plan_item(
+                SyncPlanAction::Download,
+                &cloud_file.relative_path,
+                cloud_file.size as u64,
+                "New cloud track",
+            )
+        };
+        item.cloud_file_id = Some(cloud_file.id.clone());
+        items.push(item);
+    }
+
+    let sync_mode = folder.get_sync_mode();
+    items.retain(|item| is_allowed(sync_mode, &item.action));
+    items.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+    let count = |action: SyncPlanAction| items.iter().filter(|i| i.action == action).count();
+    let bytes = |action: SyncPlanAction| -> u64 {
+        items.iter().filter(|i| i.action == action).map(|i| i.size).sum()
+    };
+
+    let upload_bytes = bytes(SyncPlanAction::Upload);
+    let download_bytes = bytes(SyncPlanAction::Download);
+
+    Ok(SyncPlanDTO {
+        folder_id: folder.id.clone(),
+        cloud_folder_path: folder.cloud_folder_path.clone(),
+        local_folder_path: folder.local_folder_path.clone(),
+        upload_count: count(SyncPlanAction::Upload),
+        download_count: count(SyncPlanAction::Download),
+        delete_count: count(SyncPlanAction::Delete),
+        move_count: count(SyncPlanAction::Move),
+        conflict_count: count(SyncPlanAction::Conflict),
+        metadata_update_count: count(SyncPlanAction::MetadataUpdate),
+        upload_bytes,
+        download_bytes,
+        estimated_seconds: (upload_bytes + download_bytes).div_ceil(transfer_rate.max(1)),
+        created_at: Utc::now(),
+        items,
+    })
+}
+
+/// Get the map of a plan item, creating the cloud track for new files
+async fn get_or_create_map(
+    db: &mut DB,
+    folder: &CloudMusicFolder,
+    item: &SyncPlanItemDTO,
+) -> AnyResult<Option<CloudTrackMap>> {
+    if let Some(map_id) = &item.cloud_map_id {
+        let map = CloudTrackMap::select()
+            .where_("id = ?")
+            .bind(map_id)
+            .fetch_optional(&mut db.connection)
+            .await?;
+        return Ok(map);
+    }
+
+    let local_path = folder.local_path_of(&item.relative_path).to_string_lossy().to_string();
+    let local_track = Track::select()
+        .where_("path = ?")
+        .bind(&local_path)
+        .fetch_optional(&mut db.connection)
+        .await?;
+
+    let track = match local_track {
+        Some(local_track) => CloudTrack::from_track(local_track)?,
+        // New local tracks must be in the library to be uploaded
+        None if item.action == SyncPlanAction::Upload => return Ok(None),
+        None => CloudTrack {
+            id: Uuid::new_v4().to_string(),
+            file_name: RelativePath::new(&item.relative_path).file_name().to_string(),
+            size: item.size as u32,
+            updated_at: Utc::now(),
+            tags: None,
+        },
+    };
+    let track = track.insert(&mut db.connection).await?;
+
+    let map = CloudTrackMap::new(
+        track.id,
+        folder.id.clone(),
+        RelativePath::new(&item.relative_path),
+        item.cloud_file_id.clone(),
+    );
+
+    Ok(Some(map.insert(&mut db.connection).await?))
+}
+
+/**
+ * Check a plan item against the database before applying anything. Plans come
+ * from the front-end and may be stale: the map of an item and its cloud track
+ * must still exist in the folder of the plan, and its paths must stay inside
+ * the folder.
+ */
+async fn validate_plan_item(db: &mut DB, folder: &CloudMusicFolder, item: &SyncPlanItemDTO) -> AnyResult<()> {
+    let invalid = |reason: &str| SyncudioError::InvalidSyncPlan(format!("{} {}", item.relative_path, reason));
+
+    for path in std::iter::once(&item.relative_path).chain(&item.previous_relative_path) {
+        let relative_path = RelativePath::new(path);
+        if relative_path.is_empty() || relative_path.segments().any(|segment| segment == "..") {
+            return Err(invalid("is not a path inside the folder"));
+        }
+    }
+
+    let Some(map_id) = &item.cloud_map_id else {
+        return Ok(());
+    };
+
+    let map = CloudTrackMap::select()
+        .where_("id = ? AND cloud_music_folder_id = ?")
+        .bind(map_id)
+        .bind(&folder.id)
+        .fetch_optional(&mut db.connection)
+        .await?
+        .ok_or_else(|| invalid("is not mapped in the folder anymore"))?;
+
+    if item.cloud_track_id.as_ref().is_some_and(|id| *id != map.cloud_track_id) {
+        return Err(invalid("is mapped to another cloud track"));
+    }
+
+    let cloud_track = CloudTrack::select()
+        .where_("id = ?")
+        .bind(&map.cloud_track_id)
+        .fetch_optional(&mut db.connection)
+        .await?;
+    if cloud_track.is_none() {
+        return Err(invalid("has no cloud track anymore"));
+    }
+
+    Ok(())
+}
+
+/**
+ * Move the local file of a track moved in the cloud, so the mapping keeps
+ * pointing to it. The library follows the file, files already at the new path
+ * are left alone. Moved files are recorded, to be moved back if the plan is
+ * rolled back.
+ */
+async fn move_local_file(
+    db: &mut DB,
+    folder: &CloudMusicFolder,
+    from: &str,
+    to: &str,
+    moved_files: &mut Vec<(PathBuf, PathBuf)>,
+) -> AnyResult<()> {
+    let from = folder.local_path_of(from);
+    let to = folder.local_path_of(to);
+    if !from.exists() || to.exists() {
+        return Ok(());
+    }
+
+    let to_path = to.to_string_lossy().to_string();
+    ormlite::query("UPDATE tracks SET path = ?, path_key = ? WHERE path = ?")
+        .bind(&to_path)
+        .bind(local_path_key(&to_path))
+        .bind(from.to_string_lossy().to_string())
+        .execute(&mut db.connection)
+        .await?;
+
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(&from, &to)?;
+    moved_files.push((from, to));
+
+    Ok(())
+}
+
+/// Move back the local files moved by a plan that was rolled back
+fn undo_local_moves(moved_files: Vec<(PathBuf, PathBuf)>) {
+    for (from, to) in moved_files.into_iter().rev() {
+        if let Err(err) = std::fs::rename(&to, &from) {
+            warn!("Could not move {:?} back to {:?}: {}", to, from, err);
+        }
+    }
+}
+
+async fn apply_plan_items(
+    db: &mut DB,
+    folder: &CloudMusicFolder,
+    plan: &SyncPlanDTO,
+    moved_files: &mut Vec<(PathBuf, PathBuf)>,
+) -> AnyResult<SyncPlanApplyResultDTO> {
+    let mut result = SyncPlanApplyResultDTO::default();
+
+    for item in &plan.items {
+        validate_plan_item(db, folder, item).await?;
+    }
+
+    for item in &plan.items {
+        // Conflicts are left for the user to resolve, no map is created for them
+        let map = match item.action {
+            SyncPlanAction::Conflict => None,
+            _ => get_or_create_map(db, folder, item).await?,
+        };
+
+        match (&item.action, map) {
+            (SyncPlanAction::Conflict, _) => result.conflicts_skipped += 1,
+            (_, None) => result.items_skipped += 1,
+            (SyncPlanAction::Upload | SyncPlanAction::Download, Some(mut map)) => {
+                let active: Option<(String,)> = ormlite::query_as(
+                    "SELECT id FROM upload_queue WHERE cloud_map_id = ? AND status IN ('pending', 'in_progress')
+                     UNION
+                     SELECT id FROM download_queue WHERE cloud_map_id = ? AND status IN ('pending', 'in_progress')",
+                )
+                .bind(&map.id)
+                .bind(&map.id)
+                .fetch_optional(&mut db.connection)
+                .await?;
+
+                if active.is_some() {
+                    result.items_skipped += 1;
+                } else if item.action == SyncPlanAction::Upload {
+                    UploadQueueItem::new(map.id, folder.provider_type.clone(), 0)
+                        .insert(&mut db.connection)
+                        .await?;
+                    result.uploads_queued += 1;
+                } else {
+                    if map.cloud_file_id.is_none() || map.cloud_file_id != item.cloud_file_id {
+                        map.cloud_file_id = item.cloud_file_id.clone();
+                        map = map.update_all_fields(&mut db.connection).await?;
+                    }
+                    DownloadQueueItem::new(map.id, folder.provider_type.clone(), 0)
+                        .insert(&mut db.connection)
+                        .await?;
+                    result.downloads_queued += 1;
+                }
+            }
+            (SyncPlanAction::Move, Some(mut map)) => {
+                move_local_file(db, folder, &map.relative_path, &item.relative_path, moved_files).await?;
+                map.set_relative_path(RelativePath::new(&item.relative_path));
+                map.update_all_fields(&mut db.connection).await?;
+                result.moves_applied += 1;
+            }
+            (SyncPlanAction::Delete, Some(map)) => {
+                ormlite::query("DELETE FROM cloud_file_hashes WHERE cloud_map_id = ?")
+                    .bind(&map.id)
+                    .execute(&mut db.connection)
+                    .await?;
+                db.record_sync_history(SyncHistoryEntry {
+                    details: Some(item.reason.clone()),
+                    ..SyncHistoryEntry::new(SyncHistoryOperation::Delete, folder.provider_type.clone())
+                        .with_track(&map)
+                })
+                .await?;
+
+                let cloud_track_id = map.cloud_track_id.clone();
+                map.delete(&mut db.connection).await?;
+
+                // Remove the cloud track once it is not mapped anywhere
+                ormlite::query(
+                    "DELETE FROM cloud_tracks WHERE id = ?
+                     AND NOT EXISTS (SELECT 1 FROM cloud_maps WHERE cloud_track_id = ?)",
+                )
+                .bind(&cloud_track_id)
+                .bind(&cloud_track_id)
+                .execute(&mut db.connection)
+                .await?;
+                result.deletes_applied += 1;
+            }
+            (SyncPlanAction::MetadataUpdate, Some(map)) => {
+                let local_path = folder.local_path_of(&map.relative_path).to_string_lossy().to_string();
+                let local_track = Track::select()
+                    .where_("path = ?")
+                    .bind(&local_path)
+                    .fetch_optional(&mut db.connection)
+                    .await?;
+                let track = CloudTrack::select()
+                    .where_("id = ?")
+                    .bind(&map.cloud_track_id)
+                    .fetch_optional(&mut db.connection)
+                    .await?;
+
+                match (local_track, track) {
+                    (Some(local_track), Some(mut track)) => {
+                        track.tags = Some(CloudTrackTag::from_track(local_track));
+                        track.updated_at = Utc::now();
+                        track.update_all_fields(&mut db.connection).await?;
+                        result.metadata_updated += 1;
+                    }
+                    _ => result.items_skipped += 1,
+                }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Preview what syncing a cloud folder would do
+#[tauri::command]
+pub async fn compute_sync_plan(
+    folder_id: String,
+    transfer_rate: Option<u64>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<SyncPlanDTO> {
+    info!("Computing sync plan for folder {}", folder_id);
+    let account_id = {
+        let mut db = db_state.get_lock().await;
+        CloudMusicFolder::select()
+            .where_("id = ?")
+            .bind(&folder_id)
+            .fetch_one(&mut db.connection)
+            .await?
+            .account_id
+    };
+    let provider = cloud_state.get_provider(account_id.as_deref()).await?;
+
+    let plan = compute_folder_sync_plan(
+        &db_state,
+        &*provider,
+        &folder_id,
+        transfer_rate.unwrap_or(DEFAULT_TRANSFER_RATE),
+    )
+    .await?;
+
+    info!(
+        "Sync plan for {}: {} uploads, {} downloads, {} deletes, {} moves, {} conflicts, {} metadata updates",
+        plan.cloud_folder_path,
+        plan.upload_count,
+        plan.download_count,
+        plan.delete_count,
+        plan.move_count,
+        plan.conflict_count,
+        plan.metadata_update_count
+    );
+
+    Ok(plan)
+}
+
+/**
+ * Apply a sync plan: queue its transfers and update the database for the
+ * other actions. Everything is applied in a single transaction, conflicts are
+ * left untouched. Local files moved by a plan that fails are moved back.
+ */
+pub(crate) async fn apply_folder_sync_plan(db_state: &DBState, plan: &SyncPlanDTO) -> AnyResult<SyncPlanApplyResultDTO> {
+    let mut db = db_state.get_lock().await;
+
+    let folder = CloudMusicFolder::select()
+        .where_("id = ?")
+        .bind(&plan.folder_id)
+        .fetch_one(&mut db.connection)
+        .await?;
+
+    ormlite::query("BEGIN").execute(&mut db.connection).await?;
+
+    let mut moved_files = Vec::new();
+    let result = match apply_plan_items(&mut db, &folder, plan, &mut moved_files).await {
+        Ok(result) => ormlite::query("COMMIT")
+            .execute(&mut db.connection)
+            .await
+            .map(|_| result)
+            .map_err(SyncudioError::from),
+        Err(e) => Err(e),
+    };
+
+    if result.is_err() {
+        // A failed COMMIT leaves the transaction open
+        if let Err(err) = ormlite::query("ROLLBACK").execute(&mut db.connection).await {
+            warn!("Could not roll back the sync plan: {}", err);
+        }
+        undo_local_moves(moved_files);
+    }
+
+    result
+}
+
+/// Apply a sync plan, plans whose uploads do not fit in the storage quota are
+/// refused unless forced
+#[tauri::command]
+pub async fn apply_sync_plan(
+    plan: SyncPlanDTO,
+    force: Option<bool>,
+    db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
+) -> AnyResult<SyncPlanApplyResultDTO> {
+    info!("Applying sync plan for folder {} ({} items)", plan.folder_id, plan.items.len());
+    if plan.upload_bytes > 0 {
+        let upload_bytes = HashMap::from([(plan.folder_id.clone(), plan.upload_bytes)]);
+        check_upload_quota(&db_state, &cloud_state, upload_bytes, force.unwrap_or(false)).await?;
+    }
+
+    apply_folder_sync_plan(&db_state, &plan).await
+}
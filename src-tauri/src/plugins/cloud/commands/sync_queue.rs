@@ -1,36 +1,44 @@
-use chrono::Utc;
-use log::info;
+use chrono::{Duration, Utc};
+use log::{info, warn};
 use ormlite::Model;
 use serde::Deserialize;
 use serde::Serialize;
 use tauri::Emitter;
 use tauri::Runtime;
 use ts_rs::TS;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::{Instant, UNIX_EPOCH};
 use tauri::{Manager, State};
 use uuid::Uuid;
 
 
+use crate::libs::database::core::DB;
 use crate::libs::error::SyncudioError;
 use crate::libs::track::{self, Track};
+use crate::libs::utils::TempFile;
 use crate::plugins::cloud::CloudProvider;
 use crate::plugins::cloud::CloudProviderType;
 use crate::plugins::cloud::CloudState;
+use crate::plugins::cloud::hash_file;
 use crate::plugins::cloud::models::*;
 use crate::plugins::cloud::models::dto::*;
 use crate::plugins::db::DBState;
 use crate::libs::error::AnyResult;
 
+use super::encryption::get_folder_key;
+use super::quota::check_upload_quota;
+
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../src/generated/typings/index.ts")]
-struct TrackDownloadedPayload {
-    track_id: String,
-    location_type: String,
-    local_track_id: String,
-    cloud_track_id: String,
-    sync_folder_id: String,
-    relative_path: String,
+pub struct TrackDownloadedPayload {
+    pub track_id: String,
+    pub location_type: String,
+    pub local_track_id: String,
+    pub cloud_track_id: String,
+    pub sync_folder_id: String,
+    pub relative_path: String,
 }
 
 #[derive(Debug, ormlite::FromRow)]
@@ -39,6 +47,96 @@ struct QueueStatsRow {
     count: i32,
 }
 
+/**
+ * Queue the upload of local tracks that changed in cloud folders. Tracks that
+ * are not mapped yet get a cloud track and a mapping, like a folder scan does.
+ * Cloud folders that only download are left alone.
+ * Returns the number of queued uploads.
+ */
+pub(crate) async fn queue_local_changes(db: &mut DB, tracks: &[Track]) -> AnyResult<usize> {
+    let folders = db.get_cloud_music_folders().await?;
+    let mut active_map_ids = HashSet::new();
+    for table in ["upload_queue", "download_queue"] {
+        let query = format!(
+            "SELECT cloud_map_id FROM {} WHERE status = 'pending' OR status = 'in_progress'",
+            table
+        );
+        let map_ids: Vec<(String,)> = ormlite::query_as(&query).fetch_all(&mut db.connection).await?;
+        active_map_ids.extend(map_ids.into_iter().map(|(map_id,)| map_id));
+    }
+    let mut queued = 0;
+
+    for local_track in tracks {
+        // A local folder may be synced with several cloud folders
+        let targets = folders
+            .iter()
+            .filter(|f| f.get_sync_mode().can_upload())
+            .filter_map(|f| f.relative_path_of(&local_track.path).map(|rel| (f, rel)));
+
+        for (folder, relative_path) in targets {
+            let track_map = CloudTrackMap::select()
+                .where_("cloud_music_folder_id = ? AND path_key = ?")
+                .bind(&folder.id)
+                .bind(relative_path.key())
+                .fetch_optional(&mut db.connection)
+                .await?;
+
+            let track_map = match track_map {
+                Some(track_map) => {
+                    let track = CloudTrack::select()
+                        .where_("id = ?")
+                        .bind(&track_map.cloud_track_id)
+                        .fetch_one(&mut db.connection)
+                        .await?;
+
+                    // Files written by a download are already up to date in the cloud
+                    let modified = match std::fs::metadata(&local_track.path)
+                        .and_then(|metadata| metadata.modified())
+                        .map(|modified| modified.duration_since(UNIX_EPOCH).unwrap_or_default())
+                    {
+                        Ok(modified) => modified,
+                        Err(err) => {
+                            warn!("Could not read the modification time of {}: {}", local_track.path, err);
+                            continue;
+                        }
+                    };
+                    if track_map.cloud_file_id.is_some() && modified.as_secs() as i64 <= track.updated_at.timestamp() {
+                        continue;
+                    }
+
+                    track_map
+                }
+                None => {
+                    let track = CloudTrack {
+                        id: Uuid::new_v4().to_string(),
+                        file_name: relative_path.file_name().to_string(),
+                        size: local_track.size,
+                        updated_at: Utc::now(),
+                        tags: Some(CloudTrackTag::from_track(local_track.clone())),
+                    };
+                    let track = track.insert(&mut db.connection).await?;
+
+                    CloudTrackMap::new(track.id, folder.id.clone(), relative_path, None)
+                        .insert(&mut db.connection)
+                        .await?
+                }
+            };
+
+            // Only adds the map if it has no active operation
+            if !active_map_ids.insert(track_map.id.clone()) {
+                continue;
+            }
+
+            UploadQueueItem::new(track_map.id, folder.provider_type.clone(), 0)
+                .insert(&mut db.connection)
+                .await?;
+            queued += 1;
+        }
+    }
+
+    Ok(queued)
+}
+
 /// Get active queue items
 #[tauri::command]
 pub async fn get_queue_items(
@@ -212,12 +310,14 @@ pub async fn get_queue_stats(
     let mut in_progress_count = 0;
     let mut completed_count = 0;
     let mut failed_count = 0;
+    let mut cancelled_count = 0;
 
     for stat in stats {
         match stat.status.as_str() {
             "pending" => pending_count = stat.count,
             "in_progress" => in_progress_count = stat.count,
             "completed" => completed_count = stat.count,
+            "cancelled" => cancelled_count = stat.count,
             _ => failed_count += stat.count,
         }
     }
@@ -227,16 +327,29 @@ pub async fn get_queue_stats(
         in_progress_count,
         completed_count,
         failed_count,
+        cancelled_count,
     })
 }
 
-/// Add tracks to the upload queue
+/// Add tracks to the upload queue, refused when they do not fit in the storage quota unless forced
 #[tauri::command]
 pub async fn add_to_upload_queue(
     track_ids: Vec<String>,
     priority: Option<i32>,
+    force: Option<bool>,
     db_state: State<'_, DBState>,
+    cloud_state: State<'_, CloudState>,
 ) -> AnyResult<()> {
+    let upload_bytes = {
+        let mut db = db_state.get_lock().await;
+        db.get_tracks_size_by_folder(&track_ids).await?
+    };
+    check_upload_quota(&db_state, &cloud_state, upload_bytes, force.unwrap_or(false)).await?;
+
+    queue_uploads(&db_state, track_ids, priority).await
+}
+
+pub(crate) async fn queue_uploads(db_state: &DBState, track_ids: Vec<String>, priority: Option<i32>) -> AnyResult<()> {
     let mut db = db_state.get_lock().await;
     let now = Utc::now();
 
@@ -261,11 +374,13 @@ pub async fn add_to_upload_queue(
             .fetch_one(&mut db.connection)
             .await?;
 
+        if !folder.get_sync_mode().can_upload() {
+            info!("Skipping track {} as its cloud folder {} is download only", track_id, folder.id);
+            continue;
+        }
+
         // Check if file exists locally
-        let local_path = Path::new(&folder.local_folder_path)
-            .join(&track_map.relative_path)
-            .to_string_lossy()
-            .to_string();
+        let local_path = folder.local_path_of(&track_map.relative_path).to_string_lossy().to_string();
 
         if !Path::new(&local_path).exists() {
             return Err(SyncudioError::FileNotFound(local_path));
@@ -297,6 +412,10 @@ pub async fn add_to_download_queue(
     priority: Option<i32>,
     db_state: State<'_, DBState>,
 ) -> AnyResult<()> {
+    queue_downloads(&db_state, track_ids, priority).await
+}
+
+pub(crate) async fn queue_downloads(db_state: &DBState, track_ids: Vec<String>, priority: Option<i32>) -> AnyResult<()> {
     let mut db = db_state.get_lock().await;
     let now = Utc::now();
 
@@ -342,6 +461,11 @@ pub async fn add_to_download_queue(
             .fetch_one(&mut db.connection)
             .await?;
 
+        if !folder.get_sync_mode().can_download() {
+            info!("Skipping track {} as its cloud folder {} is upload only", track_id, folder.id);
+            continue;
+        }
+
         // Create download queue item
         let download_item = DownloadQueueItem {
             id: Uuid::new_v4().to_string(),
@@ -353,6 +477,7 @@ pub async fn add_to_download_queue(
             created_at: now,
             updated_at: now,
             attempts: 0,
+            revision_id: None,
         };
 
         download_item.insert(&mut db.connection).await?;
@@ -457,9 +582,15 @@ pub async fn get_next_upload_item(
 ) -> AnyResult<Option<UploadQueueItem>> {
     let mut db = db_state.get_lock().await;
 
-    let item = UploadQueueItem::query("SELECT * FROM upload_queue WHERE status = 'pending' ORDER BY created_at ASC LIMIT 1")
-        .fetch_optional(&mut db.connection)
-        .await?;
+    if db.is_sync_paused(&SyncOperationType::Upload).await? {
+        return Ok(None);
+    }
+
+    let item = UploadQueueItem::query(
+        "SELECT * FROM upload_queue WHERE status = 'pending' ORDER BY priority DESC, created_at ASC LIMIT 1",
+    )
+    .fetch_optional(&mut db.connection)
+    .await?;
 
     if let Some(ref item) = item {
         info!("Found next upload item: {}", item.id);
@@ -473,9 +604,15 @@ pub async fn get_next_download_item(
 ) -> AnyResult<Option<DownloadQueueItem>> {
     let mut db = db_state.get_lock().await;
 
-    let item = DownloadQueueItem::query("SELECT * FROM download_queue WHERE status = 'pending' ORDER BY created_at ASC LIMIT 1")
-        .fetch_optional(&mut db.connection)
-        .await?;
+    if db.is_sync_paused(&SyncOperationType::Download).await? {
+        return Ok(None);
+    }
+
+    let item = DownloadQueueItem::query(
+        "SELECT * FROM download_queue WHERE status = 'pending' ORDER BY priority DESC, created_at ASC LIMIT 1",
+    )
+    .fetch_optional(&mut db.connection)
+    .await?;
 
     if let Some(ref item) = item {
         info!("Found next download item: {}", item.id);
@@ -484,20 +621,57 @@ pub async fn get_next_download_item(
     Ok(item)
 }
 
+/// Get the folder of the track transferred by a queue item
+async fn get_map_folder(db: &mut DB, cloud_map_id: &str) -> AnyResult<CloudMusicFolder> {
+    let folder = CloudMusicFolder::query(
+        "SELECT cmf.* FROM cloud_music_folders cmf
+         INNER JOIN cloud_maps ctm ON ctm.cloud_music_folder_id = cmf.id
+         WHERE ctm.id = ?",
+    )
+    .bind(cloud_map_id)
+    .fetch_one(&mut db.connection)
+    .await?;
+    Ok(folder)
+}
+
 #[tauri::command]
 pub async fn start_upload(
     item_id: String,
     db_state: State<'_, DBState>,
     cloud_state: State<'_, CloudState>,
 ) -> AnyResult<()> {
+    let folder = {
+        let mut db = db_state.get_lock().await;
+        let item = UploadQueueItem::select()
+            .where_("id = ?")
+            .bind(&item_id)
+            .fetch_one(&mut db.connection)
+            .await?;
+        get_map_folder(&mut db, &item.cloud_map_id).await?
+    };
+
+    // Get cloud provider - No database lock needed
+    let provider = match folder.provider_type.as_str() {
+        "dropbox" => cloud_state.get_provider(folder.account_id.as_deref()).await?,
+        _ => return Err(SyncudioError::UnsupportedProvider(folder.provider_type)),
+    };
+
+    upload_item(&db_state, &*provider, &item_id).await
+}
+
+/// Upload the file of a queue item and complete the item
+pub(crate) async fn upload_item<P>(db_state: &DBState, provider: &P, item_id: &str) -> AnyResult<()>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
     // First database operation block - get required info
-    let (track_map, track, folder, mut item) = {
+    let (track_map, track, folder, key, mut item) = {
         let mut db = db_state.get_lock().await;
         
         // Get queue item
         let item = UploadQueueItem::select()
             .where_("id = ?")
-            .bind(&item_id)
+            .bind(item_id)
             .fetch_one(&mut db.connection)
             .await?;
 
@@ -521,14 +695,13 @@ pub async fn start_upload(
             .fetch_one(&mut db.connection)
             .await?;
 
-        (track_map, track, folder, item)
+        let key = get_folder_key(&mut db, &folder).await?;
+
+        (track_map, track, folder, key, item)
     };
 
     // Get local file path - No database lock needed
-    let local_path = Path::new(&folder.local_folder_path)
-        .join(&track_map.relative_path)
-        .to_string_lossy()
-        .to_string();
+    let local_path = folder.local_path_of(&track_map.relative_path).to_string_lossy().to_string();
 
     if !Path::new(&local_path).exists() {
         return Err(SyncudioError::FileNotFound(local_path));
@@ -542,29 +715,57 @@ pub async fn start_upload(
         item = item.update_all_fields(&mut db.connection).await?;
     }
 
-    // Get cloud provider - No database lock needed
-    let provider = match folder.provider_type.as_str() {
-        "dropbox" => &cloud_state.dropbox,
-        _ => return Err(SyncudioError::UnsupportedProvider(folder.provider_type)),
-    };
+    let started_at = Instant::now();
 
     // Upload file - No database lock needed
-    let cloud_file = provider
-        .upload_file(
-            &PathBuf::from(&local_path),
-            &track_map.relative_path,
-            Some(&folder.cloud_folder_path),
-        )
-        .await?;
+    let (cloud_file, plaintext_hash) = match &key {
+        Some(key) => {
+            // Encrypted folders get an encrypted copy, under an encrypted name
+            let plaintext_hash = hash_file(Path::new(&local_path))?;
+            let encrypted_file = TempFile::new(&format!("upload_{}", item_id));
+            key.encrypt_file(Path::new(&local_path), encrypted_file.path())?;
+
+            let uploaded = provider
+                .upload_file(
+                    encrypted_file.path(),
+                    &key.encrypt_path(&track_map.relative_path)?,
+                    Some(&folder.cloud_folder_path),
+                )
+                .await?;
+
+            (uploaded, Some(plaintext_hash))
+        }
+        None => {
+            let cloud_file = provider
+                .upload_file(
+                    &PathBuf::from(&local_path),
+                    &track_map.relative_path,
+                    Some(&folder.cloud_folder_path),
+                )
+                .await?;
+            (cloud_file, None)
+        }
+    };
 
     // Final database operation block - update track map and complete the operation
     {
         let mut db = db_state.get_lock().await;
 
+        db.record_sync_history(SyncHistoryEntry {
+            bytes: track.size as i64,
+            duration_ms: started_at.elapsed().as_millis() as i64,
+            ..SyncHistoryEntry::new(SyncHistoryOperation::Upload, folder.provider_type.clone()).with_track(&track_map)
+        })
+        .await?;
+
         // Update track map with cloud file ID
         let mut updated_map = track_map;
         updated_map.cloud_file_id = Some(cloud_file.id.clone());
-        updated_map.update_all_fields(&mut db.connection).await?;
+        let updated_map = updated_map.update_all_fields(&mut db.connection).await?;
+
+        if let Some(plaintext_hash) = plaintext_hash {
+            db.set_cloud_file_hash(&updated_map.id, &plaintext_hash).await?;
+        }
 
         // Update track metadata
         let mut updated_track = track;
@@ -587,14 +788,68 @@ pub async fn start_download<R: Runtime>(
     db_state: State<'_, DBState>,
     cloud_state: State<'_, CloudState>,
 ) -> AnyResult<()> {
+    let folder = {
+        let mut db = db_state.get_lock().await;
+        let item = DownloadQueueItem::select()
+            .where_("id = ?")
+            .bind(&item_id)
+            .fetch_one(&mut db.connection)
+            .await?;
+        get_map_folder(&mut db, &item.cloud_map_id).await?
+    };
+
+    // Get cloud provider
+    let provider = match folder.provider_type.as_str() {
+        "dropbox" => cloud_state.get_provider(folder.account_id.as_deref()).await?,
+        _ => return Err(SyncudioError::UnsupportedProvider(folder.provider_type)),
+    };
+
+    let payload = download_item(&db_state, &*provider, &item_id).await?;
+
+    // Emit track downloaded event - No database lock needed
+    app.emit("track-downloaded", payload)?;
+
+    Ok(())
+}
+
+/// Download the cloud file of a track, or one of its previous revisions
+async fn fetch_file<P>(
+    provider: &P,
+    track_map: &CloudTrackMap,
+    revision_id: Option<&str>,
+    local_path: &PathBuf,
+) -> AnyResult<()>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
+    let file_id = track_map
+        .cloud_file_id
+        .as_deref()
+        .ok_or(SyncudioError::FileNotFound(track_map.relative_path.clone()))?;
+
+    match revision_id {
+        Some(revision_id) => provider.download_revision(file_id, revision_id, local_path).await,
+        None => provider.download_file(file_id, local_path).await,
+    }
+}
+
+/// Download the file of a queue item, add it to the library and complete the item
+pub(crate) async fn download_item<P>(
+    db_state: &DBState,
+    provider: &P,
+    item_id: &str,
+) -> AnyResult<TrackDownloadedPayload>
+where
+    P: CloudProvider + Sync + ?Sized,
+{
     // First database operation block - get required info
-    let (track_map, track, folder, mut item) = {
+    let (track_map, track, folder, key, mut item) = {
         let mut db = db_state.get_lock().await;
         
         // Get queue item
         let item = DownloadQueueItem::select()
             .where_("id = ?")
-            .bind(&item_id)
+            .bind(item_id)
             .fetch_one(&mut db.connection)
             .await?;
 
@@ -618,14 +873,13 @@ pub async fn start_download<R: Runtime>(
             .fetch_one(&mut db.connection)
             .await?;
 
-        (track_map, track, folder, item)
+        let key = get_folder_key(&mut db, &folder).await?;
+
+        (track_map, track, folder, key, item)
     };
 
     // Get local file path
-    let local_path = Path::new(&folder.local_folder_path)
-        .join(&track_map.relative_path)
-        .to_string_lossy()
-        .to_string();
+    let local_path = folder.local_path_of(&track_map.relative_path).to_string_lossy().to_string();
 
     // Create parent directories if they don't exist
     if let Some(parent) = Path::new(&local_path).parent() {
@@ -640,16 +894,25 @@ pub async fn start_download<R: Runtime>(
         item = item.update_all_fields(&mut db.connection).await?;
     }
 
-    // Get cloud provider
-    let provider = match folder.provider_type.as_str() {
-        "dropbox" => &cloud_state.dropbox,
-        _ => return Err(SyncudioError::UnsupportedProvider(folder.provider_type)),
-    };
+    let started_at = Instant::now();
 
     // Download file - No database lock needed here
-    provider
-        .download_file(&track_map.cloud_file_id.clone().unwrap(), &PathBuf::from(&local_path))
-        .await?;
+    let plaintext_hash = match &key {
+        Some(key) => {
+            let encrypted_file = TempFile::new(&format!("download_{}", item_id));
+            fetch_file(provider, &track_map, item.revision_id.as_deref(), encrypted_file.path()).await?;
+            key.decrypt_file(encrypted_file.path(), Path::new(&local_path))?;
+
+            Some(hash_file(Path::new(&local_path))?)
+        }
+        None => {
+            fetch_file(provider, &track_map, item.revision_id.as_deref(), &PathBuf::from(&local_path)).await?;
+            None
+        }
+    };
+
+    // A previous revision does not match the cloud file anymore
+    let plaintext_hash = plaintext_hash.filter(|_| item.revision_id.is_none());
 
     // Parse local track metadata - No database lock needed
     let mut local_track = track::get_track_from_file(&PathBuf::from(&local_path))
@@ -681,6 +944,17 @@ pub async fn start_download<R: Runtime>(
         track.tags = Some(CloudTrackTag::from_track(local_track.clone()));
         let track = track.update_all_fields(&mut db.connection).await?;
 
+        if let Some(plaintext_hash) = plaintext_hash {
+            db.set_cloud_file_hash(&track_map.id, &plaintext_hash).await?;
+        }
+
+        db.record_sync_history(SyncHistoryEntry {
+            bytes: track.size as i64,
+            duration_ms: started_at.elapsed().as_millis() as i64,
+            ..SyncHistoryEntry::new(SyncHistoryOperation::Download, folder.provider_type.clone()).with_track(&track_map)
+        })
+        .await?;
+
         // Mark item as completed
         item.status = "completed".to_string();
         item.updated_at = Utc::now();
@@ -689,20 +963,14 @@ pub async fn start_download<R: Runtime>(
         (local_track, track)
     };
 
-    // Emit track downloaded event - No database lock needed
-    app.emit(
-        "track-downloaded",
-        TrackDownloadedPayload {
-            track_id: track.id.clone(),
-            location_type: "both".to_string(),
-            local_track_id: local_track.id.clone(),
-            cloud_track_id: track.id.clone(),
-            sync_folder_id: folder.id.clone(),
-            relative_path: track_map.relative_path,
-        },
-    )?;
-
-    Ok(())
+    Ok(TrackDownloadedPayload {
+        track_id: track.id.clone(),
+        location_type: "both".to_string(),
+        local_track_id: local_track.id.clone(),
+        cloud_track_id: track.id.clone(),
+        sync_folder_id: folder.id.clone(),
+        relative_path: track_map.relative_path,
+    })
 }
 
 #[tauri::command]
@@ -720,6 +988,19 @@ pub async fn fail_upload(
         .fetch_one(&mut db.connection)
         .await?;
 
+    let track_map = CloudTrackMap::select()
+        .where_("id = ?")
+        .bind(&item.cloud_map_id)
+        .fetch_optional(&mut db.connection)
+        .await?;
+
+    let mut entry = SyncHistoryEntry::new(SyncHistoryOperation::Upload, item.provider_type.clone())
+        .fail(error.clone());
+    if let Some(track_map) = &track_map {
+        entry = entry.with_track(track_map);
+    }
+    db.record_sync_history(entry).await?;
+
     item.fail(error);
     item.update_all_fields(&mut db.connection).await?;
     info!("Upload item {} marked as failed", item_id);
@@ -742,9 +1023,113 @@ pub async fn fail_download(
         .fetch_one(&mut db.connection)
         .await?;
 
+    let track_map = CloudTrackMap::select()
+        .where_("id = ?")
+        .bind(&item.cloud_map_id)
+        .fetch_optional(&mut db.connection)
+        .await?;
+
+    let mut entry = SyncHistoryEntry::new(SyncHistoryOperation::Download, item.provider_type.clone())
+        .fail(error.clone());
+    if let Some(track_map) = &track_map {
+        entry = entry.with_track(track_map);
+    }
+    db.record_sync_history(entry).await?;
+
     item.fail(error);
     item.update_all_fields(&mut db.connection).await?;
     info!("Download item {} marked as failed", item_id);
 
     Ok(())
 }
+
+/// Cancel queue items, items being transferred cannot be cancelled
+#[tauri::command]
+pub async fn cancel_queue_items(
+    item_ids: Vec<String>,
+    db_state: State<'_, DBState>,
+) -> AnyResult<u64> {
+    let mut db = db_state.get_lock().await;
+    let cancelled = db.cancel_queue_items(&item_ids).await?;
+    info!("Cancelled {} of {} queue items", cancelled, item_ids.len());
+
+    Ok(cancelled)
+}
+
+/// Cancel all queue items of a cloud folder
+#[tauri::command]
+pub async fn cancel_folder_queue(
+    folder_id: String,
+    db_state: State<'_, DBState>,
+) -> AnyResult<u64> {
+    let mut db = db_state.get_lock().await;
+    let cancelled = db.cancel_folder_queue(&folder_id).await?;
+    info!("Cancelled {} queue items of folder {}", cancelled, folder_id);
+
+    Ok(cancelled)
+}
+
+/// Pause or resume the queue, for one direction or both
+#[tauri::command]
+pub async fn set_sync_paused(
+    operation: Option<SyncOperationType>,
+    paused: bool,
+    db_state: State<'_, DBState>,
+) -> AnyResult<QueuePauseStateDTO> {
+    let mut db = db_state.get_lock().await;
+
+    let operations = match operation {
+        Some(operation) => vec![operation],
+        None => vec![SyncOperationType::Upload, SyncOperationType::Download],
+    };
+    for operation in operations {
+        info!("Setting {} queue paused: {}", operation.as_str(), paused);
+        db.set_sync_paused(&operation, paused).await?;
+    }
+
+    Ok(QueuePauseStateDTO {
+        upload_paused: db.is_sync_paused(&SyncOperationType::Upload).await?,
+        download_paused: db.is_sync_paused(&SyncOperationType::Download).await?,
+    })
+}
+
+#[tauri::command]
+pub async fn get_sync_paused(
+    db_state: State<'_, DBState>,
+) -> AnyResult<QueuePauseStateDTO> {
+    let mut db = db_state.get_lock().await;
+
+    Ok(QueuePauseStateDTO {
+        upload_paused: db.is_sync_paused(&SyncOperationType::Upload).await?,
+        download_paused: db.is_sync_paused(&SyncOperationType::Download).await?,
+    })
+}
+
+/// Change the priority of pending queue items, higher priorities are processed first
+#[tauri::command]
+pub async fn set_queue_items_priority(
+    item_ids: Vec<String>,
+    priority: i32,
+    db_state: State<'_, DBState>,
+) -> AnyResult<u64> {
+    let mut db = db_state.get_lock().await;
+    db.set_queue_items_priority(&item_ids, priority).await
+}
+
+/// Delete completed and cancelled queue items older than a number of days
+#[tauri::command]
+pub async fn purge_queue(
+    older_than_days: u32,
+    db_state: State<'_, DBState>,
+) -> AnyResult<QueuePurgeResultDTO> {
+    let mut db = db_state.get_lock().await;
+    let result = db
+        .purge_queue(Utc::now() - Duration::days(i64::from(older_than_days)))
+        .await?;
+    info!(
+        "Purged {} upload and {} download queue items older than {} days",
+        result.uploads_purged, result.downloads_purged, older_than_days
+    );
+
+    Ok(result)
+}
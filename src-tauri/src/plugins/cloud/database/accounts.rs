@@ -0,0 +1,58 @@
+use ormlite::Model;
+
+use crate::libs::database::core::DB;
+use crate::libs::error::AnyResult;
+use crate::plugins::cloud::models::*;
+
+impl DB {
+    pub async fn get_cloud_accounts(&mut self) -> AnyResult<Vec<CloudAccount>> {
+        let accounts = CloudAccount::query("SELECT * FROM cloud_accounts ORDER BY created_at ASC")
+            .fetch_all(&mut self.connection)
+            .await?;
+        Ok(accounts)
+    }
+
+    pub async fn get_cloud_account(&mut self, id: &str) -> AnyResult<Option<CloudAccount>> {
+        let account = CloudAccount::select()
+            .where_bind("id = ?", id)
+            .fetch_optional(&mut self.connection)
+            .await?;
+        Ok(account)
+    }
+
+    pub async fn get_cloud_account_by_provider_id(
+        &mut self,
+        provider_type: &str,
+        provider_account_id: &str,
+    ) -> AnyResult<Option<CloudAccount>> {
+        let account = CloudAccount::select()
+            .where_("provider_type = ? AND provider_account_id = ?")
+            .bind(provider_type)
+            .bind(provider_account_id)
+            .fetch_optional(&mut self.connection)
+            .await?;
+        Ok(account)
+    }
+
+    pub async fn save_cloud_account(&mut self, account: CloudAccount) -> AnyResult<CloudAccount> {
+        let saved = account.insert(&mut self.connection).await?;
+        Ok(saved)
+    }
+
+    pub async fn update_cloud_account(&mut self, account: CloudAccount) -> AnyResult<CloudAccount> {
+        let updated = account.update_all_fields(&mut self.connection).await?;
+        Ok(updated)
+    }
+
+    /// Attach the folders of a provider that have no account yet to an account
+    pub async fn assign_cloud_folders_to_account(&mut self, provider_type: &str, account_id: &str) -> AnyResult<u64> {
+        let result = ormlite::query(
+            "UPDATE cloud_music_folders SET account_id = ? WHERE account_id IS NULL AND provider_type = ?",
+        )
+        .bind(account_id)
+        .bind(provider_type)
+        .execute(&mut self.connection)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::Utc;
+use ormlite::Model;
+
+use crate::libs::database::core::DB;
+use crate::libs::error::AnyResult;
+use crate::plugins::cloud::models::*;
+
+impl DB {
+    /// Get the encryption settings of a cloud folder, None if it is not encrypted
+    pub async fn get_cloud_folder_encryption(
+        &mut self,
+        cloud_music_folder_id: &str,
+    ) -> AnyResult<Option<CloudFolderEncryption>> {
+        let encryption = CloudFolderEncryption::select()
+            .where_bind("cloud_music_folder_id = ?", cloud_music_folder_id)
+            .fetch_optional(&mut self.connection)
+            .await?;
+        Ok(encryption)
+    }
+
+    pub async fn save_cloud_folder_encryption(
+        &mut self,
+        encryption: CloudFolderEncryption,
+    ) -> AnyResult<CloudFolderEncryption> {
+        let saved = encryption.insert(&mut self.connection).await?;
+        Ok(saved)
+    }
+
+    /// Get the plaintext hash of a track at its last transfer
+    pub async fn get_cloud_file_hash(&mut self, cloud_map_id: &str) -> AnyResult<Option<String>> {
+        let hash = CloudFileHash::select()
+            .where_bind("cloud_map_id = ?", cloud_map_id)
+            .fetch_optional(&mut self.connection)
+            .await?;
+        Ok(hash.map(|h| h.plaintext_hash))
+    }
+
+    /// Get the plaintext hash of a track of a folder at its last transfer
+    pub async fn get_cloud_file_hash_by_track(
+        &mut self,
+        cloud_track_id: &str,
+        cloud_music_folder_id: &str,
+    ) -> AnyResult<Option<String>> {
+        let hash: Option<(String,)> = ormlite::query_as(
+            "SELECT h.plaintext_hash
+             FROM cloud_file_hashes h
+             INNER JOIN cloud_maps m ON m.id = h.cloud_map_id
+             WHERE m.cloud_track_id = ? AND m.cloud_music_folder_id = ?",
+        )
+        .bind(cloud_track_id)
+        .bind(cloud_music_folder_id)
+        .fetch_optional(&mut self.connection)
+        .await?;
+        Ok(hash.map(|(h,)| h))
+    }
+
+    pub async fn set_cloud_file_hash(&mut self, cloud_map_id: &str, plaintext_hash: &str) -> AnyResult<()> {
+        ormlite::query(
+            "INSERT INTO cloud_file_hashes (cloud_map_id, plaintext_hash, updated_at) VALUES (?, ?, ?)
+            ON CONFLICT(cloud_map_id) DO UPDATE SET
+                plaintext_hash = excluded.plaintext_hash,
+                updated_at = excluded.updated_at;",
+        )
+        .bind(cloud_map_id)
+        .bind(plaintext_hash)
+        .bind(Utc::now())
+        .execute(&mut self.connection)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use ormlite::Model;
+
+use crate::libs::database::core::DB;
+use crate::libs::error::AnyResult;
+use crate::plugins::cloud::models::*;
+
+const DEFAULT_SYNC_HISTORY_LIMIT: u32 = 500;
+
+impl DB {
+    pub async fn record_sync_history(&mut self, entry: SyncHistoryEntry) -> AnyResult<SyncHistoryEntry> {
+        let saved = entry.insert(&mut self.connection).await?;
+        Ok(saved)
+    }
+
+    /// Get sync history entries matching a filter, most recent first
+    pub async fn get_sync_history(&mut self, filter: SyncHistoryFilter) -> AnyResult<Vec<SyncHistoryEntry>> {
+        let entries = SyncHistoryEntry::query(
+            "SELECT * FROM sync_history
+            WHERE (?1 IS NULL OR cloud_track_id = ?1)
+            AND (?2 IS NULL OR cloud_music_folder_id = ?2)
+            AND (?3 IS NULL OR operation = ?3)
+            AND (?4 IS NULL OR timestamp >= ?4)
+            AND (?5 IS NULL OR timestamp <= ?5)
+            ORDER BY timestamp DESC
+            LIMIT ?6 OFFSET ?7",
+        )
+        .bind(filter.cloud_track_id)
+        .bind(filter.cloud_music_folder_id)
+        .bind(filter.operation.map(|op| op.as_str()))
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.limit.unwrap_or(DEFAULT_SYNC_HISTORY_LIMIT))
+        .bind(filter.offset.unwrap_or(0))
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        Ok(entries)
+    }
+
+    /// Delete sync history entries older than a date, returns the number of deleted entries
+    pub async fn purge_sync_history(&mut self, before: DateTime<Utc>) -> AnyResult<u64> {
+        let result = ormlite::query("DELETE FROM sync_history WHERE timestamp < ?")
+            .bind(before)
+            .execute(&mut self.connection)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -1,4 +1,11 @@
+mod accounts;
+mod encryption;
+mod history;
 mod operations;
-mod schema;
+mod playlists;
+mod queue;
+mod search;
+mod unified_tracks;
 
-pub use schema::*; 
\ No newline at end of file
+pub use search::{create_library_search, rebuild_library_search, search_match_query, DEFAULT_SEARCH_LIMIT};
+pub use unified_tracks::{create_unified_tracks, link_maps_to_local_tracks, rebuild_unified_tracks};
@@ -5,6 +5,8 @@ use ormlite::Model;
 
 use crate::libs::database::core::DB;
 use crate::libs::error::AnyResult;
+use crate::libs::relative_path::local_path_key;
+use crate::libs::track_query::{QueryValue, TrackPage, TrackQuery, TrackQueryTarget};
 use crate::plugins::cloud::models::*;
 
 impl DB {
@@ -35,23 +37,25 @@ impl DB {
         Ok(folders)
     }
 
-    pub async fn get_cloud_folder_by_local_path(
-        &mut self,
-        local_path: &str,
-    ) -> AnyResult<Option<CloudMusicFolder>> {
-        let folder = CloudMusicFolder::select()
+    /// Get all the cloud folders a local folder is synced with
+    pub async fn get_cloud_folders_by_local_path(&mut self, local_path: &str) -> AnyResult<Vec<CloudMusicFolder>> {
+        let folders = CloudMusicFolder::select()
             .where_bind("local_folder_path = ?", local_path)
-            .fetch_optional(&mut self.connection)
+            .fetch_all(&mut self.connection)
             .await?;
-        Ok(folder)
+        Ok(folders)
     }
 
-    pub async fn save_cloud_folder(&mut self, folder: CloudMusicFolder) -> AnyResult<CloudMusicFolder> {
+    /// Save a folder, its path key is computed here as folders from the
+    /// front-end have none
+    pub async fn save_cloud_folder(&mut self, mut folder: CloudMusicFolder) -> AnyResult<CloudMusicFolder> {
+        folder.local_path_key = local_path_key(&folder.local_folder_path);
         let saved = folder.insert(&mut self.connection).await?;
         Ok(saved)
     }
 
-    pub async fn update_cloud_folder(&mut self, folder: CloudMusicFolder) -> AnyResult<CloudMusicFolder> {
+    pub async fn update_cloud_folder(&mut self, mut folder: CloudMusicFolder) -> AnyResult<CloudMusicFolder> {
+        folder.local_path_key = local_path_key(&folder.local_folder_path);
         let updated = folder.update_all_fields(&mut self.connection).await?;
         Ok(updated)
     }
@@ -61,6 +65,10 @@ impl DB {
             .where_bind("id = ?", id)
             .fetch_optional(&mut self.connection)
             .await? {
+            ormlite::query("DELETE FROM cloud_folder_encryption WHERE cloud_music_folder_id = ?")
+                .bind(id)
+                .execute(&mut self.connection)
+                .await?;
             folder.delete(&mut self.connection).await?;
         }
         Ok(())
@@ -73,6 +81,46 @@ impl DB {
         Ok(tracks)
     }
 
+    /// Get a page of unified tracks, filtered and sorted
+    pub async fn query_unified_tracks(&mut self, query: &TrackQuery) -> AnyResult<TrackPage<UnifiedTrack>> {
+        let total = if query.with_total {
+            let (where_sql, values) = query.where_sql(TrackQueryTarget::UnifiedTracks);
+            let count_sql = format!("SELECT COUNT(*) FROM unified_tracks {}", where_sql);
+            let mut count_query = ormlite::query_as::<_, (i64,)>(&count_sql);
+            for value in &values {
+                count_query = match value {
+                    QueryValue::Text(text) => count_query.bind(text),
+                    QueryValue::Integer(integer) => count_query.bind(integer),
+                };
+            }
+            let (total,) = count_query.fetch_one(&mut self.connection).await?;
+            Some(total as u32)
+        } else {
+            None
+        };
+
+        // One more row than the limit tells whether there is a next page
+        let (where_sql, values) = query.page_where_sql(TrackQueryTarget::UnifiedTracks)?;
+        let select_sql = format!(
+            "SELECT * FROM unified_tracks {} {} LIMIT ?",
+            where_sql,
+            query.order_sql(TrackQueryTarget::UnifiedTracks)
+        );
+        let mut select_query = ormlite::query_as::<_, UnifiedTrack>(&select_sql);
+        for value in &values {
+            select_query = match value {
+                QueryValue::Text(text) => select_query.bind(text),
+                QueryValue::Integer(integer) => select_query.bind(integer),
+            };
+        }
+        let items = select_query
+            .bind(query.get_limit() + 1)
+            .fetch_all(&mut self.connection)
+            .await?;
+
+        Ok(TrackPage::new(items, query, TrackQueryTarget::UnifiedTracks, total))
+    }
+
     pub async fn get_unified_tracks_by_ids(&mut self, ids: &[String]) -> AnyResult<Vec<UnifiedTrack>> {
         let mut query = "SELECT * FROM unified_tracks WHERE local_track_id IN (".to_string();
 
@@ -0,0 +1,25 @@
+use ormlite::Model;
+
+use crate::libs::database::core::DB;
+use crate::libs::error::AnyResult;
+use crate::plugins::cloud::models::*;
+
+impl DB {
+    /// Get all cloud playlists, including tombstones
+    pub async fn get_cloud_playlists(&mut self) -> AnyResult<Vec<CloudPlaylist>> {
+        let playlists = CloudPlaylist::select()
+            .fetch_all(&mut self.connection)
+            .await?;
+        Ok(playlists)
+    }
+
+    pub async fn save_cloud_playlist(&mut self, playlist: CloudPlaylist) -> AnyResult<CloudPlaylist> {
+        let saved = playlist.insert(&mut self.connection).await?;
+        Ok(saved)
+    }
+
+    pub async fn update_cloud_playlist(&mut self, playlist: CloudPlaylist) -> AnyResult<CloudPlaylist> {
+        let updated = playlist.update_all_fields(&mut self.connection).await?;
+        Ok(updated)
+    }
+}
@@ -0,0 +1,182 @@
+use chrono::{DateTime, Utc};
+use ormlite::Model;
+use std::collections::HashMap;
+
+use crate::libs::database::core::DB;
+use crate::libs::error::AnyResult;
+use crate::plugins::cloud::models::*;
+
+const QUEUE_TABLES: [&str; 2] = ["upload_queue", "download_queue"];
+
+fn placeholders(count: usize) -> String {
+    std::iter::repeat("?").take(count).collect::<Vec<_>>().join(",")
+}
+
+impl DB {
+    pub async fn is_sync_paused(&mut self, operation: &SyncOperationType) -> AnyResult<bool> {
+        let state = SyncQueueState::select()
+            .where_bind("operation = ?", operation.as_str())
+            .fetch_optional(&mut self.connection)
+            .await?;
+        Ok(state.is_some_and(|s| s.paused))
+    }
+
+    pub async fn set_sync_paused(&mut self, operation: &SyncOperationType, paused: bool) -> AnyResult<()> {
+        ormlite::query(
+            "INSERT INTO sync_queue_state (operation, paused, updated_at) VALUES (?, ?, ?)
+             ON CONFLICT(operation) DO UPDATE SET paused = excluded.paused, updated_at = excluded.updated_at",
+        )
+        .bind(operation.as_str())
+        .bind(paused)
+        .bind(Utc::now())
+        .execute(&mut self.connection)
+        .await?;
+        Ok(())
+    }
+
+    /// Cancel pending and failed queue items, items being transferred are left
+    /// untouched. Returns the number of cancelled items.
+    pub async fn cancel_queue_items(&mut self, item_ids: &[String]) -> AnyResult<u64> {
+        if item_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut cancelled = 0;
+        for table in QUEUE_TABLES {
+            let query = format!(
+                "UPDATE {} SET status = 'cancelled', updated_at = ?
+                 WHERE status IN ('pending', 'failed') AND id IN ({})",
+                table,
+                placeholders(item_ids.len())
+            );
+            let mut q_builder = ormlite::query(&query).bind(Utc::now());
+            for id in item_ids {
+                q_builder = q_builder.bind(id);
+            }
+            cancelled += q_builder.execute(&mut self.connection).await?.rows_affected();
+        }
+
+        Ok(cancelled)
+    }
+
+    /// Cancel the pending and failed queue items of a cloud folder
+    pub async fn cancel_folder_queue(&mut self, folder_id: &str) -> AnyResult<u64> {
+        let mut cancelled = 0;
+        for table in QUEUE_TABLES {
+            let query = format!(
+                "UPDATE {} SET status = 'cancelled', updated_at = ?
+                 WHERE status IN ('pending', 'failed')
+                 AND cloud_map_id IN (SELECT id FROM cloud_maps WHERE cloud_music_folder_id = ?)",
+                table
+            );
+            cancelled += ormlite::query(&query)
+                .bind(Utc::now())
+                .bind(folder_id)
+                .execute(&mut self.connection)
+                .await?
+                .rows_affected();
+        }
+
+        Ok(cancelled)
+    }
+
+    /// Change the priority of pending queue items, higher priorities are
+    /// processed first. Returns the number of items whose priority changed.
+    pub async fn set_queue_items_priority(&mut self, item_ids: &[String], priority: i32) -> AnyResult<u64> {
+        if item_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut updated = 0;
+        for table in QUEUE_TABLES {
+            let query = format!(
+                "UPDATE {} SET priority = ?, updated_at = ?
+                 WHERE status = 'pending' AND priority != ? AND id IN ({})",
+                table,
+                placeholders(item_ids.len())
+            );
+            let mut q_builder = ormlite::query(&query).bind(priority).bind(Utc::now()).bind(priority);
+            for id in item_ids {
+                q_builder = q_builder.bind(id);
+            }
+            updated += q_builder.execute(&mut self.connection).await?.rows_affected();
+        }
+
+        Ok(updated)
+    }
+
+    /// Delete completed and cancelled queue items last updated before a date
+    pub async fn purge_queue(&mut self, before: DateTime<Utc>) -> AnyResult<QueuePurgeResultDTO> {
+        let uploads_purged = ormlite::query(
+            "DELETE FROM upload_queue WHERE status IN ('completed', 'cancelled') AND updated_at < ?",
+        )
+        .bind(before)
+        .execute(&mut self.connection)
+        .await?
+        .rows_affected();
+
+        let downloads_purged = ormlite::query(
+            "DELETE FROM download_queue WHERE status IN ('completed', 'cancelled') AND updated_at < ?",
+        )
+        .bind(before)
+        .execute(&mut self.connection)
+        .await?
+        .rows_affected();
+
+        Ok(QueuePurgeResultDTO {
+            uploads_purged,
+            downloads_purged,
+        })
+    }
+
+    /// Get the size of the uploads waiting in the queue for some cloud folders.
+    /// Overwritten cloud files are counted in full, so this is an upper bound.
+    pub async fn get_pending_upload_bytes(&mut self, folder_ids: &[String]) -> AnyResult<u64> {
+        if folder_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let query = format!(
+            "SELECT COALESCE(SUM(t.size), 0) FROM upload_queue u
+             INNER JOIN cloud_maps m ON u.cloud_map_id = m.id
+             INNER JOIN cloud_tracks t ON m.cloud_track_id = t.id
+             WHERE u.status IN ('pending', 'in_progress') AND m.cloud_music_folder_id IN ({})",
+            placeholders(folder_ids.len())
+        );
+        let mut q_builder = ormlite::query_as(&query);
+        for id in folder_ids {
+            q_builder = q_builder.bind(id);
+        }
+        let (bytes,): (i64,) = q_builder.fetch_one(&mut self.connection).await?;
+
+        Ok(bytes.max(0) as u64)
+    }
+
+    /// Get the size of the cloud tracks that would take new space once uploaded,
+    /// by the ID of the folder they are mapped in. Tracks already in the cloud
+    /// or waiting in the upload queue are already counted in the usage.
+    pub async fn get_tracks_size_by_folder(&mut self, track_ids: &[String]) -> AnyResult<HashMap<String, u64>> {
+        if track_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let query = format!(
+            "SELECT m.cloud_music_folder_id, COALESCE(SUM(t.size), 0) FROM cloud_tracks t
+             INNER JOIN cloud_maps m ON m.cloud_track_id = t.id
+             WHERE t.id IN ({}) AND m.cloud_file_id IS NULL
+               AND NOT EXISTS (
+                 SELECT 1 FROM upload_queue u
+                 WHERE u.cloud_map_id = m.id AND u.status IN ('pending', 'in_progress')
+               )
+             GROUP BY m.cloud_music_folder_id",
+            placeholders(track_ids.len())
+        );
+        let mut q_builder = ormlite::query_as(&query);
+        for id in track_ids {
+            q_builder = q_builder.bind(id);
+        }
+        let rows: Vec<(String, i64)> = q_builder.fetch_all(&mut self.connection).await?;
+
+        Ok(rows.into_iter().map(|(folder_id, size)| (folder_id, size.max(0) as u64)).collect())
+    }
+}
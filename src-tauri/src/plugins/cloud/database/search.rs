@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use ormlite::sqlite::SqliteConnection;
+use ormlite::Model;
+
+use crate::libs::database::core::DB;
+use crate::libs::error::AnyResult;
+use crate::plugins::cloud::models::*;
+
+/*
+ * Library search runs on the library_search FTS5 table, with a row for each
+ * local track and each cloud track. FTS5 rows are identified by rowid, which
+ * VACUUM may renumber for tables without an integer primary key, so the rows
+ * are numbered by library_search_rows instead, which also tells which track
+ * a row is for. Triggers on tracks and cloud tracks keep both tables in sync.
+ *
+ * The tokenizer strips diacritics, and prefix indexes make prefix queries of
+ * a few characters cheap.
+ */
+
+/// Results of a search when no limit is given
+pub const DEFAULT_SEARCH_LIMIT: u32 = 100;
+
+/// Weights of the indexed columns for ranking, in the order of the table columns
+const SEARCH_WEIGHTS: &str = "10.0, 5.0, 5.0, 3.0, 2.0, 1.0";
+
+/// Index a local track, its tags are JSON arrays which the tokenizer splits like any text
+fn index_track_sql(track: &str) -> String {
+    format!(
+        "INSERT INTO library_search_rows (local_track_id) VALUES ({track}.id);
+        INSERT INTO library_search (rowid, title, album, artists, album_artists, composers, genres)
+            SELECT id, {track}.title, {track}.album, {track}.artists, {track}.album_artists, {track}.composers, {track}.genres
+            FROM library_search_rows WHERE local_track_id = {track}.id;"
+    )
+}
+
+fn unindex_track_sql(track: &str) -> String {
+    format!(
+        "DELETE FROM library_search WHERE rowid IN (SELECT id FROM library_search_rows WHERE local_track_id = {track}.id);
+        DELETE FROM library_search_rows WHERE local_track_id = {track}.id;"
+    )
+}
+
+/// Index a cloud track, with the file name as title when its tags were not read yet
+fn index_cloud_track_sql(track: &str) -> String {
+    format!(
+        "INSERT INTO library_search_rows (cloud_track_id) VALUES ({track}.id);
+        INSERT INTO library_search (rowid, title, album, artists, album_artists, composers, genres)
+            SELECT id,
+                COALESCE({track}.tags->>'$.title', {track}.file_name),
+                {track}.tags->>'$.album',
+                {track}.tags->>'$.artists',
+                {track}.tags->>'$.album_artists',
+                {track}.tags->>'$.composers',
+                {track}.tags->>'$.genres'
+            FROM library_search_rows WHERE cloud_track_id = {track}.id;"
+    )
+}
+
+fn unindex_cloud_track_sql(track: &str) -> String {
+    format!(
+        "DELETE FROM library_search WHERE rowid IN (SELECT id FROM library_search_rows WHERE cloud_track_id = {track}.id);
+        DELETE FROM library_search_rows WHERE cloud_track_id = {track}.id;"
+    )
+}
+
+/// Index every track again from scratch, eg. when the search tables are created
+pub async fn rebuild_library_search(connection: &mut SqliteConnection) -> AnyResult<()> {
+    let rebuild = "BEGIN;
+        DELETE FROM library_search;
+        DELETE FROM library_search_rows;
+        INSERT INTO library_search_rows (local_track_id) SELECT id FROM tracks;
+        INSERT INTO library_search_rows (cloud_track_id) SELECT id FROM cloud_tracks;
+        INSERT INTO library_search (rowid, title, album, artists, album_artists, composers, genres)
+            SELECT s.id, t.title, t.album, t.artists, t.album_artists, t.composers, t.genres
+            FROM library_search_rows s JOIN tracks t ON t.id = s.local_track_id;
+        INSERT INTO library_search (rowid, title, album, artists, album_artists, composers, genres)
+            SELECT s.id,
+                COALESCE(ct.tags->>'$.title', ct.file_name),
+                ct.tags->>'$.album',
+                ct.tags->>'$.artists',
+                ct.tags->>'$.album_artists',
+                ct.tags->>'$.composers',
+                ct.tags->>'$.genres'
+            FROM library_search_rows s JOIN cloud_tracks ct ON ct.id = s.cloud_track_id;
+        COMMIT;";
+    let result = ormlite::query(rebuild).execute(&mut *connection).await;
+    if result.is_err() {
+        ormlite::query("ROLLBACK").execute(&mut *connection).await.ok();
+    }
+
+    result?;
+    Ok(())
+}
+
+pub async fn create_library_search(connection: &mut SqliteConnection, rebuild: bool) -> AnyResult<()> {
+    // Triggers are always created again to pick up changes, like the unified tracks ones
+    let triggers = format!(
+        "DROP TRIGGER IF EXISTS library_search_track_insert;
+        CREATE TRIGGER library_search_track_insert AFTER INSERT ON tracks BEGIN
+            {index_new_track}
+        END;
+
+        DROP TRIGGER IF EXISTS library_search_track_update;
+        CREATE TRIGGER library_search_track_update
+        AFTER UPDATE OF id, title, album, artists, album_artists, composers, genres ON tracks
+        WHEN OLD.id IS NOT NEW.id OR OLD.title IS NOT NEW.title OR OLD.album IS NOT NEW.album
+            OR OLD.artists IS NOT NEW.artists OR OLD.album_artists IS NOT NEW.album_artists
+            OR OLD.composers IS NOT NEW.composers OR OLD.genres IS NOT NEW.genres BEGIN
+            {unindex_old_track}
+            {index_new_track}
+        END;
+
+        DROP TRIGGER IF EXISTS library_search_track_delete;
+        CREATE TRIGGER library_search_track_delete AFTER DELETE ON tracks BEGIN
+            {unindex_old_track}
+        END;
+
+        DROP TRIGGER IF EXISTS library_search_cloud_track_insert;
+        CREATE TRIGGER library_search_cloud_track_insert AFTER INSERT ON cloud_tracks BEGIN
+            {index_new_cloud_track}
+        END;
+
+        DROP TRIGGER IF EXISTS library_search_cloud_track_update;
+        CREATE TRIGGER library_search_cloud_track_update AFTER UPDATE OF id, file_name, tags ON cloud_tracks
+        WHEN OLD.id IS NOT NEW.id OR OLD.file_name IS NOT NEW.file_name OR OLD.tags IS NOT NEW.tags BEGIN
+            {unindex_old_cloud_track}
+            {index_new_cloud_track}
+        END;
+
+        DROP TRIGGER IF EXISTS library_search_cloud_track_delete;
+        CREATE TRIGGER library_search_cloud_track_delete AFTER DELETE ON cloud_tracks BEGIN
+            {unindex_old_cloud_track}
+        END;",
+        index_new_track = index_track_sql("NEW"),
+        unindex_old_track = unindex_track_sql("OLD"),
+        index_new_cloud_track = index_cloud_track_sql("NEW"),
+        unindex_old_cloud_track = unindex_cloud_track_sql("OLD"),
+    );
+    ormlite::query(&triggers).execute(&mut *connection).await?;
+
+    // Rows are only missing when the table was just migrated
+    if rebuild {
+        rebuild_library_search(connection).await?;
+    }
+
+    Ok(())
+}
+
+/// Turn what the user typed into an FTS5 query: every word must match the
+/// start of a word of the track. Words are quoted so that FTS5 operators
+/// and punctuation are searched as text.
+pub fn search_match_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+impl DB {
+    /// Search the local tracks and the cloud-only tracks, best matches first
+    pub async fn search_tracks(&mut self, text: &str, limit: u32) -> AnyResult<Vec<TrackSearchResultDTO>> {
+        let Some(match_query) = search_match_query(text) else {
+            return Ok(vec![]);
+        };
+
+        // Cloud tracks of a local track are found through it
+        let rows: Vec<(Option<String>, Option<String>, f64)> = ormlite::query_as(&format!(
+            "SELECT s.local_track_id, s.cloud_track_id, bm25(library_search, {}) AS score
+            FROM library_search
+            JOIN library_search_rows s ON s.id = library_search.rowid
+            WHERE library_search MATCH ?
+            AND (
+                s.local_track_id IS NOT NULL
+                OR NOT EXISTS (
+                    SELECT 1 FROM unified_track_links l
+                    WHERE l.cloud_track_id = s.cloud_track_id AND l.local_track_id IS NOT NULL
+                )
+            )
+            ORDER BY score
+            LIMIT ?",
+            SEARCH_WEIGHTS
+        ))
+        .bind(match_query)
+        .bind(limit)
+        .fetch_all(&mut self.connection)
+        .await?;
+
+        let track_ids: Vec<String> = rows.iter().filter_map(|(id, _, _)| id.clone()).collect();
+        let mut tracks: HashMap<String, _> = self
+            .get_tracks(&track_ids)
+            .await?
+            .into_iter()
+            .map(|track| (track.id.clone(), track))
+            .collect();
+
+        let cloud_track_ids: Vec<&String> = rows.iter().filter_map(|(_, id, _)| id.as_ref()).collect();
+        let mut cloud_tracks = HashMap::new();
+        if !cloud_track_ids.is_empty() {
+            let placeholders = cloud_track_ids.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+            let where_statement = format!("id IN ({})", placeholders);
+
+            let mut query_builder = CloudTrack::select().dangerous_where(&where_statement);
+            for id in cloud_track_ids {
+                query_builder = query_builder.bind(id);
+            }
+
+            cloud_tracks = query_builder
+                .fetch_all(&mut self.connection)
+                .await?
+                .into_iter()
+                .map(|cloud_track| (cloud_track.id.clone(), cloud_track))
+                .collect();
+        }
+
+        let results = rows
+            .into_iter()
+            .filter_map(|(track_id, cloud_track_id, rank)| {
+                let track = track_id.and_then(|id| tracks.remove(&id));
+                let cloud_track = cloud_track_id.and_then(|id| cloud_tracks.remove(&id));
+                (track.is_some() || cloud_track.is_some()).then_some(TrackSearchResultDTO {
+                    track,
+                    cloud_track,
+                    rank,
+                })
+            })
+            .collect();
+
+        Ok(results)
+    }
+}
@@ -0,0 +1,367 @@
+use ormlite::sqlite::SqliteConnection;
+
+use crate::libs::error::AnyResult;
+use crate::libs::relative_path::{local_path_key, RelativePath};
+
+/*
+ * The unified tracks join local tracks with the cloud folders they live in,
+ * and with their cloud maps. Matching tracks and maps by path is expensive, so
+ * the matches are stored in the unified_track_links table, and kept up to date
+ * by triggers on tracks, maps and folders. The unified_tracks view then only
+ * joins the links with their rows by primary key.
+ *
+ * A link is either a local track in a folder, with the map of its path if any,
+ * or a map without local track (a cloud-only track). Paths are matched with
+ * their keys, computed by the app (see RelativePath::key and local_path_key),
+ * so separators, Unicode normalization and case do not matter: the key of a
+ * track is the key of its folder, a slash and the key of its map.
+ *
+ * Maps remember the last local track they were linked with, which is still
+ * theirs once the local file is moved somewhere else.
+ */
+
+/// Condition of a track living in a folder `f`: its key starts with the key of
+/// the folder and a slash, '0' being the character after the slash
+fn track_in_folder_sql(track: &str) -> String {
+    format!("{track}.path_key > f.local_path_key || '/' AND {track}.path_key < f.local_path_key || '0'")
+}
+
+/// Link the tracks of the folders matching a filter on `f`, with their maps
+fn link_folders_sql(filter: &str) -> String {
+    format!(
+        "INSERT INTO unified_track_links (local_track_id, cloud_music_folder_id, cloud_map_id, cloud_track_id)
+            SELECT DISTINCT t.id, f.id, m.id, m.cloud_track_id
+            FROM cloud_music_folders f
+            JOIN tracks t ON {in_folder}
+            LEFT JOIN cloud_maps m ON m.cloud_music_folder_id = f.id
+                AND t.path_key = f.local_path_key || '/' || m.path_key
+            WHERE {filter};
+        INSERT INTO unified_track_links (local_track_id, cloud_music_folder_id, cloud_map_id, cloud_track_id)
+            SELECT NULL, f.id, m.id, m.cloud_track_id
+            FROM cloud_music_folders f
+            JOIN cloud_maps m ON m.cloud_music_folder_id = f.id
+            WHERE {filter}
+            AND NOT EXISTS (SELECT 1 FROM unified_track_links l WHERE l.cloud_map_id = m.id);",
+        in_folder = track_in_folder_sql("t"),
+    )
+}
+
+/// Link a track with the folders it lives in, taking over cloud-only maps
+fn link_track_sql(track: &str) -> String {
+    format!(
+        "INSERT INTO unified_track_links (local_track_id, cloud_music_folder_id, cloud_map_id, cloud_track_id)
+            SELECT DISTINCT {track}.id, f.id, m.id, m.cloud_track_id
+            FROM cloud_music_folders f
+            LEFT JOIN cloud_maps m ON m.cloud_music_folder_id = f.id
+                AND m.path_key = SUBSTR({track}.path_key, LENGTH(f.local_path_key) + 2)
+            WHERE {in_folder};
+        DELETE FROM unified_track_links
+            WHERE local_track_id IS NULL
+            AND cloud_map_id IN (SELECT cloud_map_id FROM unified_track_links WHERE local_track_id = {track}.id);",
+        in_folder = track_in_folder_sql(track),
+    )
+}
+
+/// Unlink a track, its maps become cloud-only unless another track matches them
+fn unlink_track_sql(track: &str) -> String {
+    format!(
+        "INSERT INTO unified_track_links (local_track_id, cloud_music_folder_id, cloud_map_id, cloud_track_id)
+            SELECT NULL, l.cloud_music_folder_id, l.cloud_map_id, l.cloud_track_id
+            FROM unified_track_links l
+            WHERE l.local_track_id = {track}.id AND l.cloud_map_id IS NOT NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM unified_track_links o
+                WHERE o.cloud_map_id = l.cloud_map_id AND o.local_track_id != {track}.id
+            );
+        DELETE FROM unified_track_links WHERE local_track_id = {track}.id;"
+    )
+}
+
+/// Link a map with the local tracks of its path, or as a cloud-only track
+fn link_map_sql(map: &str) -> String {
+    format!(
+        "INSERT INTO unified_track_links (local_track_id, cloud_music_folder_id, cloud_map_id, cloud_track_id)
+            SELECT t.id, f.id, {map}.id, {map}.cloud_track_id
+            FROM cloud_music_folders f
+            JOIN tracks t ON t.path_key = f.local_path_key || '/' || {map}.path_key
+            WHERE f.id = {map}.cloud_music_folder_id;
+        DELETE FROM unified_track_links
+            WHERE cloud_map_id IS NULL
+            AND cloud_music_folder_id = {map}.cloud_music_folder_id
+            AND local_track_id IN (SELECT local_track_id FROM unified_track_links WHERE cloud_map_id = {map}.id);
+        INSERT INTO unified_track_links (local_track_id, cloud_music_folder_id, cloud_map_id, cloud_track_id)
+            SELECT NULL, f.id, {map}.id, {map}.cloud_track_id
+            FROM cloud_music_folders f
+            WHERE f.id = {map}.cloud_music_folder_id
+            AND NOT EXISTS (SELECT 1 FROM unified_track_links WHERE cloud_map_id = {map}.id);"
+    )
+}
+
+/// Unlink a map, its tracks are left not mapped unless they have another map
+fn unlink_map_sql(map: &str) -> String {
+    format!(
+        "DELETE FROM unified_track_links
+            WHERE cloud_map_id = {map}.id
+            AND (
+                local_track_id IS NULL
+                OR local_track_id IN (
+                    SELECT local_track_id FROM unified_track_links
+                    WHERE cloud_music_folder_id = {map}.cloud_music_folder_id
+                    AND cloud_map_id IS NOT NULL AND cloud_map_id != {map}.id
+                )
+            );
+        UPDATE unified_track_links SET cloud_map_id = NULL, cloud_track_id = NULL WHERE cloud_map_id = {map}.id;"
+    )
+}
+
+fn unlink_folder_sql(folder: &str) -> String {
+    format!("DELETE FROM unified_track_links WHERE cloud_music_folder_id = {folder}.id;")
+}
+
+/**
+ * Compute the keys of the rows written before keys existed, or by the
+ * migrations. Returns true if any key was missing, the links matched with
+ * them are then out of date.
+ */
+async fn fill_path_keys(connection: &mut SqliteConnection) -> AnyResult<bool> {
+    let tracks: Vec<(String, String)> = ormlite::query_as("SELECT id, path FROM tracks WHERE path_key = ''")
+        .fetch_all(&mut *connection)
+        .await?;
+    let maps: Vec<(String, String)> = ormlite::query_as("SELECT id, relative_path FROM cloud_maps WHERE path_key = ''")
+        .fetch_all(&mut *connection)
+        .await?;
+    let folders: Vec<(String, String)> =
+        ormlite::query_as("SELECT id, local_folder_path FROM cloud_music_folders WHERE local_path_key = ''")
+            .fetch_all(&mut *connection)
+            .await?;
+
+    let keys = tracks
+        .into_iter()
+        .map(|(id, path)| ("tracks", "path_key", id, local_path_key(&path)))
+        .chain(maps.into_iter().map(|(id, path)| ("cloud_maps", "path_key", id, RelativePath::new(&path).key())))
+        .chain(
+            folders
+                .into_iter()
+                .map(|(id, path)| ("cloud_music_folders", "local_path_key", id, local_path_key(&path))),
+        )
+        .collect::<Vec<_>>();
+
+    for (table, column, id, key) in &keys {
+        ormlite::query(&format!("UPDATE {} SET {} = ? WHERE id = ?", table, column))
+            .bind(key)
+            .bind(id)
+            .execute(&mut *connection)
+            .await?;
+    }
+
+    Ok(!keys.is_empty())
+}
+
+/// Link everything again from scratch, eg. when the links table is created
+pub async fn rebuild_unified_tracks(connection: &mut SqliteConnection) -> AnyResult<()> {
+    let rebuild = format!(
+        "BEGIN;
+         DELETE FROM unified_track_links;
+         {}
+         COMMIT;",
+        link_folders_sql("1")
+    );
+    let result = ormlite::query(&rebuild).execute(&mut *connection).await;
+    if result.is_err() {
+        ormlite::query("ROLLBACK").execute(&mut *connection).await.ok();
+    }
+
+    result?;
+    Ok(())
+}
+
+/// Remember the local track of the maps that have none, the track they are
+/// linked with by their path keys
+pub async fn link_maps_to_local_tracks(connection: &mut SqliteConnection) -> AnyResult<()> {
+    ormlite::query(
+        "UPDATE cloud_maps SET local_track_id = (
+            SELECT l.local_track_id FROM unified_track_links l
+            WHERE l.cloud_map_id = cloud_maps.id AND l.local_track_id IS NOT NULL
+            LIMIT 1
+        )
+        WHERE local_track_id IS NULL;",
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(())
+}
+
+/// Create the triggers and the view of the unified tracks, the links table is
+/// created by the migrations. Links are built from scratch when `rebuild` is set.
+pub async fn create_unified_tracks(connection: &mut SqliteConnection, rebuild: bool) -> AnyResult<()> {
+    // Before the triggers are created again, so they do not relink every row
+    let keys_filled = fill_path_keys(connection).await?;
+
+    // Triggers are cheap to create, so they are always created again to pick up changes.
+    // Updates only relink rows when their path changes, as models update all their fields.
+    let triggers = format!(
+        "DROP TRIGGER IF EXISTS unified_tracks_track_insert;
+        CREATE TRIGGER unified_tracks_track_insert AFTER INSERT ON tracks BEGIN
+            {link_new_track}
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_track_update;
+        CREATE TRIGGER unified_tracks_track_update AFTER UPDATE OF id, path_key ON tracks
+        WHEN OLD.id IS NOT NEW.id OR OLD.path_key IS NOT NEW.path_key BEGIN
+            {unlink_old_track}
+            {link_new_track}
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_track_delete;
+        CREATE TRIGGER unified_tracks_track_delete AFTER DELETE ON tracks BEGIN
+            {unlink_old_track}
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_map_insert;
+        CREATE TRIGGER unified_tracks_map_insert AFTER INSERT ON cloud_maps BEGIN
+            {link_new_map}
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_map_update;
+        CREATE TRIGGER unified_tracks_map_update AFTER UPDATE OF path_key, cloud_music_folder_id ON cloud_maps
+        WHEN OLD.path_key IS NOT NEW.path_key OR OLD.cloud_music_folder_id IS NOT NEW.cloud_music_folder_id BEGIN
+            {unlink_old_map}
+            {link_new_map}
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_map_track_update;
+        CREATE TRIGGER unified_tracks_map_track_update AFTER UPDATE OF cloud_track_id ON cloud_maps
+        WHEN OLD.cloud_track_id IS NOT NEW.cloud_track_id BEGIN
+            UPDATE unified_track_links SET cloud_track_id = NEW.cloud_track_id WHERE cloud_map_id = NEW.id;
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_map_delete;
+        CREATE TRIGGER unified_tracks_map_delete AFTER DELETE ON cloud_maps BEGIN
+            {unlink_old_map}
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_link_insert;
+        CREATE TRIGGER unified_tracks_link_insert AFTER INSERT ON unified_track_links
+        WHEN NEW.local_track_id IS NOT NULL AND NEW.cloud_map_id IS NOT NULL BEGIN
+            UPDATE cloud_maps SET local_track_id = NEW.local_track_id
+            WHERE id = NEW.cloud_map_id AND local_track_id IS NOT NEW.local_track_id;
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_folder_insert;
+        CREATE TRIGGER unified_tracks_folder_insert AFTER INSERT ON cloud_music_folders BEGIN
+            {link_new_folder}
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_folder_update;
+        CREATE TRIGGER unified_tracks_folder_update AFTER UPDATE OF id, local_path_key ON cloud_music_folders
+        WHEN OLD.id IS NOT NEW.id OR OLD.local_path_key IS NOT NEW.local_path_key BEGIN
+            {unlink_old_folder}
+            {link_new_folder}
+        END;
+
+        DROP TRIGGER IF EXISTS unified_tracks_folder_delete;
+        CREATE TRIGGER unified_tracks_folder_delete AFTER DELETE ON cloud_music_folders BEGIN
+            {unlink_old_folder}
+        END;",
+        link_new_track = link_track_sql("NEW"),
+        unlink_old_track = unlink_track_sql("OLD"),
+        link_new_map = link_map_sql("NEW"),
+        unlink_old_map = unlink_map_sql("OLD"),
+        link_new_folder = link_folders_sql("f.id = NEW.id"),
+        unlink_old_folder = unlink_folder_sql("OLD"),
+    );
+    ormlite::query(&triggers).execute(&mut *connection).await?;
+
+    // Links are only missing when the table was just migrated, or when the keys
+    // they are matched with were
+    if rebuild || keys_filled {
+        rebuild_unified_tracks(connection).await?;
+    }
+
+    // Create unified tracks view, with a row per cloud folder a track is synced with.
+    // Views are cheap to create, so it is always created again to pick up changes.
+    // Its rows are the ones of the path-matching view the links replaced.
+    ormlite::query("DROP VIEW IF EXISTS unified_tracks;")
+        .execute(&mut *connection)
+        .await?;
+
+    ormlite::query(
+        "CREATE VIEW unified_tracks AS
+        SELECT
+            -- Track identifiers
+            l.local_track_id,
+            l.cloud_track_id,
+            l.cloud_map_id,
+            l.cloud_music_folder_id as cloud_folder_id,
+
+            -- Paths and locations
+            t.path as local_path,
+            ctm.relative_path as cloud_relative_path,
+            cmf.cloud_folder_path,
+            cmf.local_folder_path as cloud_local_folder_path,
+            cmf.provider_type as cloud_provider_type,
+            ctm.cloud_file_id,
+
+            -- Location of the track for this cloud folder, a TrackLocationState
+            CASE
+                WHEN l.cloud_map_id IS NULL THEN 'not_mapped'
+                WHEN l.local_track_id IS NOT NULL AND ctm.cloud_file_id IS NOT NULL THEN 'complete'
+                WHEN l.local_track_id IS NOT NULL THEN 'local_only'
+                WHEN ctm.cloud_file_id IS NOT NULL THEN 'cloud_only'
+                ELSE 'missing'
+            END as location_state,
+            cmf.sync_mode as cloud_sync_mode,
+
+            -- Metadata (preferring local over cloud)
+            COALESCE(t.title, ct.tags->>'$.title', ct.file_name) as title,
+            COALESCE(t.album, ct.tags->>'$.album', 'Unknown') as album,
+            COALESCE(
+                json(t.artists),
+                ct.tags->>'$.artists',
+                json_array('Unknown Artist')
+            ) as artists,
+            COALESCE(
+                json(t.composers),
+                ct.tags->>'$.composers',
+                json_array()
+            ) as composers,
+            COALESCE(
+                json(t.album_artists),
+                ct.tags->>'$.album_artists',
+                json_array()
+            ) as album_artists,
+            COALESCE(
+                json(t.genres),
+                ct.tags->>'$.genres',
+                json_array()
+            ) as genres,
+            COALESCE(t.track_no, CAST(ct.tags->>'$.track_no' AS INTEGER)) as track_no,
+            COALESCE(t.track_of, CAST(ct.tags->>'$.track_of' AS INTEGER)) as track_of,
+            COALESCE(t.disk_no, CAST(ct.tags->>'$.disk_no' AS INTEGER)) as disk_no,
+            COALESCE(t.disk_of, CAST(ct.tags->>'$.disk_of' AS INTEGER)) as disk_of,
+            COALESCE(t.date, ct.tags->>'$.date') as date,
+            COALESCE(t.year, CAST(ct.tags->>'$.year' AS INTEGER)) as year,
+            COALESCE(t.duration, CAST(ct.tags->>'$.duration' AS INTEGER), 0) as duration,
+            COALESCE(t.bitrate, CAST(ct.tags->>'$.bitrate' AS INTEGER)) as bitrate,
+            COALESCE(t.sampling_rate, CAST(ct.tags->>'$.sampling_rate' AS INTEGER)) as sampling_rate,
+            COALESCE(t.channels, CAST(ct.tags->>'$.channels' AS INTEGER)) as channels,
+            COALESCE(t.encoder, ct.tags->>'$.encoder') as encoder,
+            COALESCE(t.size, ct.size) as size,
+
+            -- Sync state
+            ct.updated_at as cloud_updated_at
+
+        FROM unified_track_links l
+        JOIN cloud_music_folders cmf ON cmf.id = l.cloud_music_folder_id
+        LEFT JOIN tracks t ON t.id = l.local_track_id
+        LEFT JOIN cloud_maps ctm ON ctm.id = l.cloud_map_id
+        LEFT JOIN cloud_tracks ct ON ct.id = l.cloud_track_id
+        -- Cloud-only maps are only listed with their cloud track
+        WHERE l.local_track_id IS NOT NULL OR ct.id IS NOT NULL;"
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use argon2::Argon2;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{Aead, OsRng};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::plugins::config::get_storage_dir;
+
+/// Name of the file describing the encryption of a cloud folder. It only holds
+/// the salt and a key check, so it is stored in plaintext at the folder root.
+pub const ENCRYPTION_HEADER_FILE: &str = ".syncudio-encryption.json";
+
+const FILE_MAGIC: &[u8] = b"SYNCUDIO-ENC1";
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const STREAM_NONCE_SIZE: usize = 19; // XChaCha20 nonce minus the STREAM counter
+const NAME_NONCE_SIZE: usize = 24;
+const KEY_CHECK_CONTEXT: &[u8] = b"syncudio key check";
+const NAME_NONCE_CONTEXT: &[u8] = b"syncudio name nonce\0";
+const MAX_ENCRYPTED_NAME_SIZE: usize = 255; // Path component limit of most file systems and providers
+
+/// Encryption settings of a cloud folder, shared between devices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionHeader {
+    pub version: u32,
+    pub salt: String,      // Base64 salt used to derive the key from the passphrase
+    pub key_check: String, // Lets devices verify a passphrase without decrypting files
+}
+
+/**
+ * Key of an encrypted cloud folder.
+ *
+ * It is derived from a passphrase with Argon2 and only ever stored locally, in
+ * the storage dir. Files are encrypted with XChaCha20-Poly1305 in STREAM mode,
+ * so large tracks never need to fit in memory.
+ */
+#[derive(Clone)]
+pub struct FolderKey([u8; 32]);
+
+impl FolderKey {
+    pub fn derive(passphrase: &str, salt: &[u8]) -> AnyResult<Self> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| SyncudioError::Encryption(e.to_string()))?;
+        Ok(Self(key))
+    }
+
+    pub fn generate_salt() -> Vec<u8> {
+        let mut salt = vec![0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        salt
+    }
+
+    fn get_key_path(cloud_folder_id: &str) -> PathBuf {
+        let file_name = blake3::hash(cloud_folder_id.as_bytes()).to_hex();
+        get_storage_dir().join("keys").join(format!("{}.key", file_name))
+    }
+
+    /// Load the key of a cloud folder, None if this device does not have it
+    pub fn load(cloud_folder_id: &str) -> AnyResult<Option<Self>> {
+        let Ok(content) = fs::read_to_string(Self::get_key_path(cloud_folder_id)) else {
+            return Ok(None);
+        };
+
+        let bytes = URL_SAFE_NO_PAD
+            .decode(content.trim())
+            .map_err(|e| SyncudioError::Encryption(e.to_string()))?;
+        let key: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| SyncudioError::Encryption("Invalid key file".to_string()))?;
+
+        Ok(Some(Self(key)))
+    }
+
+    pub fn save(&self, cloud_folder_id: &str) -> AnyResult<()> {
+        let path = Self::get_key_path(cloud_folder_id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, URL_SAFE_NO_PAD.encode(self.0))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn key_check(&self) -> String {
+        blake3::keyed_hash(&self.0, KEY_CHECK_CONTEXT).to_hex().to_string()
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0.into())
+    }
+
+    /// Encrypt a file chunk by chunk
+    pub fn encrypt_file(&self, source: &Path, destination: &Path) -> AnyResult<()> {
+        let mut nonce = [0u8; STREAM_NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut reader = File::open(source)?;
+        let mut writer = File::create(destination)?;
+        writer.write_all(FILE_MAGIC)?;
+        writer.write_all(&nonce)?;
+
+        let mut encryptor = EncryptorBE32::from_aead(self.cipher(), nonce.as_ref().into());
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+
+        let last_len = loop {
+            let read = read_chunk(&mut reader, &mut buffer)?;
+            if read < CHUNK_SIZE {
+                break read;
+            }
+            let chunk = encryptor.encrypt_next(&buffer[..read]).map_err(encryption_error)?;
+            writer.write_all(&chunk)?;
+        };
+
+        let chunk = encryptor.encrypt_last(&buffer[..last_len]).map_err(encryption_error)?;
+        writer.write_all(&chunk)?;
+
+        Ok(())
+    }
+
+    /// Decrypt a file encrypted with `encrypt_file`, failing if it was tampered with
+    pub fn decrypt_file(&self, source: &Path, destination: &Path) -> AnyResult<()> {
+        let mut reader = File::open(source)?;
+
+        let mut magic = [0u8; FILE_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != FILE_MAGIC {
+            return Err(SyncudioError::Encryption(format!(
+                "{} is not an encrypted file",
+                source.display()
+            )));
+        }
+
+        let mut nonce = [0u8; STREAM_NONCE_SIZE];
+        reader.read_exact(&mut nonce)?;
+
+        let mut decryptor = DecryptorBE32::from_aead(self.cipher(), nonce.as_ref().into());
+        let mut buffer = vec![0u8; CHUNK_SIZE + TAG_SIZE];
+
+        // Write to a temporary file so a failed decryption never leaves a
+        // truncated track behind
+        let temp_path = destination.with_extension("decrypting");
+        let mut writer = File::create(&temp_path)?;
+
+        let result = (|| -> AnyResult<()> {
+            let last_len = loop {
+                let read = read_chunk(&mut reader, &mut buffer)?;
+                if read < CHUNK_SIZE + TAG_SIZE {
+                    break read;
+                }
+                let chunk = decryptor.decrypt_next(&buffer[..read]).map_err(encryption_error)?;
+                writer.write_all(&chunk)?;
+            };
+
+            let chunk = decryptor.decrypt_last(&buffer[..last_len]).map_err(encryption_error)?;
+            writer.write_all(&chunk)?;
+            writer.flush()?;
+            Ok(())
+        })();
+
+        match result {
+            Ok(()) => {
+                fs::rename(&temp_path, destination)?;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(e)
+            }
+        }
+    }
+
+    /**
+     * Encrypt a file or folder name.
+     *
+     * The nonce is derived from the name itself, so a given name always maps
+     * to the same encrypted name and files can still be found by path. The
+     * encrypted name is about 4/3 of the name plus 54 bytes, so names longer
+     * than 151 bytes are rejected instead of producing an unusable path.
+     */
+    pub fn encrypt_name(&self, name: &str) -> AnyResult<String> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.0);
+        hasher.update(NAME_NONCE_CONTEXT);
+        hasher.update(name.as_bytes());
+        let nonce_hash = hasher.finalize();
+        let nonce = XNonce::from_slice(&nonce_hash.as_bytes()[..NAME_NONCE_SIZE]);
+        let ciphertext = self
+            .cipher()
+            .encrypt(nonce, name.as_bytes())
+            .map_err(encryption_error)?;
+
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(ciphertext);
+        let encrypted_name = URL_SAFE_NO_PAD.encode(encrypted);
+
+        if encrypted_name.len() > MAX_ENCRYPTED_NAME_SIZE {
+            return Err(SyncudioError::Encryption(format!(
+                "Name is too long to be encrypted ({} bytes, at most {} once encrypted): {}",
+                name.len(),
+                MAX_ENCRYPTED_NAME_SIZE,
+                name
+            )));
+        }
+
+        Ok(encrypted_name)
+    }
+
+    pub fn decrypt_name(&self, encrypted_name: &str) -> AnyResult<String> {
+        let encrypted = URL_SAFE_NO_PAD
+            .decode(encrypted_name)
+            .map_err(|e| SyncudioError::Encryption(e.to_string()))?;
+        if encrypted.len() < NAME_NONCE_SIZE + TAG_SIZE {
+            return Err(SyncudioError::Encryption(format!("Invalid encrypted name: {}", encrypted_name)));
+        }
+
+        let (nonce, ciphertext) = encrypted.split_at(NAME_NONCE_SIZE);
+        let name = self
+            .cipher()
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(encryption_error)?;
+
+        String::from_utf8(name).map_err(|e| SyncudioError::Encryption(e.to_string()))
+    }
+
+    /// Encrypt every component of a relative path, keeping the folder structure
+    pub fn encrypt_path(&self, relative_path: &str) -> AnyResult<String> {
+        let components = relative_path
+            .split('/')
+            .map(|component| self.encrypt_name(component))
+            .collect::<AnyResult<Vec<_>>>()?;
+        Ok(components.join("/"))
+    }
+
+    pub fn decrypt_path(&self, encrypted_path: &str) -> AnyResult<String> {
+        let components = encrypted_path
+            .split('/')
+            .map(|component| self.decrypt_name(component))
+            .collect::<AnyResult<Vec<_>>>()?;
+        Ok(components.join("/"))
+    }
+}
+
+/// Size of the plaintext of a file encrypted with `encrypt_file`
+pub fn decrypted_size(encrypted_size: u64) -> u64 {
+    let payload = encrypted_size.saturating_sub((FILE_MAGIC.len() + STREAM_NONCE_SIZE) as u64);
+    let chunks = payload.div_ceil((CHUNK_SIZE + TAG_SIZE) as u64).max(1);
+    payload.saturating_sub(chunks * TAG_SIZE as u64)
+}
+
+/// Hash the content of a local file, used to detect changes of encrypted
+/// tracks since cloud hashes only describe the ciphertext
+pub fn hash_file(path: &Path) -> AnyResult<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+fn read_chunk(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let read = reader.read(&mut buffer[filled..])?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    Ok(filled)
+}
+
+fn encryption_error(error: chacha20poly1305::aead::Error) -> SyncudioError {
+    SyncudioError::Encryption(error.to_string())
+}
@@ -2,18 +2,99 @@ mod commands;
 mod models;
 mod providers;
 mod database;
+mod encryption;
+#[cfg(test)]
+pub(crate) mod tests;
 
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Arc;
 use tauri::plugin::{Builder, TauriPlugin};
 use tauri::{Manager, Runtime};
+use tokio::sync::RwLock;
 
+use crate::libs::device::get_device_settings;
+use crate::libs::error::{AnyResult, SyncudioError};
 use crate::plugins::cloud::providers::*;
 
 pub use commands::*;
 pub use models::*;
 pub use database::*;
+pub use encryption::*;
 
 pub struct CloudState {
+    /// Default account, used by folders and commands without an account
     pub dropbox: Dropbox,
+    /// Other authorized accounts, by account ID
+    pub accounts: RwLock<HashMap<String, Arc<Dropbox>>>,
+    /// Client of an account being authorized, accounts are only known once authorized
+    pub pending_dropbox: Dropbox,
+}
+
+/// Client of an account, either the default one or a shared one
+pub enum AccountProvider<'a> {
+    Default(&'a Dropbox),
+    Account(Arc<Dropbox>),
+}
+
+impl Deref for AccountProvider<'_> {
+    type Target = Dropbox;
+
+    fn deref(&self) -> &Dropbox {
+        match self {
+            AccountProvider::Default(dropbox) => dropbox,
+            AccountProvider::Account(dropbox) => dropbox,
+        }
+    }
+}
+
+impl CloudState {
+    /// Load the clients of all the accounts with stored credentials
+    pub fn new() -> AnyResult<Self> {
+        let stored_accounts = Dropbox::get_stored_account_ids();
+        let default_account = get_device_settings()?.default_cloud_account;
+        let dropbox = match &default_account {
+            Some(account_id) => Dropbox::for_account(account_id),
+            None => Dropbox::new(), // Credentials from before multiple accounts were supported
+        };
+
+        let mut accounts = HashMap::new();
+        for account_id in stored_accounts {
+            if default_account.as_deref() != Some(account_id.as_str()) {
+                let client = Arc::new(Dropbox::for_account(&account_id));
+                accounts.insert(account_id, client);
+            }
+        }
+
+        Ok(Self {
+            dropbox,
+            accounts: RwLock::new(accounts),
+            pending_dropbox: Dropbox::empty(),
+        })
+    }
+
+    pub fn is_default_account(&self, account_id: Option<&str>) -> AnyResult<bool> {
+        match account_id {
+            None => Ok(true),
+            Some(account_id) => Ok(get_device_settings()?.default_cloud_account.as_deref() == Some(account_id)),
+        }
+    }
+
+    /// Get the client of an account, None is the default account
+    pub async fn get_provider(&self, account_id: Option<&str>) -> AnyResult<AccountProvider<'_>> {
+        if self.is_default_account(account_id)? {
+            return Ok(AccountProvider::Default(&self.dropbox));
+        }
+
+        let account_id = account_id.unwrap_or_default();
+        self.accounts
+            .read()
+            .await
+            .get(account_id)
+            .cloned()
+            .map(AccountProvider::Account)
+            .ok_or_else(|| SyncudioError::CloudAccountNotFound(account_id.to_string()))
+    }
 }
 
 /**
@@ -27,6 +108,10 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
             dropbox_complete_auth,
             dropbox_is_authorized,
             dropbox_unauthorize,
+            // Cloud accounts
+            get_cloud_accounts,
+            refresh_cloud_account,
+            get_cloud_storage_usage,
             // Generic cloud operations
             cloud_list_files,
             cloud_list_root_files,
@@ -37,24 +122,54 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
             // Cloud folder database operations
             get_cloud_music_folders,
             get_cloud_music_folders_by_provider,
-            get_cloud_folder_by_local_path,
+            get_cloud_music_folders_by_local_path,
+            set_cloud_folder_sync_mode,
             save_cloud_folder,
             update_cloud_folder,
             delete_cloud_folder,
+            relocate_cloud_folder,
+            verify_cloud_folder,
+            list_cloud_track_revisions,
+            restore_cloud_track_revision,
+            // Device registry
+            get_current_device,
+            set_device_name,
+            publish_device_manifest,
+            get_cloud_devices,
+            get_adoptable_cloud_folders,
+            adopt_cloud_folder,
+            // Encryption
+            get_cloud_folder_encryption_status,
+            enable_cloud_folder_encryption,
             // Cloud sync operations
             cleanup_missing_local_tracks,
             scan_cloud_music_folder,
             pull_cloud_metadata,
             push_cloud_metadata,
+            sync_cloud_playlists,
+            sync_cloud_track_stats,
             get_cloud_folder_sync_details,
+            compute_sync_plan,
+            apply_sync_plan,
+            resolve_sync_conflict,
+            get_sync_history,
+            purge_sync_history,
             get_queue_items,
             get_queue_stats,
             retry_failed_items,
+            cancel_queue_items,
+            cancel_folder_queue,
+            set_sync_paused,
+            get_sync_paused,
+            set_queue_items_priority,
+            purge_queue,
             add_to_upload_queue,
             add_to_download_queue,
             get_track_sync_status,
             // Unified track commands
             get_unified_tracks,
+            query_unified_tracks,
+            search_tracks,
             get_unified_tracks_by_ids,
             get_unified_tracks_by_folder,
             get_unified_tracks_by_provider,
@@ -70,8 +185,7 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
             check_file_exists,
         ])
         .setup(move |app_handle, _api| {
-            let dropbox = Dropbox::new();
-            app_handle.manage(CloudState { dropbox });
+            app_handle.manage(CloudState::new()?);
             
             Ok(())
         })
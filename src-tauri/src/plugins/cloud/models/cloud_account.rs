@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use ormlite::model::*;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::plugins::cloud::ProviderAccountInfo;
+
+/// A cloud storage account, each one has its own credentials
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
+#[ormlite(table = "cloud_accounts")]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudAccount {
+    #[ormlite(primary_key)]
+    pub id: String,
+    pub provider_type: String,
+    pub provider_account_id: String, // ID of the account for the provider
+    pub display_name: String,
+    pub email: String,
+    pub quota_used: Option<i64>,      // Bytes
+    pub quota_allocated: Option<i64>, // Bytes, None when unknown
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CloudAccount {
+    pub fn new(provider_type: String, info: ProviderAccountInfo) -> Self {
+        let now = Utc::now();
+        let mut account = Self {
+            id: Uuid::new_v4().to_string(),
+            provider_type,
+            provider_account_id: String::new(),
+            display_name: String::new(),
+            email: String::new(),
+            quota_used: None,
+            quota_allocated: None,
+            created_at: now,
+            updated_at: now,
+        };
+        account.update_info(info);
+        account
+    }
+
+    pub fn update_info(&mut self, info: ProviderAccountInfo) {
+        self.provider_account_id = info.provider_account_id;
+        self.display_name = info.display_name;
+        self.email = info.email;
+        self.quota_used = info.quota_used.map(|used| used as i64);
+        self.quota_allocated = info.quota_allocated.map(|allocated| allocated as i64);
+        self.updated_at = Utc::now();
+    }
+}
+
+/// A cloud account with the state of its credentials on this device
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudAccountStatus {
+    pub account: CloudAccount,
+    pub authorized: bool,
+    pub is_default: bool, // Used by folders and commands without an account
+}
@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::CloudMusicFolder;
+
+/// Cloud folder synced by a device, and where it lives on that device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct DeviceFolder {
+    pub provider_type: String,
+    pub cloud_folder_id: String,
+    pub cloud_folder_path: String,
+    pub local_folder_path: String, // Only meaningful on the device itself
+}
+
+impl From<&CloudMusicFolder> for DeviceFolder {
+    fn from(folder: &CloudMusicFolder) -> Self {
+        Self {
+            provider_type: folder.provider_type.clone(),
+            cloud_folder_id: folder.cloud_folder_id.clone(),
+            cloud_folder_path: folder.cloud_folder_path.clone(),
+            local_folder_path: folder.local_folder_path.clone(),
+        }
+    }
+}
+
+/// Description of a device, stored in `/Syncudio/devices/<device id>.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct DeviceManifest {
+    pub id: String,
+    pub name: String,
+    pub os: String,
+    pub app_version: String,
+    pub folders: Vec<DeviceFolder>,
+    pub last_sync_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Cloud folder synced by other devices, that can be mapped to a local folder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct AdoptableCloudFolder {
+    pub provider_type: String,
+    pub cloud_folder_id: String,
+    pub cloud_folder_path: String,
+    pub device_names: Vec<String>, // Devices already syncing this folder
+}
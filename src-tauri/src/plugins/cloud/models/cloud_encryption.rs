@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use ormlite::model::*;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Encryption settings of a cloud music folder. The key itself is never stored
+/// in the database, see `FolderKey`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
+#[ormlite(table = "cloud_folder_encryption")]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudFolderEncryption {
+    #[ormlite(primary_key)]
+    pub cloud_music_folder_id: String,
+    pub salt: String,
+    pub key_check: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Hash of the plaintext of a track at its last transfer. Cloud hashes of
+/// encrypted tracks describe the ciphertext, so they cannot be compared with
+/// local files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
+#[ormlite(table = "cloud_file_hashes")]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudFileHash {
+    #[ormlite(primary_key)]
+    pub cloud_map_id: String,
+    pub plaintext_hash: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudFolderEncryptionStatus {
+    pub encrypted: bool, // Whether the folder is encrypted
+    pub unlocked: bool,  // Whether this device has the key of the folder
+}
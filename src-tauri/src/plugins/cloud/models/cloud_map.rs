@@ -1,6 +1,9 @@
 use ormlite::Model;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::libs::relative_path::RelativePath;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
 #[ormlite(table = "cloud_maps")]
@@ -11,5 +14,35 @@ pub struct CloudTrackMap {
     pub cloud_track_id: String,
     pub cloud_music_folder_id: String,
     pub cloud_file_id: Option<String>,
-    pub relative_path: String,
+    pub relative_path: String, // Always a normalized RelativePath, see set_relative_path
+    #[serde(skip)]
+    pub path_key: String, // Key of the relative path, to match local tracks
+}
+
+impl CloudTrackMap {
+    pub fn new(
+        cloud_track_id: String,
+        cloud_music_folder_id: String,
+        relative_path: RelativePath,
+        cloud_file_id: Option<String>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            cloud_track_id,
+            cloud_music_folder_id,
+            cloud_file_id,
+            path_key: relative_path.key(),
+            relative_path: relative_path.into(),
+        }
+    }
+
+    pub fn get_relative_path(&self) -> RelativePath {
+        RelativePath::new(&self.relative_path)
+    }
+
+    /// Move the map to another path, its key follows
+    pub fn set_relative_path(&mut self, relative_path: RelativePath) {
+        self.path_key = relative_path.key();
+        self.relative_path = relative_path.into();
+    }
 }
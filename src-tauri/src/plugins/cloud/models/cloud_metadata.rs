@@ -2,7 +2,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-use super::CloudTrackTag;
+use super::{CloudPlaylistSyncResult, CloudTrackStatsSyncResult, CloudTrackTag};
 
 /// Represents track metadata stored in cloud storage
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
@@ -50,6 +50,8 @@ pub struct CloudMetadataSyncResult {
     pub tracks_updated: u32,      // Number of tracks updated from cloud
     pub tracks_created: u32,      // Number of new tracks created from cloud
     pub is_fresh_start: bool,     // Whether this was the first sync
+    pub playlists: CloudPlaylistSyncResult, // Playlists merged from cloud
+    pub stats: CloudTrackStatsSyncResult, // Play counts and ratings merged from cloud
 }
 
 impl CloudMetadataSyncResult {
@@ -58,6 +60,8 @@ impl CloudMetadataSyncResult {
             tracks_updated: 0,
             tracks_created: 0,
             is_fresh_start,
+            playlists: CloudPlaylistSyncResult::default(),
+            stats: CloudTrackStatsSyncResult::default(),
         }
     }
 }
@@ -68,6 +72,8 @@ impl CloudMetadataSyncResult {
 pub struct CloudMetadataUpdateResult {
     pub tracks_included: u32,     // Number of tracks included in metadata
     pub tracks_skipped: u32,      // Number of tracks skipped (missing cloud_id)
+    pub playlists: CloudPlaylistSyncResult, // Playlists merged and pushed to cloud
+    pub stats: CloudTrackStatsSyncResult, // Play counts and ratings merged and pushed to cloud
 }
 
 impl CloudMetadataUpdateResult {
@@ -75,6 +81,8 @@ impl CloudMetadataUpdateResult {
         Self {
             tracks_included: 0,
             tracks_skipped: 0,
+            playlists: CloudPlaylistSyncResult::default(),
+            stats: CloudTrackStatsSyncResult::default(),
         }
     }
 } 
\ No newline at end of file
@@ -1,8 +1,62 @@
+use std::path::{Path, PathBuf};
+
 use ormlite::model::*;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 use uuid::Uuid;
 
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::libs::relative_path::{local_path_key, RelativePath};
+
+/// How a local folder is synced with one of its cloud folders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum CloudSyncMode {
+    /// Changes go both ways
+    #[default]
+    TwoWay,
+    /// Local changes are uploaded, cloud changes are ignored (eg. a backup)
+    UploadOnly,
+    /// Cloud changes are downloaded, local changes are ignored
+    DownloadOnly,
+}
+
+impl CloudSyncMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CloudSyncMode::TwoWay => "two_way",
+            CloudSyncMode::UploadOnly => "upload_only",
+            CloudSyncMode::DownloadOnly => "download_only",
+        }
+    }
+
+    pub fn from_str(s: &str) -> AnyResult<Self> {
+        match s {
+            "two_way" => Ok(CloudSyncMode::TwoWay),
+            "upload_only" => Ok(CloudSyncMode::UploadOnly),
+            "download_only" => Ok(CloudSyncMode::DownloadOnly),
+            _ => Err(SyncudioError::InvalidSyncMode(s.to_string())),
+        }
+    }
+
+    pub fn can_upload(&self) -> bool {
+        *self != CloudSyncMode::DownloadOnly
+    }
+
+    pub fn can_download(&self) -> bool {
+        *self != CloudSyncMode::UploadOnly
+    }
+}
+
+fn default_sync_mode() -> String {
+    CloudSyncMode::default().as_str().to_string()
+}
+
+/**
+ * A cloud folder synced with a local folder. A local folder can be synced with
+ * several cloud folders, each with its own tracks maps, queue and sync mode.
+ */
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
 #[ormlite(table = "cloud_music_folders")]
 
@@ -14,6 +68,11 @@ pub struct CloudMusicFolder {
     pub cloud_folder_id: String,
     pub cloud_folder_path: String,
     pub local_folder_path: String,
+    pub account_id: Option<String>, // None for the default account
+    #[serde(default = "default_sync_mode")]
+    pub sync_mode: String, // A CloudSyncMode
+    #[serde(skip)]
+    pub local_path_key: String, // Key of the local folder path, see local_path_key
 }
 
 impl CloudMusicFolder {
@@ -29,7 +88,25 @@ impl CloudMusicFolder {
             provider_type,
             cloud_folder_id,
             cloud_folder_path,
+            local_path_key: local_path_key(&local_folder_path),
             local_folder_path,
+            account_id: None,
+            sync_mode: default_sync_mode(),
         }
     }
+
+    /// Get the sync mode, unknown modes are synced both ways
+    pub fn get_sync_mode(&self) -> CloudSyncMode {
+        CloudSyncMode::from_str(&self.sync_mode).unwrap_or_default()
+    }
+
+    /// Get the path of a local file relative to this folder, if the file lives in it
+    pub fn relative_path_of(&self, local_path: &str) -> Option<RelativePath> {
+        RelativePath::from_local(Path::new(&self.local_folder_path), Path::new(local_path))
+    }
+
+    /// Get the local path of a file of this folder
+    pub fn local_path_of(&self, relative_path: &str) -> PathBuf {
+        RelativePath::new(relative_path).to_local_path(Path::new(&self.local_folder_path))
+    }
 }
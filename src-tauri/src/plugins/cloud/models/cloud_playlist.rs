@@ -1,7 +1,30 @@
+use std::collections::{HashMap, HashSet};
+
 use chrono::{DateTime, Utc};
 use ormlite::model::*;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
+use uuid::Uuid;
+
+/// Reference to a track inside a cloud playlist.
+///
+/// Local track IDs are derived from local paths and differ between devices, so
+/// playlists stored in the cloud reference tracks by their location in a cloud
+/// folder instead. The cloud track ID is kept as a hint for faster lookups.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudPlaylistTrackRef {
+    pub cloud_track_id: Option<String>,
+    pub cloud_folder_id: String, // Provider folder ID, shared between devices
+    pub relative_path: String,
+}
+
+impl CloudPlaylistTrackRef {
+    /// Key used to compare references coming from different devices
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.cloud_folder_id, self.relative_path)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
 #[ormlite(table = "cloud_playlists")]
@@ -11,7 +34,169 @@ pub struct CloudPlaylist {
     pub id: String,
     pub name: String,
     #[ormlite(json)]
-    pub tracks: Vec<String>, // Track IDs
+    pub tracks: Vec<CloudPlaylistTrackRef>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-}
\ No newline at end of file
+    pub local_playlist_id: Option<String>, // Linked local playlist, if any
+    #[ormlite(json)]
+    pub synced_tracks: Vec<CloudPlaylistTrackRef>, // Tracks at last sync, used as merge base
+    pub synced_name: String, // Name at last sync, used as merge base
+    pub deleted: bool,
+}
+
+impl CloudPlaylist {
+    pub fn new(name: String, local_playlist_id: Option<String>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name: name.clone(),
+            tracks: Vec::new(),
+            created_at: now,
+            updated_at: now,
+            local_playlist_id,
+            synced_tracks: Vec::new(),
+            synced_name: name,
+            deleted: false,
+        }
+    }
+}
+
+/// Playlist as stored in `/Syncudio/metadata/playlists.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudPlaylistMetadata {
+    pub id: String,
+    pub name: String,
+    pub tracks: Vec<CloudPlaylistTrackRef>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted: bool, // Tombstone, so deletions propagate to other devices
+}
+
+impl From<&CloudPlaylist> for CloudPlaylistMetadata {
+    fn from(playlist: &CloudPlaylist) -> Self {
+        Self {
+            id: playlist.id.clone(),
+            name: playlist.name.clone(),
+            tracks: playlist.tracks.clone(),
+            created_at: playlist.created_at,
+            updated_at: playlist.updated_at,
+            deleted: playlist.deleted,
+        }
+    }
+}
+
+/// Collection of playlists for cloud storage
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudPlaylistCollection {
+    pub playlists: Vec<CloudPlaylistMetadata>,
+}
+
+/// Result of a playlist sync operation
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudPlaylistSyncResult {
+    pub playlists_created: u32, // Local playlists created from the cloud
+    pub playlists_updated: u32, // Local playlists updated from the cloud
+    pub playlists_deleted: u32, // Local playlists deleted because of a cloud tombstone
+    pub playlists_pushed: u32,  // Playlists written to the cloud
+}
+
+/// Identify every occurrence of a reference, so playlists containing the same
+/// track twice are merged correctly
+fn occurrence_keys(refs: &[CloudPlaylistTrackRef]) -> Vec<(String, usize)> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    refs.iter()
+        .map(|r| {
+            let key = r.key();
+            let count = seen.entry(key.clone()).or_insert(0);
+            *count += 1;
+            (key, *count)
+        })
+        .collect()
+}
+
+/**
+ * Three-way merge of playlist tracks.
+ *
+ * - tracks removed on either side since the last sync are removed
+ * - tracks added on either side are kept
+ * - if only one side reordered the tracks, its order wins, otherwise the local
+ *   order is kept. Additions from the other side are inserted after their
+ *   closest preceding neighbour.
+ */
+pub fn merge_playlist_tracks(
+    base: &[CloudPlaylistTrackRef],
+    local: &[CloudPlaylistTrackRef],
+    remote: &[CloudPlaylistTrackRef],
+) -> Vec<CloudPlaylistTrackRef> {
+    let base_keys = occurrence_keys(base);
+    let local_keys = occurrence_keys(local);
+    let remote_keys = occurrence_keys(remote);
+
+    let base_set: HashSet<&(String, usize)> = base_keys.iter().collect();
+    let local_set: HashSet<&(String, usize)> = local_keys.iter().collect();
+    let remote_set: HashSet<&(String, usize)> = remote_keys.iter().collect();
+
+    let keep = |key: &(String, usize)| -> bool {
+        if base_set.contains(key) {
+            // Present at last sync: keep only if nobody removed it
+            local_set.contains(key) && remote_set.contains(key)
+        } else {
+            // Added since last sync by at least one side
+            true
+        }
+    };
+
+    // Whoever changed the order of the common tracks wins
+    let common = |keys: &[(String, usize)]| -> Vec<(String, usize)> {
+        keys.iter()
+            .filter(|k| base_set.contains(k) && local_set.contains(k) && remote_set.contains(k))
+            .cloned()
+            .collect()
+    };
+    let local_reordered = common(&local_keys) != common(&base_keys);
+
+    let (primary, primary_keys, secondary, secondary_keys) = if local_reordered {
+        (local, &local_keys, remote, &remote_keys)
+    } else {
+        (remote, &remote_keys, local, &local_keys)
+    };
+
+    let mut merged: Vec<(CloudPlaylistTrackRef, (String, usize))> = primary
+        .iter()
+        .zip(primary_keys.iter())
+        .filter(|(_, key)| keep(key))
+        .map(|(r, key)| (r.clone(), key.clone()))
+        .collect();
+
+    let primary_set: HashSet<&(String, usize)> = primary_keys.iter().collect();
+    let mut previous: Option<(String, usize)> = None;
+
+    for (track_ref, key) in secondary.iter().zip(secondary_keys.iter()) {
+        if !primary_set.contains(key) && keep(key) {
+            let position = previous
+                .as_ref()
+                .and_then(|prev| merged.iter().position(|(_, k)| k == prev))
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            merged.insert(position, (track_ref.clone(), key.clone()));
+        }
+
+        if merged.iter().any(|(_, k)| k == key) {
+            previous = Some(key.clone());
+        }
+    }
+
+    merged.into_iter().map(|(r, _)| r).collect()
+}
+
+/// Merge playlist names, a local rename takes precedence over a remote one
+pub fn merge_playlist_name(base: &str, local: &str, remote: &str) -> String {
+    if local != base {
+        local.to_string()
+    } else {
+        remote.to_string()
+    }
+}
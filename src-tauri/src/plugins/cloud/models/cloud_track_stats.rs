@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use ormlite::model::*;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/**
+ * Listening statistics of a cloud track, shared between devices.
+ *
+ * Play counts and last played dates are recorded per device, so concurrent
+ * updates can be merged without losing plays. The rating is last-writer-wins.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
+#[ormlite(table = "cloud_track_stats")]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudTrackStats {
+    #[ormlite(primary_key)]
+    pub cloud_track_id: String,
+    pub cloud_file_id: Option<String>, // Fallback key, as cloud track IDs are generated per device
+    #[ormlite(json)]
+    pub play_counts: HashMap<String, u32>, // device id -> plays
+    #[ormlite(json)]
+    pub last_played: HashMap<String, DateTime<Utc>>, // device id -> last play
+    pub rating: Option<u32>,
+    pub rating_updated_at: Option<DateTime<Utc>>,
+}
+
+impl CloudTrackStats {
+    pub fn new(cloud_track_id: String, cloud_file_id: Option<String>) -> Self {
+        Self {
+            cloud_track_id,
+            cloud_file_id,
+            play_counts: HashMap::new(),
+            last_played: HashMap::new(),
+            rating: None,
+            rating_updated_at: None,
+        }
+    }
+
+    /// Sum of the plays of every device, except the given one
+    pub fn play_count_excluding(&self, device_id: &str) -> u32 {
+        self.play_counts
+            .iter()
+            .filter(|(id, _)| id.as_str() != device_id)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Most recent play among every device, except the given one
+    pub fn last_played_excluding(&self, device_id: &str) -> Option<DateTime<Utc>> {
+        self.last_played
+            .iter()
+            .filter(|(id, _)| id.as_str() != device_id)
+            .map(|(_, date)| *date)
+            .max()
+    }
+
+    /// Set the rating if it is more recent than the current one
+    pub fn merge_rating(&mut self, rating: Option<u32>, updated_at: Option<DateTime<Utc>>) -> bool {
+        if updated_at.is_some() && updated_at > self.rating_updated_at {
+            self.rating = rating;
+            self.rating_updated_at = updated_at;
+            return true;
+        }
+        false
+    }
+
+    /// Merge stats coming from another device. Returns true if anything changed.
+    pub fn merge(&mut self, other: &CloudTrackStats) -> bool {
+        let mut changed = false;
+
+        for (device_id, count) in &other.play_counts {
+            let current = self.play_counts.entry(device_id.clone()).or_insert(0);
+            if *count > *current {
+                *current = *count;
+                changed = true;
+            }
+        }
+
+        for (device_id, date) in &other.last_played {
+            match self.last_played.get(device_id) {
+                Some(current) if current >= date => {}
+                _ => {
+                    self.last_played.insert(device_id.clone(), *date);
+                    changed = true;
+                }
+            }
+        }
+
+        if self.cloud_file_id.is_none() && other.cloud_file_id.is_some() {
+            self.cloud_file_id = other.cloud_file_id.clone();
+            changed = true;
+        }
+
+        self.merge_rating(other.rating, other.rating_updated_at) || changed
+    }
+}
+
+/// Collection of track statistics, stored in `/Syncudio/metadata/stats.json`
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudTrackStatsCollection {
+    pub tracks: Vec<CloudTrackStats>,
+}
+
+/// Result of a stats sync operation
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudTrackStatsSyncResult {
+    pub tracks_updated: u32, // Tracks whose stats changed with the cloud ones
+    pub tracks_pushed: u32,  // Tracks whose stats were written to the cloud
+}
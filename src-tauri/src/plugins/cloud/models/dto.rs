@@ -4,7 +4,9 @@ use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 use std::path::{Path, PathBuf};
 
-use super::cloud_track::CloudTrackTag;
+use super::cloud_track::{CloudTrack, CloudTrackTag};
+use crate::libs::track::Track;
+use crate::libs::relative_path::RelativePath;
 
 /// Represents the location state of a track by checking both local and cloud existence by cloud_file_id and relative_path (should be in local storage and cloud storage)
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
@@ -25,6 +27,19 @@ pub enum TrackLocationState {
     NotMapped,
 }
 
+impl TrackLocationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackLocationState::Complete => "complete",
+            TrackLocationState::LocalOnly => "local_only",
+            TrackLocationState::CloudOnly => "cloud_only",
+            TrackLocationState::OutOfSync => "out_of_sync",
+            TrackLocationState::Missing => "missing",
+            TrackLocationState::NotMapped => "not_mapped",
+        }
+    }
+}
+
 /// Represents operation type for sync operations
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../src/generated/typings/index.ts")]
@@ -34,6 +49,15 @@ pub enum SyncOperationType {
     Download,
 }
 
+impl SyncOperationType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncOperationType::Upload => "upload",
+            SyncOperationType::Download => "download",
+        }
+    }
+}
+
 /// Represents the status of a sync operation
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../src/generated/typings/index.ts")]
@@ -46,6 +70,7 @@ pub enum SyncStatus {
         error: String,
         attempts: i32,
     },
+    Cancelled,
 }
 
 /// Represents the sync status of a cloud folder
@@ -101,15 +126,7 @@ pub struct QueueStatsDTO {
     pub in_progress_count: i32,
     pub completed_count: i32,
     pub failed_count: i32,
-}
-
-/// Represents a sync history entry
-#[derive(Debug, Clone, Serialize, Deserialize, TS)]
-#[ts(export, export_to = "../../src/generated/typings/index.ts")]
-pub struct SyncHistoryEntry {
-    pub timestamp: DateTime<Utc>,
-    pub operation: SyncOperationType,
-    pub status: SyncStatus,
+    pub cancelled_count: i32,
 }
 
 /// Represents detailed sync information for a track
@@ -134,6 +151,228 @@ pub struct CloudFolderSyncDetailsDTO {
     pub tracks: Vec<CloudTrackDTO>,
 }
 
+/// Represents an action planned by a sync
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPlanAction {
+    /// Local file is missing from the cloud, or changed locally
+    Upload,
+    /// Cloud file is missing locally, or changed in the cloud
+    Download,
+    /// Track is gone from both locations, its mapping is removed
+    Delete,
+    /// Cloud file was moved, its mapping and the local file follow it
+    Move,
+    /// Track changed on both sides, or was deleted on one side only. Never applied.
+    Conflict,
+    /// Local tags differ from the cloud ones, cloud tags are updated
+    MetadataUpdate,
+}
+
+/// Represents a single action of a sync plan
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct SyncPlanItemDTO {
+    pub action: SyncPlanAction,
+    pub cloud_track_id: Option<String>, // None for files not tracked yet
+    pub cloud_map_id: Option<String>,
+    pub cloud_file_id: Option<String>,
+    pub relative_path: String,
+    pub previous_relative_path: Option<String>, // Only for moves, and local moves not made in the cloud
+    #[ts(type = "number")]
+    pub size: u64, // Bytes to transfer
+    pub reason: String,
+}
+
+/// Represents everything a sync of a cloud folder would do, without doing it
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct SyncPlanDTO {
+    pub folder_id: String,
+    pub cloud_folder_path: String,
+    pub local_folder_path: String,
+    pub items: Vec<SyncPlanItemDTO>,
+    pub upload_count: usize,
+    pub download_count: usize,
+    pub delete_count: usize,
+    pub move_count: usize,
+    pub conflict_count: usize,
+    pub metadata_update_count: usize,
+    #[ts(type = "number")]
+    pub upload_bytes: u64,
+    #[ts(type = "number")]
+    pub download_bytes: u64,
+    #[ts(type = "number")]
+    pub estimated_seconds: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents the outcome of applying a sync plan
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct SyncPlanApplyResultDTO {
+    pub uploads_queued: usize,
+    pub downloads_queued: usize,
+    pub deletes_applied: usize,
+    pub moves_applied: usize,
+    pub metadata_updated: usize,
+    pub conflicts_skipped: usize,
+    pub items_skipped: usize, // Items that no longer match the database
+}
+
+/// Represents the pause state of the sync queue
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct QueuePauseStateDTO {
+    pub upload_paused: bool,
+    pub download_paused: bool,
+}
+
+/// Represents the number of queue items removed by a purge
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct QueuePurgeResultDTO {
+    #[ts(type = "number")]
+    pub uploads_purged: u64,
+    #[ts(type = "number")]
+    pub downloads_purged: u64,
+}
+
+/// Represents the outcome of moving the local folder of a cloud folder
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct FolderRelocationResultDTO {
+    pub folder_id: String,
+    pub previous_local_folder_path: String,
+    pub local_folder_path: String,
+    pub tracks_relocated: usize,
+    pub playlists_updated: usize,
+    pub files_verified: usize, // Sampled files whose hash matched the cloud
+}
+
+/// Represents a problem found when verifying a synced track
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityIssueKind {
+    /// File cannot be decoded as a track
+    Corrupted,
+    /// File is smaller than its other copy
+    Truncated,
+    /// File is gone, or its cloud file ID no longer resolves
+    Missing,
+    /// Both copies exist but their content differs
+    Mismatched,
+}
+
+/// Represents which copy of a track an integrity issue is about
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityIssueLocation {
+    Local,
+    Cloud,
+}
+
+/// Represents a problem found when verifying a synced track
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct IntegrityIssueDTO {
+    pub cloud_track_id: String,
+    pub cloud_map_id: String,
+    pub relative_path: String,
+    pub kind: IntegrityIssueKind,
+    pub location: IntegrityIssueLocation,
+    pub detail: String,
+    pub repair: Option<SyncOperationType>, // None without a healthy copy, or when the sync mode forbids it
+    pub repair_queued: bool,
+}
+
+/// Represents the outcome of verifying every mapped track of a cloud folder
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct FolderIntegrityReportDTO {
+    pub folder_id: String,
+    pub tracks_checked: usize,
+    pub tracks_verified: usize, // Tracks without issues
+    pub corrupted_count: usize,
+    pub truncated_count: usize,
+    pub missing_count: usize,
+    pub mismatched_count: usize,
+    pub repairs_queued: usize,
+    pub issues: Vec<IntegrityIssueDTO>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Represents which copies of a track are rolled back to a previous revision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionRestoreTarget {
+    /// The revision becomes the current cloud file, the local file is left as is
+    Cloud,
+    /// The revision is downloaded, the cloud file is left as is
+    Local,
+    /// The revision becomes the current cloud file, and is downloaded
+    Both,
+}
+
+impl RevisionRestoreTarget {
+    pub fn restores_cloud(&self) -> bool {
+        *self != RevisionRestoreTarget::Local
+    }
+
+    pub fn restores_local(&self) -> bool {
+        *self != RevisionRestoreTarget::Cloud
+    }
+}
+
+/// Represents the outcome of rolling a track back to a previous revision
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct RevisionRestoreResultDTO {
+    pub cloud_map_id: String,
+    pub revision_id: String,
+    pub cloud_revision_id: Option<String>, // New current cloud revision, when the cloud was restored
+    pub download_item_id: Option<String>, // Queued download of the local copy
+}
+
+/// Represents the space used in a cloud account, including the pending uploads
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudStorageUsageDTO {
+    pub account_id: Option<String>, // None for the default account
+    #[ts(type = "number")]
+    pub used: u64,
+    #[ts(type = "number | null")]
+    pub allocated: Option<u64>, // None when the provider has no quota
+    #[ts(type = "number")]
+    pub pending_upload_bytes: u64, // Uploads waiting in the queue
+    #[ts(type = "number | null")]
+    pub available_after_uploads: Option<u64>,
+    pub exceeds_quota: bool, // The queued uploads do not fit in the quota
+}
+
+impl CloudStorageUsageDTO {
+    /// Whether uploading more bytes on top of the queued uploads fits in the quota
+    pub fn fits(&self, bytes: u64) -> bool {
+        match self.available_after_uploads {
+            Some(available) => !self.exceeds_quota && bytes <= available,
+            None => true,
+        }
+    }
+}
+
+/// A track matching a library search, either a local track or a cloud-only track
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct TrackSearchResultDTO {
+    pub track: Option<Track>,
+    pub cloud_track: Option<CloudTrack>, // Only for tracks not in the library
+    pub rank: f64, // Lower is better, results are sorted by rank
+}
+
 /// DTO for queue statistics
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct QueueStatsGroupDTO {
@@ -168,31 +407,32 @@ pub struct CloudTrackFullDTO {
 }
 
 impl CloudTrackFullDTO {
-    /// Get the absolute path in cloud storage
+    pub fn get_relative_path(&self) -> RelativePath {
+        RelativePath::new(&self.relative_path)
+    }
+
+    /// Get the absolute path in cloud storage, cloud paths always use slashes
     pub fn cloud_path(&self) -> String {
-        Path::new(&self.cloud_folder_path)
-            .join(&self.relative_path)
-            .to_string_lossy()
-            .to_string()
+        format!("{}/{}", self.cloud_folder_path.trim_end_matches('/'), self.get_relative_path())
     }
 
     /// Get the absolute path in local filesystem
     pub fn local_path(&self) -> PathBuf {
-        Path::new(&self.local_folder_path)
-            .join(&self.relative_path)
+        self.get_relative_path().to_local_path(Path::new(&self.local_folder_path))
     }
 
     /// Get the parent directory path in cloud storage
     pub fn cloud_parent_path(&self) -> String {
-        Path::new(&self.cloud_folder_path)
-            .join(Path::new(&self.relative_path).parent().unwrap_or_else(|| Path::new("")))
-            .to_string_lossy()
-            .to_string()
+        let cloud_path = self.cloud_path();
+        match cloud_path.rsplit_once('/') {
+            Some((parent, _)) if !parent.is_empty() => parent.to_string(),
+            _ => "/".to_string(),
+        }
     }
 
     /// Get the parent directory path in local filesystem
     pub fn local_parent_path(&self) -> PathBuf {
-        Path::new(&self.local_folder_path)
-            .join(Path::new(&self.relative_path).parent().unwrap_or_else(|| Path::new("")))
+        let local_path = self.local_path();
+        local_path.parent().map(Path::to_path_buf).unwrap_or(local_path)
     }
 }
\ No newline at end of file
@@ -1,16 +1,28 @@
+pub mod cloud_account;
+pub mod cloud_device;
+pub mod cloud_encryption;
 pub mod cloud_music_folder;
+pub mod cloud_playlist;
 pub mod cloud_track;
+pub mod cloud_track_stats;
 pub mod dto;
 pub mod sync_queue;
+pub mod sync_history;
 pub mod query_models;
 pub mod cloud_map;
 pub mod unified_track;
 pub mod cloud_metadata;
 
+pub use cloud_account::*;
+pub use cloud_device::*;
+pub use cloud_encryption::*;
 pub use cloud_music_folder::*;
+pub use cloud_playlist::*;
 pub use cloud_track::*;
+pub use cloud_track_stats::*;
 pub use dto::*;
 pub use sync_queue::*;
+pub use sync_history::*;
 pub use query_models::*;
 pub use cloud_map::*;
 pub use unified_track::*;
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+use ormlite::model::*;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// How long sync history entries are kept
+pub const SYNC_HISTORY_RETENTION_DAYS: i64 = 90;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum SyncHistoryOperation {
+    Upload,
+    Download,
+    Delete,
+    ConflictResolution,
+    MetadataPush,
+    MetadataPull,
+}
+
+impl SyncHistoryOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncHistoryOperation::Upload => "upload",
+            SyncHistoryOperation::Download => "download",
+            SyncHistoryOperation::Delete => "delete",
+            SyncHistoryOperation::ConflictResolution => "conflict_resolution",
+            SyncHistoryOperation::MetadataPush => "metadata_push",
+            SyncHistoryOperation::MetadataPull => "metadata_pull",
+        }
+    }
+}
+
+/// Record of a sync operation, kept for `SYNC_HISTORY_RETENTION_DAYS`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
+#[ormlite(table = "sync_history")]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct SyncHistoryEntry {
+    #[ormlite(primary_key)]
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub operation: String, // See SyncHistoryOperation
+    pub status: String,    // "completed" or "failed"
+    pub provider_type: String,
+    pub cloud_music_folder_id: Option<String>,
+    pub cloud_track_id: Option<String>,
+    pub relative_path: Option<String>,
+    pub bytes: i64,
+    pub duration_ms: i64,
+    pub error_message: Option<String>,
+    pub details: Option<String>, // Free-form summary, eg. metadata sync counts
+}
+
+impl SyncHistoryEntry {
+    pub fn new(operation: SyncHistoryOperation, provider_type: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            operation: operation.as_str().to_string(),
+            status: "completed".to_string(),
+            provider_type,
+            cloud_music_folder_id: None,
+            cloud_track_id: None,
+            relative_path: None,
+            bytes: 0,
+            duration_ms: 0,
+            error_message: None,
+            details: None,
+        }
+    }
+
+    /// Set the track the operation was about
+    pub fn with_track(mut self, map: &super::CloudTrackMap) -> Self {
+        self.cloud_music_folder_id = Some(map.cloud_music_folder_id.clone());
+        self.cloud_track_id = Some(map.cloud_track_id.clone());
+        self.relative_path = Some(map.relative_path.clone());
+        self
+    }
+
+    pub fn fail(mut self, error: String) -> Self {
+        self.status = "failed".to_string();
+        self.error_message = Some(error);
+        self
+    }
+}
+
+/// Filter for sync history queries, every field is optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct SyncHistoryFilter {
+    pub cloud_track_id: Option<String>,
+    pub cloud_music_folder_id: Option<String>,
+    pub operation: Option<SyncHistoryOperation>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// Side kept when resolving a sync conflict
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum SyncConflictResolution {
+    /// Local file overwrites the cloud one
+    KeepLocal,
+    /// Cloud file overwrites the local one
+    KeepCloud,
+}
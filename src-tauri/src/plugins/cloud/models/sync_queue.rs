@@ -59,6 +59,7 @@ pub struct DownloadQueueItem {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub attempts: i32,
+    pub revision_id: Option<String>, // None for the current version of the file
 }
 
 impl DownloadQueueItem {
@@ -74,6 +75,7 @@ impl DownloadQueueItem {
             created_at: now,
             updated_at: now,
             attempts: 0,
+            revision_id: None,
         }
     }
 
@@ -204,4 +206,15 @@ impl UploadQueueItem {
     pub fn is_failed(&self) -> bool {
         matches!(self.get_status(), Ok(SyncQueueStatus::Failed))
     }
-} 
\ No newline at end of file
+}
+
+/// Pause state of a queue direction, missing rows mean the queue is running
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Model, TS)]
+#[ormlite(table = "sync_queue_state")]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct SyncQueueState {
+    #[ormlite(primary_key)]
+    pub operation: String, // "upload" or "download"
+    pub paused: bool,
+    pub updated_at: DateTime<Utc>,
+}
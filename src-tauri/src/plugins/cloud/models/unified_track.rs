@@ -20,6 +20,10 @@ pub struct UnifiedTrack {
     pub cloud_provider_type: Option<String>,
     pub cloud_file_id: Option<String>,
 
+    // Sync target, a track synced with several cloud folders has a row for each
+    pub location_state: String, // A TrackLocationState
+    pub cloud_sync_mode: Option<String>, // A CloudSyncMode
+
     // Core metadata
     pub title: String,
     pub album: String,
@@ -3,21 +3,28 @@ use chrono::DateTime;
 use dropbox_sdk::{
     default_client::{NoauthDefaultClient, UserAuthDefaultClient},
     files::{self},
+    users,
     oauth2::{Authorization, AuthorizeUrlBuilder, Oauth2Type, PkceCode},
 };
-use log::info;
+use log::{info, warn};
 use mime_guess::from_path;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use std::fs;
 use std::io::Read;
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
 use tokio::sync::Mutex;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::libs::credentials::CredentialStore;
+use crate::libs::relative_path::RelativePath;
 use crate::plugins::cloud::providers::CloudProviderType;
+use crate::plugins::cloud::CloudFileRevision;
 use crate::plugins::cloud::CloudProvider;
 use crate::plugins::cloud::FileHash;
+use crate::plugins::cloud::ProviderAccountInfo;
+use crate::plugins::cloud::SpaceUsage;
 use crate::plugins::config::get_storage_dir;
 use crate::{
     libs::error::{AnyResult, SyncudioError},
@@ -25,6 +32,9 @@ use crate::{
 };
 
 const DROPBOX_CLIENT_ID: &str = "jgibk23zkucv2ec";
+const CREDENTIALS_PREFIX: &str = "dropbox-";
+const LEGACY_CREDENTIALS_NAME: &str = "dropbox"; // Authorized before multiple accounts were supported
+const CONTENT_HASH_BLOCK_SIZE: usize = 4 * 1024 * 1024;
 
 type DropboxAuthData = Option<String>;
 
@@ -36,13 +46,11 @@ pub struct Dropbox {
 
 impl Dropbox {
     pub fn new() -> Self {
-        let auth_data = Self::load_auth_data_from_file();
-        if let Some(auth_data) = auth_data {
-            if let Some(dropbox) = Self::new_with_auth_data(auth_data) {
-                return dropbox;
-            }
-        }
+        Self::load_credentials(LEGACY_CREDENTIALS_NAME)
+    }
 
+    /// Create an unauthorized client
+    pub fn empty() -> Self {
         Self {
             pkce_code: Mutex::new(None),
             authorization: Mutex::new(None),
@@ -50,26 +58,87 @@ impl Dropbox {
         }
     }
 
+    /// Create a client from the stored credentials of an account
+    pub fn for_account(account_id: &str) -> Self {
+        Self::load_credentials(&Self::get_credentials_name(account_id))
+    }
+
+    fn load_credentials(name: &str) -> Self {
+        match CredentialStore::get(name) {
+            Ok(auth_data) => auth_data.and_then(Self::new_with_auth_data).unwrap_or_else(Self::empty),
+            Err(e) => {
+                warn!("Could not load Dropbox credentials: {}", e);
+                Self::empty()
+            }
+        }
+    }
+
     pub fn provider_type() -> CloudProviderType {
         CloudProviderType::Dropbox
     }
 
-    fn get_auth_file_path() -> PathBuf {
-        get_storage_dir().join("dropbox_auth.dat")
+    fn get_credentials_name(account_id: &str) -> String {
+        format!("{}{}", CREDENTIALS_PREFIX, account_id)
     }
 
-    fn load_auth_data_from_file() -> Option<String> {
-        let path = Self::get_auth_file_path();
-        fs::read_to_string(path).ok()
-    }
+    /**
+     * Move the credentials stored in plaintext by previous versions to the
+     * credential store, then get the IDs of the accounts with credentials
+     */
+    pub fn get_stored_account_ids() -> Vec<String> {
+        let legacy_path = get_storage_dir().join("dropbox_auth.dat");
+        if let Err(e) = CredentialStore::migrate_plaintext_file(LEGACY_CREDENTIALS_NAME, &legacy_path) {
+            warn!("Could not migrate Dropbox credentials: {}", e);
+        }
 
-    fn save_auth_data_to_file(auth_data: &str) -> AnyResult<()> {
-        let path = Self::get_auth_file_path();
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+        if let Ok(entries) = fs::read_dir(get_storage_dir().join("credentials")) {
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if path.extension().is_none_or(|ext| ext != "dat") {
+                    continue;
+                }
+                let Some(account_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+                if let Err(e) = CredentialStore::migrate_plaintext_file(&Self::get_credentials_name(account_id), &path) {
+                    warn!("Could not migrate Dropbox credentials of account {}: {}", account_id, e);
+                }
+            }
         }
-        fs::write(path, auth_data)?;
-        Ok(())
+
+        CredentialStore::list(CREDENTIALS_PREFIX)
+            .into_iter()
+            .map(|name| name[CREDENTIALS_PREFIX.len()..].to_string())
+            .collect()
+    }
+
+    /// Forget the credentials of the account authorized before multiple accounts were supported
+    pub fn delete_legacy_credentials() -> AnyResult<()> {
+        CredentialStore::delete(LEGACY_CREDENTIALS_NAME)
+    }
+
+    /// Store the credentials of this client for an account
+    pub async fn save_credentials(&self, account_id: &str) -> AnyResult<()> {
+        let auth_data = self
+            .authorization
+            .lock()
+            .await
+            .as_ref()
+            .and_then(|auth| auth.save())
+            .ok_or(SyncudioError::Dropbox("Not authorized".to_string()))?;
+
+        CredentialStore::set(&Self::get_credentials_name(account_id), &auth_data)
+    }
+
+    pub fn delete_credentials(account_id: &str) -> AnyResult<()> {
+        CredentialStore::delete(&Self::get_credentials_name(account_id))
+    }
+
+    /// Use the authorization of another client, eg. once an account is known
+    pub async fn authorize_with(&self, other: &Dropbox) {
+        let auth = other.authorization.lock().await.clone();
+        let client = auth.clone().map(UserAuthDefaultClient::new);
+        *self.authorization.lock().await = auth;
+        *self.client.write().await = client;
     }
 
     pub fn new_with_auth_data(auth_data: String) -> Option<Self> {
@@ -88,6 +157,75 @@ impl Dropbox {
         }
     }
 
+    /// Get the identity and storage quota of the authorized account
+    pub async fn get_account_info(&self) -> AnyResult<ProviderAccountInfo> {
+        let client = self.client.read().await;
+        let client_ref = client
+            .as_ref()
+            .ok_or(SyncudioError::Dropbox("Not authorized".to_string()))?;
+
+        let account = users::get_current_account(client_ref)?;
+        let space_usage = Self::space_usage(client_ref)?;
+
+        Ok(ProviderAccountInfo {
+            provider_account_id: account.account_id,
+            display_name: account.name.display_name,
+            email: account.email,
+            quota_used: Some(space_usage.used),
+            quota_allocated: space_usage.allocated,
+        })
+    }
+
+    fn download(client: &UserAuthDefaultClient, download_arg: &files::DownloadArg, local_path: &PathBuf) -> AnyResult<()> {
+        let result = match files::download(client, download_arg, None, None) {
+            Ok(result) => result,
+            Err(dropbox_sdk::Error::Api(files::DownloadError::Path(files::LookupError::NotFound))) => {
+                return Err(SyncudioError::CloudFileNotFound(download_arg.path.clone()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut buffer = Vec::new();
+        result
+            .body
+            .ok_or(SyncudioError::Dropbox(
+                "Failed to read file content".to_string(),
+            ))?
+            .read_to_end(&mut buffer)?;
+
+        if let Some(parent) = local_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(local_path, buffer)?;
+        Ok(())
+    }
+
+    fn to_revision(file: &files::FileMetadata, is_current: bool) -> CloudFileRevision {
+        CloudFileRevision {
+            id: file.rev.clone(),
+            size: file.size as u32,
+            modified_at: DateTime::parse_from_rfc3339(&file.server_modified).unwrap_or_default().into(),
+            hash: file.content_hash.as_ref().map(|h| FileHash::ContentHash(h.clone())),
+            is_current,
+        }
+    }
+
+    fn space_usage(client: &UserAuthDefaultClient) -> AnyResult<SpaceUsage> {
+        let space_usage = users::get_space_usage(client)?;
+        let (used, allocated) = match space_usage.allocation {
+            users::SpaceAllocation::Individual(allocation) => (space_usage.used, Some(allocation.allocated)),
+            // Without a limit for the user (0), the user shares the space of the whole team
+            users::SpaceAllocation::Team(allocation) => match allocation.user_within_team_space_allocated {
+                0 => (allocation.used, Some(allocation.allocated)),
+                limit => (space_usage.used, Some(limit)),
+            },
+            _ => (space_usage.used, None),
+        };
+
+        Ok(SpaceUsage { used, allocated })
+    }
+
     pub async fn start_authorization(&self) -> AnyResult<String> {
         info!("Generating Dropbox authorization URL");
         let pkce_code = PkceCode::new();
@@ -129,13 +267,9 @@ impl Dropbox {
         let mut write_guard = self.client.write().await;
         *write_guard = Some(client);
 
+        // Credentials are stored per account by the caller, once the account is known
         let auth_data = auth.save();
-        info!("Authorization data saved successfully: {:?}", auth_data);
-
-        // Save auth data to file
-        if let Some(auth_data_str) = &auth_data {
-            Self::save_auth_data_to_file(auth_data_str)?;
-        }
+        info!("Authorization completed successfully");
 
         Ok(auth_data)
     }
@@ -164,9 +298,6 @@ impl CloudProvider for Dropbox {
         *auth_guard = None;
         let mut write_guard = self.client.write().await;
         *write_guard = None;
-
-        // Remove auth file
-        let _ = fs::remove_file(Self::get_auth_file_path());
     }
 
     async fn list_files(&self, folder_id: &str, folder_path: &str, recursive: bool) -> AnyResult<Vec<CloudFile>> {
@@ -192,7 +323,7 @@ impl CloudProvider for Dropbox {
                 mime_type: Some(from_path(&f.name).first_or_octet_stream().to_string()),
                 hash: f.content_hash.as_ref().map(|h| FileHash::ContentHash(h.clone())),
                 display_path: f.path_display.clone(),
-                relative_path: f.path_display.clone().unwrap_or_default().strip_prefix(folder_path).unwrap_or_default().to_string().trim_start_matches('/').to_string(),
+                relative_path: RelativePath::from_cloud(folder_path, f.path_display.as_deref().unwrap_or_default()).map(String::from).unwrap_or_default(),
             }),
             files::Metadata::Folder(f) => Some(CloudFile {
                 id: f.id.clone(),
@@ -203,7 +334,7 @@ impl CloudProvider for Dropbox {
                 mime_type: None,
                 hash: None,
                 display_path: f.path_display.clone(),
-                relative_path: f.path_display.clone().unwrap_or_default().strip_prefix(folder_path).unwrap_or_default().to_string().trim_start_matches('/').to_string(),
+                relative_path: RelativePath::from_cloud(folder_path, f.path_display.as_deref().unwrap_or_default()).map(String::from).unwrap_or_default(),
             }),
             _ => None,
         }).collect();
@@ -293,23 +424,8 @@ impl CloudProvider for Dropbox {
 
         let download_arg = files::DownloadArg::new(file_id.to_string());
         info!("Downloading file from Dropbox: {} -> {}", file_id, local_path.display());
-        let result = files::download(client_ref, &download_arg, None, None)?;
-
+        Self::download(client_ref, &download_arg, local_path)?;
         info!("Downloaded file from Dropbox: {} -> {}", file_id, local_path.display());
-
-        let mut buffer = Vec::new();
-        result
-            .body
-            .ok_or(SyncudioError::Dropbox(
-                "Failed to read file content".to_string(),
-            ))?
-            .read_to_end(&mut buffer)?;
-
-        if let Some(parent) = local_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
-        std::fs::write(local_path, buffer)?;
         Ok(())
     }
 
@@ -323,4 +439,87 @@ impl CloudProvider for Dropbox {
         files::delete_v2(client_ref, &delete_arg)?;
         Ok(())
     }
+
+    async fn get_space_usage(&self) -> AnyResult<SpaceUsage> {
+        let client = self.client.read().await;
+        let client_ref = client
+            .as_ref()
+            .ok_or(SyncudioError::Dropbox("Not authorized".to_string()))?;
+
+        Self::space_usage(client_ref)
+    }
+
+    async fn list_revisions(&self, file_id: &str, limit: usize) -> AnyResult<Vec<CloudFileRevision>> {
+        let client = self.client.read().await;
+        let client_ref = client
+            .as_ref()
+            .ok_or(SyncudioError::Dropbox("Not authorized".to_string()))?;
+
+        // Listing by ID follows the file across moves
+        let list_revisions_arg = files::ListRevisionsArg::new(file_id.to_string())
+            .with_mode(files::ListRevisionsMode::Id)
+            .with_limit(limit.clamp(1, 100) as u64);
+        let result = files::list_revisions(client_ref, &list_revisions_arg)?;
+
+        Ok(result
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, file)| Self::to_revision(file, i == 0 && !result.is_deleted))
+            .collect())
+    }
+
+    async fn restore_revision(&self, file_id: &str, revision_id: &str) -> AnyResult<CloudFileRevision> {
+        let client = self.client.read().await;
+        let client_ref = client
+            .as_ref()
+            .ok_or(SyncudioError::Dropbox("Not authorized".to_string()))?;
+
+        // Restoring needs a path, the file may have moved since the revision
+        let path = match files::get_metadata(client_ref, &files::GetMetadataArg::new(file_id.to_string()))? {
+            files::Metadata::File(f) => f.path_lower.ok_or(SyncudioError::Dropbox(format!("No path for {}", file_id)))?,
+            _ => return Err(SyncudioError::Dropbox(format!("Not a file: {}", file_id))),
+        };
+
+        info!("Restoring Dropbox file {} to revision {}", path, revision_id);
+        let restore_arg = files::RestoreArg::new(path, revision_id.to_string());
+        let result = files::restore(client_ref, &restore_arg)?;
+
+        Ok(Self::to_revision(&result, true))
+    }
+
+    async fn download_revision(&self, file_id: &str, revision_id: &str, local_path: &PathBuf) -> AnyResult<()> {
+        let client = self.client.read().await;
+        let client_ref = client
+            .as_ref()
+            .ok_or(SyncudioError::Dropbox("Not authorized".to_string()))?;
+
+        let download_arg = files::DownloadArg::new(format!("rev:{}", revision_id));
+        info!("Downloading revision {} of Dropbox file {} -> {}", revision_id, file_id, local_path.display());
+        Self::download(client_ref, &download_arg, local_path)
+    }
+
+    // https://www.dropbox.com/developers/reference/content-hash
+    fn hash_local_file(&self, path: &Path) -> AnyResult<FileHash> {
+        let mut file = fs::File::open(path)?;
+        let mut block = vec![0u8; CONTENT_HASH_BLOCK_SIZE];
+        let mut hasher = Sha256::new();
+
+        loop {
+            let mut filled = 0;
+            while filled < block.len() {
+                match file.read(&mut block[filled..])? {
+                    0 => break,
+                    read => filled += read,
+                }
+            }
+            if filled == 0 {
+                break;
+            }
+            hasher.update(Sha256::digest(&block[..filled]));
+        }
+
+        let hash = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(FileHash::ContentHash(hash))
+    }
 }
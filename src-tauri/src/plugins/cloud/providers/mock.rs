@@ -0,0 +1,440 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mime_guess::from_path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::libs::error::{AnyResult, SyncudioError};
+use crate::libs::relative_path::RelativePath;
+use crate::plugins::cloud::providers::CloudProviderType;
+use crate::plugins::cloud::{CloudFile, CloudFileRevision, CloudProvider, FileHash, SpaceUsage};
+
+#[derive(Debug, Clone)]
+struct MockEntry {
+    id: String,
+    path: String, // Display path, eg. /Music/track.mp3
+    is_folder: bool,
+    content: Vec<u8>,
+    modified_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct MockRevision {
+    id: String,
+    content: Vec<u8>,
+    modified_at: DateTime<Utc>,
+}
+
+/// Faults injected in the calls made to a mock provider
+#[derive(Debug, Default)]
+struct MockFaults {
+    latency: Option<Duration>,
+    failures_left: usize,           // Number of calls that fail from now on
+    failing_paths: HashSet<String>, // Calls on these paths always fail
+    rate_limit: Option<usize>,      // Calls allowed before being rate limited
+}
+
+/**
+ * In-memory cloud provider, with the path semantics of Dropbox.
+ *
+ * Files can be referenced by ID or by path. Latency, failures and rate limits
+ * can be injected to test how callers deal with an unreliable provider.
+ */
+pub struct MockProvider {
+    entries: Mutex<BTreeMap<String, MockEntry>>, // By lowercase path, like Dropbox
+    revisions: Mutex<HashMap<String, Vec<MockRevision>>>, // By file ID, oldest first
+    faults: Mutex<MockFaults>,
+    quota: Mutex<Option<u64>>,
+    authorized: AtomicBool,
+    calls: AtomicUsize,
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+            revisions: Mutex::new(HashMap::new()),
+            faults: Mutex::new(MockFaults::default()),
+            quota: Mutex::new(None),
+            authorized: AtomicBool::new(true),
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Delay every call
+    pub fn set_latency(&self, latency: Duration) {
+        self.faults.lock().unwrap().latency = Some(latency);
+    }
+
+    /// Make the next calls fail
+    pub fn fail_next(&self, count: usize) {
+        self.faults.lock().unwrap().failures_left = count;
+    }
+
+    /// Make every call on a path fail, until the faults are cleared
+    pub fn fail_path(&self, path: &str) {
+        self.faults.lock().unwrap().failing_paths.insert(path.to_lowercase());
+    }
+
+    /// Only allow a number of calls, the next ones are rate limited
+    pub fn set_rate_limit(&self, calls: usize) {
+        self.faults.lock().unwrap().rate_limit = Some(calls);
+    }
+
+    pub fn clear_faults(&self) {
+        *self.faults.lock().unwrap() = MockFaults::default();
+    }
+
+    /// Limit the space of the account, None for no quota
+    pub fn set_quota(&self, bytes: Option<u64>) {
+        *self.quota.lock().unwrap() = bytes;
+    }
+
+    /// Number of calls made so far, including the failed ones
+    pub fn call_count(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Add a file as if another device uploaded it
+    pub fn put_file(&self, path: &str, content: &[u8]) -> CloudFile {
+        let entry = self.insert_entry(path, false, content.to_vec());
+        Self::to_cloud_file(&entry, "")
+    }
+
+    /// Get the content of a file, by ID or path
+    pub fn get_content(&self, path_or_id: &str) -> Option<Vec<u8>> {
+        let entries = self.entries.lock().unwrap();
+        Self::find(&entries, path_or_id).map(|entry| entry.content.clone())
+    }
+
+    /// Get the paths of all the files, sorted
+    pub fn file_paths(&self) -> Vec<String> {
+        let entries = self.entries.lock().unwrap();
+        entries.values().filter(|e| !e.is_folder).map(|e| e.path.clone()).collect()
+    }
+
+    /// Apply the injected faults to a call on a file, by ID or path
+    async fn call(&self, path_or_id: &str) -> AnyResult<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+
+        let path = {
+            let entries = self.entries.lock().unwrap();
+            match Self::find(&entries, path_or_id) {
+                Some(entry) => entry.path.to_lowercase(),
+                None => path_or_id.to_lowercase(),
+            }
+        };
+
+        let latency = {
+            let mut faults = self.faults.lock().unwrap();
+
+            if let Some(rate_limit) = faults.rate_limit.as_mut() {
+                if *rate_limit == 0 {
+                    return Err(SyncudioError::Dropbox("Too many requests".to_string()));
+                }
+                *rate_limit -= 1;
+            }
+
+            if faults.failures_left > 0 {
+                faults.failures_left -= 1;
+                return Err(SyncudioError::Dropbox("Injected failure".to_string()));
+            }
+
+            if faults.failing_paths.iter().any(|p| path.starts_with(p)) {
+                return Err(SyncudioError::Dropbox(format!("Injected failure on {}", path)));
+            }
+
+            faults.latency
+        };
+
+        if let Some(latency) = latency {
+            tokio::time::sleep(latency).await;
+        }
+
+        if !self.authorized.load(Ordering::SeqCst) {
+            return Err(SyncudioError::Dropbox("Not authorized".to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn normalize_path(path: &str) -> String {
+        format!("/{}", path.trim_matches('/'))
+    }
+
+    fn find<'a>(entries: &'a BTreeMap<String, MockEntry>, path_or_id: &str) -> Option<&'a MockEntry> {
+        if path_or_id.starts_with("id:") {
+            entries.values().find(|entry| entry.id == path_or_id)
+        } else {
+            entries.get(&Self::normalize_path(path_or_id).to_lowercase())
+        }
+    }
+
+    /// Resolve a folder reference to its path, "" is the root
+    fn resolve_folder(&self, path_or_id: &str) -> AnyResult<String> {
+        if path_or_id.is_empty() || path_or_id == "/" {
+            return Ok("/".to_string());
+        }
+
+        let entries = self.entries.lock().unwrap();
+        match Self::find(&entries, path_or_id) {
+            Some(entry) if entry.is_folder => Ok(entry.path.clone()),
+            _ => Err(SyncudioError::Dropbox(format!("Folder not found: {}", path_or_id))),
+        }
+    }
+
+    /// Insert or overwrite an entry, creating its parent folders
+    fn insert_entry(&self, path: &str, is_folder: bool, content: Vec<u8>) -> MockEntry {
+        let path = Self::normalize_path(path);
+        let mut entries = self.entries.lock().unwrap();
+
+        let mut parent = String::new();
+        let components: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        for component in &components[..components.len() - 1] {
+            parent = format!("{}/{}", parent, component);
+            entries.entry(parent.to_lowercase()).or_insert_with(|| MockEntry {
+                id: format!("id:{}", Uuid::new_v4()),
+                path: parent.clone(),
+                is_folder: true,
+                content: Vec::new(),
+                modified_at: Utc::now(),
+            });
+        }
+
+        // Overwriting keeps the ID, like Dropbox does
+        let id = entries
+            .get(&path.to_lowercase())
+            .map(|entry| entry.id.clone())
+            .unwrap_or_else(|| format!("id:{}", Uuid::new_v4()));
+
+        let entry = MockEntry {
+            id,
+            path: path.clone(),
+            is_folder,
+            content,
+            modified_at: Utc::now(),
+        };
+        entries.insert(path.to_lowercase(), entry.clone());
+
+        // Every write of a file is a revision, like Dropbox keeps them
+        if !is_folder {
+            self.revisions.lock().unwrap().entry(entry.id.clone()).or_default().push(MockRevision {
+                id: Uuid::new_v4().simple().to_string(),
+                content: entry.content.clone(),
+                modified_at: entry.modified_at,
+            });
+        }
+
+        entry
+    }
+
+    fn find_revision(&self, file_id: &str, revision_id: &str) -> AnyResult<MockRevision> {
+        self.revisions
+            .lock()
+            .unwrap()
+            .get(file_id)
+            .and_then(|revisions| revisions.iter().find(|r| r.id == revision_id).cloned())
+            .ok_or(SyncudioError::Dropbox(format!("Revision not found: {}", revision_id)))
+    }
+
+    fn to_cloud_file(entry: &MockEntry, folder_path: &str) -> CloudFile {
+        let name = entry.path.rsplit('/').next().unwrap_or_default().to_string();
+        let relative_path = RelativePath::from_cloud(folder_path, &entry.path)
+            .map(String::from)
+            .unwrap_or_default();
+
+        CloudFile {
+            id: entry.id.clone(),
+            name: name.clone(),
+            size: entry.content.len() as u32,
+            is_folder: entry.is_folder,
+            modified_at: entry.modified_at,
+            mime_type: (!entry.is_folder).then(|| from_path(&name).first_or_octet_stream().to_string()),
+            hash: (!entry.is_folder).then(|| FileHash::ContentHash(blake3::hash(&entry.content).to_hex().to_string())),
+            display_path: Some(entry.path.clone()),
+            relative_path,
+        }
+    }
+
+    fn child_path(parent_ref: Option<&str>, name: &str) -> String {
+        match parent_ref {
+            Some(path) if !path.is_empty() => format!("{}/{}", path.trim_end_matches('/'), name),
+            _ => format!("/{}", name),
+        }
+    }
+}
+
+#[async_trait]
+impl CloudProvider for MockProvider {
+    fn provider_type(&self) -> CloudProviderType {
+        CloudProviderType::Dropbox
+    }
+
+    async fn is_authorized(&self) -> bool {
+        self.authorized.load(Ordering::SeqCst)
+    }
+
+    async fn unauthorize(&self) {
+        self.authorized.store(false, Ordering::SeqCst);
+    }
+
+    async fn list_files(&self, folder_id: &str, folder_path: &str, recursive: bool) -> AnyResult<Vec<CloudFile>> {
+        self.call(folder_path).await?;
+        let folder = self.resolve_folder(folder_id)?;
+        let prefix = match folder.as_str() {
+            "/" => "/".to_string(),
+            folder => format!("{}/", folder.to_lowercase()),
+        };
+
+        let entries = self.entries.lock().unwrap();
+        let files = entries
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .filter(|(key, _)| recursive || !key[prefix.len()..].contains('/'))
+            .map(|(_, entry)| Self::to_cloud_file(entry, &folder))
+            .collect();
+
+        Ok(files)
+    }
+
+    async fn list_root_files(&self, recursive: bool) -> AnyResult<Vec<CloudFile>> {
+        self.list_files("", "/", recursive).await
+    }
+
+    async fn create_folder(&self, name: &str, parent_ref: Option<&str>) -> AnyResult<CloudFile> {
+        let path = Self::child_path(parent_ref, name);
+        self.call(&path).await?;
+
+        if self.entries.lock().unwrap().contains_key(&path.to_lowercase()) {
+            return Err(SyncudioError::Dropbox(format!("Folder already exists: {}", path)));
+        }
+
+        let entry = self.insert_entry(&path, true, Vec::new());
+        Ok(CloudFile {
+            relative_path: name.to_string(),
+            ..Self::to_cloud_file(&entry, "")
+        })
+    }
+
+    async fn upload_file(&self, local_path: &PathBuf, name: &str, parent_ref: Option<&str>) -> AnyResult<CloudFile> {
+        let path = Self::child_path(parent_ref, name);
+        self.call(&path).await?;
+
+        let content = std::fs::read(local_path)?;
+        let entry = self.insert_entry(&path, false, content);
+        Ok(CloudFile {
+            relative_path: name.to_string(),
+            ..Self::to_cloud_file(&entry, "")
+        })
+    }
+
+    async fn download_file(&self, file_id: &str, local_path: &PathBuf) -> AnyResult<()> {
+        self.call(file_id).await?;
+
+        let content = {
+            let entries = self.entries.lock().unwrap();
+            match Self::find(&entries, file_id) {
+                Some(entry) if !entry.is_folder => entry.content.clone(),
+                _ => return Err(SyncudioError::CloudFileNotFound(file_id.to_string())),
+            }
+        };
+
+        if let Some(parent) = local_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(local_path, content)?;
+        Ok(())
+    }
+
+    async fn delete_file(&self, file_id: &str) -> AnyResult<()> {
+        self.call(file_id).await?;
+
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = Self::find(&entries, file_id).cloned() else {
+            return Err(SyncudioError::Dropbox(format!("File not found: {}", file_id)));
+        };
+
+        // Deleting a folder deletes its content
+        let key = entry.path.to_lowercase();
+        let folder_prefix = format!("{}/", key);
+        entries.retain(|path, _| *path != key && !path.starts_with(&folder_prefix));
+        Ok(())
+    }
+
+    async fn get_space_usage(&self) -> AnyResult<SpaceUsage> {
+        self.call("/").await?;
+
+        let used = self.entries.lock().unwrap().values().map(|entry| entry.content.len() as u64).sum();
+        Ok(SpaceUsage {
+            used,
+            allocated: *self.quota.lock().unwrap(),
+        })
+    }
+
+    fn hash_local_file(&self, path: &Path) -> AnyResult<FileHash> {
+        let content = std::fs::read(path)?;
+        Ok(FileHash::ContentHash(blake3::hash(&content).to_hex().to_string()))
+    }
+
+    async fn list_revisions(&self, file_id: &str, limit: usize) -> AnyResult<Vec<CloudFileRevision>> {
+        self.call(file_id).await?;
+
+        let exists = Self::find(&self.entries.lock().unwrap(), file_id).is_some();
+        let revisions = self.revisions.lock().unwrap().get(file_id).cloned().unwrap_or_default();
+
+        Ok(revisions
+            .iter()
+            .rev()
+            .take(limit)
+            .enumerate()
+            .map(|(i, revision)| CloudFileRevision {
+                id: revision.id.clone(),
+                size: revision.content.len() as u32,
+                modified_at: revision.modified_at,
+                hash: Some(FileHash::ContentHash(blake3::hash(&revision.content).to_hex().to_string())),
+                is_current: i == 0 && exists,
+            })
+            .collect())
+    }
+
+    async fn restore_revision(&self, file_id: &str, revision_id: &str) -> AnyResult<CloudFileRevision> {
+        self.call(file_id).await?;
+
+        let revision = self.find_revision(file_id, revision_id)?;
+        let path = Self::find(&self.entries.lock().unwrap(), file_id)
+            .map(|entry| entry.path.clone())
+            .ok_or(SyncudioError::Dropbox(format!("File not found: {}", file_id)))?;
+
+        let entry = self.insert_entry(&path, false, revision.content);
+        let revisions = self.revisions.lock().unwrap();
+        let current = revisions.get(&entry.id).and_then(|r| r.last()).unwrap();
+        Ok(CloudFileRevision {
+            id: current.id.clone(),
+            size: current.content.len() as u32,
+            modified_at: current.modified_at,
+            hash: Some(FileHash::ContentHash(blake3::hash(&current.content).to_hex().to_string())),
+            is_current: true,
+        })
+    }
+
+    async fn download_revision(&self, file_id: &str, revision_id: &str, local_path: &PathBuf) -> AnyResult<()> {
+        self.call(file_id).await?;
+
+        let revision = self.find_revision(file_id, revision_id)?;
+        if let Some(parent) = local_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(local_path, revision.content)?;
+        Ok(())
+    }
+}
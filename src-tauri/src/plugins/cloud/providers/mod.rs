@@ -1,7 +1,11 @@
 mod dropbox;
+#[cfg(test)]
+mod mock;
 
 use chrono::{DateTime, Utc};
 pub use dropbox::*;
+#[cfg(test)]
+pub use mock::*;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
@@ -55,8 +59,47 @@ pub enum FileHash {
     ContentHash(String), // For Dropbox
 }
 
+impl FileHash {
+    pub fn value(&self) -> &str {
+        match self {
+            FileHash::Sha1(hash) | FileHash::Sha256(hash) | FileHash::ContentHash(hash) => hash,
+        }
+    }
+}
+
+/// A version of a cloud file kept by the provider, the newest one is current
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct CloudFileRevision {
+    pub id: String,
+    pub size: u32,
+    pub modified_at: DateTime<Utc>,
+    pub hash: Option<FileHash>,
+    pub is_current: bool,
+}
+
+/// Space used in a cloud account and its quota, in bytes
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct SpaceUsage {
+    #[ts(type = "number")]
+    pub used: u64,
+    #[ts(type = "number | null")]
+    pub allocated: Option<u64>, // None when the provider has no quota
+}
+
+/// Identity and storage quota of a cloud account
+#[derive(Debug, Clone)]
+pub struct ProviderAccountInfo {
+    pub provider_account_id: String,
+    pub display_name: String,
+    pub email: String,
+    pub quota_used: Option<u64>,
+    pub quota_allocated: Option<u64>,
+}
+
 use async_trait::async_trait;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[async_trait]
 pub trait CloudProvider {
@@ -68,6 +111,25 @@ pub trait CloudProvider {
     async fn upload_file(&self, local_path: &PathBuf, name: &str, parent_ref: Option<&str>) -> AnyResult<CloudFile>;
     async fn download_file(&self, file_id: &str, local_path: &PathBuf) -> AnyResult<()>;
     async fn delete_file(&self, file_id: &str) -> AnyResult<()>;
+    async fn get_space_usage(&self) -> AnyResult<SpaceUsage>;
+
+    // Hash a local file the way the provider hashes its files, to compare them
+    fn hash_local_file(&self, path: &Path) -> AnyResult<FileHash>;
+
+    // File revisions, newest first, for providers that keep them
+    async fn list_revisions(&self, _file_id: &str, _limit: usize) -> AnyResult<Vec<CloudFileRevision>> {
+        Err(SyncudioError::RevisionsNotSupported(self.provider_type().as_str().to_string()))
+    }
+
+    // Make a revision the current version of a file, returns the new current revision
+    async fn restore_revision(&self, _file_id: &str, _revision_id: &str) -> AnyResult<CloudFileRevision> {
+        Err(SyncudioError::RevisionsNotSupported(self.provider_type().as_str().to_string()))
+    }
+
+    // Download a revision of a file, leaving the cloud file as it is
+    async fn download_revision(&self, _file_id: &str, _revision_id: &str, _local_path: &PathBuf) -> AnyResult<()> {
+        Err(SyncudioError::RevisionsNotSupported(self.provider_type().as_str().to_string()))
+    }
 
     // Get the full path or ID for a parent reference based on provider
     fn get_parent_ref(&self, parent_id: Option<&str>, parent_path: Option<&str>) -> Option<String> {
@@ -82,6 +144,11 @@ pub trait CloudProvider {
 
     // Metadata sync methods
     async fn ensure_metadata_folder(&self) -> AnyResult<String> {
+        self.ensure_syncudio_folder("metadata").await
+    }
+
+    // Create /Syncudio/<name> if it doesn't exist, returns its ID
+    async fn ensure_syncudio_folder(&self, name: &str) -> AnyResult<String> {
         // Create /Syncudio if it doesn't exist
         let syncudio = match self.list_root_files(false).await?.iter()
             .find(|f| f.is_folder && f.name == "Syncudio") {
@@ -92,18 +159,18 @@ pub trait CloudProvider {
                 }
             };
 
-        // Create /Syncudio/metadata if it doesn't exist
-        let metadata = match self.list_files(&syncudio, "/Syncudio", false).await?.iter()
-            .find(|f| f.is_folder && f.name == "metadata") {
+        // Create /Syncudio/<name> if it doesn't exist
+        let folder = match self.list_files(&syncudio, "/Syncudio", false).await?.iter()
+            .find(|f| f.is_folder && f.name == name) {
                 Some(f) => Ok(f.id.clone()),
                 None => {
                     let parent_ref = self.get_parent_ref(
                         Some(&syncudio),
                         Some("/Syncudio")
                     );
-                    Ok(self.create_folder("metadata", parent_ref.as_deref()).await?.id)
+                    Ok(self.create_folder(name, parent_ref.as_deref()).await?.id)
                 }
             };
-        metadata
+        folder
     }
 } 
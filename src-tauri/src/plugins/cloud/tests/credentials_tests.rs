@@ -0,0 +1,80 @@
+use std::fs;
+
+use tokio::sync::Mutex;
+
+use crate::libs::credentials::CredentialStore;
+use crate::libs::error::SyncudioError;
+use crate::plugins::config::get_storage_dir;
+
+// Every test shares the key file of the storage dir
+static CREDENTIALS_LOCK: Mutex<()> = Mutex::const_new(());
+
+fn secret_name(prefix: &str) -> String {
+    format!("{}{}", prefix, uuid::Uuid::new_v4())
+}
+
+#[tokio::test]
+async fn test_credentials_round_trip() {
+    let _lock = CREDENTIALS_LOCK.lock().await;
+    let name = secret_name("test_round_trip_");
+
+    assert_eq!(CredentialStore::get(&name).unwrap(), None);
+
+    CredentialStore::set(&name, "secret token").unwrap();
+    assert_eq!(CredentialStore::get(&name).unwrap().as_deref(), Some("secret token"));
+    assert!(CredentialStore::list("test_round_trip_").contains(&name));
+
+    // The secret is not stored in plaintext
+    let content = fs::read(get_storage_dir().join("credentials").join(format!("{}.enc", name))).unwrap();
+    assert!(!content.windows(6).any(|w| w == b"secret"));
+
+    CredentialStore::set(&name, "new token").unwrap();
+    assert_eq!(CredentialStore::get(&name).unwrap().as_deref(), Some("new token"));
+
+    CredentialStore::delete(&name).unwrap();
+    assert_eq!(CredentialStore::get(&name).unwrap(), None);
+    CredentialStore::delete(&name).unwrap();
+}
+
+#[tokio::test]
+async fn test_credentials_plaintext_migration() {
+    let _lock = CREDENTIALS_LOCK.lock().await;
+    let name = secret_name("test_migration_");
+    let path = get_storage_dir().join(format!("{}.dat", name));
+    fs::create_dir_all(get_storage_dir()).unwrap();
+
+    fs::write(&path, "legacy token\n").unwrap();
+    CredentialStore::migrate_plaintext_file(&name, &path).unwrap();
+    assert!(!path.exists());
+    assert_eq!(CredentialStore::get(&name).unwrap().as_deref(), Some("legacy token"));
+
+    // A secret already in the store is kept
+    fs::write(&path, "older token").unwrap();
+    CredentialStore::migrate_plaintext_file(&name, &path).unwrap();
+    assert!(!path.exists());
+    assert_eq!(CredentialStore::get(&name).unwrap().as_deref(), Some("legacy token"));
+
+    // Nothing to migrate
+    CredentialStore::migrate_plaintext_file(&name, &path).unwrap();
+
+    CredentialStore::delete(&name).unwrap();
+}
+
+#[tokio::test]
+async fn test_credentials_with_another_key_are_not_readable() {
+    let _lock = CREDENTIALS_LOCK.lock().await;
+    let name = secret_name("test_wrong_key_");
+    CredentialStore::set(&name, "secret token").unwrap();
+
+    let key_path = get_storage_dir().join("credentials.key");
+    let key = fs::read(&key_path).unwrap();
+    fs::write(&key_path, [7u8; 32]).unwrap();
+
+    let result = CredentialStore::get(&name);
+
+    fs::write(&key_path, &key).unwrap();
+    assert!(matches!(result, Err(SyncudioError::Credentials(_))));
+    assert_eq!(CredentialStore::get(&name).unwrap().as_deref(), Some("secret token"));
+
+    CredentialStore::delete(&name).unwrap();
+}
@@ -0,0 +1,66 @@
+use crate::libs::error::SyncudioError;
+use crate::plugins::cloud::{decrypted_size, FolderKey};
+
+fn derive_key(passphrase: &str) -> FolderKey {
+    FolderKey::derive(passphrase, b"syncudio-test-salt").unwrap()
+}
+
+#[test]
+fn test_name_encryption_round_trip() {
+    let key = derive_key("passphrase");
+
+    for name in ["track.flac", "Café del Mar.mp3", "a", ""] {
+        let encrypted = key.encrypt_name(name).unwrap();
+        assert!(!encrypted.contains('/'));
+        assert_eq!(key.decrypt_name(&encrypted).unwrap(), name);
+    }
+
+    // Names are encrypted deterministically, so they can be found by path
+    assert_eq!(key.encrypt_name("track.flac").unwrap(), key.encrypt_name("track.flac").unwrap());
+
+    let path = "Artist/Album/01 - Track.flac";
+    let encrypted = key.encrypt_path(path).unwrap();
+    assert_eq!(encrypted.split('/').count(), 3);
+    assert_eq!(key.decrypt_path(&encrypted).unwrap(), path);
+
+    // Another key cannot read the names
+    assert!(derive_key("other").decrypt_name(&key.encrypt_name("track.flac").unwrap()).is_err());
+}
+
+#[test]
+fn test_long_names_are_rejected() {
+    let key = derive_key("passphrase");
+
+    // The longest name whose encrypted form fits in a path component
+    let longest = "a".repeat(151);
+    let encrypted = key.encrypt_name(&longest).unwrap();
+    assert_eq!(encrypted.len(), 255);
+    assert_eq!(key.decrypt_name(&encrypted).unwrap(), longest);
+
+    let too_long = format!("{}.flac", "a".repeat(150));
+    assert!(matches!(key.encrypt_name(&too_long), Err(SyncudioError::Encryption(_))));
+    assert!(key.encrypt_path(&format!("Album/{}", too_long)).is_err());
+}
+
+#[test]
+fn test_file_encryption_round_trip() {
+    let key = derive_key("passphrase");
+    let dir = std::env::temp_dir().join(format!("syncudio-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // Spans several chunks
+    let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let source = dir.join("track.wav");
+    let encrypted = dir.join("track.enc");
+    let decrypted = dir.join("track.dec");
+    std::fs::write(&source, &content).unwrap();
+
+    key.encrypt_file(&source, &encrypted).unwrap();
+    let encrypted_size = std::fs::metadata(&encrypted).unwrap().len();
+    assert_eq!(decrypted_size(encrypted_size), content.len() as u64);
+
+    key.decrypt_file(&encrypted, &decrypted).unwrap();
+    assert_eq!(std::fs::read(&decrypted).unwrap(), content);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
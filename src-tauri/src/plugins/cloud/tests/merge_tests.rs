@@ -0,0 +1,136 @@
+use chrono::{TimeZone, Utc};
+
+use crate::plugins::cloud::{merge_playlist_name, merge_playlist_tracks, CloudPlaylistTrackRef, CloudTrackStats};
+
+/** ----------------------------------------------------------------------------
+ * Playlists
+ * -------------------------------------------------------------------------- */
+
+fn refs(relative_paths: &[&str]) -> Vec<CloudPlaylistTrackRef> {
+    relative_paths
+        .iter()
+        .map(|relative_path| CloudPlaylistTrackRef {
+            cloud_track_id: None,
+            cloud_folder_id: "folder".to_string(),
+            relative_path: relative_path.to_string(),
+        })
+        .collect()
+}
+
+fn merge(base: &[&str], local: &[&str], remote: &[&str]) -> Vec<String> {
+    merge_playlist_tracks(&refs(base), &refs(local), &refs(remote))
+        .into_iter()
+        .map(|track_ref| track_ref.relative_path)
+        .collect()
+}
+
+#[test]
+fn test_playlist_merge_keeps_changes_of_both_sides() {
+    // b removed and d added locally, e added remotely
+    let merged = merge(&["a", "b", "c"], &["a", "c", "d"], &["a", "b", "c", "e"]);
+    assert_eq!(merged, vec!["a", "c", "d", "e"]);
+
+    // Without a base, nothing was removed
+    assert_eq!(merge(&[], &["a"], &["b"]), vec!["a", "b"]);
+}
+
+#[test]
+fn test_playlist_merge_keeps_the_order_of_the_side_that_reordered() {
+    // Local reordered, the remote addition follows its neighbour
+    let merged = merge(&["a", "b", "c"], &["c", "a", "b"], &["a", "b", "x", "c"]);
+    assert_eq!(merged, vec!["c", "a", "b", "x"]);
+
+    // Remote reordered
+    let merged = merge(&["a", "b", "c"], &["a", "b", "c"], &["c", "b", "a"]);
+    assert_eq!(merged, vec!["c", "b", "a"]);
+
+    // Both reordered, local wins
+    let merged = merge(&["a", "b", "c"], &["b", "a", "c"], &["c", "b", "a"]);
+    assert_eq!(merged, vec!["b", "a", "c"]);
+}
+
+#[test]
+fn test_playlist_merge_tells_duplicates_apart() {
+    // The second occurrence of a is removed locally, not the first one
+    let merged = merge(&["a", "b", "a"], &["a", "b"], &["a", "b", "a", "c"]);
+    assert_eq!(merged, vec!["a", "b", "c"]);
+
+    // The same track added on both sides is only there once
+    let merged = merge(&["a"], &["a", "b"], &["a", "b"]);
+    assert_eq!(merged, vec!["a", "b"]);
+}
+
+#[test]
+fn test_playlist_name_merge() {
+    assert_eq!(merge_playlist_name("Old", "Local", "Old"), "Local");
+    assert_eq!(merge_playlist_name("Old", "Old", "Remote"), "Remote");
+    assert_eq!(merge_playlist_name("Old", "Local", "Remote"), "Local");
+}
+
+/** ----------------------------------------------------------------------------
+ * Stats
+ * -------------------------------------------------------------------------- */
+
+fn stats(play_counts: &[(&str, u32)]) -> CloudTrackStats {
+    let mut stats = CloudTrackStats::new("track".to_string(), None);
+    for (device_id, count) in play_counts {
+        stats.play_counts.insert(device_id.to_string(), *count);
+    }
+    stats
+}
+
+#[test]
+fn test_stats_merge_keeps_the_plays_of_every_device() {
+    let mut local = stats(&[("desktop", 3), ("laptop", 1)]);
+    let remote = stats(&[("desktop", 2), ("laptop", 4), ("phone", 2)]);
+
+    assert!(local.merge(&remote));
+    assert_eq!(local.play_counts.get("desktop"), Some(&3));
+    assert_eq!(local.play_counts.get("laptop"), Some(&4));
+    assert_eq!(local.play_counts.get("phone"), Some(&2));
+    assert_eq!(local.play_count_excluding("desktop"), 6);
+
+    // Merging the same stats again changes nothing
+    assert!(!local.merge(&remote));
+}
+
+#[test]
+fn test_stats_merge_keeps_the_latest_plays() {
+    let older = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let newer = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+
+    let mut local = stats(&[]);
+    local.last_played.insert("desktop".to_string(), newer);
+    local.last_played.insert("laptop".to_string(), older);
+
+    let mut remote = stats(&[]);
+    remote.last_played.insert("desktop".to_string(), older);
+    remote.last_played.insert("laptop".to_string(), newer);
+
+    assert!(local.merge(&remote));
+    assert_eq!(local.last_played.get("desktop"), Some(&newer));
+    assert_eq!(local.last_played.get("laptop"), Some(&newer));
+    assert_eq!(local.last_played_excluding("laptop"), Some(newer));
+}
+
+#[test]
+fn test_stats_merge_keeps_the_latest_rating() {
+    let older = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let newer = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+
+    let mut local = stats(&[]);
+    local.merge_rating(Some(3), Some(newer));
+
+    let mut remote = stats(&[]);
+    remote.merge_rating(Some(5), Some(older));
+    remote.cloud_file_id = Some("file".to_string());
+
+    // The older rating is ignored, the file ID is filled in
+    assert!(local.merge(&remote));
+    assert_eq!(local.rating, Some(3));
+    assert_eq!(local.cloud_file_id.as_deref(), Some("file"));
+
+    remote.merge_rating(Some(1), Some(newer + chrono::Duration::days(1)));
+    assert!(local.merge(&remote));
+    assert_eq!(local.rating, Some(1));
+}
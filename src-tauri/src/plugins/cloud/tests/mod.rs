@@ -0,0 +1,189 @@
+mod credentials_tests;
+mod encryption_tests;
+mod merge_tests;
+mod path_tests;
+mod sync_tests;
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use lofty::config::WriteOptions;
+use lofty::tag::{Accessor, Tag, TagExt, TagType};
+use ormlite::sqlite::{SqliteConnectOptions, SqliteConnection};
+use ormlite::Connection;
+use uuid::Uuid;
+
+use crate::libs::database::DB;
+use crate::libs::track::{get_track_from_file, Track};
+use crate::plugins::cloud::providers::MockProvider;
+use crate::plugins::cloud::{CloudMusicFolder, CloudProvider, CloudSyncMode, UnifiedTrack};
+use crate::plugins::db::DBState;
+
+/** ----------------------------------------------------------------------------
+ * Test environment: a device with its own database and local folders, syncing
+ * with a cloud provider that can be shared with other devices
+ * -------------------------------------------------------------------------- */
+
+pub struct TestDevice {
+    pub db_state: DBState,
+    pub provider: Arc<MockProvider>,
+    pub dir: PathBuf,
+}
+
+impl TestDevice {
+    pub async fn new() -> Self {
+        Self::with_provider(Arc::new(MockProvider::new())).await
+    }
+
+    /// Create a device syncing with the same cloud as another one
+    pub async fn with_provider(provider: Arc<MockProvider>) -> Self {
+        let options = SqliteConnectOptions::new().in_memory(true);
+        let connection = SqliteConnection::connect_with(&options).await.unwrap();
+
+        let mut db = DB { connection };
+        db.create_tables(None).await.unwrap();
+
+        let dir = std::env::temp_dir().join(format!("syncudio-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        Self {
+            db_state: DBState::new(db),
+            provider,
+            dir,
+        }
+    }
+
+    /// Sync a local folder with a cloud folder, creating the cloud folder if needed
+    pub async fn add_folder(&self, name: &str) -> CloudMusicFolder {
+        let cloud_path = format!("/{}", name);
+        let cloud_folder_id = match self
+            .provider
+            .list_root_files(false)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|f| f.is_folder && f.name == name)
+        {
+            Some(folder) => folder.id,
+            None => self.provider.create_folder(name, Some("/")).await.unwrap().id,
+        };
+
+        let local_path = self.dir.join(name);
+        fs::create_dir_all(&local_path).unwrap();
+
+        let folder = CloudMusicFolder::new(
+            "dropbox".to_string(),
+            cloud_folder_id,
+            cloud_path,
+            local_path.to_string_lossy().to_string(),
+        );
+
+        let mut db = self.db_state.get_lock().await;
+        db.save_cloud_folder(folder).await.unwrap()
+    }
+
+    /// Sync an existing local folder with another cloud folder, eg. a backup on another provider
+    pub async fn add_target(
+        &self,
+        folder: &CloudMusicFolder,
+        provider: &MockProvider,
+        name: &str,
+        sync_mode: CloudSyncMode,
+    ) -> CloudMusicFolder {
+        let cloud_folder = provider.create_folder(name, Some("/")).await.unwrap();
+
+        let target = CloudMusicFolder {
+            sync_mode: sync_mode.as_str().to_string(),
+            ..CloudMusicFolder::new(
+                "dropbox".to_string(),
+                cloud_folder.id,
+                format!("/{}", name),
+                folder.local_folder_path.clone(),
+            )
+        };
+
+        let mut db = self.db_state.get_lock().await;
+        db.save_cloud_folder(target).await.unwrap()
+    }
+
+    /// Write a track in a local folder and add it to the library
+    pub async fn add_local_track(&self, folder: &CloudMusicFolder, relative_path: &str, title: &str) -> Track {
+        let path = PathBuf::from(&folder.local_folder_path).join(relative_path);
+        write_track_file(&path, title);
+
+        let track = get_track_from_file(&path).expect("Test track should be readable");
+        let mut db = self.db_state.get_lock().await;
+        db.insert_tracks(vec![track.clone()]).await.unwrap();
+
+        track
+    }
+}
+
+impl Drop for TestDevice {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub async fn get_folder_tracks(device: &TestDevice, folder: &CloudMusicFolder) -> Vec<UnifiedTrack> {
+    let mut db = device.db_state.get_lock().await;
+    db.get_unified_tracks_by_folder(&folder.id).await.unwrap()
+}
+
+pub fn find_track<'a>(tracks: &'a [UnifiedTrack], relative_path: &str) -> &'a UnifiedTrack {
+    tracks
+        .iter()
+        .find(|t| t.cloud_relative_path.as_deref() == Some(relative_path))
+        .unwrap_or_else(|| panic!("Track not found: {}", relative_path))
+}
+
+/// Upload a track file as if another device synced it
+pub fn put_cloud_track(device: &TestDevice, cloud_path: &str, title: &str) {
+    let path = device.dir.join("remote").join(format!("{}.wav", title));
+    write_track_file(&path, title);
+    device.provider.put_file(cloud_path, &fs::read(&path).unwrap());
+}
+
+/// Write a short WAV file with an ID3v2 title. The samples are made of the
+/// title, so that tracks with different titles have different audio.
+pub fn write_track_file(path: &PathBuf, title: &str) {
+    write_untagged_track_file(path, title);
+    tag_track_file(path, title);
+}
+
+/// Write a short WAV file without tags, its samples are made of the title
+pub fn write_untagged_track_file(path: &PathBuf, title: &str) {
+    let samples: u32 = 8000;
+    let data_len = samples * 2;
+
+    let mut content = Vec::new();
+    content.extend_from_slice(b"RIFF");
+    content.extend_from_slice(&(36 + data_len).to_le_bytes());
+    content.extend_from_slice(b"WAVE");
+    content.extend_from_slice(b"fmt ");
+    content.extend_from_slice(&16u32.to_le_bytes()); // Chunk size
+    content.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    content.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    content.extend_from_slice(&8000u32.to_le_bytes()); // Sample rate
+    content.extend_from_slice(&16000u32.to_le_bytes()); // Byte rate
+    content.extend_from_slice(&2u16.to_le_bytes()); // Block align
+    content.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+    content.extend_from_slice(b"data");
+    content.extend_from_slice(&data_len.to_le_bytes());
+    content.extend(title.bytes().chain([0]).cycle().take(data_len as usize));
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    fs::write(path, content).unwrap();
+}
+
+/// Replace the ID3v2 tag of a track file, its audio stays the same
+pub fn tag_track_file(path: &PathBuf, title: &str) {
+    let mut tag = Tag::new(TagType::Id3v2);
+    tag.set_title(title.to_string());
+    tag.set_artist("Test Artist".to_string());
+    tag.set_album("Test Album".to_string());
+    tag.save_to_path(path, WriteOptions::default()).unwrap();
+}
@@ -0,0 +1,100 @@
+use std::path::Path;
+
+use proptest::prelude::*;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::libs::relative_path::RelativePath;
+
+/// A file or folder name, with accents that have distinct NFC and NFD forms
+fn segment() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9éÉüÜçñ _-]{1,12}"
+}
+
+fn segments() -> impl Strategy<Value = Vec<String>> {
+    prop::collection::vec(segment(), 1..5)
+}
+
+/** ----------------------------------------------------------------------------
+ * Separators
+ * -------------------------------------------------------------------------- */
+
+proptest! {
+    #[test]
+    fn test_separators_give_the_same_path(segments in segments()) {
+        let unix = RelativePath::new(&segments.join("/"));
+        let windows = RelativePath::new(&segments.join("\\"));
+        let messy = RelativePath::new(&format!("/{}/", segments.join("//./")));
+
+        prop_assert_eq!(&unix, &windows);
+        prop_assert_eq!(&unix, &messy);
+        prop_assert!(!unix.as_str().starts_with('/') && !unix.as_str().ends_with('/'));
+        prop_assert_eq!(unix.segments().count(), segments.len());
+    }
+
+    #[test]
+    fn test_local_paths_round_trip(segments in segments()) {
+        let root = Path::new("/music/library");
+        let relative_path = RelativePath::new(&segments.join("/"));
+        let local_path = relative_path.to_local_path(root);
+
+        prop_assert_eq!(RelativePath::from_local(root, &local_path), Some(relative_path));
+        prop_assert_eq!(RelativePath::from_local(Path::new("/elsewhere"), &local_path), None);
+    }
+}
+
+/** ----------------------------------------------------------------------------
+ * Unicode forms
+ * -------------------------------------------------------------------------- */
+
+proptest! {
+    #[test]
+    fn test_unicode_forms_give_the_same_path(segments in segments()) {
+        let path = segments.join("/");
+        let nfc = RelativePath::new(&path.nfc().collect::<String>());
+        let nfd = RelativePath::new(&path.nfd().collect::<String>());
+
+        prop_assert_eq!(&nfc, &nfd);
+        prop_assert_eq!(nfc.key(), nfd.key());
+        prop_assert_eq!(nfc.as_str(), path.nfc().collect::<String>());
+    }
+}
+
+/** ----------------------------------------------------------------------------
+ * Case collisions
+ * -------------------------------------------------------------------------- */
+
+proptest! {
+    #[test]
+    fn test_case_variants_share_a_key(segments in segments()) {
+        let path = segments.join("/");
+        let lower = RelativePath::new(&path.to_lowercase());
+        let upper = RelativePath::new(&path.to_uppercase());
+
+        // Both names are kept, but the providers see the same file
+        prop_assert_eq!(lower.key(), upper.key());
+        prop_assert_eq!(lower.key(), RelativePath::new(&path).key());
+        if path.to_lowercase() != path.to_uppercase() {
+            prop_assert_ne!(lower, upper);
+        }
+    }
+
+    #[test]
+    fn test_cloud_paths_ignore_the_case_of_the_folder(folder in segments(), file in segments()) {
+        let file_path = file.join("/");
+        let cloud_path = format!("/{}/{}", folder.join("/").to_uppercase(), file_path);
+
+        let relative_path = RelativePath::from_cloud(&folder.join("/").to_lowercase(), &cloud_path);
+        prop_assert_eq!(relative_path, Some(RelativePath::new(&file_path)));
+    }
+}
+
+#[test]
+fn test_cloud_paths_outside_the_folder() {
+    assert_eq!(RelativePath::from_cloud("/Music", "/Music"), None);
+    assert_eq!(RelativePath::from_cloud("/Music", "/Podcasts/episode.mp3"), None);
+    assert_eq!(RelativePath::from_cloud("/Music", "/Musical/track.mp3"), None);
+    assert_eq!(
+        RelativePath::from_cloud("/music/", "/MUSIC/Album/Track.mp3"),
+        Some(RelativePath::new("Album/Track.mp3"))
+    );
+}
@@ -0,0 +1,1373 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use ormlite::Model;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::{find_track, get_folder_tracks, put_cloud_track, write_track_file, TestDevice};
+use crate::libs::device::{get_device_settings, save_device_settings, DeviceSettings};
+use crate::libs::error::SyncudioError;
+use crate::libs::relative_path::RelativePath;
+use crate::plugins::cloud::providers::{Dropbox, MockProvider, ProviderAccountInfo};
+use crate::libs::track::Track;
+use crate::plugins::cloud::{
+    adopt_folder, apply_folder_sync_plan, check_quota, cleanup_missing_tracks, compute_folder_sync_plan, download_item, list_revisions, get_storage_usage, pull_metadata, push_metadata, queue_downloads,
+    queue_uploads, rebuild_unified_tracks, relocate_folder, restore_revision, scan_folder, upload_item, verify_folder, verify_relocation, CloudMusicFolder, CloudProvider, CloudSyncMode, CloudTrack, CloudTrackMap, DownloadQueueItem, IntegrityIssueKind, IntegrityIssueLocation, RevisionRestoreTarget, SyncHistoryEntry, SyncHistoryFilter,
+    SyncHistoryOperation, SyncOperationType, SyncPlanAction, UnifiedTrack, UploadQueueItem, DeviceFolder, DeviceManifest, fetch_device_manifests,
+    list_adoptable_folders, publish_manifest, get_account_folder_ids, unauthorize_account, CloudAccount, CloudState,
+};
+
+// Metadata syncs share the device settings and temporary files
+static METADATA_LOCK: Mutex<()> = Mutex::const_new(());
+
+/** ----------------------------------------------------------------------------
+ * Scan
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_scan_matches_local_and_cloud_tracks() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+
+    device.add_local_track(&folder, "both.wav", "Both").await;
+    device.add_local_track(&folder, "album/local.wav", "Local").await;
+    put_cloud_track(&device, "/Music/both.wav", "Both");
+    put_cloud_track(&device, "/Music/cloud.wav", "Cloud");
+    device.provider.put_file("/Music/cover.jpg", b"not a track");
+
+    let result = scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+    assert_eq!(result.local_tracks_found, 2);
+    assert_eq!(result.tracks_created, 3);
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    assert_eq!(tracks.len(), 3);
+
+    let both = find_track(&tracks, "both.wav");
+    assert!(both.local_track_id.is_some());
+    assert!(both.cloud_file_id.is_some());
+
+    let local = find_track(&tracks, "album/local.wav");
+    assert!(local.local_track_id.is_some());
+    assert!(local.cloud_file_id.is_none());
+
+    let cloud = find_track(&tracks, "cloud.wav");
+    assert!(cloud.local_track_id.is_none());
+    assert!(cloud.cloud_file_id.is_some());
+
+    // Scanning again finds nothing new
+    let result = scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+    assert_eq!(result.tracks_created, 0);
+    assert_eq!(get_folder_tracks(&device, &folder).await.len(), 3);
+}
+
+#[tokio::test]
+async fn test_scan_clears_mappings_of_deleted_cloud_files() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+
+    device.add_local_track(&folder, "track.wav", "Track").await;
+    put_cloud_track(&device, "/Music/track.wav", "Track");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    device.provider.delete_file("/Music/track.wav").await.unwrap();
+    let result = scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+    assert_eq!(result.mappings_cleared, 1);
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    assert!(find_track(&tracks, "track.wav").cloud_file_id.is_none());
+}
+
+#[tokio::test]
+async fn test_scan_normalizes_relative_paths() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+
+    // macOS writes names in NFD, Dropbox ignores case
+    device.add_local_track(&folder, "Album/Cafe\u{301}.wav", "Café").await;
+    device.add_local_track(&folder, "Track.wav", "Track").await;
+    device.add_local_track(&folder, "track.wav", "Other track").await;
+    put_cloud_track(&device, "/Music/album/Caf\u{e9}.wav", "Café");
+
+    let result = scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+    assert_eq!(result.local_tracks_found, 3);
+    assert_eq!(result.path_collisions, 1);
+    assert_eq!(result.tracks_created, 2);
+
+    let mut db = device.db_state.get_lock().await;
+    let maps = CloudTrackMap::select()
+        .where_bind("cloud_music_folder_id = ?", &folder.id)
+        .fetch_all(&mut db.connection)
+        .await
+        .unwrap();
+    assert_eq!(maps.len(), 2);
+
+    let cafe = maps
+        .iter()
+        .find(|m| m.get_relative_path().key() == "album/caf\u{e9}.wav")
+        .expect("Local and cloud tracks should share a map");
+    assert_eq!(cafe.relative_path, "Album/Caf\u{e9}.wav");
+    assert!(cafe.cloud_file_id.is_some());
+}
+
+/** ----------------------------------------------------------------------------
+ * Unified tracks
+ * -------------------------------------------------------------------------- */
+
+async fn get_sorted_unified_tracks(device: &TestDevice) -> Vec<UnifiedTrack> {
+    let mut db = device.db_state.get_lock().await;
+    let mut tracks = db.get_unified_tracks().await.unwrap();
+    tracks.sort_by_key(|t| (t.cloud_folder_id.clone(), t.local_track_id.clone(), t.cloud_map_id.clone()));
+    tracks
+}
+
+/// The links maintained by triggers are the ones a full rebuild finds
+async fn assert_links_up_to_date(device: &TestDevice) -> Vec<UnifiedTrack> {
+    let tracks = get_sorted_unified_tracks(device).await;
+    {
+        let mut db = device.db_state.get_lock().await;
+        rebuild_unified_tracks(&mut db.connection).await.unwrap();
+    }
+    assert_eq!(tracks, get_sorted_unified_tracks(device).await);
+    tracks
+}
+
+#[tokio::test]
+async fn test_unified_tracks_follow_changes() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+
+    let local = device.add_local_track(&folder, "local.wav", "Local").await;
+    device.add_local_track(&folder, "both.wav", "Both").await;
+    put_cloud_track(&device, "/Music/both.wav", "Both");
+    put_cloud_track(&device, "/Music/cloud.wav", "Cloud");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+    assert_eq!(assert_links_up_to_date(&device).await.len(), 3);
+
+    // A cloud-only track becomes local once downloaded
+    let cloud = device.add_local_track(&folder, "cloud.wav", "Cloud").await;
+    let tracks = assert_links_up_to_date(&device).await;
+    assert_eq!(tracks.len(), 3);
+    assert_eq!(find_track(&tracks, "cloud.wav").location_state, "complete");
+
+    // And cloud-only again once removed
+    {
+        let mut db = device.db_state.get_lock().await;
+        db.remove_tracks(&vec![cloud.id.clone(), local.id.clone()]).await.unwrap();
+    }
+    let tracks = assert_links_up_to_date(&device).await;
+    assert_eq!(tracks.len(), 3);
+    assert!(find_track(&tracks, "cloud.wav").local_track_id.is_none());
+    assert_eq!(find_track(&tracks, "local.wav").location_state, "missing");
+
+    // Maps follow moves, and leave their local track not mapped when deleted
+    {
+        let mut db = device.db_state.get_lock().await;
+        let mut map = CloudTrackMap::select()
+            .where_bind("relative_path = ?", "both.wav")
+            .fetch_one(&mut db.connection)
+            .await
+            .unwrap();
+        map.set_relative_path(RelativePath::new("moved.wav"));
+        let map = map.update_all_fields(&mut db.connection).await.unwrap();
+        map.delete(&mut db.connection).await.unwrap();
+    }
+    let tracks = assert_links_up_to_date(&device).await;
+    assert_eq!(tracks.len(), 3);
+    assert!(tracks.iter().any(|t| t.location_state == "not_mapped"));
+
+    // Folders bring their tracks along
+    let backup_provider = MockProvider::new();
+    let backup = device.add_target(&folder, &backup_provider, "Backup", CloudSyncMode::UploadOnly).await;
+    scan_folder(&device.db_state, &backup_provider, &backup.id).await.unwrap();
+    assert_eq!(assert_links_up_to_date(&device).await.len(), 4);
+
+    {
+        let mut db = device.db_state.get_lock().await;
+        ormlite::query("DELETE FROM cloud_maps WHERE cloud_music_folder_id = ?")
+            .bind(&backup.id)
+            .execute(&mut db.connection)
+            .await
+            .unwrap();
+        db.delete_cloud_folder(&backup.id).await.unwrap();
+    }
+    assert_eq!(assert_links_up_to_date(&device).await.len(), 3);
+}
+
+/// View the links replaced, which matched paths in SQL, without the metadata
+/// columns: they are read from the same joined rows
+const LEGACY_UNIFIED_TRACKS_VIEW: &str = "CREATE TEMP VIEW legacy_unified_tracks AS
+    WITH track_mappings AS (
+        SELECT DISTINCT t.id as local_track_id, t.path as local_path, ctm.cloud_track_id, ctm.id as cloud_map_id,
+            ctm.relative_path, ctm.cloud_file_id, cmf.id as cloud_music_folder_id, cmf.local_folder_path,
+            cmf.cloud_folder_path, cmf.provider_type, cmf.sync_mode
+        FROM tracks t
+        CROSS JOIN cloud_music_folders cmf
+        LEFT JOIN cloud_maps ctm ON
+            REPLACE(SUBSTR(t.path, LENGTH(cmf.local_folder_path) + 2), '\\', '/') = ctm.relative_path COLLATE NOCASE
+            AND ctm.cloud_music_folder_id = cmf.id
+        WHERE t.path LIKE cmf.local_folder_path || '/%'
+        UNION
+        SELECT DISTINCT NULL as local_track_id, NULL as local_path, ct.id as cloud_track_id, ctm.id as cloud_map_id,
+            ctm.relative_path, ctm.cloud_file_id, cmf.id as cloud_music_folder_id, cmf.local_folder_path,
+            cmf.cloud_folder_path, cmf.provider_type, cmf.sync_mode
+        FROM cloud_tracks ct
+        JOIN cloud_maps ctm ON ct.id = ctm.cloud_track_id
+        JOIN cloud_music_folders cmf ON ctm.cloud_music_folder_id = cmf.id
+        WHERE NOT EXISTS (
+            SELECT 1 FROM tracks t
+            WHERE REPLACE(SUBSTR(t.path, LENGTH(cmf.local_folder_path) + 2), '\\', '/') = ctm.relative_path COLLATE NOCASE
+            AND t.path LIKE cmf.local_folder_path || '/%'
+        )
+    )
+    SELECT tm.local_track_id, tm.cloud_track_id, tm.cloud_map_id, tm.cloud_music_folder_id as cloud_folder_id,
+        tm.local_path, tm.relative_path as cloud_relative_path, tm.cloud_folder_path,
+        tm.local_folder_path as cloud_local_folder_path, tm.provider_type as cloud_provider_type, tm.cloud_file_id,
+        CASE
+            WHEN tm.cloud_map_id IS NULL THEN 'not_mapped'
+            WHEN tm.local_track_id IS NOT NULL AND tm.cloud_file_id IS NOT NULL THEN 'complete'
+            WHEN tm.local_track_id IS NOT NULL THEN 'local_only'
+            WHEN tm.cloud_file_id IS NOT NULL THEN 'cloud_only'
+            ELSE 'missing'
+        END as location_state,
+        tm.sync_mode as cloud_sync_mode
+    FROM track_mappings tm";
+
+/// Columns of the unified tracks compared with the legacy view
+const LEGACY_UNIFIED_TRACKS_COLUMNS: &str = "local_track_id, cloud_track_id, cloud_map_id, cloud_folder_id, \
+    local_path, cloud_relative_path, cloud_folder_path, cloud_local_folder_path, cloud_provider_type, \
+    cloud_file_id, location_state, cloud_sync_mode";
+
+type Link = (Option<String>, Option<String>, String);
+
+async fn get_links(device: &TestDevice, view: &str) -> Vec<Link> {
+    let mut db = device.db_state.get_lock().await;
+    let mut links: Vec<Link> =
+        ormlite::query_as(&format!("SELECT local_track_id, cloud_map_id, cloud_folder_id FROM {}", view))
+            .fetch_all(&mut db.connection)
+            .await
+            .unwrap();
+    links.sort();
+    links
+}
+
+/// Get the rows of a view with the columns of the legacy view, as JSON to compare them
+async fn get_legacy_rows(device: &TestDevice, view: &str) -> Vec<String> {
+    let mut db = device.db_state.get_lock().await;
+    let query = format!("SELECT json_array({}) FROM {}", LEGACY_UNIFIED_TRACKS_COLUMNS, view);
+    let rows: Vec<(String,)> = ormlite::query_as(&query).fetch_all(&mut db.connection).await.unwrap();
+    let mut rows: Vec<String> = rows.into_iter().map(|(row,)| row).collect();
+    rows.sort();
+    rows
+}
+
+/// Add a map with its cloud track, without cloud file
+async fn add_map(device: &TestDevice, folder: &CloudMusicFolder, relative_path: &str) -> CloudTrackMap {
+    let mut db = device.db_state.get_lock().await;
+    let track = CloudTrack {
+        id: Uuid::new_v4().to_string(),
+        file_name: RelativePath::new(relative_path).file_name().to_string(),
+        size: 0,
+        updated_at: Utc::now(),
+        tags: None,
+    }
+    .insert(&mut db.connection)
+    .await
+    .unwrap();
+
+    CloudTrackMap::new(track.id, folder.id.clone(), RelativePath::new(relative_path), None)
+        .insert(&mut db.connection)
+        .await
+        .unwrap()
+}
+
+/// Add a track at a path of a folder, written as is
+async fn add_track_at(device: &TestDevice, base: &Track, folder: &CloudMusicFolder, relative_path: &str) -> Track {
+    let track = Track {
+        id: Uuid::new_v4().to_string(),
+        path: format!("{}/{}", folder.local_folder_path, relative_path),
+        ..base.clone()
+    };
+    let mut db = device.db_state.get_lock().await;
+    db.insert_tracks(vec![track.clone()]).await.unwrap();
+    track
+}
+
+#[tokio::test]
+async fn test_unified_tracks_match_the_legacy_view() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let other = device.add_folder("Music (other)").await;
+    {
+        let mut db = device.db_state.get_lock().await;
+        ormlite::query(LEGACY_UNIFIED_TRACKS_VIEW).execute(&mut db.connection).await.unwrap();
+    }
+
+    let base = device.add_local_track(&folder, "plain.wav", "Plain").await;
+    add_map(&device, &folder, "plain.wav").await;
+
+    // Case, mixed separators and Unicode, in the same normalization
+    add_track_at(&device, &base, &folder, "Album/Case.wav").await;
+    add_map(&device, &folder, "album/case.WAV").await;
+    add_track_at(&device, &base, &folder, "album\\mixed/separators.wav").await;
+    add_map(&device, &folder, "album/mixed/separators.wav").await;
+    add_track_at(&device, &base, &folder, "Björk/Jóga.wav").await;
+    add_map(&device, &folder, "Björk/Jóga.wav").await;
+
+    // Local-only, cloud-only, and a folder whose path starts like another one
+    add_track_at(&device, &base, &folder, "local.wav").await;
+    add_map(&device, &folder, "cloud.wav").await;
+    add_track_at(&device, &base, &other, "plain.wav").await;
+    add_map(&device, &other, "plain.wav").await;
+
+    // The view keeps the output of the legacy one, including the location of each track
+    let links = get_links(&device, "unified_tracks").await;
+    assert_eq!(links.len(), 7);
+    assert_eq!(links, get_links(&device, "legacy_unified_tracks").await);
+    assert_eq!(
+        get_legacy_rows(&device, "unified_tracks").await,
+        get_legacy_rows(&device, "legacy_unified_tracks").await
+    );
+    assert_links_up_to_date(&device).await;
+
+    // Unlike the view, keys also match other Unicode normalizations and cases
+    let nfd = add_track_at(&device, &base, &folder, "Sigur Ro\u{301}s/Ágætis.wav").await;
+    let nfc = add_map(&device, &folder, "SIGUR RÓS/ágætis.wav").await;
+    let links = get_links(&device, "unified_tracks").await;
+    assert_eq!(links.len(), 8);
+    assert!(links.contains(&(Some(nfd.id.clone()), Some(nfc.id.clone()), folder.id.clone())));
+    assert!(!get_links(&device, "legacy_unified_tracks")
+        .await
+        .contains(&(Some(nfd.id), Some(nfc.id), folder.id.clone())));
+    assert_links_up_to_date(&device).await;
+}
+
+#[tokio::test]
+async fn test_unified_tracks_queries_use_indexes() {
+    let device = TestDevice::new().await;
+    let mut db = device.db_state.get_lock().await;
+
+    let queries = [
+        "SELECT * FROM unified_tracks WHERE cloud_folder_id = 'id'",
+        "SELECT * FROM unified_tracks WHERE local_track_id IN ('id') OR cloud_track_id IN ('id')",
+    ];
+
+    for query in queries {
+        let plan: Vec<(i64, i64, i64, String)> = ormlite::query_as(&format!("EXPLAIN QUERY PLAN {}", query))
+            .fetch_all(&mut db.connection)
+            .await
+            .unwrap();
+
+        for (_, _, _, detail) in plan {
+            assert!(!detail.starts_with("SCAN"), "{}: {}", query, detail);
+        }
+    }
+}
+
+/** ----------------------------------------------------------------------------
+ * Queues
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_upload_queue() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let track = device.add_local_track(&folder, "album/track.wav", "Track").await;
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let cloud_track_id = find_track(&tracks, "album/track.wav").cloud_track_id.clone().unwrap();
+    queue_uploads(&device.db_state, vec![cloud_track_id], None).await.unwrap();
+
+    let item = {
+        let mut db = device.db_state.get_lock().await;
+        UploadQueueItem::select().fetch_one(&mut db.connection).await.unwrap()
+    };
+    assert_eq!(item.status, "pending");
+
+    upload_item(&device.db_state, &*device.provider, &item.id).await.unwrap();
+
+    assert_eq!(
+        device.provider.get_content("/Music/album/track.wav"),
+        Some(std::fs::read(&track.path).unwrap())
+    );
+
+    let item = {
+        let mut db = device.db_state.get_lock().await;
+        UploadQueueItem::select().fetch_one(&mut db.connection).await.unwrap()
+    };
+    assert_eq!(item.status, "completed");
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    assert!(find_track(&tracks, "album/track.wav").cloud_file_id.is_some());
+}
+
+#[tokio::test]
+async fn test_upload_failures_leave_the_item_to_the_worker() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    device.add_local_track(&folder, "track.wav", "Track").await;
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let cloud_track_id = find_track(&tracks, "track.wav").cloud_track_id.clone().unwrap();
+    queue_uploads(&device.db_state, vec![cloud_track_id], None).await.unwrap();
+
+    let item = {
+        let mut db = device.db_state.get_lock().await;
+        UploadQueueItem::select().fetch_one(&mut db.connection).await.unwrap()
+    };
+
+    device.provider.fail_path("/Music/track.wav");
+    assert!(upload_item(&device.db_state, &*device.provider, &item.id).await.is_err());
+    assert!(device.provider.get_content("/Music/track.wav").is_none());
+
+    // The worker marks failed items, until then they stay in progress
+    let failed = {
+        let mut db = device.db_state.get_lock().await;
+        UploadQueueItem::select().fetch_one(&mut db.connection).await.unwrap()
+    };
+    assert_eq!(failed.status, "in_progress");
+
+    device.provider.clear_faults();
+    upload_item(&device.db_state, &*device.provider, &item.id).await.unwrap();
+    assert!(device.provider.get_content("/Music/track.wav").is_some());
+}
+
+#[tokio::test]
+async fn test_queue_directions_pause_resume_and_cancel() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    device.add_local_track(&folder, "local.wav", "Local").await;
+    device.add_local_track(&folder, "busy.wav", "Busy").await;
+    put_cloud_track(&device, "/Music/cloud.wav", "Cloud");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let map_id = |relative_path: &str| find_track(&tracks, relative_path).cloud_map_id.clone().unwrap();
+
+    let mut db = device.db_state.get_lock().await;
+    let upload = UploadQueueItem::new(map_id("local.wav"), "dropbox".to_string(), 0)
+        .insert(&mut db.connection)
+        .await
+        .unwrap();
+    let mut busy = UploadQueueItem::new(map_id("busy.wav"), "dropbox".to_string(), 0);
+    busy.status = "in_progress".to_string();
+    let busy = busy.insert(&mut db.connection).await.unwrap();
+    let download = DownloadQueueItem::new(map_id("cloud.wav"), "dropbox".to_string(), 0)
+        .insert(&mut db.connection)
+        .await
+        .unwrap();
+
+    // Directions are paused independently
+    assert!(!db.is_sync_paused(&SyncOperationType::Upload).await.unwrap());
+    db.set_sync_paused(&SyncOperationType::Upload, true).await.unwrap();
+    assert!(db.is_sync_paused(&SyncOperationType::Upload).await.unwrap());
+    assert!(!db.is_sync_paused(&SyncOperationType::Download).await.unwrap());
+
+    db.set_sync_paused(&SyncOperationType::Upload, false).await.unwrap();
+    assert!(!db.is_sync_paused(&SyncOperationType::Upload).await.unwrap());
+
+    // Only pending items are prioritized, and only the changed ones are counted
+    let item_ids = [upload.id.clone(), busy.id.clone(), download.id.clone()];
+    assert_eq!(db.set_queue_items_priority(&item_ids, 5).await.unwrap(), 2);
+    assert_eq!(db.set_queue_items_priority(&item_ids, 5).await.unwrap(), 0);
+
+    // Items being transferred cannot be cancelled
+    let cancelled = db.cancel_queue_items(&[upload.id.clone(), busy.id.clone()]).await.unwrap();
+    assert_eq!(cancelled, 1);
+
+    let cancelled = db.cancel_folder_queue(&folder.id).await.unwrap();
+    assert_eq!(cancelled, 1);
+
+    let uploads = UploadQueueItem::select().fetch_all(&mut db.connection).await.unwrap();
+    let status = |id: &str| uploads.iter().find(|i| i.id == id).unwrap().status.clone();
+    assert_eq!(status(&upload.id), "cancelled");
+    assert_eq!(status(&busy.id), "in_progress");
+
+    let download = DownloadQueueItem::select()
+        .where_bind("id = ?", &download.id)
+        .fetch_one(&mut db.connection)
+        .await
+        .unwrap();
+    assert_eq!(download.status, "cancelled");
+}
+
+#[tokio::test]
+async fn test_download_queue() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    put_cloud_track(&device, "/Music/album/cloud.wav", "Cloud");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let cloud_track_id = find_track(&tracks, "album/cloud.wav").cloud_track_id.clone().unwrap();
+    queue_downloads(&device.db_state, vec![cloud_track_id.clone()], None).await.unwrap();
+
+    // Tracks with an active transfer are not queued twice
+    queue_downloads(&device.db_state, vec![cloud_track_id.clone()], None).await.unwrap();
+
+    let items = {
+        let mut db = device.db_state.get_lock().await;
+        DownloadQueueItem::select().fetch_all(&mut db.connection).await.unwrap()
+    };
+    assert_eq!(items.len(), 1);
+
+    let payload = download_item(&device.db_state, &*device.provider, &items[0].id).await.unwrap();
+    assert_eq!(payload.cloud_track_id, cloud_track_id);
+    assert_eq!(payload.relative_path, "album/cloud.wav");
+
+    let local_path = PathBuf::from(&folder.local_folder_path).join("album/cloud.wav");
+    assert!(local_path.exists());
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let track = find_track(&tracks, "album/cloud.wav");
+    assert_eq!(track.local_track_id.as_ref(), Some(&payload.local_track_id));
+    assert_eq!(track.title, "Cloud");
+}
+
+#[tokio::test]
+async fn test_storage_usage_counts_queued_uploads() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let track = device.add_local_track(&folder, "track.wav", "Track").await;
+    device.provider.put_file("/Music/cover.jpg", &[0; 1000]);
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let track_size = track.size as u64;
+    device.provider.set_quota(Some(1000 + track_size + 10));
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let cloud_track_id = find_track(&tracks, "track.wav").cloud_track_id.clone().unwrap();
+    queue_uploads(&device.db_state, vec![cloud_track_id], None).await.unwrap();
+
+    let folder_ids = vec![folder.id.clone()];
+    let usage = get_storage_usage(&device.db_state, &*device.provider, None, &folder_ids).await.unwrap();
+    assert_eq!(usage.used, 1000);
+    assert_eq!(usage.pending_upload_bytes, track_size);
+    assert_eq!(usage.available_after_uploads, Some(10));
+    assert!(!usage.exceeds_quota);
+
+    // Another copy of the track does not fit anymore
+    assert!(check_quota(&usage, 10, false).is_ok());
+    assert!(check_quota(&usage, track_size, false).is_err());
+    assert!(check_quota(&usage, track_size, true).is_ok());
+
+    // Without a quota, everything fits
+    device.provider.set_quota(None);
+    let usage = get_storage_usage(&device.db_state, &*device.provider, None, &folder_ids).await.unwrap();
+    assert_eq!(usage.available_after_uploads, None);
+    assert!(check_quota(&usage, u64::MAX, false).is_ok());
+}
+
+#[tokio::test]
+async fn test_upload_size_skips_tracks_already_counted() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let local = device.add_local_track(&folder, "local.wav", "Local").await;
+    device.add_local_track(&folder, "queued.wav", "Queued").await;
+    device.add_local_track(&folder, "both.wav", "Both").await;
+    put_cloud_track(&device, "/Music/both.wav", "Both");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let track_ids: Vec<String> = ["local.wav", "queued.wav", "both.wav"]
+        .iter()
+        .map(|path| find_track(&tracks, path).cloud_track_id.clone().unwrap())
+        .collect();
+    queue_uploads(&device.db_state, vec![track_ids[1].clone()], None).await.unwrap();
+
+    // Only the track neither in the cloud nor in the queue takes new space
+    let sizes = {
+        let mut db = device.db_state.get_lock().await;
+        db.get_tracks_size_by_folder(&track_ids).await.unwrap()
+    };
+    assert_eq!(sizes, HashMap::from([(folder.id.clone(), local.size as u64)]));
+}
+
+/** ----------------------------------------------------------------------------
+ * Sync plans
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_sync_plan_moves_local_files_and_skips_conflicts() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let root = PathBuf::from(&folder.local_folder_path);
+
+    let moved = device.add_local_track(&folder, "moved.wav", "Moved").await;
+    put_cloud_track(&device, "/Music/moved.wav", "Moved");
+    device.add_local_track(&folder, "conflict.wav", "Conflict").await;
+    put_cloud_track(&device, "/Music/conflict.wav", "Conflict");
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    // Moved in the cloud, the file keeps its ID
+    let content = device.provider.get_content("/Music/moved.wav").unwrap();
+    let cloud_file = device.provider.put_file("/Music/album/moved.wav", &content);
+    device.provider.delete_file("/Music/moved.wav").await.unwrap();
+    {
+        let mut db = device.db_state.get_lock().await;
+        ormlite::query("UPDATE cloud_maps SET cloud_file_id = ? WHERE relative_path = 'moved.wav'")
+            .bind(&cloud_file.id)
+            .execute(&mut db.connection)
+            .await
+            .unwrap();
+    }
+
+    // Deleted from the cloud only
+    device.provider.delete_file("/Music/conflict.wav").await.unwrap();
+
+    let plan = compute_folder_sync_plan(&device.db_state, &*device.provider, &folder.id, 1).await.unwrap();
+    assert_eq!((plan.move_count, plan.conflict_count), (1, 1));
+
+    let result = apply_folder_sync_plan(&device.db_state, &plan).await.unwrap();
+    assert_eq!((result.moves_applied, result.conflicts_skipped), (1, 1));
+
+    // The local file and the library follow the move
+    let moved_path = root.join("album").join("moved.wav");
+    assert!(moved_path.exists());
+    assert!(!root.join("moved.wav").exists());
+    assert!(root.join("conflict.wav").exists());
+
+    let mut db = device.db_state.get_lock().await;
+    let tracks = db.get_tracks(&vec![moved.id.clone()]).await.unwrap();
+    assert_eq!(tracks[0].path, moved_path.to_string_lossy());
+    drop(db);
+
+    let plan = compute_folder_sync_plan(&device.db_state, &*device.provider, &folder.id, 1).await.unwrap();
+    assert_eq!((plan.move_count, plan.upload_count, plan.download_count), (0, 0, 0));
+}
+
+#[tokio::test]
+async fn test_sync_plan_refuses_forged_and_stale_items() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    device.add_local_track(&folder, "track.wav", "Track").await;
+
+    let plan = compute_folder_sync_plan(&device.db_state, &*device.provider, &folder.id, 1).await.unwrap();
+    assert_eq!(plan.upload_count, 1);
+
+    // Paths outside of the folder
+    let mut forged = plan.clone();
+    forged.items[0].relative_path = "../outside.wav".to_string();
+    let result = apply_folder_sync_plan(&device.db_state, &forged).await;
+    assert!(matches!(result, Err(SyncudioError::InvalidSyncPlan(_))));
+
+    // Maps removed since the plan was computed
+    let mut stale = plan.clone();
+    stale.items[0].cloud_map_id = Some("gone".to_string());
+    let result = apply_folder_sync_plan(&device.db_state, &stale).await;
+    assert!(matches!(result, Err(SyncudioError::InvalidSyncPlan(_))));
+
+    // Nothing was queued by the refused plans
+    let result = apply_folder_sync_plan(&device.db_state, &plan).await.unwrap();
+    assert_eq!(result.uploads_queued, 1);
+}
+
+/** ----------------------------------------------------------------------------
+ * History
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_transfers_are_recorded_in_the_history() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let track = device.add_local_track(&folder, "track.wav", "Track").await;
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let cloud_track_id = find_track(&tracks, "track.wav").cloud_track_id.clone().unwrap();
+    queue_uploads(&device.db_state, vec![cloud_track_id.clone()], None).await.unwrap();
+    let item = {
+        let mut db = device.db_state.get_lock().await;
+        UploadQueueItem::select().fetch_one(&mut db.connection).await.unwrap()
+    };
+    upload_item(&device.db_state, &*device.provider, &item.id).await.unwrap();
+
+    let mut db = device.db_state.get_lock().await;
+    db.record_sync_history(SyncHistoryEntry::new(SyncHistoryOperation::MetadataPush, "dropbox".to_string()))
+        .await
+        .unwrap();
+    let old = SyncHistoryEntry {
+        timestamp: Utc::now() - chrono::Duration::days(100),
+        ..SyncHistoryEntry::new(SyncHistoryOperation::Download, "dropbox".to_string())
+            .fail("Injected failure".to_string())
+    };
+    db.record_sync_history(old).await.unwrap();
+
+    // Most recent first
+    let history = db.get_sync_history(SyncHistoryFilter::default()).await.unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[2].status, "failed");
+    assert_eq!(history[2].error_message.as_deref(), Some("Injected failure"));
+
+    let uploads = db
+        .get_sync_history(SyncHistoryFilter {
+            operation: Some(SyncHistoryOperation::Upload),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[0].status, "completed");
+    assert_eq!(uploads[0].cloud_track_id.as_deref(), Some(cloud_track_id.as_str()));
+    assert_eq!(uploads[0].cloud_music_folder_id.as_deref(), Some(folder.id.as_str()));
+    assert_eq!(uploads[0].relative_path.as_deref(), Some("track.wav"));
+    assert_eq!(uploads[0].bytes, track.size as i64);
+
+    let by_track = db
+        .get_sync_history(SyncHistoryFilter {
+            cloud_track_id: Some(cloud_track_id.clone()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(by_track.len(), 1);
+
+    let purged = db.purge_sync_history(Utc::now() - chrono::Duration::days(90)).await.unwrap();
+    assert_eq!(purged, 1);
+    assert_eq!(db.get_sync_history(SyncHistoryFilter::default()).await.unwrap().len(), 2);
+}
+
+/** ----------------------------------------------------------------------------
+ * Several cloud folders per local folder
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_local_folder_synced_with_several_cloud_folders() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let backup_provider = MockProvider::new();
+    let backup = device
+        .add_target(&folder, &backup_provider, "Backup", CloudSyncMode::UploadOnly)
+        .await;
+
+    let track = device.add_local_track(&folder, "track.wav", "Track").await;
+    put_cloud_track(&device, "/Music/cloud.wav", "Cloud");
+    backup_provider.put_file("/Backup/extra.wav", &std::fs::read(&track.path).unwrap());
+
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+    scan_folder(&device.db_state, &backup_provider, &backup.id).await.unwrap();
+
+    // Each cloud folder has its own rows, with its own location state
+    let tracks = get_folder_tracks(&device, &folder).await;
+    assert_eq!(find_track(&tracks, "track.wav").location_state, "local_only");
+    assert_eq!(find_track(&tracks, "cloud.wav").location_state, "cloud_only");
+
+    let backup_tracks = get_folder_tracks(&device, &backup).await;
+    assert_eq!(backup_tracks.len(), 2);
+    assert_eq!(find_track(&backup_tracks, "track.wav").cloud_sync_mode.as_deref(), Some("upload_only"));
+
+    // Uploading to the backup leaves the other cloud folder alone
+    let cloud_track_id = find_track(&backup_tracks, "track.wav").cloud_track_id.clone().unwrap();
+    queue_uploads(&device.db_state, vec![cloud_track_id], None).await.unwrap();
+    let item = {
+        let mut db = device.db_state.get_lock().await;
+        UploadQueueItem::select().fetch_one(&mut db.connection).await.unwrap()
+    };
+    upload_item(&device.db_state, &backup_provider, &item.id).await.unwrap();
+
+    assert!(backup_provider.get_content("/Backup/track.wav").is_some());
+    assert!(device.provider.get_content("/Music/track.wav").is_none());
+
+    let backup_tracks = get_folder_tracks(&device, &backup).await;
+    assert_eq!(find_track(&backup_tracks, "track.wav").location_state, "complete");
+    let tracks = get_folder_tracks(&device, &folder).await;
+    assert_eq!(find_track(&tracks, "track.wav").location_state, "local_only");
+}
+
+#[tokio::test]
+async fn test_sync_mode_limits_transfers() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let backup_provider = MockProvider::new();
+    let backup = device
+        .add_target(&folder, &backup_provider, "Backup", CloudSyncMode::UploadOnly)
+        .await;
+
+    let track = device.add_local_track(&folder, "track.wav", "Track").await;
+    put_cloud_track(&device, "/Music/cloud.wav", "Cloud");
+    backup_provider.put_file("/Backup/extra.wav", &std::fs::read(&track.path).unwrap());
+
+    // Two-way folders download cloud tracks, upload-only ones never do
+    let plan = compute_folder_sync_plan(&device.db_state, &*device.provider, &folder.id, 1).await.unwrap();
+    assert_eq!(plan.upload_count, 1);
+    assert_eq!(plan.download_count, 1);
+
+    let plan = compute_folder_sync_plan(&device.db_state, &backup_provider, &backup.id, 1).await.unwrap();
+    assert_eq!(plan.upload_count, 1);
+    assert_eq!(plan.download_count, 0);
+    assert!(plan.items.iter().all(|i| i.action != SyncPlanAction::Download));
+
+    scan_folder(&device.db_state, &backup_provider, &backup.id).await.unwrap();
+    let backup_tracks = get_folder_tracks(&device, &backup).await;
+    let cloud_track_id = find_track(&backup_tracks, "extra.wav").cloud_track_id.clone().unwrap();
+    queue_downloads(&device.db_state, vec![cloud_track_id], None).await.unwrap();
+
+    let mut db = device.db_state.get_lock().await;
+    let items = DownloadQueueItem::select().fetch_all(&mut db.connection).await.unwrap();
+    assert!(items.is_empty());
+}
+
+/** ----------------------------------------------------------------------------
+ * Relocation
+ * -------------------------------------------------------------------------- */
+
+/// Add a track both locally and in the cloud, and move the folder elsewhere
+async fn add_track_and_move_folder(device: &TestDevice, folder: &CloudMusicFolder) -> (Track, PathBuf) {
+    let track = device.add_local_track(folder, "album/track.wav", "Track").await;
+    device
+        .provider
+        .put_file("/Music/album/track.wav", &std::fs::read(&track.path).unwrap());
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let new_path = device.dir.join("Moved");
+    std::fs::rename(&folder.local_folder_path, &new_path).unwrap();
+    (track, new_path)
+}
+
+#[tokio::test]
+async fn test_relocate_folder() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let track = device.add_local_track(&folder, "album/other.wav", "Other").await;
+    let playlist = {
+        let mut db = device.db_state.get_lock().await;
+        db.record_track_play(&track.id, Utc::now()).await.unwrap();
+        db.create_playlist("Playlist".to_string(), vec![track.id.clone()], None).await.unwrap()
+    };
+    let (_, new_path) = add_track_and_move_folder(&device, &folder).await;
+
+    // The watcher may import a moved file before the folder is relocated
+    let duplicate_playlist = {
+        let mut db = device.db_state.get_lock().await;
+        let duplicate = Track {
+            id: "duplicate".to_string(),
+            path: new_path.join("album/other.wav").to_string_lossy().to_string(),
+            ..track.clone()
+        };
+        db.insert_tracks(vec![duplicate.clone()]).await.unwrap();
+        db.record_track_play(&duplicate.id, Utc::now()).await.unwrap();
+        db.create_playlist("Duplicate".to_string(), vec![duplicate.id.clone()], None).await.unwrap()
+    };
+
+    let verified = verify_relocation(&device.db_state, &*device.provider, &folder, &new_path, 10).await.unwrap();
+    assert_eq!(verified, 1); // The other track is not in the cloud
+
+    let result = relocate_folder(&device.db_state, &folder.id, &new_path).await.unwrap();
+    assert_eq!(result.tracks_relocated, 2);
+    assert_eq!(result.playlists_updated, 0);
+    assert_eq!(result.local_folder_path, new_path.to_string_lossy());
+
+    // Tracks keep their ID, and so their playlists and stats, duplicates are merged into them
+    {
+        let mut db = device.db_state.get_lock().await;
+        let tracks = db.get_tracks(&vec![track.id.clone(), "duplicate".to_string()]).await.unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].path, new_path.join("album/other.wav").to_string_lossy());
+
+        let playlist = db.get_playlist(&playlist.id).await.unwrap().unwrap();
+        assert_eq!(playlist.tracks, vec![track.id.clone()]);
+        let playlist = db.get_playlist(&duplicate_playlist.id).await.unwrap().unwrap();
+        assert_eq!(playlist.tracks, vec![track.id.clone()]);
+
+        let stats = db.get_all_track_stats().await.unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].track_id, track.id);
+        assert_eq!(stats[0].play_count, 2);
+    }
+
+    // Tracks are still matched with the cloud ones
+    let tracks = get_folder_tracks(&device, &folder).await;
+    assert_eq!(tracks.len(), 2);
+    assert!(find_track(&tracks, "album/track.wav").local_track_id.is_some());
+    assert!(find_track(&tracks, "album/track.wav").cloud_file_id.is_some());
+}
+
+#[tokio::test]
+async fn test_relocate_folder_verification_detects_different_files() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let (_, new_path) = add_track_and_move_folder(&device, &folder).await;
+
+    write_track_file(&new_path.join("album/track.wav"), "Changed");
+    let result = verify_relocation(&device.db_state, &*device.provider, &folder, &new_path, 10).await;
+    assert!(result.is_err());
+
+    std::fs::remove_file(new_path.join("album/track.wav")).unwrap();
+    let result = verify_relocation(&device.db_state, &*device.provider, &folder, &new_path, 10).await;
+    assert!(result.is_err());
+
+    // Relocating to a missing folder fails
+    let result = relocate_folder(&device.db_state, &folder.id, &device.dir.join("Missing")).await;
+    assert!(result.is_err());
+}
+
+/** ----------------------------------------------------------------------------
+ * Integrity
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_verify_folder_reports_and_repairs_issues() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    for name in ["gone", "short", "changed", "deleted", "healthy"] {
+        let track = device.add_local_track(&folder, &format!("{}.wav", name), name).await;
+        device
+            .provider
+            .put_file(&format!("/Music/{}.wav", name), &std::fs::read(&track.path).unwrap());
+    }
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let report = verify_folder(&device.db_state, &*device.provider, &folder.id, false).await.unwrap();
+    assert_eq!(report.tracks_checked, 5);
+    assert_eq!(report.tracks_verified, 5);
+
+    let local_folder = PathBuf::from(&folder.local_folder_path);
+    device.provider.delete_file("/Music/gone.wav").await.unwrap();
+    let content = std::fs::read(local_folder.join("short.wav")).unwrap();
+    device.provider.put_file("/Music/short.wav", &content[..content.len() / 2]);
+    write_track_file(&local_folder.join("changed.wav"), "CHANGED");
+    std::fs::remove_file(local_folder.join("deleted.wav")).unwrap();
+
+    let report = verify_folder(&device.db_state, &*device.provider, &folder.id, true).await.unwrap();
+    assert_eq!(report.tracks_verified, 1);
+    assert_eq!(report.missing_count, 2);
+    assert_eq!(report.truncated_count, 1);
+    assert_eq!(report.mismatched_count, 1);
+    assert_eq!(report.repairs_queued, 4);
+
+    let issue = |path: &str| report.issues.iter().find(|i| i.relative_path == path).unwrap();
+    assert_eq!(issue("gone.wav").location, IntegrityIssueLocation::Cloud);
+    assert_eq!(issue("gone.wav").repair, Some(SyncOperationType::Upload));
+    assert_eq!(issue("short.wav").kind, IntegrityIssueKind::Truncated);
+    assert_eq!(issue("short.wav").location, IntegrityIssueLocation::Cloud);
+    assert_eq!(issue("deleted.wav").location, IntegrityIssueLocation::Local);
+    assert_eq!(issue("deleted.wav").repair, Some(SyncOperationType::Download));
+
+    // Repairs are not queued twice
+    let report = verify_folder(&device.db_state, &*device.provider, &folder.id, true).await.unwrap();
+    assert_eq!(report.repairs_queued, 0);
+
+    let mut db = device.db_state.get_lock().await;
+    let uploads = UploadQueueItem::select().fetch_all(&mut db.connection).await.unwrap();
+    let downloads = DownloadQueueItem::select().fetch_all(&mut db.connection).await.unwrap();
+    assert_eq!(uploads.len() + downloads.len(), 4);
+}
+
+/** ----------------------------------------------------------------------------
+ * Revisions
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_restore_revision() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let track = device.add_local_track(&folder, "track.wav", "First").await;
+    let first = std::fs::read(&track.path).unwrap();
+    device.provider.put_file("/Music/track.wav", &first);
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    // A bad tag write reaches the cloud
+    write_track_file(&PathBuf::from(&track.path), "Second");
+    let second = std::fs::read(&track.path).unwrap();
+    device.provider.put_file("/Music/track.wav", &second);
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    let cloud_map_id = find_track(&tracks, "track.wav").cloud_map_id.clone().unwrap();
+    let revisions = list_revisions(&device.db_state, &*device.provider, &cloud_map_id, 10).await.unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert!(revisions[0].is_current);
+    let first_revision = revisions[1].id.clone();
+
+    // Rolling back the local copy downloads the revision, the cloud is left as is
+    let result = restore_revision(
+        &device.db_state,
+        &*device.provider,
+        &cloud_map_id,
+        &first_revision,
+        RevisionRestoreTarget::Local,
+    )
+    .await
+    .unwrap();
+    assert!(result.cloud_revision_id.is_none());
+
+    let item = {
+        let mut db = device.db_state.get_lock().await;
+        DownloadQueueItem::select().fetch_one(&mut db.connection).await.unwrap()
+    };
+    assert_eq!(item.revision_id, Some(first_revision.clone()));
+    download_item(&device.db_state, &*device.provider, &item.id).await.unwrap();
+    assert_eq!(std::fs::read(&track.path).unwrap(), first);
+    assert_eq!(device.provider.get_content("/Music/track.wav"), Some(second));
+
+    // Rolling back the cloud copy makes the revision current
+    let result = restore_revision(
+        &device.db_state,
+        &*device.provider,
+        &cloud_map_id,
+        &first_revision,
+        RevisionRestoreTarget::Cloud,
+    )
+    .await
+    .unwrap();
+    assert!(result.cloud_revision_id.is_some());
+    assert!(result.download_item_id.is_none());
+    assert_eq!(device.provider.get_content("/Music/track.wav"), Some(first));
+
+    let result = restore_revision(
+        &device.db_state,
+        &*device.provider,
+        &cloud_map_id,
+        "unknown",
+        RevisionRestoreTarget::Both,
+    )
+    .await;
+    assert!(result.is_err());
+}
+
+/** ----------------------------------------------------------------------------
+ * Metadata
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_metadata_push_and_pull_between_devices() {
+    let _lock = METADATA_LOCK.lock().await;
+    let laptop = TestDevice::new().await;
+    let laptop_folder = laptop.add_folder("Music").await;
+    laptop.add_local_track(&laptop_folder, "track.wav", "Shared").await;
+    laptop.add_local_track(&laptop_folder, "unsynced.wav", "Unsynced").await;
+    put_cloud_track(&laptop, "/Music/track.wav", "Shared");
+    scan_folder(&laptop.db_state, &*laptop.provider, &laptop_folder.id).await.unwrap();
+
+    let folder_ids = HashSet::from([laptop_folder.id.clone()]);
+    let pushed = push_metadata(&laptop.db_state, &*laptop.provider, &folder_ids).await.unwrap();
+    assert_eq!(pushed.tracks_included, 1);
+    assert_eq!(pushed.tracks_skipped, 1); // Not uploaded yet
+
+    // Another device syncing the same cloud folder
+    let desktop = TestDevice::with_provider(Arc::clone(&laptop.provider)).await;
+    let desktop_folder = desktop.add_folder("Music").await;
+    assert_eq!(desktop_folder.cloud_folder_id, laptop_folder.cloud_folder_id);
+
+    let folder_ids = HashSet::from([desktop_folder.id.clone()]);
+    let pulled = pull_metadata(&desktop.db_state, &*desktop.provider, &folder_ids).await.unwrap();
+    assert!(!pulled.is_fresh_start);
+    assert_eq!(pulled.tracks_created, 1);
+
+    let tracks = get_folder_tracks(&desktop, &desktop_folder).await;
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].title, "Shared");
+    assert!(tracks[0].local_track_id.is_none());
+
+    // Pulling again does not duplicate tracks
+    let pulled = pull_metadata(&desktop.db_state, &*desktop.provider, &folder_ids).await.unwrap();
+    assert_eq!(pulled.tracks_created, 0);
+}
+
+#[tokio::test]
+async fn test_metadata_pull_maps_tracks_to_the_folders_of_this_device() {
+    let _lock = METADATA_LOCK.lock().await;
+    let laptop = TestDevice::new().await;
+    let music = laptop.add_folder("Music").await;
+    let other = laptop.add_folder("Other").await;
+    laptop.add_local_track(&music, "track.wav", "Music track").await;
+    laptop.add_local_track(&other, "track.wav", "Other track").await;
+    put_cloud_track(&laptop, "/Music/track.wav", "Music track");
+    put_cloud_track(&laptop, "/Other/track.wav", "Other track");
+    scan_folder(&laptop.db_state, &*laptop.provider, &music.id).await.unwrap();
+    scan_folder(&laptop.db_state, &*laptop.provider, &other.id).await.unwrap();
+
+    let folder_ids = HashSet::from([music.id.clone(), other.id.clone()]);
+    let pushed = push_metadata(&laptop.db_state, &*laptop.provider, &folder_ids).await.unwrap();
+    assert_eq!(pushed.tracks_included, 2);
+
+    // The desktop only syncs one of the folders, under its own folder ID
+    let desktop = TestDevice::with_provider(Arc::clone(&laptop.provider)).await;
+    let desktop_music = desktop.add_folder("Music").await;
+    assert_ne!(desktop_music.id, music.id);
+
+    let folder_ids = HashSet::from([desktop_music.id.clone()]);
+    let pulled = pull_metadata(&desktop.db_state, &*desktop.provider, &folder_ids).await.unwrap();
+    assert_eq!(pulled.tracks_created, 1);
+
+    let maps = {
+        let mut db = desktop.db_state.get_lock().await;
+        CloudTrackMap::select().fetch_all(&mut db.connection).await.unwrap()
+    };
+    assert_eq!(maps.len(), 1);
+    assert_eq!(maps[0].cloud_music_folder_id, desktop_music.id);
+
+    let tracks = get_folder_tracks(&desktop, &desktop_music).await;
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].title, "Music track");
+}
+
+#[tokio::test]
+async fn test_metadata_pull_creates_tracks_in_every_local_folder_of_a_cloud_folder() {
+    let _lock = METADATA_LOCK.lock().await;
+    let laptop = TestDevice::new().await;
+    let music = laptop.add_folder("Music").await;
+    laptop.add_local_track(&music, "track.wav", "Track").await;
+    put_cloud_track(&laptop, "/Music/track.wav", "Track");
+    scan_folder(&laptop.db_state, &*laptop.provider, &music.id).await.unwrap();
+    push_metadata(&laptop.db_state, &*laptop.provider, &HashSet::from([music.id.clone()])).await.unwrap();
+
+    // The desktop syncs the same cloud folder in two local folders
+    let desktop = TestDevice::with_provider(Arc::clone(&laptop.provider)).await;
+    let first = desktop.add_folder("Music").await;
+    let second = {
+        let folder = CloudMusicFolder::new(
+            "dropbox".to_string(),
+            first.cloud_folder_id.clone(),
+            first.cloud_folder_path.clone(),
+            desktop.dir.join("Copy").to_string_lossy().to_string(),
+        );
+        let mut db = desktop.db_state.get_lock().await;
+        db.save_cloud_folder(folder).await.unwrap()
+    };
+
+    let folder_ids = HashSet::from([first.id.clone(), second.id.clone()]);
+    let pulled = pull_metadata(&desktop.db_state, &*desktop.provider, &folder_ids).await.unwrap();
+    assert_eq!(pulled.tracks_created, 2);
+    assert_eq!(get_folder_tracks(&desktop, &first).await.len(), 1);
+    assert_eq!(get_folder_tracks(&desktop, &second).await.len(), 1);
+
+    let pulled = pull_metadata(&desktop.db_state, &*desktop.provider, &folder_ids).await.unwrap();
+    assert_eq!(pulled.tracks_created, 0);
+}
+
+#[tokio::test]
+async fn test_metadata_push_fails_on_rate_limits() {
+    let _lock = METADATA_LOCK.lock().await;
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    device.add_local_track(&folder, "track.wav", "Track").await;
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    device.provider.set_rate_limit(1);
+    let folder_ids = HashSet::from([folder.id.clone()]);
+    assert!(push_metadata(&device.db_state, &*device.provider, &folder_ids).await.is_err());
+
+    device.provider.clear_faults();
+    push_metadata(&device.db_state, &*device.provider, &folder_ids).await.unwrap();
+    assert!(device
+        .provider
+        .file_paths()
+        .contains(&"/Syncudio/metadata/tracks.json".to_string()));
+}
+
+#[tokio::test]
+async fn test_metadata_pull_fails_when_the_download_fails() {
+    let _lock = METADATA_LOCK.lock().await;
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    device.add_local_track(&folder, "track.wav", "Track").await;
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    let folder_ids = HashSet::from([folder.id.clone()]);
+    push_metadata(&device.db_state, &*device.provider, &folder_ids).await.unwrap();
+
+    // A failed download must not be mistaken for missing metadata
+    device.provider.fail_next(1);
+    assert!(pull_metadata(&device.db_state, &*device.provider, &folder_ids).await.is_err());
+
+    device.provider.clear_faults();
+    let pulled = pull_metadata(&device.db_state, &*device.provider, &folder_ids).await.unwrap();
+    assert!(!pulled.is_fresh_start);
+}
+
+/** ----------------------------------------------------------------------------
+ * Devices
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_devices_publish_manifests_and_adopt_folders() {
+    let _lock = METADATA_LOCK.lock().await;
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+
+    let manifest = publish_manifest(&device.db_state, &*device.provider, true).await.unwrap();
+    assert_eq!(manifest.folders.len(), 1);
+    assert!(manifest.last_sync_at.is_some());
+
+    // Another device syncs the same folder and one of its own
+    let shared = device.provider.create_folder("Shared", Some("/")).await.unwrap();
+    let other = DeviceManifest {
+        id: "other-device".to_string(),
+        name: "Other".to_string(),
+        folders: vec![
+            DeviceFolder::from(&folder),
+            DeviceFolder {
+                provider_type: "dropbox".to_string(),
+                cloud_folder_id: shared.id.clone(),
+                cloud_folder_path: "/Shared".to_string(),
+                local_folder_path: "/elsewhere/Shared".to_string(),
+            },
+        ],
+        ..manifest.clone()
+    };
+    device
+        .provider
+        .put_file("/Syncudio/devices/other-device.json", &serde_json::to_vec(&other).unwrap());
+
+    let manifests = fetch_device_manifests(&*device.provider).await.unwrap();
+    assert_eq!(manifests.len(), 2);
+    assert!(manifests.iter().any(|m| m.id == manifest.id));
+
+    // Only the folder this device does not sync yet can be adopted
+    let adoptable = list_adoptable_folders(&device.db_state, &*device.provider).await.unwrap();
+    assert_eq!(adoptable.len(), 1);
+    assert_eq!(adoptable[0].cloud_folder_id, shared.id);
+    assert_eq!(adoptable[0].device_names, vec!["Other"]);
+
+    let local_path = device.dir.join("Adopted").to_string_lossy().to_string();
+    let adopted = adopt_folder(&device.db_state, &*device.provider, &shared.id, &local_path, None)
+        .await
+        .unwrap();
+    assert_eq!(adopted.cloud_folder_path, "/Shared");
+    assert_eq!(adopted.local_folder_path, local_path);
+    assert!(PathBuf::from(&local_path).is_dir());
+
+    assert!(list_adoptable_folders(&device.db_state, &*device.provider).await.unwrap().is_empty());
+    assert!(adopt_folder(&device.db_state, &*device.provider, &shared.id, &local_path, None)
+        .await
+        .is_err());
+}
+
+/** ----------------------------------------------------------------------------
+ * Accounts
+ * -------------------------------------------------------------------------- */
+
+fn account_info(provider_account_id: &str, email: &str) -> ProviderAccountInfo {
+    ProviderAccountInfo {
+        provider_account_id: provider_account_id.to_string(),
+        display_name: email.to_string(),
+        email: email.to_string(),
+        quota_used: Some(10),
+        quota_allocated: Some(100),
+    }
+}
+
+#[tokio::test]
+async fn test_accounts_are_added_and_removed() {
+    let _lock = METADATA_LOCK.lock().await;
+    let device = TestDevice::new().await;
+    let previous_settings = get_device_settings().unwrap();
+
+    // Existing folders belong to the first account, which becomes the default one
+    let folder = device.add_folder("Music").await;
+    let (personal, work) = {
+        let mut db = device.db_state.get_lock().await;
+        let personal = db
+            .save_cloud_account(CloudAccount::new("dropbox".to_string(), account_info("dbid:1", "me@home")))
+            .await
+            .unwrap();
+        let work = db
+            .save_cloud_account(CloudAccount::new("dropbox".to_string(), account_info("dbid:2", "me@work")))
+            .await
+            .unwrap();
+        assert_eq!(db.assign_cloud_folders_to_account("dropbox", &personal.id).await.unwrap(), 1);
+
+        // Authorizing an account again updates it
+        let mut again = db.get_cloud_account_by_provider_id("dropbox", "dbid:2").await.unwrap().unwrap();
+        assert_eq!(again.id, work.id);
+        again.update_info(account_info("dbid:2", "new@work"));
+        db.update_cloud_account(again).await.unwrap();
+
+        let accounts = db.get_cloud_accounts().await.unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[1].email, "new@work");
+
+        (personal, work)
+    };
+
+    save_device_settings(&DeviceSettings {
+        default_cloud_account: Some(personal.id.clone()),
+        ..previous_settings.clone()
+    })
+    .unwrap();
+
+    let cloud_state = CloudState {
+        dropbox: Dropbox::empty(),
+        accounts: tokio::sync::RwLock::new(HashMap::from([(work.id.clone(), Arc::new(Dropbox::empty()))])),
+        pending_dropbox: Dropbox::empty(),
+    };
+    let work_folder = CloudMusicFolder {
+        account_id: Some(work.id.clone()),
+        ..CloudMusicFolder::new("dropbox".to_string(), "id:work".to_string(), "/Work".to_string(), "/work".to_string())
+    };
+    let folders = vec![folder.clone(), work_folder.clone()];
+
+    assert!(cloud_state.is_default_account(None).unwrap());
+    assert!(cloud_state.is_default_account(Some(&personal.id)).unwrap());
+    assert!(cloud_state.get_provider(Some(&work.id)).await.is_ok());
+    assert_eq!(
+        get_account_folder_ids(&cloud_state, &folders, None).unwrap(),
+        HashSet::from([folder.id.clone()])
+    );
+    assert_eq!(
+        get_account_folder_ids(&cloud_state, &folders, Some(&work.id)).unwrap(),
+        HashSet::from([work_folder.id.clone()])
+    );
+
+    // Removing an account forgets its client
+    unauthorize_account(&cloud_state, Some(&work.id)).await.unwrap();
+    assert!(matches!(
+        cloud_state.get_provider(Some(&work.id)).await,
+        Err(SyncudioError::CloudAccountNotFound(_))
+    ));
+
+    // Removing the default one forgets it as the default
+    unauthorize_account(&cloud_state, None).await.unwrap();
+    assert!(get_device_settings().unwrap().default_cloud_account.is_none());
+
+    save_device_settings(&previous_settings).unwrap();
+}
+
+/** ----------------------------------------------------------------------------
+ * Cleanup and faults
+ * -------------------------------------------------------------------------- */
+
+#[tokio::test]
+async fn test_cleanup_removes_missing_local_tracks() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    let removed = device.add_local_track(&folder, "removed.wav", "Removed").await;
+    device.add_local_track(&folder, "kept.wav", "Kept").await;
+    scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+
+    std::fs::remove_file(&removed.path).unwrap();
+    let result = cleanup_missing_tracks(&device.db_state).await.unwrap();
+    assert_eq!(result.removed_tracks, 1);
+    assert_eq!(result.removed_cloud_mappings, 1);
+
+    let tracks = get_folder_tracks(&device, &folder).await;
+    assert_eq!(tracks.len(), 1);
+    assert!(find_track(&tracks, "kept.wav").local_track_id.is_some());
+}
+
+#[tokio::test]
+async fn test_scan_with_unreliable_provider() {
+    let device = TestDevice::new().await;
+    let folder = device.add_folder("Music").await;
+    device.add_local_track(&folder, "track.wav", "Track").await;
+
+    device.provider.fail_next(1);
+    assert!(scan_folder(&device.db_state, &*device.provider, &folder.id).await.is_err());
+
+    // Nothing is recorded by a failed scan
+    {
+        let mut db = device.db_state.get_lock().await;
+        assert!(CloudTrack::select().fetch_all(&mut db.connection).await.unwrap().is_empty());
+    }
+
+    device.provider.set_latency(Duration::from_millis(20));
+    let calls = device.provider.call_count();
+    let result = scan_folder(&device.db_state, &*device.provider, &folder.id).await.unwrap();
+    assert_eq!(result.tracks_created, 1);
+    assert_eq!(device.provider.call_count(), calls + 1);
+}
@@ -20,7 +20,7 @@ pub enum Repeat {
     None,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, TS)]
 #[ts(export, export_to = "../../src/generated/typings/index.ts")]
 pub enum SortBy {
     Artist,
@@ -30,7 +30,7 @@ pub enum SortBy {
     Genre,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, TS)]
 #[ts(export, export_to = "../../src/generated/typings/index.ts")]
 pub enum SortOrder {
     Asc,
@@ -156,6 +156,11 @@ impl ConfigManager {
  */
 #[tauri::command]
 pub fn get_storage_dir() -> PathBuf {
+    // Keep tests away from the user's data
+    if cfg!(test) {
+        return std::env::temp_dir().join("syncudio-tests");
+    }
+
     // TODO: Replace with PathResolver::app_config_dir() + app identifier, somehow
     let path = dirs::config_dir().expect("Get config dir");
     path.join("Syncudio")
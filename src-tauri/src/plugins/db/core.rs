@@ -1,3 +1,6 @@
+use std::path::PathBuf;
+
+use chrono::{Duration, Utc};
 use log::{error, info};
 use ormlite::sqlite::{SqliteConnectOptions, SqliteConnection};
 use ormlite::{Connection, TableMeta};
@@ -8,6 +11,7 @@ use tokio::sync::{Mutex, MutexGuard};
 use crate::libs::database::DB;
 use crate::libs::error::AnyResult;
 use crate::libs::playlist::Playlist;
+use crate::plugins::cloud::SYNC_HISTORY_RETENTION_DAYS;
 use crate::libs::track::Track;
 use crate::libs::utils::TimeLogger;
 use crate::plugins::config::get_storage_dir;
@@ -19,15 +23,23 @@ use super::library::*;
 pub struct DBState(Mutex<DB>);
 
 impl DBState {
+    pub fn new(db: DB) -> Self {
+        DBState(Mutex::new(db))
+    }
+
     /// Get a lock on the database connection
     pub async fn get_lock(&self) -> MutexGuard<'_, DB> {
         self.0.lock().await
     }
 }
 
+fn get_database_path() -> PathBuf {
+    get_storage_dir().join("syncudio.db")
+}
+
 /// Setup the database connection and create tables
 async fn setup() -> AnyResult<DB> {
-    let database_path = get_storage_dir().join("syncudio.db");
+    let database_path = get_database_path();
 
     info!("Opening connection to database: {:?}", database_path);
 
@@ -75,10 +87,16 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
         .invoke_handler(tauri::generate_handler![
             // Track operations
             get_all_tracks,
+            query_tracks,
             get_tracks,
             remove_tracks,
             update_track,
             import_tracks_to_library,
+            cancel_library_import,
+            get_scan_failures,
+            record_track_play,
+            set_track_rating,
+            get_tracks_stats,
             // Playlist operations
             get_all_playlists,
             get_playlist,
@@ -91,6 +109,8 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
             reset,
         ])
         .setup(move |app_handle, _api| {
+            app_handle.manage(ImportJobs::default());
+
             let app_handle = app_handle.clone();
             tauri::async_runtime::spawn(async move {
                 let mut db = match setup().await {
@@ -101,11 +121,17 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
                     }
                 };
 
-                db.create_tables()
+                db.create_tables(Some(&get_database_path()))
                     .await
                     .expect("Could not create DB tables");
 
-                app_handle.manage(DBState(Mutex::new(db)));
+                let retention = Duration::days(SYNC_HISTORY_RETENTION_DAYS);
+                match db.purge_sync_history(Utc::now() - retention).await {
+                    Ok(purged) => info!("Purged {} expired sync history entries", purged),
+                    Err(err) => error!("Failed to purge sync history: {:?}", err),
+                }
+
+                app_handle.manage(DBState::new(db));
             });
             Ok(())
         })
@@ -1,78 +1,424 @@
 use std::{
-    collections::HashSet,
-    path::PathBuf,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 
 use log::{error, info, warn};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
-use tauri::{Emitter, Manager, Runtime, State};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
 use ts_rs::TS;
+use uuid::Uuid;
 
 use crate::{
     libs::{
         constants::{SUPPORTED_PLAYLISTS_EXTENSIONS, SUPPORTED_TRACKS_EXTENSIONS},
+        database::DB,
         error::{AnyResult, SyncudioError},
         events::IPCEvent,
-        track::{get_track_from_file, get_track_id_for_path, Track},
-        utils::{scan_dirs, TimeLogger},
+        track::{is_track_file_changed, read_track_from_file, ScanFailure, ScanFailureReason, Track},
+        utils::{normalize_path, scan_dirs, TimeLogger},
     },
-    plugins::db::DBState,
+    plugins::{cloud::remove_local_tracks, db::DBState},
 };
 
+/// Files read between two saves to the DB, which is not locked while files are read
+const IMPORT_BATCH_SIZE: usize = 500;
+
+/// Minimum time between two progress events
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Scan progress information
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../src/generated/typings/index.ts")]
 pub struct ScanProgress {
+    job_id: String,
     current: usize,
     total: usize,
+    current_file: Option<String>, // Last file read
+}
+
+/// How a scan treats the tracks already in the library
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum ScanMode {
+    /// Only add the files that are not in the library yet
+    #[default]
+    Import,
+    /// Also read again the files changed since they were read, and remove
+    /// the tracks of the scanned folders whose file is gone
+    Rescan,
 }
 
 /// Result of a library scan operation
 #[derive(Default, Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../src/generated/typings/index.ts")]
 pub struct ScanResult {
-    track_count: usize,
-    track_failures: usize,
-    playlist_count: usize,
-    playlist_failures: usize,
+    pub track_count: usize, // Files successfully read
+    pub track_failures: usize,
+    pub tracks_added: usize,
+    pub tracks_updated: usize,
+    pub tracks_moved: usize, // New files of tracks whose file is gone
+    pub tracks_removed: usize,
+    pub playlist_count: usize,
+    pub playlist_failures: usize,
+    pub cancelled: bool, // The tracks saved before the import was cancelled are kept
+    pub failures: Vec<ScanFailure>, // Files that could not be imported, and why
+}
+
+/// Outcome of a library import job, sent once it is over
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct ScanJobResult {
+    job_id: String,
+    result: Option<ScanResult>,
+    error: Option<String>,
+}
+
+/// A library import running in the background
+#[derive(Debug, Clone)]
+pub struct ImportJob {
+    pub id: String,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ImportJob {
+    pub fn new() -> Self {
+        ImportJob {
+            id: Uuid::new_v4().to_string(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Stop the import once the batch being read is saved
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl Default for ImportJob {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Library imports running in the background, by job ID, and the failures of
+/// the last one
+#[derive(Default)]
+pub struct ImportJobs {
+    jobs: Mutex<HashMap<String, ImportJob>>,
+    last_failures: Mutex<Vec<ScanFailure>>,
+}
+
+impl ImportJobs {
+    fn start(&self) -> ImportJob {
+        let job = ImportJob::new();
+        self.jobs.lock().unwrap().insert(job.id.clone(), job.clone());
+        job
+    }
+
+    /// Cancel a running job, returns false if there is none with this ID
+    fn cancel(&self, job_id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some(job) => {
+                job.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn finish(&self, job_id: &str, scan_result: Option<&ScanResult>) {
+        self.jobs.lock().unwrap().remove(job_id);
+
+        if let Some(scan_result) = scan_result {
+            *self.last_failures.lock().unwrap() = scan_result.failures.clone();
+        }
+    }
 }
 
-/// Scan the selected folders, extract all ID3 tags from it, and update the DB accordingly
+/// Lets events through at most once per interval, from any thread
+struct Throttle {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    fn new(interval: Duration) -> Self {
+        Throttle {
+            interval,
+            last: Mutex::new(None),
+        }
+    }
+
+    fn ready(&self) -> bool {
+        // Another thread is already sending one
+        let Ok(mut last) = self.last.try_lock() else {
+            return false;
+        };
+
+        if last.is_some_and(|last| last.elapsed() < self.interval) {
+            return false;
+        }
+
+        *last = Some(Instant::now());
+        true
+    }
+}
+
+/// Ends an import job once dropped: removes it from the running jobs and sends
+/// its result, or an error if the import stopped before having one
+struct ImportCompletion<R: Runtime> {
+    app_handle: AppHandle<R>,
+    job_id: String,
+    result: Option<AnyResult<ScanResult>>,
+}
+
+impl<R: Runtime> Drop for ImportCompletion<R> {
+    fn drop(&mut self) {
+        let result = self
+            .result
+            .take()
+            .unwrap_or_else(|| Err(SyncudioError::Unknown(anyhow::anyhow!("The import stopped unexpectedly"))));
+
+        self.app_handle
+            .state::<ImportJobs>()
+            .finish(&self.job_id, result.as_ref().ok());
+
+        let payload = match result {
+            Ok(scan_result) => ScanJobResult {
+                job_id: self.job_id.clone(),
+                result: Some(scan_result),
+                error: None,
+            },
+            Err(err) => {
+                error!("Library import failed: {}", err);
+                ScanJobResult {
+                    job_id: self.job_id.clone(),
+                    result: None,
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+
+        if let Err(err) = self.app_handle.emit(IPCEvent::LibraryScanComplete.as_ref(), payload) {
+            error!("Could not emit scan result: {}", err);
+        }
+    }
+}
+
+/// Scan the selected folders, extract all ID3 tags from it, and update the DB
+/// accordingly. The import runs in the background, its progress and result are
+/// sent as events with the returned job ID. Files without tags are skipped,
+/// unless import_untagged is set.
 #[tauri::command]
 pub async fn import_tracks_to_library<R: Runtime>(
-    window: tauri::Window<R>,
-    db_state: State<'_, DBState>,
+    app_handle: AppHandle<R>,
+    import_jobs: State<'_, ImportJobs>,
     import_paths: Vec<PathBuf>,
-) -> AnyResult<ScanResult> {
-    let mut db = db_state.get_lock().await;
+    mode: Option<ScanMode>,
+    import_untagged: Option<bool>,
+) -> AnyResult<String> {
+    let job = import_jobs.start();
+    let job_id = job.id.clone();
+
+    tauri::async_runtime::spawn(async move {
+        // Reports the end of the job whichever way the import ends
+        let mut completion = ImportCompletion {
+            app_handle: app_handle.clone(),
+            job_id: job.id.clone(),
+            result: None,
+        };
+
+        let db_state = app_handle.state::<DBState>();
+        let result = import_tracks(
+            &db_state,
+            &import_paths,
+            mode.unwrap_or_default(),
+            import_untagged.unwrap_or(false),
+            &job,
+            |progress| {
+                if let Err(err) = app_handle.emit(IPCEvent::LibraryScanProgress.as_ref(), progress) {
+                    warn!("Could not emit scan progress: {}", err);
+                }
+            },
+        )
+        .await;
 
-    let webview_window = window.get_webview_window("main").unwrap();
+        completion.result = Some(result);
+    });
+
+    Ok(job_id)
+}
 
-    info!("Importing paths to library:");
-    for path in &import_paths {
+/// Cancel a library import. Tracks saved until then stay in the library.
+#[tauri::command]
+pub async fn cancel_library_import(import_jobs: State<'_, ImportJobs>, job_id: String) -> AnyResult<bool> {
+    let cancelled = import_jobs.cancel(&job_id);
+    if cancelled {
+        info!("Cancelling library import {}", job_id);
+    }
+    Ok(cancelled)
+}
+
+/// Get the files the last library import could not import, and why
+#[tauri::command]
+pub async fn get_scan_failures(import_jobs: State<'_, ImportJobs>) -> AnyResult<Vec<ScanFailure>> {
+    Ok(import_jobs.last_failures.lock().unwrap().clone())
+}
+
+/// Whether saving a track failed because another track already has its path,
+/// e.g. the watcher added it while the batch was read
+fn is_duplicate_path(err: &SyncudioError) -> bool {
+    let err = match err {
+        SyncudioError::ORMLite(ormlite::Error::SqlxError(err)) | SyncudioError::ORMLiteSqlx(err) => err,
+        _ => return false,
+    };
+
+    err.as_database_error().is_some_and(|err| err.is_unique_violation())
+}
+
+/// Save a batch of tracks read from their files. Changed files keep the ID of
+/// their track, and new files of a track whose file is gone take it over: they
+/// are the same file, moved or renamed. Tracks whose path is taken are
+/// reported as failures without failing the batch.
+async fn save_scanned_tracks(
+    db: &mut DB,
+    tracks: Vec<Track>,
+    existing_tracks: &HashMap<PathBuf, Track>,
+    scan_result: &mut ScanResult,
+    moved_track_ids: &mut HashSet<String>,
+) -> AnyResult<()> {
+    for mut track in tracks {
+        let path = PathBuf::from(&track.path);
+        let result = match existing_tracks.get(Path::new(&track.path)) {
+            Some(existing_track) => {
+                track.id = existing_track.id.clone();
+                db.update_track(track).await.map(|_| scan_result.tracks_updated += 1)
+            }
+            None => match db.find_moved_track(&track).await? {
+                Some(moved_track) => {
+                    info!("Track moved from {:?} to {:?}", moved_track.path, track.path);
+                    track.id = moved_track.id;
+                    moved_track_ids.insert(track.id.clone());
+                    // Saved right away, so another file cannot take the same track over
+                    db.update_track(track).await.map(|_| scan_result.tracks_moved += 1)
+                }
+                None => db.insert_tracks(vec![track]).await.map(|_| scan_result.tracks_added += 1),
+            },
+        };
+
+        match result {
+            Ok(_) => {}
+            Err(err) if is_duplicate_path(&err) => {
+                warn!("Track already in the library: {:?}", path);
+                scan_result.track_count -= 1;
+                scan_result.track_failures += 1;
+                scan_result
+                    .failures
+                    .push(ScanFailure::new(&path, ScanFailureReason::DuplicatePath, err));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the name of a playlist and the paths of its tracks from its file
+fn read_playlist_file(playlist_path: &Path) -> AnyResult<(String, Vec<PathBuf>)> {
+    let mut reader = m3u::Reader::open(playlist_path)?;
+    let playlist_dir_path = playlist_path
+        .parent()
+        .ok_or_else(|| SyncudioError::Path(format!("{:?} has no parent folder", playlist_path)))?;
+
+    let track_paths: Vec<PathBuf> = reader
+        .entries()
+        .filter_map(|entry| {
+            let Ok(entry) = entry else {
+                return None;
+            };
+
+            match entry {
+                m3u::Entry::Path(path) => Some(normalize_path(&playlist_dir_path.join(path))),
+                _ => None, // We don't support (yet?) URLs in playlists
+            }
+        })
+        .collect();
+
+    let playlist_name = playlist_path
+        .file_stem()
+        .ok_or_else(|| SyncudioError::Path(format!("{:?} has no file name", playlist_path)))?
+        .to_str()
+        .unwrap_or("unknown playlist")
+        .to_owned();
+
+    Ok((playlist_name, track_paths))
+}
+
+/// Import the tracks and playlists of folders. Tags are read in parallel by
+/// batches, with the DB unlocked, and each batch is then saved in a single
+/// transaction. A cancelled import stops after the batch being read.
+pub(crate) async fn import_tracks(
+    db_state: &DBState,
+    import_paths: &[PathBuf],
+    mode: ScanMode,
+    import_untagged: bool,
+    job: &ImportJob,
+    on_progress: impl Fn(ScanProgress) + Sync,
+) -> AnyResult<ScanResult> {
+    info!("Importing paths to library ({:?}):", mode);
+    for path in import_paths {
         info!("  - {:?}", path)
     }
 
     let mut scan_result = ScanResult::default();
 
     // Scan all directories for valid files to be scanned and imported
-    let mut track_paths = scan_dirs(&import_paths, &SUPPORTED_TRACKS_EXTENSIONS);
+    let mut track_paths = scan_dirs(import_paths, &SUPPORTED_TRACKS_EXTENSIONS);
     let scanned_paths_count = track_paths.len();
 
-    // Remove files that are already in the DB (speedup scan + prevent duplicate errors)
-    let existing_paths = db
+    let existing_tracks = db_state
+        .get_lock()
+        .await
         .get_all_tracks()
         .await?
-        .iter()
-        .map(move |track| PathBuf::from(track.path.to_owned()))
-        .collect::<HashSet<_>>();
-
-    track_paths.retain(|path| !existing_paths.contains(path));
+        .into_iter()
+        .map(|track| (PathBuf::from(&track.path), track))
+        .collect::<HashMap<_, _>>();
+
+    let mut removed_tracks = Vec::new();
+
+    match mode {
+        // Remove files that are already in the DB (speedup scan + prevent duplicate errors)
+        ScanMode::Import => track_paths.retain(|path| !existing_tracks.contains_key(path)),
+        ScanMode::Rescan => {
+            // Tracks of the scanned folders whose file is gone
+            let scanned_paths = track_paths.iter().collect::<HashSet<_>>();
+            removed_tracks = existing_tracks
+                .iter()
+                .filter(|(path, _)| import_paths.iter().any(|import_path| path.starts_with(import_path)))
+                .filter(|(path, _)| !scanned_paths.contains(path) && !path.exists())
+                .map(|(_, track)| track.clone())
+                .collect::<Vec<_>>();
+
+            // Only read again the files changed since they were read
+            track_paths.retain(|path| match existing_tracks.get(path) {
+                Some(track) => std::fs::metadata(path).map_or(true, |metadata| is_track_file_changed(track, &metadata)),
+                None => true,
+            });
+        }
+    }
 
     info!("Found {} files to import", track_paths.len());
     info!(
@@ -81,75 +427,123 @@ pub async fn import_tracks_to_library<R: Runtime>(
     );
 
     // Setup progress tracking for the UI
-    let progress = Arc::new(AtomicUsize::new(1));
-    let total = Arc::new(AtomicUsize::new(track_paths.len()));
-
-    webview_window
-        .emit(
-            IPCEvent::LibraryScanProgress.as_ref(),
-            ScanProgress {
-                current: 0,
-                total: track_paths.len(),
-            },
-        )
-        .unwrap();
-
-    // Let's get all tracks ID3
-    info!("Importing ID3 tags from {} files", track_paths.len());
-    let scan_logger = TimeLogger::new("Scanned all id3 tags".into());
-
-    let tracks = track_paths
-        .par_iter()
-        .map(|path| -> Option<Track> {
-            // let counter = processed.clone();
-            let p_current = progress.clone().fetch_add(1, Ordering::SeqCst);
-            let p_total = total.clone().load(Ordering::SeqCst);
-
-            if p_current % 200 == 0 || p_current == p_total {
-                info!("Processing tracks {:?}/{:?}", p_current, total);
-                webview_window
-                    .emit(
-                        IPCEvent::LibraryScanProgress.as_ref(),
-                        ScanProgress {
-                            current: p_current,
-                            total: p_total,
-                        },
-                    )
-                    .unwrap();
+    let progress = AtomicUsize::new(0);
+    let total = track_paths.len();
+    let throttle = Throttle::new(PROGRESS_INTERVAL);
+
+    on_progress(ScanProgress {
+        job_id: job.id.clone(),
+        current: 0,
+        total,
+        current_file: None,
+    });
+
+    info!("Importing ID3 tags from {} files", total);
+    let scan_logger = TimeLogger::new("Scanned and saved all id3 tags".into());
+    let mut moved_track_ids = HashSet::new();
+
+    for batch in track_paths.chunks(IMPORT_BATCH_SIZE) {
+        if job.is_cancelled() {
+            info!("Library import {} cancelled", job.id);
+            scan_result.cancelled = true;
+            break;
+        }
+
+        // Let's get the batch tracks ID3, the DB is not locked meanwhile
+        let results = batch
+            .par_iter()
+            .map(|path| {
+                let track = read_track_from_file(path, import_untagged);
+                let current = progress.fetch_add(1, Ordering::SeqCst) + 1;
+
+                if throttle.ready() {
+                    on_progress(ScanProgress {
+                        job_id: job.id.clone(),
+                        current,
+                        total,
+                        current_file: Some(path.to_string_lossy().to_string()),
+                    });
+                }
+
+                track
+            })
+            .collect::<Vec<_>>();
+
+        let mut tracks = Vec::with_capacity(results.len());
+        for result in results {
+            match result {
+                Ok(track) => tracks.push(track),
+                Err(failure) => scan_result.failures.push(failure),
             }
+        }
 
-            get_track_from_file(path)
-        })
-        .flatten()
-        .collect::<Vec<Track>>();
+        scan_result.track_count += tracks.len();
+        scan_result.track_failures += batch.len() - tracks.len();
+
+        let mut db = db_state.get_lock().await;
+        ormlite::query("BEGIN").execute(&mut db.connection).await?;
+
+        let result = save_scanned_tracks(&mut db, tracks, &existing_tracks, &mut scan_result, &mut moved_track_ids).await;
+        match result {
+            Ok(_) => {
+                ormlite::query("COMMIT").execute(&mut db.connection).await?;
+            }
+            Err(err) => {
+                ormlite::query("ROLLBACK").execute(&mut db.connection).await?;
+                return Err(err);
+            }
+        }
+    }
 
-    let track_failures = track_paths.len() - tracks.len();
-    scan_result.track_count = tracks.len();
-    scan_result.track_failures = track_failures;
-    info!("{} tracks successfully scanned", tracks.len());
-    info!("{} tracks failed to be scanned", track_failures);
+    on_progress(ScanProgress {
+        job_id: job.id.clone(),
+        current: progress.load(Ordering::SeqCst),
+        total,
+        current_file: None,
+    });
 
+    info!("{} tracks successfully scanned", scan_result.track_count);
+    info!("{} tracks failed to be scanned", scan_result.track_failures);
     scan_logger.complete();
 
-    // Insert all tracks in the DB, we'are kind of assuming it cannot fail (regarding scan progress information), but
-    // it technically could.
-    let db_insert_logger: TimeLogger = TimeLogger::new("Inserted tracks".into());
-    let result = db.insert_tracks(tracks).await;
+    // Files missing from a cancelled import may have been moved
+    if scan_result.cancelled {
+        return Ok(scan_result);
+    }
+
+    let mut db = db_state.get_lock().await;
+
+    removed_tracks.retain(|track| !moved_track_ids.contains(&track.id));
+    if !removed_tracks.is_empty() {
+        ormlite::query("BEGIN").execute(&mut db.connection).await?;
+        let cleanup = match remove_local_tracks(&mut db, removed_tracks).await {
+            Ok(cleanup) => {
+                ormlite::query("COMMIT").execute(&mut db.connection).await?;
+                cleanup
+            }
+            Err(err) => {
+                ormlite::query("ROLLBACK").execute(&mut db.connection).await?;
+                return Err(err);
+            }
+        };
 
-    if result.is_err() {
-        error!(
-            "Something went wrong when inserting tracks: {}",
-            result.err().unwrap()
+        scan_result.tracks_removed = cleanup.removed_tracks;
+        info!(
+            "{} tracks removed, {} cloud mappings and {} playlists updated",
+            cleanup.removed_tracks, cleanup.removed_cloud_mappings, cleanup.updated_playlists
         );
     }
 
-    db_insert_logger.complete();
+    // Files are read without the DB, like tracks
+    drop(db);
 
     // Now that all tracks are inserted, let's scan for playlists, and import them
-    let mut playlist_paths = scan_dirs(&import_paths, &SUPPORTED_PLAYLISTS_EXTENSIONS);
+    let mut playlist_paths = scan_dirs(import_paths, &SUPPORTED_PLAYLISTS_EXTENSIONS);
 
     // Ignore playlists that are already in the DB (speedup scan + prevent duplicate errors)
-    let existing_playlists_paths = db
+    let existing_playlists_paths = db_state
+        .get_lock()
+        .await
         .get_all_playlists()
         .await?
         .iter()
@@ -161,43 +555,25 @@ pub async fn import_tracks_to_library<R: Runtime>(
 
     info!("Found {} playlist(s) to import", playlist_paths.len());
 
-    // Start scanning the content of the playlists and adding them to the DB
+    let mut playlists = Vec::with_capacity(playlist_paths.len());
     for playlist_path in playlist_paths {
-        let res = {
-            let mut reader = m3u::Reader::open(&playlist_path).unwrap();
-            let playlist_dir_path = playlist_path.parent().unwrap();
-
-            let track_paths: Vec<PathBuf> = reader
-                .entries()
-                .filter_map(|entry| {
-                    let Ok(entry) = entry else {
-                        return None;
-                    };
-
-                    match entry {
-                        m3u::Entry::Path(path) => Some(playlist_dir_path.join(path)),
-                        _ => None, // We don't support (yet?) URLs in playlists
-                    }
-                })
-                .collect();
-
-            // Ok, this is sketchy. To avoid having to create a TrackByPath DB View,
-            // let's guess the ID of the track with UUID::v3
-            let track_ids = track_paths
-                .iter()
-                .flat_map(get_track_id_for_path)
-                .collect::<Vec<String>>();
-
-            let playlist_name = playlist_path
-                .file_stem()
-                .unwrap()
-                .to_str()
-                .unwrap_or("unknown playlist")
-                .to_owned();
+        match read_playlist_file(&playlist_path) {
+            Ok((playlist_name, track_paths)) => playlists.push((playlist_path, playlist_name, track_paths)),
+            Err(err) => {
+                warn!("Failed to read playlist {:?}: {}", playlist_path, err);
+                scan_result.playlist_failures += 1;
+            }
+        }
+    }
 
-            let tracks = db.get_tracks(&track_ids).await?;
+    // Start adding the content of the playlists to the DB
+    let mut db = db_state.get_lock().await;
+    for (playlist_path, playlist_name, track_paths) in playlists {
+        let res = {
+            let tracks = db.get_tracks_by_paths(&track_paths).await?;
+            let track_ids = tracks.iter().map(|track| track.id.clone()).collect::<Vec<String>>();
 
-            if tracks.len() != track_ids.len() {
+            if tracks.len() != track_paths.len() {
                 warn!(
                     "Playlist track mismatch ({} from playlist, {} from library)",
                     track_paths.len(),
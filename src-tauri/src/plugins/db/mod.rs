@@ -3,4 +3,6 @@ mod track;
 mod playlist;
 mod library;
 
-pub use core::*; 
\ No newline at end of file
+pub use core::*;
+pub use library::{ScanMode, ScanResult};
+pub(crate) use library::{import_tracks, ImportJob};
@@ -8,8 +8,7 @@ use tauri_plugin_dialog::{DialogExt, FilePath};
 use crate::libs::constants::SUPPORTED_PLAYLISTS_EXTENSIONS;
 use crate::libs::error::{AnyResult, SyncudioError};
 use crate::libs::playlist::Playlist;
-use crate::libs::track::get_track_id_for_path;
-use crate::libs::utils::scan_dirs;
+use crate::libs::utils::{normalize_path, scan_dirs};
 
 use super::core::DBState;
 
@@ -160,18 +159,14 @@ pub async fn import_playlists(
                     };
 
                     match entry {
-                        m3u::Entry::Path(path) => Some(playlist_dir_path.join(path)),
+                        m3u::Entry::Path(path) => Some(normalize_path(&playlist_dir_path.join(path))),
                         _ => None, // We don't support (yet?) URLs in playlists
                     }
                 })
                 .collect();
 
-            // Ok, this is sketchy. To avoid having to create a TrackByPath DB View,
-            // let's guess the ID of the track with UUID::v3
-            let track_ids = track_paths
-                .iter()
-                .flat_map(get_track_id_for_path)
-                .collect::<Vec<String>>();
+            let tracks = db.get_tracks_by_paths(&track_paths).await?;
+            let track_ids = tracks.iter().map(|track| track.id.clone()).collect::<Vec<String>>();
 
             let playlist_name = playlist_path
                 .file_stem()
@@ -180,9 +175,7 @@ pub async fn import_playlists(
                 .unwrap_or("unknown playlist")
                 .to_owned();
 
-            let tracks = db.get_tracks(&track_ids).await?;
-
-            if tracks.len() != track_ids.len() {
+            if tracks.len() != track_paths.len() {
                 info!(
                     "Playlist track mismatch ({} from playlist, {} from library)",
                     track_paths.len(),
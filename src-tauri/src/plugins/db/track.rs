@@ -1,7 +1,11 @@
+use chrono::Utc;
 use tauri::State;
 
-use crate::libs::error::AnyResult;
+use crate::libs::device::get_device_id;
+use crate::libs::error::{AnyResult, SyncudioError};
 use crate::libs::track::Track;
+use crate::libs::track_query::{TrackPage, TrackQuery};
+use crate::libs::track_stats::{TrackStatsDTO, MAX_RATING};
 
 use super::core::DBState;
 
@@ -11,6 +15,12 @@ pub async fn get_all_tracks(db_state: State<'_, DBState>) -> AnyResult<Vec<Track
     db_state.get_lock().await.get_all_tracks().await
 }
 
+/// Get a page of tracks, filtered and sorted by the database
+#[tauri::command]
+pub async fn query_tracks(db_state: State<'_, DBState>, query: TrackQuery) -> AnyResult<TrackPage<Track>> {
+    db_state.get_lock().await.query_tracks(&query).await
+}
+
 /// Get specific tracks by their IDs
 #[tauri::command]
 pub async fn get_tracks(db_state: State<'_, DBState>, ids: Vec<String>) -> AnyResult<Vec<Track>> {
@@ -27,4 +37,48 @@ pub async fn update_track(db_state: State<'_, DBState>, track: Track) -> AnyResu
 #[tauri::command]
 pub async fn remove_tracks(db_state: State<'_, DBState>, ids: Vec<String>) -> AnyResult<()> {
     db_state.get_lock().await.remove_tracks(&ids).await
-} 
\ No newline at end of file
+} 
+
+/// Record that a track was played on this device
+#[tauri::command]
+pub async fn record_track_play(db_state: State<'_, DBState>, id: String) -> AnyResult<()> {
+    db_state
+        .get_lock()
+        .await
+        .record_track_play(&id, Utc::now())
+        .await
+}
+
+/// Rate a track, or remove its rating
+#[tauri::command]
+pub async fn set_track_rating(
+    db_state: State<'_, DBState>,
+    id: String,
+    rating: Option<u32>,
+) -> AnyResult<()> {
+    if let Some(rating) = rating {
+        if rating == 0 || rating > MAX_RATING {
+            return Err(SyncudioError::InvalidRating(rating));
+        }
+    }
+
+    db_state
+        .get_lock()
+        .await
+        .set_track_rating(&id, rating, Utc::now())
+        .await
+}
+
+/// Get play counts, last played dates and ratings of tracks, across devices
+#[tauri::command]
+pub async fn get_tracks_stats(
+    db_state: State<'_, DBState>,
+    ids: Vec<String>,
+) -> AnyResult<Vec<TrackStatsDTO>> {
+    let device_id = get_device_id()?;
+    db_state
+        .get_lock()
+        .await
+        .get_tracks_stats(&ids, &device_id)
+        .await
+}
@@ -1,11 +1,11 @@
-use std::path::PathBuf;
 use std::sync::RwLock;
 use serde::{Deserialize, Serialize};
 use tauri::plugin::{Builder, TauriPlugin};
 use tauri::{Manager, Runtime, State};
-use log::info;
+use log::{info, warn};
 use rustfm_scrobble::{Scrobble, Scrobbler};
 
+use crate::libs::credentials::CredentialStore;
 use crate::libs::error::{AnyResult, SyncudioError};
 use crate::plugins::config::get_storage_dir;
 
@@ -17,13 +17,17 @@ pub struct LastFmSession {
 
 pub struct LastFmManager {
     client: RwLock<Scrobbler>,
-    storage_path: PathBuf,
 }
 
+const CREDENTIALS_NAME: &str = "lastfm";
+
 impl LastFmManager {
     pub fn new() -> Self {
-        let storage_dir = get_storage_dir();
-        let session_path = storage_dir.join("lastfm_auth.dat");
+        // Sessions used to be stored in plaintext
+        let legacy_path = get_storage_dir().join("lastfm_auth.dat");
+        if let Err(e) = CredentialStore::migrate_plaintext_file(CREDENTIALS_NAME, &legacy_path) {
+            warn!("Could not migrate Last.fm session: {}", e);
+        }
 
         let scrobbler = Scrobbler::new(
             "99ae73a3fcccd31a29d59ec6ae09973e",
@@ -32,15 +36,12 @@ impl LastFmManager {
 
         let manager = Self {
             client: RwLock::new(scrobbler),
-            storage_path: session_path,
         };
 
-        if let Ok(session_str) = std::fs::read_to_string(&manager.storage_path) {
-            if let Ok(session) = toml::from_str::<LastFmSession>(&session_str) {
-                info!("Found Last.fm session for user: {}", session.username);
-                if let Ok(mut client) = manager.client.write() {
-                    client.authenticate_with_session_key(&session.session_key)
-                }
+        if let Some(session) = manager.get_session() {
+            info!("Found Last.fm session for user: {}", session.username);
+            if let Ok(mut client) = manager.client.write() {
+                client.authenticate_with_session_key(&session.session_key)
             }
         }
 
@@ -54,28 +55,29 @@ impl LastFmManager {
         };
         
         let session_str = toml::to_string(&session)?;
-        std::fs::write(&self.storage_path, session_str)?;
+        CredentialStore::set(CREDENTIALS_NAME, &session_str)?;
         info!("Saved Last.fm session for user: {}", username);
         Ok(())
     }
 
     pub fn clear_session(&self) -> AnyResult<()> {
-        if self.storage_path.exists() {
-            std::fs::remove_file(&self.storage_path)?;
-            info!("Cleared Last.fm session");
-        }
+        CredentialStore::delete(CREDENTIALS_NAME)?;
+        info!("Cleared Last.fm session");
         Ok(())
     }
 
     pub fn get_session(&self) -> Option<LastFmSession> {
-        if let Ok(session_str) = std::fs::read_to_string(&self.storage_path) {
-            if let Ok(session) = toml::from_str::<LastFmSession>(&session_str) {
-                if !session.session_key.is_empty() {
-                    return Some(session);
-                }
+        let session_str = match CredentialStore::get(CREDENTIALS_NAME) {
+            Ok(session_str) => session_str?,
+            Err(e) => {
+                warn!("Could not load Last.fm session: {}", e);
+                return None;
             }
-        }
-        None
+        };
+
+        toml::from_str::<LastFmSession>(&session_str)
+            .ok()
+            .filter(|session| !session.session_key.is_empty())
     }
 }
 
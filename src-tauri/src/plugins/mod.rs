@@ -19,6 +19,11 @@ pub mod cover;
 pub mod config;
 pub mod db;
 
+/**
+ * Library
+ */
+pub mod watcher;
+
 /**
  * Integrations
  */
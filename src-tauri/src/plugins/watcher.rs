@@ -0,0 +1,224 @@
+/**
+ * Module in charge of watching the library and cloud folders, so tracks added,
+ * changed or removed outside of Syncudio are picked up without a manual import
+ */
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, Debouncer, FileIdMap};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use tauri::plugin::{Builder, TauriPlugin};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use ts_rs::TS;
+
+use crate::libs::constants::SUPPORTED_TRACKS_EXTENSIONS;
+use crate::libs::error::AnyResult;
+use crate::libs::events::IPCEvent;
+use crate::libs::track::{get_track_from_file, Track};
+use crate::libs::utils::{is_file_valid, scan_dir};
+use crate::plugins::cloud::{queue_local_changes, remove_local_tracks};
+use crate::plugins::config::ConfigManager;
+use crate::plugins::db::DBState;
+
+/// Delay without new events before changes are applied
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct WatcherState(Mutex<Option<Debouncer<RecommendedWatcher, FileIdMap>>>);
+
+/// Tracks changed in the watched folders
+#[derive(Default, Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../src/generated/typings/index.ts")]
+pub struct LibraryChangedPayload {
+    added: Vec<String>,
+    updated: Vec<String>,
+    removed: Vec<String>,
+    uploads_queued: usize,
+}
+
+impl LibraryChangedPayload {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+/**
+ * (Re)start watching the library folders and the local folders of the cloud
+ * folders, or stop watching if library autorefresh is disabled. Returns the
+ * watched folders.
+ */
+async fn watch_folders<R: Runtime>(app_handle: &AppHandle<R>) -> AnyResult<Vec<PathBuf>> {
+    let watcher_state = app_handle.state::<WatcherState>();
+    let config = app_handle.state::<ConfigManager>().get()?;
+
+    // Dropping the debouncer stops the previous watcher
+    *watcher_state.0.lock().unwrap() = None;
+
+    if !config.library_autorefresh {
+        info!("Library autorefresh disabled, not watching folders");
+        return Ok(vec![]);
+    }
+
+    let mut folders = config.library_folders;
+    {
+        let db_state = app_handle.state::<DBState>();
+        let mut db = db_state.get_lock().await;
+        folders.extend(
+            db.get_cloud_music_folders()
+                .await?
+                .into_iter()
+                .map(|folder| PathBuf::from(folder.local_folder_path)),
+        );
+    }
+
+    // Nested folders are already covered by their parent
+    let roots: Vec<PathBuf> = folders
+        .iter()
+        .filter(|folder| !folders.iter().any(|other| other != *folder && folder.starts_with(other)))
+        .cloned()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    let handle = app_handle.clone();
+    let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, None, move |result: DebounceEventResult| {
+        let handle = handle.clone();
+        tauri::async_runtime::spawn(async move {
+            handle_events(&handle, result).await;
+        });
+    })?;
+
+    for root in &roots {
+        if let Err(err) = debouncer.watcher().watch(root, RecursiveMode::Recursive) {
+            warn!("Could not watch {:?}: {}", root, err);
+            continue;
+        }
+        debouncer.cache().add_root(root, RecursiveMode::Recursive);
+        info!("Watching {:?}", root);
+    }
+
+    *watcher_state.0.lock().unwrap() = Some(debouncer);
+
+    Ok(roots)
+}
+
+async fn handle_events<R: Runtime>(app_handle: &AppHandle<R>, result: DebounceEventResult) {
+    let events = match result {
+        Ok(events) => events,
+        Err(errors) => {
+            for err in errors {
+                warn!("Watcher error: {}", err);
+            }
+            return;
+        }
+    };
+
+    let paths: HashSet<PathBuf> = events
+        .into_iter()
+        .filter(|event| !event.kind.is_access())
+        .flat_map(|event| event.event.paths)
+        .collect();
+
+    if paths.is_empty() {
+        return;
+    }
+
+    match apply_changes(app_handle, paths).await {
+        Ok(payload) if !payload.is_empty() => {
+            info!(
+                "Library changed: {} added, {} updated, {} removed, {} uploads queued",
+                payload.added.len(),
+                payload.updated.len(),
+                payload.removed.len(),
+                payload.uploads_queued
+            );
+            if let Err(err) = app_handle.emit(IPCEvent::LibraryChanged.as_ref(), payload) {
+                error!("Could not emit library changes: {}", err);
+            }
+        }
+        Ok(_) => {}
+        Err(err) => error!("Could not apply library changes: {}", err),
+    }
+}
+
+/// Rescan the changed paths, paths that do not exist anymore are removed from the library
+async fn apply_changes<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    paths: HashSet<PathBuf>,
+) -> AnyResult<LibraryChangedPayload> {
+    let mut track_paths = Vec::new();
+    let mut removed_paths = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            // Folders moved into a watched folder only get one event
+            track_paths.extend(scan_dir(&path, &SUPPORTED_TRACKS_EXTENSIONS));
+        } else if path.is_file() {
+            if is_file_valid(&path, &SUPPORTED_TRACKS_EXTENSIONS) {
+                track_paths.push(path);
+            }
+        } else {
+            removed_paths.push(path);
+        }
+    }
+
+    // Reading tags is slow, the DB is not locked meanwhile
+    let tracks: Vec<Track> = track_paths.par_iter().filter_map(get_track_from_file).collect();
+
+    let mut payload = LibraryChangedPayload::default();
+    let db_state = app_handle.state::<DBState>();
+    let mut db = db_state.get_lock().await;
+
+    // Moved files are updated before the paths they left are removed, so they keep their track
+    for mut track in tracks.iter().cloned() {
+        if db.upsert_track(&mut track).await? {
+            payload.added.push(track.id);
+        } else {
+            payload.updated.push(track.id);
+        }
+    }
+
+    // Removed tracks also leave their cloud mappings and playlists
+    let removed_tracks = db.get_tracks_by_path(&removed_paths).await?;
+    payload.removed = removed_tracks.iter().map(|track| track.id.clone()).collect();
+    remove_local_tracks(&mut db, removed_tracks).await?;
+    payload.uploads_queued = queue_local_changes(&mut db, &tracks).await?;
+
+    Ok(payload)
+}
+
+/// Restart the watcher, eg. after the library folders or autorefresh changed
+#[tauri::command]
+pub async fn refresh<R: Runtime>(app_handle: AppHandle<R>) -> AnyResult<Vec<PathBuf>> {
+    watch_folders(&app_handle).await
+}
+
+/**
+ * Plugin in charge of watching the library folders for changes
+ */
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::<R>::new("watcher")
+        .invoke_handler(tauri::generate_handler![refresh])
+        .setup(|app_handle, _api| {
+            app_handle.manage(WatcherState(Mutex::new(None)));
+
+            let app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                // The DB is set up asynchronously, cloud folders can only be read once it is ready
+                while app_handle.try_state::<DBState>().is_none() {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+
+                if let Err(err) = watch_folders(&app_handle).await {
+                    error!("Failed to watch library folders: {}", err);
+                }
+            });
+
+            Ok(())
+        })
+        .build()
+}
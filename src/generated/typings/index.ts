@@ -1,12 +1,47 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
+/**
+ * Cloud folder synced by other devices, that can be mapped to a local folder
+ */
+export type AdoptableCloudFolder = { provider_type: string, cloud_folder_id: string, cloud_folder_path: string, device_names: Array<string>, };
+
 /**
  * Result of a cleanup operation
  */
-export type CleanupResult = { removed_tracks: number, removed_cloud_mappings: number, removed_cloud_tracks: number, };
+export type CleanupResult = { removed_tracks: number, removed_cloud_mappings: number, removed_cloud_tracks: number, updated_playlists: number, };
+
+/**
+ * A cloud storage account, each one has its own credentials
+ */
+export type CloudAccount = { id: string, provider_type: string, provider_account_id: string, display_name: string, email: string, quota_used: bigint | null, quota_allocated: bigint | null, created_at: string, updated_at: string, };
+
+/**
+ * A cloud account with the state of its credentials on this device
+ */
+export type CloudAccountStatus = { account: CloudAccount, authorized: boolean, is_default: boolean, };
 
 export type CloudFile = { id: string, name: string, size: number, is_folder: boolean, modified_at: string, mime_type: string | null, hash: FileHash | null, display_path: string | null, relative_path: string, };
 
+/**
+ * Hash of the plaintext of a track at its last transfer. Cloud hashes of
+ * encrypted tracks describe the ciphertext, so they cannot be compared with
+ * local files.
+ */
+export type CloudFileHash = { cloud_map_id: string, plaintext_hash: string, updated_at: string, };
+
+/**
+ * A version of a cloud file kept by the provider, the newest one is current
+ */
+export type CloudFileRevision = { id: string, size: number, modified_at: string, hash: FileHash | null, is_current: boolean, };
+
+/**
+ * Encryption settings of a cloud music folder. The key itself is never stored
+ * in the database, see `FolderKey`.
+ */
+export type CloudFolderEncryption = { cloud_music_folder_id: string, salt: string, key_check: string, created_at: string, };
+
+export type CloudFolderEncryptionStatus = { encrypted: boolean, unlocked: boolean, };
+
 export type CloudFolderScanResult = { 
 /**
  * Number of tracks found in cloud storage
@@ -27,7 +62,12 @@ tracks_updated: number,
 /**
  * Number of track mappings that were cleared (cloud_file_id set to None)
  */
-mappings_cleared: number, };
+mappings_cleared: number, 
+/**
+ * Number of local tracks ignored, as their path only differs by case or
+ * Unicode form from another track, which the cloud cannot tell apart
+ */
+path_collisions: number, };
 
 /**
  * Represents detailed sync information for a cloud folder
@@ -42,17 +82,57 @@ export type CloudMetadataCollection = { tracks: Array<CloudTrackMetadata>, };
 /**
  * Result of a metadata sync operation
  */
-export type CloudMetadataSyncResult = { tracks_updated: number, tracks_created: number, is_fresh_start: boolean, };
+export type CloudMetadataSyncResult = { tracks_updated: number, tracks_created: number, is_fresh_start: boolean, playlists: CloudPlaylistSyncResult, stats: CloudTrackStatsSyncResult, };
 
 /**
  * Result of a metadata update operation
  */
-export type CloudMetadataUpdateResult = { tracks_included: number, tracks_skipped: number, };
+export type CloudMetadataUpdateResult = { tracks_included: number, tracks_skipped: number, playlists: CloudPlaylistSyncResult, stats: CloudTrackStatsSyncResult, };
+
+/**
+ * A cloud folder synced with a local folder. A local folder can be synced with
+ * several cloud folders, each with its own tracks maps, queue and sync mode.
+ */
+export type CloudMusicFolder = { id: string, provider_type: string, cloud_folder_id: string, cloud_folder_path: string, local_folder_path: string, account_id: string | null, sync_mode: string, };
+
+export type CloudPlaylist = { id: string, name: string, tracks: Array<CloudPlaylistTrackRef>, created_at: string, updated_at: string, local_playlist_id: string | null, synced_tracks: Array<CloudPlaylistTrackRef>, synced_name: string, deleted: boolean, };
+
+/**
+ * Collection of playlists for cloud storage
+ */
+export type CloudPlaylistCollection = { playlists: Array<CloudPlaylistMetadata>, };
 
-export type CloudMusicFolder = { id: string, provider_type: string, cloud_folder_id: string, cloud_folder_path: string, local_folder_path: string, };
+/**
+ * Playlist as stored in `/Syncudio/metadata/playlists.json`
+ */
+export type CloudPlaylistMetadata = { id: string, name: string, tracks: Array<CloudPlaylistTrackRef>, created_at: string, updated_at: string, deleted: boolean, };
+
+/**
+ * Result of a playlist sync operation
+ */
+export type CloudPlaylistSyncResult = { playlists_created: number, playlists_updated: number, playlists_deleted: number, playlists_pushed: number, };
+
+/**
+ * Reference to a track inside a cloud playlist.
+ *
+ * Local track IDs are derived from local paths and differ between devices, so
+ * playlists stored in the cloud reference tracks by their location in a cloud
+ * folder instead. The cloud track ID is kept as a hint for faster lookups.
+ */
+export type CloudPlaylistTrackRef = { cloud_track_id: string | null, cloud_folder_id: string, relative_path: string, };
 
 export type CloudProviderType = "dropbox" | "gdrive";
 
+/**
+ * Represents the space used in a cloud account, including the pending uploads
+ */
+export type CloudStorageUsageDTO = { account_id: string | null, used: number, allocated: number | null, pending_upload_bytes: number, available_after_uploads: number | null, exceeds_quota: boolean, };
+
+/**
+ * How a local folder is synced with one of its cloud folders
+ */
+export type CloudSyncMode = "two_way" | "upload_only" | "download_only";
+
 export type CloudTrack = { id: string, file_name: string, size: number, updated_at: string, tags: CloudTrackTag | null, };
 
 /**
@@ -73,22 +153,80 @@ export type CloudTrackMap = { id: string, cloud_track_id: string, cloud_music_fo
  */
 export type CloudTrackMetadata = { cloud_file_id: string, cloud_path: string, relative_path: string, size: number, tags: CloudTrackTag | null, last_modified: string, last_sync: string | null, provider: string, cloud_folder_id: string, };
 
-export type CloudTrackTag = { title: string, album: string, artists: Array<string>, composers: Array<string>, album_artists: Array<string>, genres: Array<string>, date: string | null, year: number | null, duration: number, track_no: number | null, track_of: number | null, disk_no: number | null, disk_of: number | null, bitrate: number | null, sampling_rate: number | null, channels: number | null, encoder: string | null, size: number, };
+/**
+ * Listening statistics of a cloud track, shared between devices.
+ *
+ * Play counts and last played dates are recorded per device, so concurrent
+ * updates can be merged without losing plays. The rating is last-writer-wins.
+ */
+export type CloudTrackStats = { cloud_track_id: string, cloud_file_id: string | null, play_counts: { [key in string]?: number }, last_played: { [key in string]?: string }, rating: number | null, rating_updated_at: string | null, };
+
+/**
+ * Collection of track statistics, stored in `/Syncudio/metadata/stats.json`
+ */
+export type CloudTrackStatsCollection = { tracks: Array<CloudTrackStats>, };
+
+/**
+ * Result of a stats sync operation
+ */
+export type CloudTrackStatsSyncResult = { tracks_updated: number, tracks_pushed: number, };
+
+export type CloudTrackTag = { title: string, album: string, artists: Array<string>, composers: Array<string>, album_artists: Array<string>, genres: Array<string>, date: string | null, year: number | null, duration: number, track_no: number | null, track_of: number | null, disk_no: number | null, disk_of: number | null, bitrate: number | null, sampling_rate: number | null, channels: number | null, encoder: string | null, };
 
 export type Config = { theme: string, audio_volume: number, audio_playback_rate: number | null, audio_output_device: string, audio_muted: boolean, audio_shuffle: boolean, audio_repeat: Repeat, default_view: DefaultView, library_sort_by: SortBy, library_sort_order: SortOrder, library_folders: Array<string>, library_autorefresh: boolean, sleepblocker: boolean, auto_update_checker: boolean, minimize_to_tray: boolean, notifications: boolean, track_view_density: string, sync_worker_enabled: boolean, sync_concurrent_uploads: number, sync_concurrent_downloads: number, sync_retry_limit: number, sync_retry_delay_seconds: number, lastfm_enabled: boolean, };
 
 export type DefaultView = "Library" | "Playlists";
 
-export type DownloadQueueItem = { id: string, priority: number, cloud_map_id: string, provider_type: string, status: string, error_message: string | null, created_at: string, updated_at: string, attempts: number, };
+/**
+ * Cloud folder synced by a device, and where it lives on that device
+ */
+export type DeviceFolder = { provider_type: string, cloud_folder_id: string, cloud_folder_path: string, local_folder_path: string, };
+
+/**
+ * Description of a device, stored in `/Syncudio/devices/<device id>.json`
+ */
+export type DeviceManifest = { id: string, name: string, os: string, app_version: string, folders: Array<DeviceFolder>, last_sync_at: string | null, updated_at: string, };
+
+export type DownloadQueueItem = { id: string, priority: number, cloud_map_id: string, provider_type: string, status: string, error_message: string | null, created_at: string, updated_at: string, attempts: number, revision_id: string | null, };
 
 export type FileHash = { "Sha1": string } | { "Sha256": string } | { "ContentHash": string };
 
+/**
+ * Represents the outcome of verifying every mapped track of a cloud folder
+ */
+export type FolderIntegrityReportDTO = { folder_id: string, tracks_checked: number, tracks_verified: number, corrupted_count: number, truncated_count: number, missing_count: number, mismatched_count: number, repairs_queued: number, issues: Array<IntegrityIssueDTO>, checked_at: string, };
+
+/**
+ * Represents the outcome of moving the local folder of a cloud folder
+ */
+export type FolderRelocationResultDTO = { folder_id: string, previous_local_folder_path: string, local_folder_path: string, tracks_relocated: number, playlists_updated: number, files_verified: number, };
+
 /**
  * Represents the sync status of a cloud folder
  */
 export type FolderSyncStatus = "synced" | "syncing" | "needs_attention" | "empty";
 
-export type IPCEvent = { "Unknown": string } | "PlaybackPlay" | "PlaybackPause" | "PlaybackStop" | "PlaybackPlayPause" | "PlaybackPrevious" | "PlaybackNext" | "PlaybackStart" | "LibraryScanProgress" | "GoToLibrary" | "GoToPlaylists" | "GoToSettings" | "JumpToPlayingTrack";
+export type IPCEvent = { "Unknown": string } | "PlaybackPlay" | "PlaybackPause" | "PlaybackStop" | "PlaybackPlayPause" | "PlaybackPrevious" | "PlaybackNext" | "PlaybackStart" | "LibraryScanProgress" | "LibraryScanComplete" | "LibraryChanged" | "GoToLibrary" | "GoToPlaylists" | "GoToSettings" | "JumpToPlayingTrack";
+
+/**
+ * Represents a problem found when verifying a synced track
+ */
+export type IntegrityIssueDTO = { cloud_track_id: string, cloud_map_id: string, relative_path: string, kind: IntegrityIssueKind, location: IntegrityIssueLocation, detail: string, repair: SyncOperationType | null, repair_queued: boolean, };
+
+/**
+ * Represents a problem found when verifying a synced track
+ */
+export type IntegrityIssueKind = "corrupted" | "truncated" | "missing" | "mismatched";
+
+/**
+ * Represents which copy of a track an integrity issue is about
+ */
+export type IntegrityIssueLocation = "local" | "cloud";
+
+/**
+ * Tracks changed in the watched folders
+ */
+export type LibraryChangedPayload = { added: Array<string>, updated: Array<string>, removed: Array<string>, uploads_queued: number, };
 
 /** ----------------------------------------------------------------------------
  * Playlist
@@ -101,62 +239,185 @@ export type Playlist = { id: string, name: string, tracks: Array<string>, import
  */
 export type QueueItemDTO = { id: string, cloud_track_id: string, file_name: string, operation: SyncOperationType, status: SyncStatus, created_at: string, updated_at: string, provider_type: string, };
 
+/**
+ * Represents the pause state of the sync queue
+ */
+export type QueuePauseStateDTO = { upload_paused: boolean, download_paused: boolean, };
+
+/**
+ * Represents the number of queue items removed by a purge
+ */
+export type QueuePurgeResultDTO = { uploads_purged: number, downloads_purged: number, };
+
 /**
  * Represents queue statistics
  */
-export type QueueStatsDTO = { pending_count: number, in_progress_count: number, completed_count: number, failed_count: number, };
+export type QueueStatsDTO = { pending_count: number, in_progress_count: number, completed_count: number, failed_count: number, cancelled_count: number, };
 
 export type Repeat = "All" | "One" | "None";
 
+/**
+ * Represents the outcome of rolling a track back to a previous revision
+ */
+export type RevisionRestoreResultDTO = { cloud_map_id: string, revision_id: string, cloud_revision_id: string | null, download_item_id: string | null, };
+
+/**
+ * Represents which copies of a track are rolled back to a previous revision
+ */
+export type RevisionRestoreTarget = "cloud" | "local" | "both";
+
+/**
+ * A file that could not be imported as a track
+ */
+export type ScanFailure = { path: string, reason: ScanFailureReason, message: string, };
+
+/**
+ * Why a file could not be imported as a track
+ */
+export type ScanFailureReason = "unsupported_codec" | "corrupt_header" | "no_tag" | "permission_denied" | "io" | "duplicate_path";
+
+/**
+ * Outcome of a library import job, sent once it is over
+ */
+export type ScanJobResult = { job_id: string, result: ScanResult | null, error: string | null, };
+
+/**
+ * How a scan treats the tracks already in the library
+ */
+export type ScanMode = "import" | "rescan";
+
 /**
  * Scan progress information
  */
-export type ScanProgress = { current: number, total: number, };
+export type ScanProgress = { job_id: string, current: number, total: number, current_file: string | null, };
 
 /**
  * Result of a library scan operation
  */
-export type ScanResult = { track_count: number, track_failures: number, playlist_count: number, playlist_failures: number, };
+export type ScanResult = { track_count: number, track_failures: number, tracks_added: number, tracks_updated: number, tracks_moved: number, tracks_removed: number, playlist_count: number, playlist_failures: number, cancelled: boolean, failures: Array<ScanFailure>, };
 
 export type SortBy = "Artist" | "Album" | "Title" | "Duration" | "Genre";
 
 export type SortOrder = "Asc" | "Dsc";
 
 /**
- * Represents a sync history entry
+ * Space used in a cloud account and its quota, in bytes
  */
-export type SyncHistoryEntry = { timestamp: string, operation: SyncOperationType, status: SyncStatus, };
+export type SpaceUsage = { used: number, allocated: number | null, };
+
+/**
+ * Side kept when resolving a sync conflict
+ */
+export type SyncConflictResolution = "keep_local" | "keep_cloud";
+
+/**
+ * Record of a sync operation, kept for `SYNC_HISTORY_RETENTION_DAYS`
+ */
+export type SyncHistoryEntry = { id: string, timestamp: string, operation: string, status: string, provider_type: string, cloud_music_folder_id: string | null, cloud_track_id: string | null, relative_path: string | null, bytes: bigint, duration_ms: bigint, error_message: string | null, details: string | null, };
+
+/**
+ * Filter for sync history queries, every field is optional
+ */
+export type SyncHistoryFilter = { cloud_track_id: string | null, cloud_music_folder_id: string | null, operation: SyncHistoryOperation | null, from: string | null, to: string | null, limit: number | null, offset: number | null, };
+
+export type SyncHistoryOperation = "upload" | "download" | "delete" | "conflict_resolution" | "metadata_push" | "metadata_pull";
 
 /**
  * Represents operation type for sync operations
  */
 export type SyncOperationType = "upload" | "download";
 
+/**
+ * Represents an action planned by a sync
+ */
+export type SyncPlanAction = "upload" | "download" | "delete" | "move" | "conflict" | "metadata_update";
+
+/**
+ * Represents the outcome of applying a sync plan
+ */
+export type SyncPlanApplyResultDTO = { uploads_queued: number, downloads_queued: number, deletes_applied: number, moves_applied: number, metadata_updated: number, conflicts_skipped: number, items_skipped: number, };
+
+/**
+ * Represents everything a sync of a cloud folder would do, without doing it
+ */
+export type SyncPlanDTO = { folder_id: string, cloud_folder_path: string, local_folder_path: string, items: Array<SyncPlanItemDTO>, upload_count: number, download_count: number, delete_count: number, move_count: number, conflict_count: number, metadata_update_count: number, upload_bytes: number, download_bytes: number, estimated_seconds: number, created_at: string, };
+
+/**
+ * Represents a single action of a sync plan
+ */
+export type SyncPlanItemDTO = { action: SyncPlanAction, cloud_track_id: string | null, cloud_map_id: string | null, cloud_file_id: string | null, relative_path: string, previous_relative_path: string | null, size: number, reason: string, };
+
+/**
+ * Pause state of a queue direction, missing rows mean the queue is running
+ */
+export type SyncQueueState = { operation: string, paused: boolean, updated_at: string, };
+
 export type SyncQueueStatus = "pending" | "in_progress" | "completed" | "failed" | "cancelled";
 
 /**
  * Represents the status of a sync operation
  */
-export type SyncStatus = "pending" | "in_progress" | "completed" | { "failed": { error: string, attempts: number, } };
+export type SyncStatus = "pending" | "in_progress" | "completed" | { "failed": { error: string, attempts: number, } } | "cancelled";
 
 /**
  * Track
- * represent a single track, id and path should be unique
+ * represent a single track, id and path should be unique. The id is generated
+ * once and follows the track when its file is moved or renamed.
  */
-export type Track = { id: string, path: string, title: string, album: string, artists: Array<string>, composers: Array<string>, album_artists: Array<string>, genres: Array<string>, track_no: number | null, track_of: number | null, disk_no: number | null, disk_of: number | null, date: string | null, year: number | null, duration: number, bitrate: number | null, sampling_rate: number | null, channels: number | null, encoder: string | null, size: number, };
+export type Track = { id: string, path: string, title: string, album: string, artists: Array<string>, composers: Array<string>, album_artists: Array<string>, genres: Array<string>, track_no: number | null, track_of: number | null, disk_no: number | null, disk_of: number | null, date: string | null, year: number | null, duration: number, bitrate: number | null, sampling_rate: number | null, channels: number | null, encoder: string | null, size: number, mtime: number | null, fingerprint: string | null, };
 
 export type TrackDownloadedPayload = { track_id: string, location_type: string, local_track_id: string, cloud_track_id: string, sync_folder_id: string, relative_path: string, };
 
+/**
+ * ----------------------------------------------------------------------------
+ * TrackQuery
+ * a page of the library, filtered and sorted by the database instead of the UI
+ * -------------------------------------------------------------------------- 
+ * Conditions tracks must all meet, text conditions ignore ASCII case
+ */
+export type TrackFilter = { text: string | null, artist: string | null, album: string | null, genre: string | null, year_from: number | null, year_to: number | null, location_state: TrackLocationState | null, folder_id: string | null, };
+
 /**
  * Represents the location state of a track by checking both local and cloud existence by cloud_file_id and relative_path (should be in local storage and cloud storage)
  */
 export type TrackLocationState = "complete" | "local_only" | "cloud_only" | "out_of_sync" | "missing" | "not_mapped";
 
+export type TrackPage<T> = { items: Array<T>, total: number | null, next_cursor: string | null, };
+
+/**
+ * A query for a page of tracks. The cursor is the position of the last track
+ * of the previous page, as returned with it.
+ */
+export type TrackQuery = { filter: TrackFilter, sort: TrackSort, cursor: string | null, limit: number | null, with_total: boolean, };
+
+/**
+ * A track matching a library search, either a local track or a cloud-only track
+ */
+export type TrackSearchResultDTO = { track: Track | null, cloud_track: CloudTrack | null, rank: number, };
+
+/**
+ * Sort of a query, the first key follows the order, the next ones are always
+ * ascending like in the library view
+ */
+export type TrackSort = { sort_by: SortBy, sort_order: SortOrder, then_by: Array<SortBy>, };
+
+/** ----------------------------------------------------------------------------
+ * TrackStats
+ * listening statistics of a track, as recorded on this device
+ * -------------------------------------------------------------------------- */
+export type TrackStats = { track_id: string, play_count: number, last_played: string | null, rating: number | null, rating_updated_at: string | null, };
+
+/**
+ * Statistics of a track as displayed in the UI, combining the plays of every
+ * device sharing the same cloud track
+ */
+export type TrackStatsDTO = { track_id: string, play_count: number, last_played: string | null, rating: number | null, };
+
 /**
  * Represents detailed sync information for a track
  */
 export type TrackSyncStatusDTO = { location_state: TrackLocationState, sync_operation: SyncOperationType | null, sync_status: SyncStatus | null, updated_at: string, };
 
-export type UnifiedTrack = { local_track_id: string | null, cloud_track_id: string | null, cloud_map_id: string | null, cloud_folder_id: string | null, local_path: string | null, cloud_relative_path: string | null, cloud_folder_path: string | null, cloud_local_folder_path: string | null, cloud_provider_type: string | null, cloud_file_id: string | null, title: string, album: string, artists: Array<string> | null, genres: Array<string> | null, year: number | null, duration: number, track_no: number | null, track_of: number | null, disk_no: number | null, disk_of: number | null, cloud_updated_at: string | null, };
+export type UnifiedTrack = { local_track_id: string | null, cloud_track_id: string | null, cloud_map_id: string | null, cloud_folder_id: string | null, local_path: string | null, cloud_relative_path: string | null, cloud_folder_path: string | null, cloud_local_folder_path: string | null, cloud_provider_type: string | null, cloud_file_id: string | null, location_state: string, cloud_sync_mode: string | null, title: string, album: string, artists: Array<string> | null, genres: Array<string> | null, year: number | null, duration: number, track_no: number | null, track_of: number | null, disk_no: number | null, disk_of: number | null, size: number, cloud_updated_at: string | null, };
 
 export type UploadQueueItem = { id: string, priority: number, cloud_map_id: string, provider_type: string, status: string, error_message: string | null, created_at: string, updated_at: string, attempts: number, };
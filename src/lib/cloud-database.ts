@@ -10,8 +10,8 @@ export const cloudDatabase = {
     return invoke('plugin:cloud|get_cloud_music_folders_by_provider', { providerType });
   },
 
-  async getCloudFolderByLocalPath(localPath: string): Promise<CloudMusicFolder | null> {
-    return invoke('plugin:cloud|get_cloud_folder_by_local_path', { localPath });
+  async getCloudFoldersByLocalPath(localPath: string): Promise<CloudMusicFolder[]> {
+    return invoke('plugin:cloud|get_cloud_music_folders_by_local_path', { localPath });
   },
 
   async saveCloudFolder(folder: CloudMusicFolder): Promise<CloudMusicFolder> {
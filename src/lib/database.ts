@@ -1,6 +1,15 @@
 import { invoke } from '@tauri-apps/api/core';
-
-import type { Playlist, ScanResult, Track } from '../generated/typings';
+import { listen } from '@tauri-apps/api/event';
+
+import type {
+  IPCEvent,
+  Playlist,
+  ScanFailure,
+  ScanJobResult,
+  ScanMode,
+  ScanResult,
+  Track,
+} from '../generated/typings';
 
 /**
  * Bridge for the UI to communicate with the backend and manipulate the Database
@@ -32,10 +41,68 @@ const database = {
     });
   },
 
-  async importTracks(importPaths: Array<string>): Promise<ScanResult> {
-    return invoke('plugin:database|import_tracks_to_library', {
-      importPaths,
+  /**
+   * Import folders in the background, resolves once the import job is over
+   */
+  async importTracks(
+    importPaths: Array<string>,
+    mode?: ScanMode,
+    importUntagged?: boolean,
+    onStart?: (jobID: string) => void,
+  ): Promise<ScanResult> {
+    let jobID: string | null = null;
+    const finished: Array<ScanJobResult> = [];
+    let resolveJob: (jobResult: ScanJobResult) => void = () => {};
+    const done = new Promise<ScanJobResult>((resolve) => {
+      resolveJob = resolve;
     });
+
+    // Listen first, a short import may be over before its ID is known
+    const unlisten = await listen<ScanJobResult>(
+      'LibraryScanComplete' satisfies IPCEvent,
+      ({ payload }) => {
+        if (jobID === null) {
+          finished.push(payload);
+        } else if (payload.job_id === jobID) {
+          resolveJob(payload);
+        }
+      },
+    );
+
+    try {
+      jobID = await invoke<string>('plugin:database|import_tracks_to_library', {
+        importPaths,
+        mode,
+        importUntagged,
+      });
+      onStart?.(jobID);
+
+      const early = finished.find((jobResult) => jobResult.job_id === jobID);
+      if (early) {
+        resolveJob(early);
+      }
+
+      const { result, error } = await done;
+      if (result == null) {
+        throw new Error(error ?? 'The library import failed');
+      }
+      return result;
+    } finally {
+      unlisten();
+    }
+  },
+
+  async cancelImport(jobID: string): Promise<boolean> {
+    return invoke('plugin:database|cancel_library_import', {
+      jobId: jobID,
+    });
+  },
+
+  /**
+   * Files the last import could not import, and why
+   */
+  async getScanFailures(): Promise<Array<ScanFailure>> {
+    return invoke('plugin:database|get_scan_failures');
   },
 
   // ---------------------------------------------------------------------------
@@ -171,6 +171,7 @@ async function setDefaultView(defaultView: DefaultView): Promise<void> {
  */
 async function toggleLibraryAutorefresh(value: boolean): Promise<void> {
   await config.set('library_autorefresh', value);
+  await invoke('plugin:watcher|refresh');
 }
 
 async function checkForLibraryRefresh(): Promise<void> {
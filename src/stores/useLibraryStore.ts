@@ -1,3 +1,4 @@
+import { invoke } from '@tauri-apps/api/core';
 import { ask, open } from '@tauri-apps/plugin-dialog';
 
 import type { SortBy, SortOrder, Track } from '../generated/typings';
@@ -167,6 +168,7 @@ const useLibraryStore = createLibraryStore<LibraryState>((set, get) => ({
           ...paths,
         ]).sort();
         await config.set('library_folders', newFolders);
+        await invoke('plugin:watcher|refresh');
       } catch (err) {
         logAndNotifyError(err);
       }
@@ -177,6 +179,7 @@ const useLibraryStore = createLibraryStore<LibraryState>((set, get) => ({
       const index = musicFolders.indexOf(path);
       musicFolders.splice(index, 1);
       await config.set('library_folders', musicFolders);
+      await invoke('plugin:watcher|refresh');
     },
 
     setRefresh: async (current, total) => {
@@ -232,6 +235,7 @@ const useLibraryStore = createLibraryStore<LibraryState>((set, get) => ({
         if (confirmed) {
           await database.reset();
           await config.set('library_folders', []);
+          await invoke('plugin:watcher|refresh');
           useToastsStore.getState().api.add('success', 'Library was reset');
         }
       } catch (err) {